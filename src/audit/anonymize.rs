@@ -0,0 +1,166 @@
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::audit::log::AuditEvent;
+
+// Hex characters of the HMAC kept in client_hash
+const HASH_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnonymizeMode {
+    #[default]
+    Off,
+    // Keep only the network prefix of the address
+    Truncate,
+    // Replace the address with an HMAC under a key that is never persisted
+    Hash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnonymizeConfig {
+    pub mode: AnonymizeMode,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+    // A new random key is drawn this often, so hashes only link events within
+    // one period; 0 keeps the key for the life of the process
+    pub key_rotation_hours: u64,
+}
+
+impl Default for AnonymizeConfig {
+    fn default() -> Self {
+        Self {
+            mode: AnonymizeMode::Off,
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+            key_rotation_hours: 24,
+        }
+    }
+}
+
+struct HashKey {
+    key: [u8; 32],
+    created: Instant,
+}
+
+impl HashKey {
+    fn generate() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self { key, created: Instant::now() }
+    }
+}
+
+// Rewrites client addresses in events before they reach any audit sink. The
+// request itself keeps the real address for policy and rate limiting
+pub struct Anonymizer {
+    config: AnonymizeConfig,
+    key: Mutex<HashKey>,
+}
+
+impl Anonymizer {
+    pub fn new(config: AnonymizeConfig) -> Self {
+        Self { config, key: Mutex::new(HashKey::generate()) }
+    }
+
+    pub fn apply(&self, mut event: AuditEvent) -> AuditEvent {
+        let Some(ip) = event.client_ip else {
+            return event;
+        };
+        match self.config.mode {
+            AnonymizeMode::Off => {}
+            AnonymizeMode::Truncate => event.client_ip = Some(self.truncate(ip)),
+            AnonymizeMode::Hash => {
+                event.client_ip = None;
+                event.client_hash = Some(self.hash(ip));
+            }
+        }
+        event
+    }
+
+    // The address as log lines and trace spans may show it; hash mode gives
+    // the same value as client_hash in events
+    pub fn label(&self, ip: IpAddr) -> String {
+        match self.config.mode {
+            AnonymizeMode::Off => ip.to_string(),
+            AnonymizeMode::Truncate => self.truncate(ip).to_string(),
+            AnonymizeMode::Hash => self.hash(ip),
+        }
+    }
+
+    fn truncate(&self, ip: IpAddr) -> IpAddr {
+        let prefix = match ip {
+            IpAddr::V4(_) => self.config.ipv4_prefix.min(32),
+            IpAddr::V6(_) => self.config.ipv6_prefix.min(128),
+        };
+        IpNet::new(ip, prefix).map_or(ip, |network| network.network())
+    }
+
+    fn hash(&self, ip: IpAddr) -> String {
+        let mut key = self.key.lock().unwrap_or_else(|e| e.into_inner());
+        let rotation = Duration::from_secs(self.config.key_rotation_hours * 3600);
+        if self.config.key_rotation_hours > 0 && key.created.elapsed() >= rotation {
+            *key = HashKey::generate();
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&key.key).expect("HMAC accepts any key length");
+        mac.update(ip.to_string().as_bytes());
+        let mut digest = hex::encode(mac.finalize().into_bytes());
+        digest.truncate(HASH_LEN);
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::log::AuditEventType;
+
+    fn event(client_ip: &str) -> AuditEvent {
+        AuditEvent { client_ip: Some(client_ip.parse().unwrap()), ..AuditEvent::for_test(AuditEventType::Request) }
+    }
+
+    #[test]
+    fn test_truncate_masks_host_bits() {
+        let anonymizer = Anonymizer::new(AnonymizeConfig { mode: AnonymizeMode::Truncate, ..Default::default() });
+        assert_eq!(anonymizer.apply(event("192.0.2.77")).client_ip, Some("192.0.2.0".parse().unwrap()));
+        assert_eq!(anonymizer.apply(event("2001:db8:1234:5678::9")).client_ip, Some("2001:db8:1234::".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_hash_is_stable_per_key() {
+        let anonymizer = Anonymizer::new(AnonymizeConfig { mode: AnonymizeMode::Hash, ..Default::default() });
+        let first = anonymizer.apply(event("192.0.2.77"));
+        let second = anonymizer.apply(event("192.0.2.77"));
+        let other = anonymizer.apply(event("192.0.2.78"));
+        assert!(first.client_ip.is_none());
+        assert_eq!(first.client_hash.as_ref().map(String::len), Some(HASH_LEN));
+        assert_eq!(first.client_hash, second.client_hash);
+        assert_ne!(first.client_hash, other.client_hash);
+        
+        // A fresh key (as after rotation) unlinks earlier hashes
+        let rotated = Anonymizer::new(AnonymizeConfig { mode: AnonymizeMode::Hash, ..Default::default() });
+        assert_ne!(rotated.apply(event("192.0.2.77")).client_hash, first.client_hash);
+    }
+
+    #[test]
+    fn test_label_matches_events() {
+        let ip: IpAddr = "192.0.2.77".parse().unwrap();
+        assert_eq!(Anonymizer::new(AnonymizeConfig::default()).label(ip), "192.0.2.77");
+        let truncate = Anonymizer::new(AnonymizeConfig { mode: AnonymizeMode::Truncate, ..Default::default() });
+        assert_eq!(truncate.label(ip), "192.0.2.0");
+        let hash = Anonymizer::new(AnonymizeConfig { mode: AnonymizeMode::Hash, ..Default::default() });
+        assert_eq!(Some(hash.label(ip)), hash.apply(event("192.0.2.77")).client_hash);
+    }
+
+    #[test]
+    fn test_off_keeps_address() {
+        let anonymizer = Anonymizer::new(AnonymizeConfig::default());
+        assert_eq!(anonymizer.apply(event("192.0.2.77")).client_ip, Some("192.0.2.77".parse().unwrap()));
+    }
+}
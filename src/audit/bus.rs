@@ -0,0 +1,180 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, error};
+use crate::audit::log::AuditEvent;
+use crate::metrics::registry::Metrics;
+
+// A destination for audit events. Every sink is fed from its own bounded queue
+// on its own thread, so record() may block, and a sink that fails or falls
+// behind loses only its own events
+pub trait AuditSink: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    fn record(&self, event: &AuditEvent) -> Result<()>;
+}
+
+// Writes events to the tracing log; used when no file sink is configured
+pub struct TracingSink;
+
+impl AuditSink for TracingSink {
+    fn name(&self) -> &str {
+        "tracing"
+    }
+
+    fn record(&self, event: &AuditEvent) -> Result<()> {
+        info!("Audit: {}", serde_json::to_string(event)?);
+        Ok(())
+    }
+}
+
+enum SinkMessage {
+    // Boxed, since events are far larger than a flush request
+    Event(Box<AuditEvent>),
+    Flush(oneshot::Sender<()>),
+}
+
+struct SinkQueue {
+    name: String,
+    sender: mpsc::Sender<SinkMessage>,
+}
+
+pub struct AuditBus {
+    queues: Vec<SinkQueue>,
+}
+
+impl AuditBus {
+    pub fn new(sinks: Vec<Arc<dyn AuditSink>>, queue_size: usize) -> Self {
+        let queues = sinks
+            .into_iter()
+            .map(|sink| {
+                let (sender, receiver) = mpsc::channel(queue_size.max(1));
+                let name = sink.name().to_string();
+                tokio::task::spawn_blocking(move || run_sink(sink, receiver));
+                SinkQueue { name, sender }
+            })
+            .collect();
+        Self { queues }
+    }
+
+    // Never waits; events for a sink whose queue is full are dropped and counted
+    pub fn publish(&self, event: &AuditEvent) {
+        for queue in &self.queues {
+            match queue.sender.try_send(SinkMessage::Event(Box::new(event.clone()))) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    Metrics::global().audit_events_dropped.with_label_values(&[&queue.name]).inc();
+                }
+                Err(TrySendError::Closed(_)) => error!("Audit sink '{}' has stopped", queue.name),
+            }
+        }
+    }
+
+    // Resolves once every sink has handled the events published before the call
+    pub async fn flush(&self) {
+        let mut pending = Vec::new();
+        for queue in &self.queues {
+            let (done, wait) = oneshot::channel();
+            if queue.sender.send(SinkMessage::Flush(done)).await.is_ok() {
+                pending.push(wait);
+            }
+        }
+        for wait in pending {
+            let _ = wait.await;
+        }
+    }
+}
+
+fn run_sink(sink: Arc<dyn AuditSink>, mut receiver: mpsc::Receiver<SinkMessage>) {
+    while let Some(message) = receiver.blocking_recv() {
+        match message {
+            SinkMessage::Event(event) => {
+                if let Err(e) = sink.record(&event) {
+                    error!("Audit sink '{}' failed to record event: {}", sink.name(), e);
+                }
+            }
+            SinkMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::log::AuditEventType;
+    use std::sync::Mutex;
+
+    struct Collecting {
+        name: &'static str,
+        fail: bool,
+        // When set, each event waits for the test to send on it or drop the sender
+        gate: Option<Mutex<std::sync::mpsc::Receiver<()>>>,
+        // Told about every event recorded
+        recorded: Option<tokio::sync::mpsc::UnboundedSender<()>>,
+        events: Mutex<Vec<AuditEventType>>,
+    }
+
+    impl Collecting {
+        fn new(name: &'static str, fail: bool) -> Self {
+            Self { name, fail, gate: None, recorded: None, events: Mutex::new(Vec::new()) }
+        }
+    }
+
+    impl AuditSink for Collecting {
+        fn name(&self) -> &str {
+            self.name
+        }
+        
+        fn record(&self, event: &AuditEvent) -> Result<()> {
+            if let Some(gate) = &self.gate {
+                let _ = gate.lock().unwrap().recv();
+            }
+            if self.fail {
+                return Err(anyhow::anyhow!("unavailable"));
+            }
+            self.events.lock().unwrap().push(event.event_type.clone());
+            if let Some(recorded) = &self.recorded {
+                let _ = recorded.send(());
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_sink_is_isolated() {
+        let healthy = Arc::new(Collecting::new("healthy", false));
+        let broken = Arc::new(Collecting::new("broken", true));
+        let bus = AuditBus::new(vec![broken, healthy.clone()], 16);
+        
+        bus.publish(&AuditEvent::for_test(AuditEventType::Request));
+        bus.publish(&AuditEvent::for_test(AuditEventType::FetchSuccess));
+        bus.flush().await;
+        
+        assert_eq!(*healthy.events.lock().unwrap(), vec![AuditEventType::Request, AuditEventType::FetchSuccess]);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_for_that_sink_only() {
+        let (release, gate) = std::sync::mpsc::channel();
+        let stalled = Arc::new(Collecting { gate: Some(Mutex::new(gate)), ..Collecting::new("bus-test-stalled", false) });
+        let (recorded, mut healthy_recorded) = tokio::sync::mpsc::unbounded_channel();
+        let healthy = Arc::new(Collecting { recorded: Some(recorded), ..Collecting::new("bus-test-healthy", false) });
+        let bus = AuditBus::new(vec![stalled.clone(), healthy.clone()], 1);
+        let dropped = Metrics::global().audit_events_dropped.with_label_values(&["bus-test-stalled"]);
+        
+        for _ in 0..3 {
+            bus.publish(&AuditEvent::for_test(AuditEventType::CacheHit));
+            // The healthy sink drains its one-slot queue before the next event
+            healthy_recorded.recv().await.unwrap();
+        }
+        // Unblocks the stalled sink for good
+        drop(release);
+        bus.flush().await;
+        
+        assert_eq!(healthy.events.lock().unwrap().len(), 3);
+        assert!(dropped.get() >= 1);
+        assert!(stalled.events.lock().unwrap().len() < 3);
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use crate::audit::bus::AuditSink;
+use crate::audit::log::AuditEvent;
+use crate::audit::siem::EventFormat;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSinkConfig {
+    pub enabled: bool,
+    pub path: String,
+    // One event per line; cef and leef write only security events
+    pub format: EventFormat,
+    // Rotate once the file would grow past this size; 0 disables size rotation
+    pub max_size_mb: u64,
+    // Rotate files older than this; 0 disables time rotation
+    pub rotate_interval_hours: u64,
+    // Rotated files to keep, oldest removed first; 0 keeps all
+    pub max_files: usize,
+    // gzip rotated files
+    pub compress: bool,
+}
+
+impl Default for FileSinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/var/log/aptg/audit.jsonl".to_string(),
+            format: EventFormat::Json,
+            max_size_mb: 100,
+            rotate_interval_hours: 24,
+            max_files: 14,
+            compress: true,
+        }
+    }
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+    opened_at: DateTime<Utc>,
+}
+
+pub struct FileSink {
+    config: FileSinkConfig,
+    path: PathBuf,
+    max_bytes: u64,
+    active: Mutex<ActiveFile>,
+}
+
+impl FileSink {
+    pub fn open(config: FileSinkConfig) -> Result<Self> {
+        let path = PathBuf::from(&config.path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create audit directory {}: {}", parent.display(), e))?;
+        }
+        let active = Self::open_file(&path)?;
+        info!("Writing audit events to {}", path.display());
+        
+        Ok(Self {
+            max_bytes: config.max_size_mb * 1024 * 1024,
+            path,
+            active: Mutex::new(active),
+            config,
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<ActiveFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open audit file {}: {}", path.display(), e))?;
+        let size = file.metadata()?.len();
+        Ok(ActiveFile { file, size, opened_at: Utc::now() })
+    }
+
+    fn write_line(&self, line: &str) -> Result<()> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let len = line.len() as u64 + 1;
+        if self.needs_rotation(&active, len, Utc::now()) {
+            *active = self.rotate()?;
+        }
+        
+        active.file.write_all(line.as_bytes())?;
+        active.file.write_all(b"\n")?;
+        active.file.flush()?;
+        active.size += len;
+        Ok(())
+    }
+
+    fn needs_rotation(&self, active: &ActiveFile, len: u64, now: DateTime<Utc>) -> bool {
+        // An empty file is never rotated, even if a single event exceeds the limit
+        if active.size == 0 {
+            return false;
+        }
+        let too_large = self.max_bytes > 0 && active.size + len > self.max_bytes;
+        let too_old = self.config.rotate_interval_hours > 0
+            && now - active.opened_at >= chrono::Duration::hours(self.config.rotate_interval_hours as i64);
+        too_large || too_old
+    }
+
+    fn rotate(&self) -> Result<ActiveFile> {
+        let rotated = PathBuf::from(format!("{}.{}", self.config.path, Utc::now().format("%Y%m%dT%H%M%S%.3fZ")));
+        std::fs::rename(&self.path, &rotated)
+            .map_err(|e| anyhow!("Failed to rotate audit file {}: {}", self.path.display(), e))?;
+        let active = Self::open_file(&self.path)?;
+        
+        // Compression can take a while for large files; keep it off the request path
+        let config = self.config.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = archive(&rotated, &config) {
+                warn!("Failed to archive rotated audit file {}: {}", rotated.display(), e);
+            }
+        });
+        Ok(active)
+    }
+}
+
+impl AuditSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn record(&self, event: &AuditEvent) -> Result<()> {
+        if !self.config.format.includes(event) {
+            return Ok(());
+        }
+        self.write_line(&self.config.format.format(event)?)
+    }
+}
+
+fn archive(rotated: &Path, config: &FileSinkConfig) -> Result<()> {
+    if config.compress {
+        compress_file(rotated)?;
+    }
+    prune_rotated(Path::new(&config.path), config.max_files)
+}
+
+fn compress_file(path: &Path) -> Result<PathBuf> {
+    let compressed = PathBuf::from(format!("{}.gz", path.display()));
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(path)?;
+    Ok(compressed)
+}
+
+// Rotated files carry a sortable timestamp suffix, so name order is age order
+fn prune_rotated(path: &Path, max_files: usize) -> Result<()> {
+    if max_files == 0 {
+        return Ok(());
+    }
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", path.file_name().and_then(|f| f.to_str()).unwrap_or_default());
+
+    let mut rotated: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.file_name().and_then(|f| f.to_str()).is_some_and(|f| f.starts_with(&prefix)))
+        .collect();
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(max_files);
+    for old in rotated.into_iter().take(excess) {
+        std::fs::remove_file(&old)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn sink_config(dir: &Path, compress: bool) -> FileSinkConfig {
+        FileSinkConfig {
+            enabled: true,
+            path: dir.join("audit.jsonl").to_str().unwrap().to_string(),
+            format: EventFormat::Json,
+            max_size_mb: 0,
+            rotate_interval_hours: 0,
+            max_files: 2,
+            compress,
+        }
+    }
+
+    fn rotated_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.file_name().unwrap() != "audit.jsonl")
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_size_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = FileSink::open(sink_config(dir.path(), false)).unwrap();
+        sink.max_bytes = 20;
+        
+        sink.write_line(r#"{"event":"first"}"#).unwrap();
+        sink.write_line(r#"{"event":"second"}"#).unwrap();
+        
+        let current = std::fs::read_to_string(dir.path().join("audit.jsonl")).unwrap();
+        assert_eq!(current, "{\"event\":\"second\"}\n");
+        let rotated = rotated_files(dir.path());
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(std::fs::read_to_string(&rotated[0]).unwrap(), "{\"event\":\"first\"}\n");
+    }
+
+    #[test]
+    fn test_time_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FileSinkConfig { rotate_interval_hours: 24, ..sink_config(dir.path(), false) };
+        let sink = FileSink::open(config).unwrap();
+        sink.write_line("{}").unwrap();
+        
+        let active = sink.active.lock().unwrap();
+        assert!(!sink.needs_rotation(&active, 3, Utc::now()));
+        assert!(sink.needs_rotation(&active, 3, Utc::now() + chrono::Duration::hours(25)));
+    }
+
+    #[test]
+    fn test_archive_compresses_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = sink_config(dir.path(), true);
+        for stamp in ["20260101T000000.000Z", "20260102T000000.000Z", "20260103T000000.000Z"] {
+            std::fs::write(dir.path().join(format!("audit.jsonl.{}", stamp)), "{}\n").unwrap();
+        }
+        
+        archive(&dir.path().join("audit.jsonl.20260103T000000.000Z"), &config).unwrap();
+        
+        let rotated = rotated_files(dir.path());
+        assert_eq!(rotated.len(), 2);
+        assert!(rotated[0].ends_with("audit.jsonl.20260102T000000.000Z"));
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(File::open(&rotated[1]).unwrap()).read_to_string(&mut content).unwrap();
+        assert_eq!(content, "{}\n");
+    }
+}
@@ -1,40 +1,209 @@
 use std::net::IpAddr;
+use std::sync::Arc;
 use warp::http::{Method, HeaderMap};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use rand::RngCore;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
 use tracing::{info, warn, error};
+use crate::audit::anonymize::{AnonymizeConfig, Anonymizer};
+use crate::audit::bus::{AuditBus, AuditSink, TracingSink};
+use crate::audit::file::{FileSink, FileSinkConfig};
+use crate::audit::store::{AuditQuery, AuditStore, StoreConfig};
+use crate::audit::stream::{AuditStreamer, StreamSinkConfig};
+use crate::geoip::location::LocationInfo;
+use crate::metrics::registry::Metrics;
+use crate::verify::control::DebControl;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub file: FileSinkConfig,
+    // Queryable history for the /admin/audit endpoints
+    pub store: StoreConfig,
+    // Webhook and Kafka sinks receiving events as they happen
+    pub sinks: Vec<StreamSinkConfig>,
+    pub filter: AuditFilter,
+    // Applied to client addresses before events reach any sink
+    pub anonymize: AnonymizeConfig,
+    // Add the client's country and ASN from the router's GeoIP lookup to every event
+    pub geo_enrich: bool,
+    // Events buffered per sink; a sink that falls further behind drops events
+    pub queue_size: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            file: FileSinkConfig::default(),
+            store: StoreConfig::default(),
+            sinks: vec![],
+            filter: AuditFilter::default(),
+            anonymize: AnonymizeConfig::default(),
+            geo_enrich: false,
+            queue_size: 10000,
+        }
+    }
+}
+
+// Decides which events reach the sinks at all. Excluded events are dropped
+// before the file, store and streaming sinks see them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    pub exclude_types: Vec<AuditEventType>,
+    pub exclude_statuses: Vec<AuditStatus>,
+    // Recorded even when an exclusion above matches, e.g. security events
+    pub always_types: Vec<AuditEventType>,
+}
+
+impl AuditFilter {
+    pub fn records(&self, event: &AuditEvent) -> bool {
+        if self.always_types.contains(&event.event_type) {
+            return true;
+        }
+        !self.exclude_types.contains(&event.event_type) && !self.exclude_statuses.contains(&event.status)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEvent {
     pub timestamp: DateTime<Utc>,
+    // Shared by every event logged while handling one request
+    #[serde(default)]
+    pub request_id: Option<String>,
     pub event_type: AuditEventType,
     pub client_ip: Option<IpAddr>,
+    // Keyed hash of the client address, set instead of client_ip in hash anonymization mode
+    #[serde(default)]
+    pub client_hash: Option<String>,
+    // ISO country code and autonomous system of the client, when GeoIP enrichment is on
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub asn: Option<u32>,
+    // Subject of the verified client certificate on mutual TLS connections
+    #[serde(default)]
+    pub client_identity: Option<String>,
     pub method: Option<String>,
     pub path: String,
     pub user_agent: Option<String>,
     pub status: AuditStatus,
     pub message: Option<String>,
+    // Time since the request arrived when the event was logged
     pub duration_ms: Option<u64>,
+    // Time spent waiting on the upstream mirror, for fetch events
+    #[serde(default)]
+    pub upstream_ms: Option<u64>,
+    // Request body size as declared by Content-Length
+    #[serde(default)]
+    pub request_bytes: Option<u64>,
+    // Body bytes served to the client
+    #[serde(default)]
+    pub response_bytes: Option<u64>,
+    // Body bytes received from the upstream mirror
+    #[serde(default)]
+    pub upstream_bytes: Option<u64>,
+    // From the control file of the requested package, once it has been read
+    #[serde(default)]
+    pub package: Option<PackageFields>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Info event for the bookworm InRelease; tests override fields with struct update syntax
+#[cfg(test)]
+impl AuditEvent {
+    pub fn for_test(event_type: AuditEventType) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            request_id: None,
+            event_type,
+            client_ip: None,
+            client_hash: None,
+            country: None,
+            asn: None,
+            client_identity: None,
+            method: None,
+            path: "/debian/dists/bookworm/InRelease".to_string(),
+            user_agent: None,
+            status: AuditStatus::Info,
+            message: None,
+            duration_ms: None,
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageFields {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AuditEventType {
     Request,
     CacheHit,
     FetchSuccess,
     FetchError,
     PolicyViolation,
+    PolicyDryRun,
     VerificationFailed,
     VerificationSuccess,
+    VerificationWarning,
+    UnexpectedSigner,
+    UnverifiedContentDenied,
     GeoIPDenied,
     GeoIPAllowed,
     GeoIPRateLimit,
     GeoIPRedirect,
     GeoIPLogOnly,
     GeoIPError,
+    CertificateExpiring,
+    TlsHandshakeFailed,
+    AdminAction,
+    AuthenticationFailed,
+    VulnerablePackage,
+    SubsystemReload,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl AuditEventType {
+    // Metric label value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::CacheHit => "cache_hit",
+            Self::FetchSuccess => "fetch_success",
+            Self::FetchError => "fetch_error",
+            Self::PolicyViolation => "policy_violation",
+            Self::PolicyDryRun => "policy_dry_run",
+            Self::VerificationFailed => "verification_failed",
+            Self::VerificationSuccess => "verification_success",
+            Self::VerificationWarning => "verification_warning",
+            Self::UnexpectedSigner => "unexpected_signer",
+            Self::UnverifiedContentDenied => "unverified_content_denied",
+            Self::GeoIPDenied => "geoip_denied",
+            Self::GeoIPAllowed => "geoip_allowed",
+            Self::GeoIPRateLimit => "geoip_rate_limit",
+            Self::GeoIPRedirect => "geoip_redirect",
+            Self::GeoIPLogOnly => "geoip_log_only",
+            Self::GeoIPError => "geoip_error",
+            Self::CertificateExpiring => "certificate_expiring",
+            Self::TlsHandshakeFailed => "tls_handshake_failed",
+            Self::AdminAction => "admin_action",
+            Self::AuthenticationFailed => "authentication_failed",
+            Self::VulnerablePackage => "vulnerable_package",
+            Self::SubsystemReload => "subsystem_reload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AuditStatus {
     Success,
     Warning,
@@ -43,268 +212,947 @@ pub enum AuditStatus {
     Failed,
 }
 
+impl AuditStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Info => "info",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+// Request details attached to every event logged while handling it
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub id: String,
+    pub client_ip: Option<IpAddr>,
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub client_identity: Option<String>,
+    pub package: Option<PackageFields>,
+    started: Instant,
+}
+
+impl Default for RequestContext {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl RequestContext {
+    pub fn new(client_ip: Option<IpAddr>) -> Self {
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        Self {
+            id: hex::encode(id),
+            client_ip,
+            country: None,
+            asn: None,
+            client_identity: None,
+            package: None,
+            started: Instant::now(),
+        }
+    }
+
+    // Unresolved addresses come back as country "Unknown" and are left unset
+    pub fn set_location(&mut self, location: &LocationInfo) {
+        if location.country_code != "Unknown" {
+            self.country = Some(location.country_code.clone());
+        }
+        self.asn = location.asn;
+    }
+
+    pub fn set_package(&mut self, control: &DebControl) {
+        self.package = Some(PackageFields {
+            package: control.package().to_string(),
+            version: control.version().to_string(),
+            architecture: control.architecture().to_string(),
+        });
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+// Builds, filters and anonymizes events, then hands them to the sinks on the bus
 pub struct AuditLogger {
-    // In a real implementation, this would write to a file or database
-    // For now, we'll just log via tracing
+    bus: AuditBus,
+    // Also a sink on the bus; kept here to answer queries
+    store: Option<Arc<AuditStore>>,
+    filter: AuditFilter,
+    anonymizer: Anonymizer,
+    geo_enrich: bool,
 }
 
 impl AuditLogger {
     pub fn new() -> Self {
-        Self {}
+        Self::from_config(&AuditConfig::default())
+    }
+
+    pub fn from_config(config: &AuditConfig) -> Self {
+        let mut sinks: Vec<Arc<dyn AuditSink>> = Vec::new();
+        let file = if config.file.enabled {
+            FileSink::open(config.file.clone())
+                .map_err(|e| error!("Audit file sink disabled: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        // Without a file sink events only go to the tracing log
+        match file {
+            Some(file) => sinks.push(Arc::new(file)),
+            None => sinks.push(Arc::new(TracingSink)),
+        }
+        let store = if config.store.enabled {
+            AuditStore::open(&config.store)
+                .map_err(|e| error!("Audit store disabled: {}", e))
+                .ok()
+                .map(Arc::new)
+        } else {
+            None
+        };
+        if let Some(store) = &store {
+            sinks.push(store.clone());
+        }
+        if !config.sinks.is_empty() {
+            sinks.push(Arc::new(AuditStreamer::from_config(&config.sinks)));
+        }
+
+        Self {
+            bus: AuditBus::new(sinks, config.queue_size),
+            store,
+            filter: config.filter.clone(),
+            anonymizer: Anonymizer::new(config.anonymize.clone()),
+            geo_enrich: config.geo_enrich,
+        }
+    }
+
+    pub fn has_store(&self) -> bool {
+        self.store.is_some()
+    }
+
+    pub fn enriches_geo(&self) -> bool {
+        self.geo_enrich
+    }
+
+    // The client address for log lines and spans, anonymized like events
+    pub fn client_label(&self, client_ip: Option<IpAddr>) -> String {
+        client_ip.map_or_else(|| "unknown".to_string(), |ip| self.anonymizer.label(ip))
     }
-    
-    pub async fn log_request(&self, method: &Method, path: &str, headers: &HeaderMap) {
+
+    pub async fn log_request(&self, request: &RequestContext, method: &Method, path: &str, headers: &HeaderMap) {
         let user_agent = headers.get("user-agent")
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string());
-            
+        let request_bytes = headers.get("content-length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::Request,
-            client_ip: None, // Would extract from real connection
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: Some(method.to_string()),
             path: path.to_string(),
             user_agent,
             status: AuditStatus::Info,
             message: Some("Request received".to_string()),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: Some(request_bytes),
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         info!("Request: {} {} from {:?}", method, path, event.user_agent);
         self.write_event(&event).await;
     }
-    
-    pub async fn log_cache_hit(&self, path: &str) {
+
+    pub async fn log_cache_hit(&self, request: &RequestContext, path: &str) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::CacheHit,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Info,
             message: Some("Cache hit".to_string()),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         info!("Cache hit: {}", path);
         self.write_event(&event).await;
     }
-    
-    pub async fn log_fetch_success(&self, path: &str) {
+
+    // Logged once fetched content has passed verification and is being served
+    pub async fn log_fetch_success(&self, request: &RequestContext, path: &str, upstream: Duration, bytes: u64) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::FetchSuccess,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Success,
             message: Some("Successfully fetched from upstream".to_string()),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: Some(upstream.as_millis() as u64),
+            request_bytes: None,
+            response_bytes: Some(bytes),
+            upstream_bytes: Some(bytes),
+            package: request.package.clone(),
         };
-        
+
         info!("Fetch success: {}", path);
         self.write_event(&event).await;
     }
-    
-    pub async fn log_fetch_error(&self, path: &str, error: &anyhow::Error) {
+
+    pub async fn log_fetch_error(&self, request: &RequestContext, path: &str, error: &anyhow::Error, upstream: Duration) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::FetchError,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Error,
             message: Some(format!("Fetch error: {}", error)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: Some(upstream.as_millis() as u64),
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         error!("Fetch error for {}: {}", path, error);
         self.write_event(&event).await;
     }
-    
-    pub async fn log_policy_violation(&self, path: &str, reason: &str) {
+
+    pub async fn log_policy_violation(&self, request: &RequestContext, path: &str, reason: &str) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::PolicyViolation,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Warning,
             message: Some(format!("Policy violation: {}", reason)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         warn!("Policy violation for {}: {}", path, reason);
         self.write_event(&event).await;
     }
-    
-    pub async fn log_verification_success(&self, path: &str) {
+
+    pub async fn log_vulnerable_package(&self, request: &RequestContext, path: &str, vulnerabilities: &[String], blocked: bool) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::VulnerablePackage,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: if blocked { AuditStatus::Failed } else { AuditStatus::Warning },
+            message: Some(format!("Known vulnerabilities: {}", vulnerabilities.join(", "))),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        warn!("{} has known vulnerabilities: {}", path, vulnerabilities.join(", "));
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_policy_dry_run(&self, request: &RequestContext, path: &str, reason: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::PolicyDryRun,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Info,
+            message: Some(format!("Policy violation (not enforced): {}", reason)),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        info!("Dry-run policy violation for {}: {}", path, reason);
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_verification_success(&self, request: &RequestContext, path: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::VerificationSuccess,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Success,
             message: Some("GPG verification successful".to_string()),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         self.write_event(&event).await;
     }
 
-    pub async fn log_verification_failed(&self, path: &str, reason: &str) {
+    pub async fn log_verification_failed(&self, request: &RequestContext, path: &str, reason: &str) {
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::VerificationFailed,
-            client_ip: None,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Failed,
             message: Some(format!("GPG verification failed: {}", reason)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_unexpected_signer(&self, request: &RequestContext, path: &str, fingerprint: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::UnexpectedSigner,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Failed,
+            message: Some(format!("Release signed by unexpected key {} - possible mirror compromise", fingerprint)),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        error!("Release {} signed by unexpected key {} - possible mirror compromise", path, fingerprint);
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_verification_warning(&self, request: &RequestContext, path: &str, reason: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::VerificationWarning,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Warning,
+            message: Some(format!("Verification warning: {}", reason)),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        warn!("Verification warning for {}: {}", path, reason);
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_unverified_denied(&self, request: &RequestContext, path: &str, reason: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::UnverifiedContentDenied,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Failed,
+            message: Some(format!("Strict mode denied unverified content: {}", reason)),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
+        warn!("Strict mode denied {}: {}", path, reason);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_denied(&self, client_ip: &str, path: &str, reason: &str) {
+    pub async fn log_geoip_denied(&self, request: &RequestContext, path: &str, reason: &str) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPDenied,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Warning,
             message: Some(format!("GeoIP denied: {}", reason)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         warn!("GeoIP denied request from {} to {}: {}", client_ip, path, reason);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_allowed(&self, client_ip: &str, path: &str, reason: &str) {
+    pub async fn log_geoip_allowed(&self, request: &RequestContext, path: &str, reason: &str) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPAllowed,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Success,
             message: Some(format!("GeoIP allowed: {}", reason)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         info!("GeoIP allowed request from {} to {}: {}", client_ip, path, reason);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_rate_limit(&self, client_ip: &str, path: &str, limit: u32) {
+    pub async fn log_geoip_rate_limit(&self, request: &RequestContext, path: &str, limit: u32) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPRateLimit,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Warning,
             message: Some(format!("GeoIP rate limited: {} requests/minute", limit)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         warn!("GeoIP rate limited request from {} to {}: {} requests/minute", client_ip, path, limit);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_redirect(&self, client_ip: &str, path: &str, redirect_url: &str) {
+    pub async fn log_geoip_redirect(&self, request: &RequestContext, path: &str, redirect_url: &str) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPRedirect,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Info,
             message: Some(format!("GeoIP redirect to: {}", redirect_url)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         info!("GeoIP redirected request from {} to {} to: {}", client_ip, path, redirect_url);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_log_only(&self, client_ip: &str, path: &str, reason: &str) {
+    pub async fn log_geoip_log_only(&self, request: &RequestContext, path: &str, reason: &str) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPLogOnly,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Info,
             message: Some(format!("GeoIP log only: {}", reason)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         info!("GeoIP logged request from {} to {}: {}", client_ip, path, reason);
         self.write_event(&event).await;
     }
 
-    pub async fn log_geoip_error(&self, client_ip: &str, path: &str, error: &anyhow::Error) {
+    pub async fn log_geoip_error(&self, request: &RequestContext, path: &str, error: &anyhow::Error) {
+        let client_ip = self.client_label(request.client_ip);
         let event = AuditEvent {
             timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
             event_type: AuditEventType::GeoIPError,
-            client_ip: client_ip.parse().ok(),
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
             method: None,
             path: path.to_string(),
             user_agent: None,
             status: AuditStatus::Error,
             message: Some(format!("GeoIP error: {}", error)),
-            duration_ms: None,
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
         };
-        
+
         error!("GeoIP error for {} to {}: {}", client_ip, path, error);
         self.write_event(&event).await;
     }
-    
+
+    // Not tied to a request; path is the certificate file
+    pub async fn log_certificate_expiring(&self, cert_path: &str, days_remaining: i64) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: None,
+            event_type: AuditEventType::CertificateExpiring,
+            client_ip: None,
+            client_hash: None,
+            country: None,
+            asn: None,
+            client_identity: None,
+            method: None,
+            path: cert_path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Warning,
+            message: Some(format!("Certificate expires in {} days", days_remaining)),
+            duration_ms: None,
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: None,
+        };
+
+        warn!("Certificate {} expires in {} days", cert_path, days_remaining);
+        self.write_event(&event).await;
+    }
+
+    // Logged before any request exists, so only the peer address is known
+    pub async fn log_tls_handshake_failed(&self, client_ip: IpAddr, reason: &str, error: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: None,
+            event_type: AuditEventType::TlsHandshakeFailed,
+            client_ip: Some(client_ip),
+            client_hash: None,
+            country: None,
+            asn: None,
+            client_identity: None,
+            method: None,
+            path: String::new(),
+            user_agent: None,
+            status: AuditStatus::Failed,
+            message: Some(format!("TLS handshake failed ({}): {}", reason, error)),
+            duration_ms: None,
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: None,
+        };
+
+        warn!("TLS handshake with {} failed ({}): {}", self.client_label(Some(client_ip)), reason, error);
+        self.write_event(&event).await;
+    }
+
+    pub async fn log_authentication_failed(&self, request: &RequestContext, path: &str, reason: &str) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: Some(request.id.clone()),
+            event_type: AuditEventType::AuthenticationFailed,
+            client_ip: request.client_ip,
+            client_hash: None,
+            country: request.country.clone(),
+            asn: request.asn,
+            client_identity: request.client_identity.clone(),
+            method: None,
+            path: path.to_string(),
+            user_agent: None,
+            status: AuditStatus::Failed,
+            message: Some(format!("Authentication failed: {}", reason)),
+            duration_ms: Some(request.elapsed().as_millis() as u64),
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: request.package.clone(),
+        };
+
+        warn!("Authentication for {} from {} failed: {}", path, self.client_label(request.client_ip), reason);
+        self.write_event(&event).await;
+    }
+
+    // Every /admin request; denied is the reason it was refused
+    pub async fn log_admin_action(&self, client_ip: Option<IpAddr>, method: &Method, path: &str, token: Option<&str>, denied: Option<&str>) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: None,
+            event_type: AuditEventType::AdminAction,
+            client_ip,
+            client_hash: None,
+            country: None,
+            asn: None,
+            client_identity: token.map(|name| format!("token:{}", name)),
+            method: Some(method.to_string()),
+            path: path.to_string(),
+            user_agent: None,
+            status: if denied.is_some() { AuditStatus::Failed } else { AuditStatus::Success },
+            message: Some(match denied {
+                Some(reason) => format!("Admin request denied: {}", reason),
+                None => "Admin request allowed".to_string(),
+            }),
+            duration_ms: None,
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: None,
+        };
+
+        if let Some(reason) = denied {
+            warn!("Admin request {} {} from {} denied: {}", method, path, self.client_label(client_ip), reason);
+        }
+        self.write_event(&event).await;
+    }
+
+    // From /admin/reload; error is why the new state was rejected
+    pub async fn log_subsystem_reload(&self, subsystem: &str, error: Option<&str>) {
+        let event = AuditEvent {
+            timestamp: Utc::now(),
+            request_id: None,
+            event_type: AuditEventType::SubsystemReload,
+            client_ip: None,
+            client_hash: None,
+            country: None,
+            asn: None,
+            client_identity: None,
+            method: None,
+            path: format!("/admin/reload/{}", subsystem),
+            user_agent: None,
+            status: if error.is_some() { AuditStatus::Failed } else { AuditStatus::Success },
+            message: Some(match error {
+                Some(error) => format!("Reloading {} failed: {}", subsystem, error),
+                None => format!("Reloaded {}", subsystem),
+            }),
+            duration_ms: None,
+            upstream_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            upstream_bytes: None,
+            package: None,
+        };
+
+        if let Some(error) = error {
+            warn!("Reloading {} failed: {}", subsystem, error);
+        }
+        self.write_event(&event).await;
+    }
+
     async fn write_event(&self, event: &AuditEvent) {
-        // In a real implementation, this would write to a file, database, or logging service
-        // For now, we'll serialize to JSON and log it
-        if let Ok(json) = serde_json::to_string(event) {
-            info!("Audit: {}", json);
+        // Counted even when filtered out, so alerts do not depend on sink settings
+        Metrics::global()
+            .audit_events
+            .with_label_values(&[event.event_type.as_str(), event.status.as_str()])
+            .inc();
+        if !self.filter.records(event) {
+            return;
         }
+        self.bus.publish(&self.anonymizer.apply(event.clone()));
     }
-    
-    pub async fn get_recent_events(&self, _limit: usize) -> Vec<AuditEvent> {
-        // In a real implementation, this would query the audit storage
-        // For now, return empty vector
-        vec![]
+
+    // Resolves once every sink has handled the events logged before the call
+    pub async fn flush(&self) {
+        self.bus.flush().await;
+    }
+
+    fn store(&self) -> Result<&AuditStore> {
+        self.store.as_deref().ok_or_else(|| anyhow!("Audit store is not enabled"))
+    }
+
+    // Time bounds select [from, to); a missing bound is open
+    pub async fn query_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        let store = self.store()?;
+        self.flush().await;
+        store.query(query).await
     }
-    
-    pub async fn export_events(&self, _start_time: DateTime<Utc>, _end_time: DateTime<Utc>) -> Vec<AuditEvent> {
-        // In a real implementation, this would export events within time range
-        // For now, return empty vector
-        vec![]
+
+    // Same selection as query_events, delivered incrementally
+    pub async fn stream_events(&self, query: &AuditQuery) -> Result<tokio::sync::mpsc::Receiver<Result<AuditEvent>>> {
+        let store = self.store()?;
+        self.flush().await;
+        Ok(store.stream(query).await)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[tokio::test]
     async fn test_audit_logger_creation() {
         let logger = AuditLogger::new();
         // Test that it doesn't panic
-        logger.log_request(&Method::GET, "/test", &HeaderMap::new()).await;
+        logger.log_request(&RequestContext::default(), &Method::GET, "/test", &HeaderMap::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_events_written_to_file_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let config = AuditConfig {
+            file: FileSinkConfig {
+                enabled: true,
+                path: path.to_str().unwrap().to_string(),
+                ..FileSinkConfig::default()
+            },
+            ..Default::default()
+        };
+        let logger = AuditLogger::from_config(&config);
+        let request = RequestContext::new(Some("192.0.2.7".parse().unwrap()));
+        logger.log_cache_hit(&request, "/debian/dists/bookworm/InRelease").await;
+        logger.log_policy_violation(&request, "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb", "denied").await;
+        logger.flush().await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<AuditEvent> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1].event_type, AuditEventType::PolicyViolation));
+        assert!(events.iter().all(|e| e.client_ip == request.client_ip));
+        assert!(events.iter().all(|e| e.request_id.as_ref() == Some(&request.id)));
+        assert!(events.iter().all(|e| e.duration_ms.is_some()));
+        assert!(events.iter().all(|e| e.upstream_ms.is_none()));
+
+        logger.log_request(&request, &Method::GET, "/debian/dists/bookworm/Release", &HeaderMap::new()).await;
+        logger.log_fetch_success(&request, "/debian/dists/bookworm/Release", Duration::from_millis(5), 1024).await;
+        logger.flush().await;
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<AuditEvent> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(events[2].request_bytes, Some(0));
+        assert_eq!((events[3].upstream_bytes, events[3].response_bytes), (Some(1024), Some(1024)));
+    }
+
+    #[tokio::test]
+    async fn test_query_methods_use_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AuditConfig {
+            store: StoreConfig {
+                enabled: true,
+                path: dir.path().join("audit.db").to_str().unwrap().to_string(),
+                retention_days: 0,
+            },
+            ..Default::default()
+        };
+        let logger = AuditLogger::from_config(&config);
+        let start = Utc::now();
+        logger.log_cache_hit(&RequestContext::default(), "/debian/dists/bookworm/InRelease").await;
+        logger.log_policy_violation(&RequestContext::default(), "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb", "denied").await;
+
+        let recent = logger.query_events(&AuditQuery { limit: Some(10), newest_first: true, ..Default::default() }).await.unwrap();
+        assert_eq!(recent.len(), 2);
+        assert!(matches!(recent[0].event_type, AuditEventType::PolicyViolation));
+        let cache_hits = AuditQuery {
+            from: Some(start),
+            event_type: Some(AuditEventType::CacheHit),
+            ..Default::default()
+        };
+        assert_eq!(logger.query_events(&cache_hits).await.unwrap().len(), 1);
+        assert!(AuditLogger::new().query_events(&AuditQuery::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filter_drops_excluded_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AuditConfig {
+            store: StoreConfig {
+                enabled: true,
+                path: dir.path().join("audit.db").to_str().unwrap().to_string(),
+                retention_days: 0,
+            },
+            filter: AuditFilter {
+                exclude_types: vec![AuditEventType::Request],
+                exclude_statuses: vec![AuditStatus::Info, AuditStatus::Warning],
+                always_types: vec![AuditEventType::PolicyViolation],
+            },
+            ..Default::default()
+        };
+        let logger = AuditLogger::from_config(&config);
+        let request = RequestContext::default();
+        logger.log_request(&request, &Method::GET, "/debian/dists/bookworm/InRelease", &HeaderMap::new()).await;
+        logger.log_cache_hit(&request, "/debian/dists/bookworm/InRelease").await;
+        logger.log_policy_violation(&request, "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb", "denied").await;
+        logger.log_fetch_success(&request, "/debian/dists/bookworm/Release", Duration::from_millis(5), 1024).await;
+
+        let recorded = logger.query_events(&AuditQuery::default()).await.unwrap();
+        let types: Vec<_> = recorded.iter().map(|e| e.event_type.clone()).collect();
+        assert_eq!(types, vec![AuditEventType::PolicyViolation, AuditEventType::FetchSuccess]);
+    }
+
+    #[test]
+    fn test_set_location_skips_unresolved() {
+        let mut request = RequestContext::default();
+        request.set_location(&LocationInfo::new("192.0.2.7", "Unknown", "Unknown"));
+        assert_eq!(request.country, None);
+
+        let mut location = LocationInfo::new("192.0.2.7", "DE", "Germany");
+        location.asn = Some(3320);
+        request.set_location(&location);
+        assert_eq!((request.country.as_deref(), request.asn), (Some("DE"), Some(3320)));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_events_are_still_counted() {
+        let config = AuditConfig {
+            filter: AuditFilter { exclude_types: vec![AuditEventType::GeoIPError], ..Default::default() },
+            ..Default::default()
+        };
+        let logger = AuditLogger::from_config(&config);
+        let counter = Metrics::global().audit_events.with_label_values(&["geoip_error", "error"]);
+        let before = counter.get();
+        logger.log_geoip_error(&RequestContext::default(), "/debian/dists/bookworm/InRelease", &anyhow!("lookup failed")).await;
+        assert_eq!(counter.get(), before + 1);
     }
 }
@@ -1 +1,7 @@
+pub mod anonymize;
+pub mod bus;
+pub mod file;
 pub mod log;
+pub mod siem;
+pub mod store;
+pub mod stream;
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use crate::audit::log::{AuditEvent, AuditEventType};
+
+const VENDOR: &str = "aptg";
+const PRODUCT: &str = "aptg";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventFormat {
+    // The AuditEvent JSON, one event per line
+    #[default]
+    Json,
+    // ArcSight Common Event Format
+    Cef,
+    // QRadar Log Event Extended Format 1.0
+    Leef,
+}
+
+impl EventFormat {
+    // CEF and LEEF feeds are for SIEMs and only carry security events
+    pub fn includes(&self, event: &AuditEvent) -> bool {
+        *self == Self::Json || is_security_event(&event.event_type)
+    }
+
+    pub fn format(&self, event: &AuditEvent) -> Result<String> {
+        Ok(match self {
+            Self::Json => serde_json::to_string(event)?,
+            Self::Cef => cef(event),
+            Self::Leef => leef(event),
+        })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/x-ndjson",
+            Self::Cef | Self::Leef => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+pub fn is_security_event(event_type: &AuditEventType) -> bool {
+    matches!(
+        event_type,
+        AuditEventType::PolicyViolation
+            | AuditEventType::VerificationFailed
+            | AuditEventType::VerificationWarning
+            | AuditEventType::UnexpectedSigner
+            | AuditEventType::UnverifiedContentDenied
+            | AuditEventType::GeoIPDenied
+            | AuditEventType::GeoIPRateLimit
+            | AuditEventType::CertificateExpiring
+            | AuditEventType::TlsHandshakeFailed
+            | AuditEventType::AdminAction
+            | AuditEventType::AuthenticationFailed
+            | AuditEventType::VulnerablePackage
+            | AuditEventType::SubsystemReload
+    )
+}
+
+// 0-10 as used by CEF; LEEF takes the same scale
+fn severity(event_type: &AuditEventType) -> u8 {
+    match event_type {
+        AuditEventType::VerificationFailed | AuditEventType::UnexpectedSigner => 8,
+        AuditEventType::PolicyViolation | AuditEventType::UnverifiedContentDenied | AuditEventType::GeoIPDenied => 6,
+        AuditEventType::VulnerablePackage => 6,
+        AuditEventType::AuthenticationFailed => 5,
+        AuditEventType::VerificationWarning | AuditEventType::GeoIPRateLimit | AuditEventType::CertificateExpiring => 5,
+        AuditEventType::FetchError | AuditEventType::TlsHandshakeFailed | AuditEventType::GeoIPError => 4,
+        _ => 2,
+    }
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+// LEEF 1.0 has no escaping; tabs separate attributes and newlines separate events
+fn leef_value(value: &str) -> String {
+    value.replace(['\t', '\r', '\n'], " ")
+}
+
+fn cef(event: &AuditEvent) -> String {
+    let event_id = event.event_type.as_str();
+    let mut extension = vec![
+        format!("rt={}", event.timestamp.timestamp_millis()),
+        format!("request={}", cef_value(&event.path)),
+        format!("outcome={}", event.status.as_str()),
+    ];
+    // CEF's src only takes IPv4; IPv6 clients go in the c6a2 custom field
+    match event.client_ip {
+        Some(ip @ IpAddr::V4(_)) => extension.push(format!("src={}", ip)),
+        Some(ip @ IpAddr::V6(_)) => extension.push(format!("c6a2Label=sourceAddress c6a2={}", ip)),
+        None => {}
+    }
+    if let Some(hash) = &event.client_hash {
+        extension.push(format!("cs1Label=clientHash cs1={}", hash));
+    }
+    if let Some(request_id) = &event.request_id {
+        extension.push(format!("externalId={}", request_id));
+    }
+    if let Some(method) = &event.method {
+        extension.push(format!("requestMethod={}", cef_value(method)));
+    }
+    if let Some(user_agent) = &event.user_agent {
+        extension.push(format!("requestClientApplication={}", cef_value(user_agent)));
+    }
+    if let Some(package) = &event.package {
+        extension.push(format!(
+            "cs2Label=package cs2={} cs3Label=packageVersion cs3={} cs4Label=packageArchitecture cs4={}",
+            cef_value(&package.package),
+            cef_value(&package.version),
+            cef_value(&package.architecture),
+        ));
+    }
+    if let Some(message) = &event.message {
+        extension.push(format!("msg={}", cef_value(message)));
+    }
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        event_id,
+        cef_header(&format!("{:?}", event.event_type)),
+        severity(&event.event_type),
+        extension.join(" "),
+    )
+}
+
+fn leef(event: &AuditEvent) -> String {
+    let mut attributes = vec![
+        format!("devTime={}", event.timestamp.timestamp_millis()),
+        "devTimeFormat=epoch".to_string(),
+        format!("sev={}", severity(&event.event_type)),
+        format!("url={}", leef_value(&event.path)),
+        format!("outcome={}", event.status.as_str()),
+    ];
+    if let Some(ip) = event.client_ip {
+        attributes.push(format!("src={}", ip));
+    }
+    if let Some(hash) = &event.client_hash {
+        attributes.push(format!("clientHash={}", hash));
+    }
+    if let Some(request_id) = &event.request_id {
+        attributes.push(format!("requestId={}", request_id));
+    }
+    if let Some(method) = &event.method {
+        attributes.push(format!("method={}", leef_value(method)));
+    }
+    if let Some(user_agent) = &event.user_agent {
+        attributes.push(format!("userAgent={}", leef_value(user_agent)));
+    }
+    if let Some(package) = &event.package {
+        attributes.push(format!("package={}", leef_value(&package.package)));
+        attributes.push(format!("packageVersion={}", leef_value(&package.version)));
+        attributes.push(format!("packageArchitecture={}", leef_value(&package.architecture)));
+    }
+    if let Some(message) = &event.message {
+        attributes.push(format!("msg={}", leef_value(message)));
+    }
+    format!(
+        "LEEF:1.0|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        event.event_type.as_str(),
+        attributes.join("\t"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::log::{AuditStatus, PackageFields};
+    use chrono::TimeZone;
+
+    fn violation() -> AuditEvent {
+        AuditEvent {
+            timestamp: chrono::Utc.timestamp_millis_opt(1_767_225_600_000).unwrap(),
+            client_ip: Some("192.0.2.7".parse().unwrap()),
+            method: Some("GET".to_string()),
+            path: "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb".to_string(),
+            status: AuditStatus::Warning,
+            message: Some("Denied by rule a=b|c".to_string()),
+            package: Some(PackageFields {
+                package: "sl".to_string(),
+                version: "5.02-1".to_string(),
+                architecture: "amd64".to_string(),
+            }),
+            ..AuditEvent::for_test(AuditEventType::PolicyViolation)
+        }
+    }
+
+    #[test]
+    fn test_cef_escaping() {
+        let line = EventFormat::Cef.format(&violation()).unwrap();
+        assert!(line.starts_with(&format!("CEF:0|aptg|aptg|{}|policy_violation|PolicyViolation|6|", env!("CARGO_PKG_VERSION"))));
+        assert!(line.contains("rt=1767225600000 "));
+        assert!(line.contains("src=192.0.2.7"));
+        assert!(line.ends_with("msg=Denied by rule a\\=b|c"));
+
+        let ipv6 = AuditEvent { client_ip: Some("2001:db8::7".parse().unwrap()), ..violation() };
+        let line = EventFormat::Cef.format(&ipv6).unwrap();
+        assert!(line.contains("c6a2Label=sourceAddress c6a2=2001:db8::7") && !line.contains("src="));
+    }
+
+    #[test]
+    fn test_leef_attributes() {
+        let line = EventFormat::Leef.format(&violation()).unwrap();
+        let (header, attributes) = line.rsplit_once("policy_violation|").unwrap();
+        assert_eq!(header, format!("LEEF:1.0|aptg|aptg|{}|", env!("CARGO_PKG_VERSION")));
+        let attributes: Vec<&str> = attributes.split('\t').collect();
+        assert!(attributes.contains(&"sev=6"));
+        assert!(attributes.contains(&"src=192.0.2.7"));
+        assert!(attributes.contains(&"url=/debian/pool/main/s/sl/sl_5.02-1_amd64.deb"));
+        assert!(attributes.contains(&"packageVersion=5.02-1"));
+    }
+
+    #[test]
+    fn test_only_security_events_in_siem_formats() {
+        let cache_hit = AuditEvent { event_type: AuditEventType::CacheHit, ..violation() };
+        assert!(EventFormat::Json.includes(&cache_hit));
+        assert!(!EventFormat::Cef.includes(&cache_hit));
+        assert!(EventFormat::Leef.includes(&violation()));
+    }
+}
@@ -0,0 +1,377 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, error};
+use crate::audit::bus::AuditSink;
+use crate::audit::log::{AuditEvent, AuditEventType, AuditStatus};
+
+// Events queued by the writer task are committed in batches of up to this size
+const MAX_BATCH: usize = 256;
+// Rows buffered ahead of a slow export consumer
+const STREAM_BUFFER: usize = 256;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub enabled: bool,
+    pub path: String,
+    // Events older than this are deleted; 0 keeps everything
+    pub retention_days: u32,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/var/lib/aptg/audit.db".to_string(),
+            retention_days: 90,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub event_type: Option<AuditEventType>,
+    pub request_id: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    pub path_prefix: Option<String>,
+    pub status: Option<AuditStatus>,
+    pub limit: Option<usize>,
+    // Rows skipped before the first returned event, for paging
+    pub offset: usize,
+    pub newest_first: bool,
+}
+
+enum WriterMessage {
+    Event(Box<AuditEvent>),
+    Flush(oneshot::Sender<()>),
+}
+
+// Events are stored as JSON next to the indexed columns, so new event fields
+// need no schema migration
+pub struct AuditStore {
+    path: PathBuf,
+    sender: mpsc::UnboundedSender<WriterMessage>,
+    reader: Arc<Mutex<Connection>>,
+}
+
+impl AuditStore {
+    pub fn open(config: &StoreConfig) -> Result<Self> {
+        let path = Path::new(&config.path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create audit store directory {}: {}", parent.display(), e))?;
+        }
+        
+        let writer = Connection::open(path)?;
+        writer.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS audit_events (
+                 id INTEGER PRIMARY KEY,
+                 timestamp_us INTEGER NOT NULL,
+                 event_type TEXT NOT NULL,
+                 event TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS audit_events_timestamp ON audit_events (timestamp_us);
+             CREATE INDEX IF NOT EXISTS audit_events_type ON audit_events (event_type, timestamp_us);",
+        )?;
+        let reader = Connection::open(path)?;
+        
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let retention_days = config.retention_days;
+        tokio::task::spawn_blocking(move || run_writer(writer, receiver, retention_days));
+        info!("Storing audit events in {}", path.display());
+        
+        Ok(Self {
+            path: path.to_path_buf(),
+            sender,
+            reader: Arc::new(Mutex::new(reader)),
+        })
+    }
+
+    // Resolves once every event recorded before the call is committed
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        self.flush().await;
+        let (sql, params) = build_query(query);
+        
+        let reader = self.reader.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = reader.lock().unwrap_or_else(|e| e.into_inner());
+            let mut statement = conn.prepare(&sql)?;
+            let rows = statement.query_map(params_from_iter(params), |row| row.get::<_, String>(0))?;
+            let mut events = Vec::new();
+            for row in rows {
+                events.push(serde_json::from_str(&row?)?);
+            }
+            Ok(events)
+        })
+        .await?
+    }
+
+    // Like query(), but yields events one at a time from a dedicated connection
+    // so large exports neither buffer everything nor hold up other queries
+    pub async fn stream(&self, query: &AuditQuery) -> mpsc::Receiver<Result<AuditEvent>> {
+        self.flush().await;
+        let (sql, params) = build_query(query);
+        let path = self.path.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = stream_rows(&path, &sql, params, &sender) {
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+        receiver
+    }
+}
+
+impl AuditSink for AuditStore {
+    fn name(&self) -> &str {
+        "store"
+    }
+
+    // Queued for the writer task, which commits in batches
+    fn record(&self, event: &AuditEvent) -> Result<()> {
+        self.sender
+            .send(WriterMessage::Event(Box::new(event.clone())))
+            .map_err(|_| anyhow!("Audit store writer has stopped"))
+    }
+}
+
+fn stream_rows(path: &Path, sql: &str, params: Vec<Value>, sender: &mpsc::Sender<Result<AuditEvent>>) -> Result<()> {
+    let conn = Connection::open(path)?;
+    let mut statement = conn.prepare(sql)?;
+    let mut rows = statement.query(params_from_iter(params))?;
+    while let Some(row) = rows.next()? {
+        let event = serde_json::from_str(&row.get::<_, String>(0)?)?;
+        // The consumer went away
+        if sender.blocking_send(Ok(event)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn build_query(query: &AuditQuery) -> (String, Vec<Value>) {
+    let mut sql = "SELECT event FROM audit_events WHERE 1 = 1".to_string();
+    let mut params: Vec<Value> = Vec::new();
+    if let Some(from) = query.from {
+        sql.push_str(" AND timestamp_us >= ?");
+        params.push(Value::Integer(from.timestamp_micros()));
+    }
+    if let Some(to) = query.to {
+        sql.push_str(" AND timestamp_us < ?");
+        params.push(Value::Integer(to.timestamp_micros()));
+    }
+    if let Some(event_type) = &query.event_type {
+        sql.push_str(" AND event_type = ?");
+        params.push(Value::Text(variant_name(event_type)));
+    }
+    // The remaining fields only live in the event JSON
+    if let Some(request_id) = &query.request_id {
+        sql.push_str(" AND json_extract(event, '$.request_id') = ?");
+        params.push(Value::Text(request_id.clone()));
+    }
+    if let Some(client_ip) = query.client_ip {
+        sql.push_str(" AND json_extract(event, '$.client_ip') = ?");
+        params.push(Value::Text(client_ip.to_string()));
+    }
+    if let Some(prefix) = &query.path_prefix {
+        sql.push_str(" AND instr(json_extract(event, '$.path'), ?) = 1");
+        params.push(Value::Text(prefix.clone()));
+    }
+    if let Some(status) = &query.status {
+        sql.push_str(" AND json_extract(event, '$.status') = ?");
+        params.push(Value::Text(variant_name(status)));
+    }
+    sql.push_str(if query.newest_first { " ORDER BY timestamp_us DESC, id DESC" } else { " ORDER BY timestamp_us, id" });
+    // SQLite only accepts OFFSET after a LIMIT; -1 means unlimited
+    match query.limit {
+        Some(limit) => sql.push_str(&format!(" LIMIT {}", limit)),
+        None if query.offset > 0 => sql.push_str(" LIMIT -1"),
+        None => {}
+    }
+    if query.offset > 0 {
+        sql.push_str(&format!(" OFFSET {}", query.offset));
+    }
+    (sql, params)
+}
+
+fn variant_name<T: Serialize>(variant: &T) -> String {
+    serde_json::to_value(variant)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn run_writer(mut conn: Connection, mut receiver: mpsc::UnboundedReceiver<WriterMessage>, retention_days: u32) {
+    let mut last_prune: Option<Instant> = None;
+
+    while let Some(message) = receiver.blocking_recv() {
+        let mut batch = Vec::new();
+        let mut flushes = Vec::new();
+        let mut next = Some(message);
+        while let Some(message) = next.take() {
+            match message {
+                WriterMessage::Event(event) => batch.push(*event),
+                WriterMessage::Flush(done) => flushes.push(done),
+            }
+            if batch.len() < MAX_BATCH {
+                next = receiver.try_recv().ok();
+            }
+        }
+        
+        if let Err(e) = insert_batch(&mut conn, &batch) {
+            error!("Failed to store {} audit events: {}", batch.len(), e);
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+        
+        if retention_days > 0 && last_prune.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL) {
+            let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+            match conn.execute("DELETE FROM audit_events WHERE timestamp_us < ?1", [cutoff.timestamp_micros()]) {
+                Ok(removed) if removed > 0 => info!("Pruned {} audit events older than {} days", removed, retention_days),
+                Ok(_) => {}
+                Err(e) => error!("Failed to prune audit events: {}", e),
+            }
+            last_prune = Some(Instant::now());
+        }
+    }
+}
+
+fn insert_batch(conn: &mut Connection, events: &[AuditEvent]) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let transaction = conn.transaction()?;
+    {
+        let mut statement = transaction.prepare_cached(
+            "INSERT INTO audit_events (timestamp_us, event_type, event) VALUES (?1, ?2, ?3)",
+        )?;
+        for event in events {
+            statement.execute((
+                event.timestamp.timestamp_micros(),
+                variant_name(&event.event_type),
+                serde_json::to_string(event)?,
+            ))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: AuditEventType, path: &str, timestamp: DateTime<Utc>) -> AuditEvent {
+        AuditEvent { timestamp, path: path.to_string(), ..AuditEvent::for_test(event_type) }
+    }
+
+    fn open_store(dir: &Path) -> AuditStore {
+        AuditStore::open(&StoreConfig {
+            enabled: true,
+            path: dir.join("audit.db").to_str().unwrap().to_string(),
+            retention_days: 0,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path());
+        let now = Utc::now();
+        store.record(&event(AuditEventType::Request, "/a", now - chrono::Duration::hours(2))).unwrap();
+        store.record(&event(AuditEventType::PolicyViolation, "/b", now - chrono::Duration::hours(1))).unwrap();
+        store.record(&event(AuditEventType::Request, "/c", now)).unwrap();
+        
+        let requests = store.query(&AuditQuery { event_type: Some(AuditEventType::Request), ..Default::default() }).await.unwrap();
+        assert_eq!(requests.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), vec!["/a", "/c"]);
+        
+        let window = AuditQuery {
+            from: Some(now - chrono::Duration::minutes(90)),
+            to: Some(now),
+            ..Default::default()
+        };
+        let in_window = store.query(&window).await.unwrap();
+        assert_eq!(in_window.len(), 1);
+        assert_eq!(in_window[0].path, "/b");
+    }
+
+    #[tokio::test]
+    async fn test_newest_first_with_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path());
+        let now = Utc::now();
+        for (i, path) in ["/1", "/2", "/3"].iter().enumerate() {
+            store.record(&event(AuditEventType::CacheHit, path, now + chrono::Duration::seconds(i as i64))).unwrap();
+        }
+        
+        let recent = store.query(&AuditQuery { limit: Some(2), newest_first: true, ..Default::default() }).await.unwrap();
+        assert_eq!(recent.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), vec!["/3", "/2"]);
+        
+        let next_page = store.query(&AuditQuery { limit: Some(2), offset: 2, newest_first: true, ..Default::default() }).await.unwrap();
+        assert_eq!(next_page.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), vec!["/1"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_event_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path());
+        let now = Utc::now();
+        let client: IpAddr = "192.0.2.7".parse().unwrap();
+        store.record(&AuditEvent { client_ip: Some(client), ..event(AuditEventType::FetchSuccess, "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb", now) }).unwrap();
+        store.record(&AuditEvent { client_ip: Some(client), ..event(AuditEventType::FetchSuccess, "/debian/dists/bookworm/InRelease", now) }).unwrap();
+        store.record(&event(AuditEventType::FetchSuccess, "/debian/pool/main/s/sl/sl_5.02-1_arm64.deb", now)).unwrap();
+        store.record(&AuditEvent { status: AuditStatus::Error, ..event(AuditEventType::FetchError, "/debian/pool/main/s/sl/sl_5.02-1_i386.deb", now) }).unwrap();
+        
+        let downloads = AuditQuery {
+            client_ip: Some(client),
+            path_prefix: Some("/debian/pool/main/s/sl/".to_string()),
+            ..Default::default()
+        };
+        let found = store.query(&downloads).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].path.ends_with("amd64.deb"));
+        
+        let errors = store.query(&AuditQuery { status: Some(AuditStatus::Error), ..Default::default() }).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].path.ends_with("i386.deb"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_events_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path());
+        let now = Utc::now();
+        for (i, path) in ["/1", "/2", "/3"].iter().enumerate() {
+            store.record(&event(AuditEventType::Request, path, now + chrono::Duration::seconds(i as i64))).unwrap();
+        }
+        
+        let mut events = store.stream(&AuditQuery::default()).await;
+        let mut paths = Vec::new();
+        while let Some(event) = events.recv().await {
+            paths.push(event.unwrap().path);
+        }
+        assert_eq!(paths, vec!["/1", "/2", "/3"]);
+    }
+}
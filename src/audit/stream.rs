@@ -0,0 +1,297 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{info, warn, error};
+use crate::audit::bus::AuditSink;
+use crate::audit::log::{AuditEvent, AuditEventType};
+use crate::metrics::registry::Metrics;
+
+// Events sent to a sink in one request or produce loop
+const MAX_BATCH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    // POST a JSON array of events to url
+    #[default]
+    Webhook,
+    // One JSON message per event on topic (requires the "kafka" feature)
+    Kafka,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamSinkConfig {
+    pub name: String,
+    pub kind: SinkKind,
+    pub url: String,
+    pub bearer_token: Option<String>,
+    // Comma-separated host:port list
+    pub brokers: String,
+    pub topic: String,
+    // Event type names such as "VerificationFailed"; empty forwards every event
+    pub event_types: Vec<AuditEventType>,
+    // Events waiting for delivery; further events are dropped while it is full
+    pub queue_size: usize,
+    pub max_retries: u32,
+    // Doubled after every failed attempt
+    pub retry_backoff_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for StreamSinkConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: SinkKind::Webhook,
+            url: String::new(),
+            bearer_token: None,
+            brokers: String::new(),
+            topic: String::new(),
+            event_types: vec![],
+            queue_size: 1000,
+            max_retries: 3,
+            retry_backoff_ms: 500,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+enum Transport {
+    Webhook {
+        client: reqwest::Client,
+        url: String,
+        bearer_token: Option<String>,
+    },
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+        timeout: Duration,
+    },
+}
+
+impl Transport {
+    fn new(config: &StreamSinkConfig) -> Result<Self> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        match config.kind {
+            SinkKind::Webhook => {
+                if config.url.is_empty() {
+                    return Err(anyhow!("Webhook sink '{}' has no url", config.name));
+                }
+                Ok(Self::Webhook {
+                    client: reqwest::Client::builder().timeout(timeout).build()?,
+                    url: config.url.clone(),
+                    bearer_token: config.bearer_token.clone(),
+                })
+            }
+            #[cfg(feature = "kafka")]
+            SinkKind::Kafka => {
+                let producer = rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", &config.brokers)
+                    .set("message.timeout.ms", config.timeout_ms.to_string())
+                    .create()?;
+                Ok(Self::Kafka { producer, topic: config.topic.clone(), timeout })
+            }
+            #[cfg(not(feature = "kafka"))]
+            SinkKind::Kafka => Err(anyhow!("Kafka sink '{}' requires aptg built with the kafka feature", config.name)),
+        }
+    }
+
+    async fn deliver(&self, events: &[AuditEvent]) -> Result<()> {
+        match self {
+            Self::Webhook { client, url, bearer_token } => {
+                let mut request = client.post(url).json(events);
+                if let Some(token) = bearer_token {
+                    request = request.bearer_auth(token);
+                }
+                request.send().await?.error_for_status()?;
+                Ok(())
+            }
+            #[cfg(feature = "kafka")]
+            Self::Kafka { producer, topic, timeout } => {
+                for event in events {
+                    let payload = serde_json::to_string(event)?;
+                    let record = rdkafka::producer::FutureRecord::<(), _>::to(topic).payload(&payload);
+                    producer
+                        .send(record, *timeout)
+                        .await
+                        .map_err(|(e, _)| anyhow!("Kafka delivery to {} failed: {}", topic, e))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct SinkHandle {
+    name: String,
+    event_types: Vec<AuditEventType>,
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+impl SinkHandle {
+    fn wants(&self, event: &AuditEvent) -> bool {
+        self.event_types.is_empty() || self.event_types.contains(&event.event_type)
+    }
+}
+
+// Fans audit events out to webhook and Kafka sinks. Each sink has its own
+// bounded queue and delivery task, so a slow sink never delays requests
+pub struct AuditStreamer {
+    sinks: Vec<SinkHandle>,
+}
+
+impl AuditStreamer {
+    pub fn from_config(configs: &[StreamSinkConfig]) -> Self {
+        let mut sinks = Vec::new();
+        for config in configs {
+            let transport = match Transport::new(config) {
+                Ok(transport) => transport,
+                Err(e) => {
+                    error!("Audit sink '{}' disabled: {}", config.name, e);
+                    continue;
+                }
+            };
+            let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+            tokio::spawn(run_sink(config.clone(), transport, receiver));
+            info!("Streaming audit events to sink '{}'", config.name);
+            
+            sinks.push(SinkHandle {
+                name: config.name.clone(),
+                event_types: config.event_types.clone(),
+                sender,
+            });
+        }
+        
+        Self { sinks }
+    }
+
+    pub fn publish(&self, event: &AuditEvent) {
+        for sink in self.sinks.iter().filter(|sink| sink.wants(event)) {
+            match sink.sender.try_send(event.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    Metrics::global().audit_events_dropped.with_label_values(&[&sink.name]).inc();
+                }
+                Err(TrySendError::Closed(_)) => error!("Audit sink '{}' has stopped", sink.name),
+            }
+        }
+    }
+}
+
+// Each streaming sink keeps its own queue and retries, so recording never fails
+impl AuditSink for AuditStreamer {
+    fn name(&self) -> &str {
+        "stream"
+    }
+
+    fn record(&self, event: &AuditEvent) -> Result<()> {
+        self.publish(event);
+        Ok(())
+    }
+}
+
+async fn run_sink(config: StreamSinkConfig, transport: Transport, mut receiver: mpsc::Receiver<AuditEvent>) {
+    while let Some(event) = receiver.recv().await {
+        let mut batch = vec![event];
+        while batch.len() < MAX_BATCH {
+            match receiver.try_recv() {
+                Ok(event) => batch.push(event),
+                Err(_) => break,
+            }
+        }
+        
+        let mut backoff = Duration::from_millis(config.retry_backoff_ms);
+        for attempt in 0..=config.max_retries {
+            match transport.deliver(&batch).await {
+                Ok(()) => break,
+                Err(e) if attempt < config.max_retries => {
+                    warn!("Audit sink '{}' delivery failed (attempt {}): {}", config.name, attempt + 1, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    error!("Audit sink '{}' dropped {} events after {} attempts: {}", config.name, batch.len(), attempt + 1, e);
+                    Metrics::global().audit_events_dropped.with_label_values(&[&config.name]).inc_by(batch.len() as u64);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    // Webhook receiver that fails the first `failures` requests with a 500
+    fn spawn_receiver(failures: usize) -> (String, Arc<Mutex<Vec<AuditEvent>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let store = received.clone();
+        let route = warp::post().and(warp::body::json()).map(move |events: Vec<AuditEvent>| {
+            if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                return warp::http::StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            store.lock().unwrap().extend(events);
+            warp::http::StatusCode::OK
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/audit", addr), received)
+    }
+
+    async fn wait_for(received: &Mutex<Vec<AuditEvent>>, count: usize) {
+        for _ in 0..100 {
+            if received.lock().unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_sink_filters_event_types() {
+        let (url, received) = spawn_receiver(0);
+        let streamer = AuditStreamer::from_config(&[StreamSinkConfig {
+            name: "siem".to_string(),
+            url,
+            event_types: vec![AuditEventType::VerificationFailed],
+            ..Default::default()
+        }]);
+        
+        streamer.publish(&AuditEvent::for_test(AuditEventType::Request));
+        streamer.publish(&AuditEvent::for_test(AuditEventType::VerificationFailed));
+        wait_for(&received, 1).await;
+        
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(received[0].event_type, AuditEventType::VerificationFailed));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_sink_retries() {
+        let (url, received) = spawn_receiver(2);
+        let streamer = AuditStreamer::from_config(&[StreamSinkConfig {
+            name: "flaky".to_string(),
+            url,
+            retry_backoff_ms: 1,
+            ..Default::default()
+        }]);
+        
+        streamer.publish(&AuditEvent::for_test(AuditEventType::GeoIPDenied));
+        wait_for(&received, 1).await;
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_sinks_are_skipped() {
+        let streamer = AuditStreamer::from_config(&[StreamSinkConfig { name: "no-url".to_string(), ..Default::default() }]);
+        assert!(streamer.sinks.is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod report;
+pub mod runner;
+pub mod workload;
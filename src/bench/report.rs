@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use crate::mirror::latency::PathClass;
+
+// One request as the client saw it; status is None when no response came back
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub class: PathClass,
+    pub status: Option<u16>,
+    // Until the last body byte
+    pub elapsed: Duration,
+    pub bytes: u64,
+}
+
+// Milliseconds, nearest-rank percentiles
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    pub fn from_samples<'a>(samples: impl Iterator<Item = &'a Sample>) -> Self {
+        let mut millis: Vec<f64> = samples.map(|sample| sample.elapsed.as_secs_f64() * 1000.0).collect();
+        if millis.is_empty() {
+            return Self::default();
+        }
+        millis.sort_by(f64::total_cmp);
+        let percentile = |p: f64| millis[((p * millis.len() as f64).ceil() as usize).clamp(1, millis.len()) - 1];
+        Self {
+            count: millis.len(),
+            mean_ms: millis.iter().sum::<f64>() / millis.len() as f64,
+            p50_ms: percentile(0.50),
+            p90_ms: percentile(0.90),
+            p99_ms: percentile(0.99),
+            max_ms: millis[millis.len() - 1],
+        }
+    }
+}
+
+// aptg's own count of cache hits and upstream fetches, from /metrics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub fetches: u64,
+}
+
+impl CacheCounters {
+    pub fn parse(metrics: &str) -> Self {
+        let mut counters = Self::default();
+        for line in metrics.lines().filter(|line| line.starts_with("aptg_audit_events_total{")) {
+            let Some((labels, value)) = line.split_once('}') else {
+                continue;
+            };
+            let value = value.trim().parse::<f64>().unwrap_or(0.0) as u64;
+            if labels.contains("type=\"cache_hit\"") {
+                counters.hits += value;
+            } else if labels.contains("type=\"fetch_success\"") {
+                counters.fetches += value;
+            }
+        }
+        counters
+    }
+
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self { hits: self.hits.saturating_sub(earlier.hits), fetches: self.fetches.saturating_sub(earlier.fetches) }
+    }
+
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let total = self.hits + self.fetches;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub requests: usize,
+    pub seconds: f64,
+    pub requests_per_second: f64,
+    pub bytes: u64,
+    pub statuses: BTreeMap<u16, u64>,
+    // Requests that got no response at all
+    pub errors: u64,
+    pub index: LatencySummary,
+    pub pool: LatencySummary,
+    pub all: LatencySummary,
+    // None when /metrics could not be read
+    pub cache: Option<CacheCounters>,
+    pub cache_hit_ratio: Option<f64>,
+}
+
+impl Report {
+    pub fn new(samples: &[Sample], elapsed: Duration, cache: Option<CacheCounters>) -> Self {
+        let mut statuses = BTreeMap::new();
+        for status in samples.iter().filter_map(|sample| sample.status) {
+            *statuses.entry(status).or_insert(0) += 1;
+        }
+        let seconds = elapsed.as_secs_f64();
+        Self {
+            requests: samples.len(),
+            seconds,
+            requests_per_second: if seconds > 0.0 { samples.len() as f64 / seconds } else { 0.0 },
+            bytes: samples.iter().map(|sample| sample.bytes).sum(),
+            statuses,
+            errors: samples.iter().filter(|sample| sample.status.is_none()).count() as u64,
+            index: LatencySummary::from_samples(samples.iter().filter(|sample| sample.class == PathClass::Index)),
+            pool: LatencySummary::from_samples(samples.iter().filter(|sample| sample.class == PathClass::Pool)),
+            all: LatencySummary::from_samples(samples.iter()),
+            cache_hit_ratio: cache.and_then(|cache| cache.hit_ratio()),
+            cache,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let statuses: Vec<String> = self.statuses.iter().map(|(status, count)| format!("{}: {}", status, count)).collect();
+        let _ = writeln!(
+            text,
+            "requests:  {} in {:.1}s ({:.1}/s), {:.1} MiB",
+            self.requests, self.seconds, self.requests_per_second, self.bytes as f64 / (1024.0 * 1024.0)
+        );
+        let _ = writeln!(text, "statuses:  {}", if statuses.is_empty() { "-".to_string() } else { statuses.join(", ") });
+        let _ = writeln!(text, "errors:    {}", self.errors);
+        let _ = writeln!(text, "latency    {:>7} {:>9} {:>9} {:>9} {:>9} {:>9}", "count", "mean", "p50", "p90", "p99", "max");
+        for (name, summary) in [("index", &self.index), ("pool", &self.pool), ("all", &self.all)] {
+            let _ = writeln!(
+                text,
+                "  {:<8} {:>7} {:>7.1}ms {:>7.1}ms {:>7.1}ms {:>7.1}ms {:>7.1}ms",
+                name, summary.count, summary.mean_ms, summary.p50_ms, summary.p90_ms, summary.p99_ms, summary.max_ms
+            );
+        }
+        let _ = match (self.cache, self.cache_hit_ratio) {
+            (Some(cache), Some(ratio)) => writeln!(
+                text,
+                "cache:     {:.1}% hits ({} hits, {} upstream fetches)",
+                ratio * 100.0, cache.hits, cache.fetches
+            ),
+            (Some(_), None) => writeln!(text, "cache:     no cache hits or upstream fetches counted"),
+            (None, _) => writeln!(text, "cache:     unknown, /metrics could not be read"),
+        };
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(class: PathClass, status: Option<u16>, millis: u64) -> Sample {
+        Sample { class, status, elapsed: Duration::from_millis(millis), bytes: 100 }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut samples: Vec<Sample> = (1..=100).map(|millis| sample(PathClass::Pool, Some(200), millis)).collect();
+        samples.push(sample(PathClass::Index, Some(404), 5));
+        samples.push(sample(PathClass::Index, None, 30_000));
+        let report = Report::new(&samples, Duration::from_secs(2), Some(CacheCounters { hits: 3, fetches: 1 }));
+        
+        assert_eq!(report.requests, 102);
+        assert_eq!(report.statuses, BTreeMap::from([(200, 100), (404, 1)]));
+        assert_eq!(report.errors, 1);
+        assert_eq!((report.pool.p50_ms, report.pool.p90_ms, report.pool.p99_ms, report.pool.max_ms), (50.0, 90.0, 99.0, 100.0));
+        assert_eq!(report.index.count, 2);
+        assert_eq!(report.cache_hit_ratio, Some(0.75));
+        assert!(report.to_text().contains("75.0% hits"));
+    }
+
+    #[test]
+    fn test_cache_counters() {
+        let metrics = "# TYPE aptg_audit_events_total counter\n\
+            aptg_audit_events_total{status=\"info\",type=\"cache_hit\"} 12\n\
+            aptg_audit_events_total{status=\"success\",type=\"fetch_success\"} 4\n\
+            aptg_audit_events_total{status=\"info\",type=\"request\"} 16\n";
+        let earlier = CacheCounters { hits: 2, fetches: 4 };
+        let counters = CacheCounters::parse(metrics).since(&earlier);
+        assert_eq!(counters, CacheCounters { hits: 10, fetches: 0 });
+        assert_eq!(counters.hit_ratio(), Some(1.0));
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{Result, anyhow};
+use rand::SeedableRng;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::bench::report::{CacheCounters, Report, Sample};
+use crate::bench::workload::{self, SuiteSpec};
+use crate::mirror::latency::PathClass;
+
+const USAGE: &str = "usage: aptg-bench [--url <base url>] [--requests <n>] [--concurrency <n>] [--timeout <secs>] [--json]
+                  [--replay <file>]
+                  [--repository <name>] [--suite <suite>] [--component <component>] [--arch <arch>]
+                  [--index-ratio <0-1>] [--seed <n>]
+
+Without --replay, the package list of the suite is read from the instance and a
+synthetic mix of index refreshes and package downloads is generated.";
+
+#[derive(Debug)]
+struct BenchOptions {
+    url: String,
+    requests: Option<usize>,
+    concurrency: usize,
+    timeout: Duration,
+    json: bool,
+    replay: Option<String>,
+    suite: SuiteSpec,
+    index_ratio: f64,
+    seed: Option<u64>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            url: "http://127.0.0.1:8080".to_string(),
+            requests: None,
+            concurrency: 16,
+            timeout: Duration::from_secs(60),
+            json: false,
+            replay: None,
+            suite: SuiteSpec {
+                repository: "debian".to_string(),
+                suite: "bookworm".to_string(),
+                component: "main".to_string(),
+                architecture: "amd64".to_string(),
+            },
+            index_ratio: 0.2,
+            seed: None,
+        }
+    }
+}
+
+impl BenchOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--url" => options.url = value()?.trim_end_matches('/').to_string(),
+                "--requests" => options.requests = Some(number(arg, &value()?)?),
+                "--concurrency" => options.concurrency = number::<usize>(arg, &value()?)?.max(1),
+                "--timeout" => options.timeout = Duration::from_secs(number(arg, &value()?)?),
+                "--json" => options.json = true,
+                "--replay" => options.replay = Some(value()?),
+                "--repository" => options.suite.repository = value()?,
+                "--suite" => options.suite.suite = value()?,
+                "--component" => options.suite.component = value()?,
+                "--arch" => options.suite.architecture = value()?,
+                "--index-ratio" => options.index_ratio = number(arg, &value()?)?,
+                "--seed" => options.seed = Some(number(arg, &value()?)?),
+                "--help" | "-h" => return Err(anyhow!(USAGE)),
+                _ => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+            }
+        }
+        
+        if !(0.0..=1.0).contains(&options.index_ratio) {
+            return Err(anyhow!("--index-ratio must be between 0 and 1"));
+        }
+        Ok(options)
+    }
+}
+
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| anyhow!("Invalid value '{}' for {}", value, option))
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<bytes::Bytes> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} answered {}", url, response.status()));
+    }
+    Ok(response.bytes().await?)
+}
+
+async fn synthetic_workload(client: &reqwest::Client, options: &BenchOptions) -> Result<Vec<String>> {
+    let spec = &options.suite;
+    let mut pool = Err(anyhow!("No Packages index to try"));
+    for path in spec.packages_paths() {
+        pool = match get(client, &format!("{}{}", options.url, path)).await {
+            Ok(data) => spec.pool_paths(&path, &data),
+            Err(e) => Err(e),
+        };
+        if pool.is_ok() {
+            break;
+        }
+    }
+    let pool = pool.map_err(|e| anyhow!("Could not read the package list of {}: {}", spec.suite, e))?;
+    let mut rng = match options.seed {
+        Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+        None => rand::rngs::StdRng::from_entropy(),
+    };
+    Ok(workload::synthetic(&mut rng, &spec.index_paths(), &pool, options.requests.unwrap_or(1000), options.index_ratio))
+}
+
+// None when the instance does not expose /metrics to this client
+async fn cache_counters(client: &reqwest::Client, url: &str) -> Option<CacheCounters> {
+    let metrics = get(client, &format!("{}/metrics", url)).await.ok()?;
+    Some(CacheCounters::parse(&String::from_utf8_lossy(&metrics)))
+}
+
+async fn replay(client: reqwest::Client, url: String, workload: Arc<Vec<String>>, next: Arc<AtomicUsize>) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    loop {
+        let index = next.fetch_add(1, Ordering::SeqCst);
+        let Some(path) = workload.get(index) else {
+            return samples;
+        };
+        let started = Instant::now();
+        let (status, bytes) = match client.get(format!("{}{}", url, path)).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                match response.bytes().await {
+                    Ok(body) => (Some(status), body.len() as u64),
+                    Err(_) => (None, 0),
+                }
+            }
+            Err(_) => (None, 0),
+        };
+        samples.push(Sample { class: PathClass::from_path(path), status, elapsed: started.elapsed(), bytes });
+    }
+}
+
+// Replays an apt workload against a running aptg and reports latency
+// percentiles per path class and the cache hit ratio aptg counted meanwhile
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = BenchOptions::parse(args)?;
+    let client = reqwest::Client::builder()
+        .timeout(options.timeout)
+        .user_agent("aptg-bench/0.1.0")
+        .build()?;
+
+    let mut paths = match &options.replay {
+        Some(file) => workload::recorded(&std::fs::read_to_string(file).map_err(|e| anyhow!("Failed to read {}: {}", file, e))?)?,
+        None => synthetic_workload(&client, &options).await?,
+    };
+    if let Some(requests) = options.requests {
+        paths.truncate(requests);
+    }
+    if !options.json {
+        eprintln!("Sending {} requests to {} from {} clients", paths.len(), options.url, options.concurrency);
+    }
+
+    let before = cache_counters(&client, &options.url).await;
+    let workload = Arc::new(paths);
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|_| tokio::spawn(replay(client.clone(), options.url.clone(), workload.clone(), next.clone())))
+        .collect();
+    let mut samples = Vec::with_capacity(workload.len());
+    for worker in workers {
+        samples.extend(worker.await?);
+    }
+    let elapsed = started.elapsed();
+    let after = cache_counters(&client, &options.url).await;
+
+    let report = Report::new(&samples, elapsed, before.zip(after).map(|(before, after)| after.since(&before)));
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.to_text());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = BenchOptions::parse(&args(&["--url", "http://mirror:8080/", "--requests", "50", "--suite", "trixie", "--json"])).unwrap();
+        assert_eq!(options.url, "http://mirror:8080");
+        assert_eq!(options.requests, Some(50));
+        assert_eq!(options.suite.suite, "trixie");
+        assert!(options.json);
+        
+        assert!(BenchOptions::parse(&args(&["--requests"])).is_err());
+        assert!(BenchOptions::parse(&args(&["--index-ratio", "2"])).is_err());
+        assert!(BenchOptions::parse(&args(&["--concurrency", "many"])).is_err());
+    }
+}
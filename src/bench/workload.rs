@@ -0,0 +1,125 @@
+use anyhow::{Result, anyhow};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::Value;
+use crate::verify::index::IndexParser;
+
+// What apt asks for on `apt update` for one suite, component and architecture
+#[derive(Debug, Clone)]
+pub struct SuiteSpec {
+    pub repository: String,
+    pub suite: String,
+    pub component: String,
+    pub architecture: String,
+}
+
+impl SuiteSpec {
+    pub fn index_paths(&self) -> Vec<String> {
+        let dists = format!("/{}/dists/{}", self.repository, self.suite);
+        vec![
+            format!("{}/InRelease", dists),
+            format!("{}/{}/binary-{}/Packages.xz", dists, self.component, self.architecture),
+            format!("{}/{}/i18n/Translation-en.xz", dists, self.component),
+        ]
+    }
+
+    // Tried in order; mirrors do not all carry every compression
+    pub fn packages_paths(&self) -> Vec<String> {
+        ["xz", "gz"]
+            .iter()
+            .map(|extension| {
+                format!("/{}/dists/{}/{}/binary-{}/Packages.{}", self.repository, self.suite, self.component, self.architecture, extension)
+            })
+            .collect()
+    }
+
+    // Pool paths listed in a fetched Packages index
+    pub fn pool_paths(&self, packages_path: &str, data: &[u8]) -> Result<Vec<String>> {
+        let content = IndexParser::decompress(packages_path, data)?;
+        let paths: Vec<String> = IndexParser::parse_packages(&content)?
+            .into_iter()
+            .map(|(filename, _)| format!("/{}/{}", self.repository, filename))
+            .collect();
+        if paths.is_empty() {
+            return Err(anyhow!("{} lists no packages", packages_path));
+        }
+        Ok(paths)
+    }
+}
+
+// A mix of index refreshes and package downloads. A few packages account
+// for most downloads, as on a real mirror, so the cache sees repeats
+pub fn synthetic<R: Rng>(rng: &mut R, index_paths: &[String], pool_paths: &[String], requests: usize, index_ratio: f64) -> Vec<String> {
+    let mut pool = pool_paths.to_vec();
+    // Popularity should not follow the alphabet
+    pool.shuffle(rng);
+    (0..requests)
+        .map(|_| {
+            if pool.is_empty() || rng.gen_bool(index_ratio.clamp(0.0, 1.0)) {
+                index_paths[rng.gen_range(0..index_paths.len())].clone()
+            } else {
+                let rank = (rng.gen::<f64>().powi(3) * pool.len() as f64) as usize;
+                pool[rank.min(pool.len() - 1)].clone()
+            }
+        })
+        .collect()
+}
+
+// Paths to request in order: one per line, or the NDJSON of
+// /admin/audit/export, whose Request events are replayed
+pub fn recorded(content: &str) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('{') {
+            let event: Value = serde_json::from_str(line).map_err(|e| anyhow!("Line {}: {}", number + 1, e))?;
+            if event["event_type"] == "Request" {
+                let path = event["path"].as_str().ok_or_else(|| anyhow!("Line {}: event has no path", number + 1))?;
+                paths.push(path.to_string());
+            }
+        } else if line.starts_with('/') {
+            paths.push(line.to_string());
+        } else {
+            return Err(anyhow!("Line {}: expected a path or an audit event", number + 1));
+        }
+    }
+    if paths.is_empty() {
+        return Err(anyhow!("The recording has no requests"));
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_synthetic_mix() {
+        let spec = SuiteSpec { repository: "debian".into(), suite: "bookworm".into(), component: "main".into(), architecture: "amd64".into() };
+        let packages = "Package: sl\nFilename: pool/main/s/sl/sl_5.02-1_amd64.deb\nSize: 10\nSHA256: ab\n\n\
+                        Package: hello\nFilename: pool/main/h/hello/hello_2.10-3_amd64.deb\nSize: 20\nSHA256: cd\n";
+        let pool = spec.pool_paths("Packages", packages.as_bytes()).unwrap();
+        assert_eq!(pool[0], "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb");
+        
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let workload = synthetic(&mut rng, &spec.index_paths(), &pool, 1000, 0.2);
+        assert_eq!(workload.len(), 1000);
+        let indexes = workload.iter().filter(|path| path.contains("/dists/")).count();
+        assert!((100..300).contains(&indexes), "{} index requests", indexes);
+        assert!(workload.iter().all(|path| spec.index_paths().contains(path) || pool.contains(path)));
+    }
+
+    #[test]
+    fn test_recorded() {
+        let recording = "# refresh\n/debian/dists/bookworm/InRelease\n\
+            {\"event_type\":\"Request\",\"path\":\"/debian/pool/main/s/sl/sl_5.02-1_amd64.deb\"}\n\
+            {\"event_type\":\"CacheHit\",\"path\":\"/debian/pool/main/s/sl/sl_5.02-1_amd64.deb\"}\n";
+        assert_eq!(recorded(recording).unwrap(), vec!["/debian/dists/bookworm/InRelease", "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb"]);
+        assert!(recorded("debian/dists/bookworm/InRelease\n").is_err());
+        assert!(recorded("\n").is_err());
+    }
+}
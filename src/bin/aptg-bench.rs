@@ -0,0 +1,9 @@
+use anyhow::Result;
+
+// Replays a synthetic or recorded apt workload against a running instance;
+// `aptg-bench --help` lists the options
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    aptg::bench::runner::run(&args).await
+}
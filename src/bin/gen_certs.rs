@@ -1,52 +1,130 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use std::fs;
-use tracing_subscriber;
-use aptg::tls::certificate_simple::CertificateManager;
+use std::path::Path;
+use aptg::tls::certificate_simple::{CertificateManager, CertificateOptions, CertificatePurpose, KeyType};
+
+const USAGE: &str = "usage: gen_certs [--out <dir>] [--cn <name>] [--dns <name>]... [--ip <address>]... \
+[--days <n>] [--key-type ecdsa-p256|ecdsa-p384|ed25519] [--org <name>] [--ou <name>] [--country <code>] \
+[--client-cn <name>] [--self-signed]";
+
+// The server certificate takes the subject, names and validity from the flags;
+// the CA and client certificate share the organization and key type
+struct GenCertsOptions {
+    out_dir: String,
+    server: CertificateOptions,
+    client_cn: String,
+    self_signed: bool,
+}
+
+impl GenCertsOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self {
+            out_dir: "certs".to_string(),
+            server: CertificateOptions::new("localhost"),
+            client_cn: "client".to_string(),
+            self_signed: false,
+        };
+        let mut args = args.iter();
+        
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--out" => options.out_dir = value()?,
+                "--cn" => options.server.common_name = value()?,
+                "--dns" => options.server.dns_names.push(value()?),
+                "--ip" => {
+                    let ip = value()?;
+                    options.server.ip_addresses.push(ip.parse().map_err(|_| anyhow!("Invalid IP address '{}'", ip))?);
+                }
+                "--days" => {
+                    let days = value()?;
+                    options.server.validity_days = days.parse().map_err(|_| anyhow!("Invalid number of days '{}'", days))?;
+                }
+                "--key-type" => options.server.key_type = KeyType::parse(&value()?)?,
+                "--org" => options.server.organization = Some(value()?),
+                "--ou" => options.server.organizational_unit = Some(value()?),
+                "--country" => options.server.country = Some(value()?),
+                "--client-cn" => options.client_cn = value()?,
+                "--self-signed" => options.self_signed = true,
+                "--help" | "-h" => return Err(anyhow!(USAGE)),
+                _ => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+}
 
 fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = GenCertsOptions::parse(&args)?;
+    let path = |name: &str| Path::new(&options.out_dir).join(name).to_string_lossy().into_owned();
     
-    println!("Generating self-signed certificates for aptg - Debian Mirror Redirector");
-    
-    // Create certs directory if it doesn't exist
-    fs::create_dir_all("certs")?;
+    println!("Generating test certificates for aptg - Debian Mirror Redirector");
+    fs::create_dir_all(&options.out_dir)?;
     
-    // Generate server certificate
-    let server_cert_path = "certs/server.pem";
-    let server_key_path = "certs/server.key";
+    let server_cert_path = path("server.pem");
+    let server_key_path = path("server.key");
+
+    if options.self_signed {
+        CertificateManager::generate_self_signed_cert_with_options(&options.server, &server_cert_path, &server_key_path)?;
+        println!("✅ Self-signed server certificate generated: {}", server_cert_path);
+        println!("✅ Server private key generated: {}", server_key_path);
+        return Ok(());
+    }
+
+    // Generate the CA that signs both server and client certificates
+    let ca_cert_path = path("ca.pem");
+    let ca_key_path = path("ca.key");
+
+    CertificateManager::generate_ca("aptg test CA", options.server.key_type, &ca_cert_path, &ca_key_path)?;
+
+    println!("✅ CA certificate generated: {}", ca_cert_path);
+    println!("✅ CA private key generated: {}", ca_key_path);
     
-    CertificateManager::generate_self_signed_cert(
-        "localhost",
-        server_cert_path,
-        server_key_path,
+    CertificateManager::issue_certificate_with_options(
+        &ca_cert_path,
+        &ca_key_path,
+        &options.server,
+        CertificatePurpose::Server,
+        &server_cert_path,
+        &server_key_path,
     )?;
     
     println!("✅ Server certificate generated: {}", server_cert_path);
     println!("✅ Server private key generated: {}", server_key_path);
     
     // Generate client certificate
-    let client_cert_path = "certs/client.pem";
-    let client_key_path = "certs/client.key";
+    let client_cert_path = path("client.pem");
+    let client_key_path = path("client.key");
+    let client = CertificateOptions {
+        organization: options.server.organization.clone(),
+        organizational_unit: options.server.organizational_unit.clone(),
+        country: options.server.country.clone(),
+        validity_days: options.server.validity_days,
+        key_type: options.server.key_type,
+        ..CertificateOptions::new(&options.client_cn)
+    };
     
-    CertificateManager::generate_self_signed_cert(
-        "client",
-        client_cert_path,
-        client_key_path,
+    CertificateManager::issue_certificate_with_options(
+        &ca_cert_path,
+        &ca_key_path,
+        &client,
+        CertificatePurpose::Client,
+        &client_cert_path,
+        &client_key_path,
     )?;
     
     println!("✅ Client certificate generated: {}", client_cert_path);
     println!("✅ Client private key generated: {}", client_key_path);
     
-    // Copy server cert as CA for testing
-    let ca_cert_path = "certs/ca.pem";
-    fs::copy(server_cert_path, ca_cert_path)?;
-    println!("✅ CA certificate created: {}", ca_cert_path);
-    
     println!("\n🎉 Certificates generated successfully!");
     println!("\n📝 Usage:");
     println!("   HTTP Server:  http://localhost:8080");
-    println!("   HTTPS Server: https://localhost:8443");
-    println!("\n⚠️  Note: These are self-signed certificates for testing only.");
+    println!("   HTTPS Server: https://{}:8443", options.server.common_name);
+    println!("   mTLS client:  curl --cacert {} --cert {} --key {} https://{}:8443/healthz",
+        ca_cert_path, client_cert_path, client_key_path, options.server.common_name);
+    println!("\n⚠️  Note: These certificates come from a throwaway CA for testing only.");
     println!("   For production, use certificates from a trusted CA.");
     
     Ok(())
@@ -1,48 +1,33 @@
 use anyhow::Result;
-use std::fs;
-use tracing_subscriber;
+use aptg::config::settings::AppConfig;
+use aptg::geoip::database::GeoIpDatabase;
+use aptg::geoip::updater::GeoIpUpdater;
 
+// Downloads or refreshes the GeoIP database once, using the [geoip] and
+// [geoip.update] sections of the aptg config. The server does the same on
+// update_interval_hours when [geoip.update] is enabled
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
+    let config_path = std::env::var("APTG_CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+    let config = AppConfig::load_or_default(&config_path)?;
+    let geoip = &config.geoip;
     
     println!("🌍 GeoIP Database Manager");
-    println!();
+    println!("📥 Checking {} for {}...", geoip.update.edition_id, geoip.database_path);
     
-    // Create geoip directory if it doesn't exist
-    fs::create_dir_all("geoip")?;
+    let updater = GeoIpUpdater::new(geoip.update.clone(), &geoip.database_path)?;
+    if updater.update().await? {
+        println!("✅ Installed a new database at {}", geoip.database_path);
+    } else {
+        println!("✅ {} is already up to date", geoip.database_path);
+    }
     
-    println!("📥 Setting up GeoIP database...");
-    println!("🔧 Note: In a production environment, you would:");
-    println!("   1. Download GeoLite2-City.mmdb from MaxMind");
-    println!("   2. Place it in the geoip/ directory");
-    println!("   3. Configure the mirror redirector to use it");
-    println!();
-    
-    // Create a placeholder database file for demonstration
-    let database_path = "geoip/GeoLite2-City.mmdb";
-    fs::write(database_path, "# GeoLite2-City Database Placeholder\n# This would contain actual MaxMind database data in production\n")?;
-    
-    println!("✅ GeoIP database setup completed!");
-    println!("📝 Database location: {}", database_path);
-    println!("🔧 Update your config.toml to use this database:");
-    println!("   [geoip]");
-    println!("   enabled = true");
-    println!("   database_path = \"{}\"", database_path);
-    println!("   update_interval_hours = 24");
-    println!();
-    println!("🔍 To get a real GeoLite2 database:");
-    println!("   1. Sign up for a free MaxMind account at https://www.maxmind.com");
-    println!("   2. Download GeoLite2-City.mmdb");
-    println!("   3. Place it in the geoip/ directory");
-    println!("   4. Set up automatic updates with:");
-    println!("      wget -O geoip/GeoLite2-City.mmdb \"https://download.maxmind.com/app/geoip_download?edition_id=GeoLite2-City&license_key=YOUR_KEY&suffix=tar.gz\"");
-    println!();
-    println!("🌐 Example GeoIP policies:");
-    println!("   - Block requests from high-risk countries");
-    println!("   - Rate limit requests from specific regions");
-    println!("   - Redirect users to nearest mirror");
-    println!("   - Log-only mode for monitoring");
+    let info = GeoIpDatabase::new(&geoip.database_path)?.get_info().clone();
+    println!("📝 Built {}, {} bytes", info.last_updated.format("%Y-%m-%d"), info.size_bytes);
+    if !geoip.enabled {
+        println!("🔧 Set enabled = true under [geoip] in {} to apply GeoIP policies", config_path);
+    }
     
     Ok(())
 }
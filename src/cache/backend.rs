@@ -0,0 +1,283 @@
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use hashlink::LruCache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::cache::cache::CachedResponse;
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+// Response headers kept with a cached body; the rest describe the upstream connection
+const STORED_HEADERS: &[&str] = &["content-type", "last-modified", "etag", "x-aptg-warning"];
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// A backend refused a write for lack of space or being over its quota
+#[derive(Debug)]
+pub struct StorageFull(pub String);
+
+impl std::fmt::Display for StorageFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StorageFull {}
+
+#[derive(Clone)]
+pub struct CachedObject {
+    pub response: CachedResponse,
+    // Seconds since the Unix epoch, so replicas sharing a backend agree on freshness
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ObjectMeta {
+    status: u16,
+    expires_at: u64,
+    headers: Vec<(String, String)>,
+}
+
+impl CachedObject {
+    pub fn is_fresh(&self, now: u64) -> bool {
+        now < self.expires_at
+    }
+
+    // One JSON line of metadata followed by the body, as stored on disk and in object storage
+    pub fn encode(&self) -> Vec<u8> {
+        let headers = self.response.headers
+            .iter()
+            .filter(|(name, _)| STORED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let meta = ObjectMeta { status: self.response.status.as_u16(), expires_at: self.expires_at, headers };
+        let mut encoded = serde_json::to_vec(&meta).expect("cache metadata serializes");
+        encoded.push(b'\n');
+        encoded.extend_from_slice(&self.response.body);
+        encoded
+    }
+
+    pub fn decode(encoded: Bytes) -> Result<Self> {
+        let split = encoded.iter().position(|b| *b == b'\n').ok_or_else(|| anyhow!("Cached object has no metadata"))?;
+        let meta: ObjectMeta = serde_json::from_slice(&encoded[..split]).map_err(|e| anyhow!("Invalid cached object metadata: {}", e))?;
+        let mut headers = warp::http::HeaderMap::new();
+        for (name, value) in &meta.headers {
+            let name = warp::http::HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("Invalid cached header {}: {}", name, e))?;
+            let value = warp::http::HeaderValue::from_str(value).map_err(|e| anyhow!("Invalid cached header {}: {}", name, e))?;
+            headers.append(name, value);
+        }
+        Ok(Self {
+            response: CachedResponse {
+                status: warp::http::StatusCode::from_u16(meta.status).map_err(|e| anyhow!("Invalid cached status: {}", e))?,
+                headers,
+                body: encoded.slice(split + 1..),
+            },
+            expires_at: meta.expires_at,
+        })
+    }
+}
+
+fn write_error(action: &str, path: &dyn std::fmt::Display, e: std::io::Error) -> anyhow::Error {
+    let message = format!("Failed to {} {}: {}", action, path, e);
+    match e.kind() {
+        std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded => StorageFull(message).into(),
+        _ => anyhow!(message),
+    }
+}
+
+// Where cached responses live. Expired entries may still be returned; the
+// cache manager checks freshness so every backend need not
+pub trait CacheBackend: Send + Sync {
+    fn name(&self) -> &str;
+    fn get<'a>(&'a self, key: &'a str) -> BackendFuture<'a, Option<CachedObject>>;
+    fn put<'a>(&'a self, key: &'a str, object: &'a CachedObject) -> BackendFuture<'a, ()>;
+    fn delete<'a>(&'a self, key: &'a str) -> BackendFuture<'a, ()>;
+}
+
+struct MemoryEntries {
+    objects: LruCache<String, CachedObject>,
+    bytes: usize,
+}
+
+// Least recently used entries are evicted once the bodies exceed max_bytes
+pub struct MemoryBackend {
+    max_bytes: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+impl MemoryBackend {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: Mutex::new(MemoryEntries { objects: LruCache::new_unbounded(), bytes: 0 }),
+        }
+    }
+
+    pub fn remove_expired(&self, now: u64) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let expired: Vec<String> = entries.objects.iter().filter(|(_, object)| !object.is_fresh(now)).map(|(key, _)| key.clone()).collect();
+        for key in &expired {
+            if let Some(object) = entries.objects.remove(key) {
+                entries.bytes -= object.response.body.len();
+            }
+        }
+        expired.len()
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.objects.clear();
+        entries.bytes = 0;
+    }
+}
+
+impl CacheBackend for MemoryBackend {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BackendFuture<'a, Option<CachedObject>> {
+        let object = self.entries.lock().unwrap_or_else(|e| e.into_inner()).objects.get(key).cloned();
+        Box::pin(async move { Ok(object) })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, object: &'a CachedObject) -> BackendFuture<'a, ()> {
+        let size = object.response.body.len();
+        if size <= self.max_bytes {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(previous) = entries.objects.insert(key.to_string(), object.clone()) {
+                entries.bytes -= previous.response.body.len();
+            }
+            entries.bytes += size;
+            while entries.bytes > self.max_bytes {
+                let Some((_, evicted)) = entries.objects.remove_lru() else {
+                    break;
+                };
+                entries.bytes -= evicted.response.body.len();
+            }
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BackendFuture<'a, ()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(object) = entries.objects.remove(key) {
+            entries.bytes -= object.response.body.len();
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+// One file per entry, named by the SHA-256 of the key and fanned out over
+// 256 directories. Files are replaced by rename, so readers never see a partial write
+pub struct DiskBackend {
+    root: PathBuf,
+}
+
+impl DiskBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn file(&self, key: &str) -> PathBuf {
+        let digest = hex::encode(Sha256::digest(key.as_bytes()));
+        self.root.join(&digest[..2]).join(digest)
+    }
+}
+
+impl CacheBackend for DiskBackend {
+    fn name(&self) -> &str {
+        "disk"
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BackendFuture<'a, Option<CachedObject>> {
+        Box::pin(async move {
+            let file = self.file(key);
+            match tokio::fs::read(&file).await {
+                Ok(encoded) => Ok(Some(CachedObject::decode(Bytes::from(encoded))?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(anyhow!("Failed to read {}: {}", file.display(), e)),
+            }
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, object: &'a CachedObject) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            let file = self.file(key);
+            let directory = file.parent().expect("cache files are in a fan-out directory");
+            tokio::fs::create_dir_all(directory).await.map_err(|e| write_error("create", &directory.display(), e))?;
+            let partial = file.with_extension(format!("partial-{}", rand::random::<u32>()));
+            if let Err(e) = tokio::fs::write(&partial, object.encode()).await {
+                // A partly written file only takes up space
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(write_error("write", &partial.display(), e));
+            }
+            tokio::fs::rename(&partial, &file).await.map_err(|e| anyhow!("Failed to rename {}: {}", partial.display(), e))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            let file = self.file(key);
+            match tokio::fs::remove_file(&file).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow!("Failed to remove {}: {}", file.display(), e)),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(body: &'static [u8], expires_at: u64) -> CachedObject {
+        let mut headers = warp::http::HeaderMap::new();
+        headers.insert("content-type", "application/vnd.debian.binary-package".parse().unwrap());
+        headers.insert("connection", "keep-alive".parse().unwrap());
+        CachedObject {
+            response: CachedResponse { status: warp::http::StatusCode::OK, headers, body: Bytes::from_static(body) },
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_evicts_least_recently_used() {
+        let memory = MemoryBackend::new(10);
+        memory.put("/a", &object(b"aaaa", 100)).await.unwrap();
+        memory.put("/b", &object(b"bbbb", 100)).await.unwrap();
+        assert!(memory.get("/a").await.unwrap().is_some());
+        memory.put("/c", &object(b"cccc", 50)).await.unwrap();
+        
+        assert!(memory.get("/a").await.unwrap().is_some());
+        assert!(memory.get("/b").await.unwrap().is_none());
+        assert_eq!(memory.remove_expired(60), 1);
+        assert!(memory.get("/c").await.unwrap().is_none());
+        memory.put("/large", &object(b"larger than ten bytes", 100)).await.unwrap();
+        assert!(memory.get("/large").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disk_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = DiskBackend::new(dir.path());
+        let key = "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb";
+        assert!(disk.get(key).await.unwrap().is_none());
+        disk.put(key, &object(b"\n!<arch>\n", 1234)).await.unwrap();
+        
+        let cached = disk.get(key).await.unwrap().unwrap();
+        assert_eq!(cached.expires_at, 1234);
+        assert_eq!(&cached.response.body[..], b"\n!<arch>\n");
+        assert_eq!(cached.response.headers["content-type"], "application/vnd.debian.binary-package");
+        assert!(cached.response.headers.get("connection").is_none());
+        disk.delete(key).await.unwrap();
+        assert!(disk.get(key).await.unwrap().is_none());
+    }
+}
@@ -1,20 +1,85 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
 use warp::Reply;
 use bytes::Bytes;
 use tracing::{info, warn};
+use crate::cache::backend::{unix_now, CacheBackend, CachedObject, DiskBackend, MemoryBackend, StorageFull};
+use crate::cache::metadata::{EntryMetadata, FetchClaim, RedisMetadataConfig, SharedMetadata};
+use crate::cache::object_store::{ObjectStoreBackend, ObjectStoreConfig};
+use crate::notify::webhook::{NotificationKind, Notifier};
 
-pub struct CacheManager {
-    cache: RwLock<HashMap<String, CacheEntry>>,
-    ttl_config: TtlConfig,
+// How often a waiting replica checks whether another's fetch has landed
+const CLAIM_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    // TTL values in seconds
+    pub release_ttl: u64,
+    pub packages_ttl: u64,
+    pub deb_ttl: u64,
+    // In-process layer, checked first; 0 disables it
+    pub memory_max_mb: u64,
+    // Local directory layer between memory and the object store
+    pub disk_path: Option<String>,
+    // Bucket shared by every replica
+    pub object_store: Option<ObjectStoreConfig>,
+    // Seconds an upstream 404 is remembered; 0 disables negative caching
+    pub negative_ttl: u64,
+    // Shared metadata for clustered deployments
+    pub redis: Option<RedisMetadataConfig>,
 }
 
-#[derive(Clone)]
-struct CacheEntry {
-    data: CachedResponse,
-    created_at: Instant,
-    ttl: Duration,
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            release_ttl: 6 * 3600,
+            packages_ttl: 12 * 3600,
+            deb_ttl: 365 * 24 * 3600,
+            memory_max_mb: 256,
+            disk_path: None,
+            object_store: None,
+            negative_ttl: 300,
+            redis: None,
+        }
+    }
+}
+
+struct CacheLayer {
+    backend: Arc<dyn CacheBackend>,
+    // Disk and object storage outlive the process, but the verified index
+    // store does not; indices must be fetched and verified again after a
+    // restart, so only pool files go to these layers
+    pool_only: bool,
+    // Seen by every replica, and tracked in the shared metadata when configured
+    shared: bool,
+}
+
+impl CacheLayer {
+    fn holds(&self, path: &str) -> bool {
+        !self.pool_only || path.contains("/pool/")
+    }
+}
+
+// One replica fetches while the others wait for its result
+pub enum FetchTurn {
+    // Go to upstream; the claim, if any, is held until the response is handled
+    Fetch(Option<FetchClaim>),
+    // Another replica's fetch reached the cache meanwhile
+    Cached(CachedResponse),
+}
+
+// Read-through layers: memory, then disk, then the shared object store. A hit
+// in a later layer fills the earlier ones, and stores go to every layer
+pub struct CacheManager {
+    memory: Option<Arc<MemoryBackend>>,
+    layers: Vec<CacheLayer>,
+    // Redis metadata: which paths the shared layers hold and which upstream lacks
+    metadata: Option<Arc<SharedMetadata>>,
+    negative_ttl: Duration,
+    ttl_config: TtlConfig,
+    notifier: Option<Arc<Notifier>>,
 }
 
 #[derive(Clone)]
@@ -24,6 +89,15 @@ pub struct CachedResponse {
     pub body: Bytes,
 }
 
+impl Reply for CachedResponse {
+    fn into_response(self) -> warp::reply::Response {
+        let mut response = warp::reply::Response::new(self.body.into());
+        *response.headers_mut() = self.headers;
+        *response.status_mut() = self.status;
+        response
+    }
+}
+
 #[derive(Clone)]
 pub struct TtlConfig {
     pub release_ttl: Duration,
@@ -41,45 +115,216 @@ impl Default for TtlConfig {
     }
 }
 
+impl Default for CacheManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CacheManager {
     pub fn new() -> Self {
+        Self::from_config(&CacheConfig::default())
+    }
+
+    // A layer that cannot be set up is left out rather than failing startup
+    pub fn from_config(config: &CacheConfig) -> Self {
+        let mut layers = Vec::new();
+        if let Some(path) = &config.disk_path {
+            layers.push(CacheLayer { backend: Arc::new(DiskBackend::new(path)), pool_only: true, shared: false });
+        }
+        if let Some(object_store) = &config.object_store {
+            match ObjectStoreBackend::new(object_store) {
+                Ok(backend) => layers.push(CacheLayer { backend: Arc::new(backend), pool_only: true, shared: true }),
+                Err(e) => warn!("Object store cache disabled: {}", e),
+            }
+        }
+        let metadata = config.redis.as_ref().and_then(|redis| match SharedMetadata::new(redis) {
+            Ok(metadata) => Some(Arc::new(metadata)),
+            Err(e) => {
+                warn!("Shared cache metadata disabled: {}", e);
+                None
+            }
+        });
         Self {
-            cache: RwLock::new(HashMap::new()),
-            ttl_config: TtlConfig::default(),
+            memory: (config.memory_max_mb > 0).then(|| Arc::new(MemoryBackend::new((config.memory_max_mb * 1024 * 1024) as usize))),
+            layers,
+            metadata,
+            negative_ttl: Duration::from_secs(config.negative_ttl),
+            ttl_config: TtlConfig {
+                release_ttl: Duration::from_secs(config.release_ttl),
+                packages_ttl: Duration::from_secs(config.packages_ttl),
+                deb_ttl: Duration::from_secs(config.deb_ttl),
+            },
+            notifier: None,
         }
     }
+
+    // Alerts when a layer runs out of space
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
     
-    pub async fn get(&self, path: &str) -> Option<impl Reply> {
-        let cache = self.cache.read().await;
+    async fn read(&self, backend: &dyn CacheBackend, path: &str, now: u64) -> Option<CachedObject> {
+        match backend.get(path).await {
+            Ok(Some(object)) if object.is_fresh(now) => {
+                info!("Cache hit for {} in {}", path, backend.name());
+                Some(object)
+            }
+            Ok(Some(_)) => {
+                info!("Cache expired for {} in {}", path, backend.name());
+                if let Err(e) = backend.delete(path).await {
+                    warn!("Cache {} could not remove {}: {}", backend.name(), path, e);
+                }
+                None
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Cache {} could not read {}: {}", backend.name(), path, e);
+                None
+            }
+        }
+    }
+
+    async fn write(&self, backend: &dyn CacheBackend, path: &str, object: &CachedObject) -> bool {
+        match backend.put(path, object).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Cache {} could not store {}: {}", backend.name(), path, e);
+                if let (Some(notifier), Some(full)) = (&self.notifier, e.downcast_ref::<StorageFull>()) {
+                    notifier.notify(NotificationKind::CacheStorageFull, backend.name(), full.to_string());
+                }
+                false
+            }
+        }
+    }
+
+    // Negative entries come back as an empty 404
+    pub async fn get(&self, path: &str) -> Option<CachedResponse> {
+        let now = unix_now();
+        if let Some(memory) = &self.memory {
+            if let Some(object) = self.read(memory.as_ref(), path, now).await {
+                return Some(object.response);
+            }
+        }
+        
+        // None when unknown: without Redis, or when it cannot be reached
+        let listed = match &self.metadata {
+            Some(metadata) => metadata
+                .lookup(path)
+                .await
+                .map_err(|e| warn!("Shared cache metadata for {} unavailable: {}", path, e))
+                .ok(),
+            None => None,
+        };
+        let mut found = match &listed {
+            Some(Some(entry)) if entry.is_missing() && entry.expires_at > now => Some((0, CachedObject {
+                response: CachedResponse { status: warp::http::StatusCode::NOT_FOUND, headers: Default::default(), body: Bytes::new() },
+                expires_at: entry.expires_at,
+            })),
+            _ => None,
+        };
+        for (index, layer) in self.layers.iter().enumerate().filter(|(_, layer)| layer.holds(path)) {
+            // No replica has stored the path in a shared layer, so skip the round trip
+            if found.is_some() || (layer.shared && matches!(listed, Some(None))) {
+                continue;
+            }
+            found = self.read(layer.backend.as_ref(), path, now).await.map(|object| (index, object));
+        }
         
-        if let Some(entry) = cache.get(path) {
-            if entry.created_at.elapsed() < entry.ttl {
-                info!("Cache hit for: {}", path);
-                
-                let _response = warp::reply::Response::new(entry.data.body.clone().into());
-                
-                // Copy headers and status
-                let reply = CachedResponse {
-                    status: entry.data.status,
-                    headers: entry.data.headers.clone(),
-                    body: entry.data.body.clone(),
-                };
-                
-                return Some(self.create_warp_response(reply));
-            } else {
-                warn!("Cache expired for: {}", path);
+        // Listed but gone, e.g. removed by a bucket lifecycle rule
+        if let (None, Some(Some(entry)), Some(metadata)) = (&found, &listed, &self.metadata) {
+            if !entry.is_missing() && self.layers.iter().any(|layer| layer.shared && layer.holds(path)) {
+                if let Err(e) = metadata.forget(path).await {
+                    warn!("Shared cache metadata for {} not removed: {}", path, e);
+                }
             }
         }
         
-        None
+        let (index, object) = found?;
+        if let Some(memory) = &self.memory {
+            self.write(memory.as_ref(), path, &object).await;
+        }
+        for earlier in self.layers[..index].iter().filter(|earlier| earlier.holds(path)) {
+            self.write(earlier.backend.as_ref(), path, &object).await;
+        }
+        Some(object.response)
     }
     
-    pub async fn store(&self, path: &str, response: &impl Reply) {
-        let ttl = self.determine_ttl(path);
-        
-        // For now, we'll skip caching since we can't properly extract response data
-        // In a real implementation, you'd need to properly extract the response data
-        info!("Skipping cache storage for: {} (TTL: {:?})", path, ttl);
+    // Only successful responses are cached; a failing layer does not affect the others
+    pub async fn store(&self, path: &str, response: &CachedResponse) {
+        if response.status != warp::http::StatusCode::OK {
+            return;
+        }
+        let object = CachedObject {
+            response: response.clone(),
+            expires_at: unix_now() + self.determine_ttl(path).as_secs(),
+        };
+        if let Some(memory) = &self.memory {
+            self.write(memory.as_ref(), path, &object).await;
+        }
+        let mut shared = None;
+        for layer in self.layers.iter().filter(|layer| layer.holds(path)) {
+            if self.write(layer.backend.as_ref(), path, &object).await && layer.shared {
+                shared = Some(layer.backend.name());
+            }
+        }
+        if let (Some(metadata), Some(layer)) = (&self.metadata, shared) {
+            let entry = EntryMetadata { status: 200, expires_at: object.expires_at, size: response.body.len() as u64, layer: layer.to_string() };
+            if let Err(e) = metadata.record(path, &entry).await {
+                warn!("Shared cache metadata for {} not recorded: {}", path, e);
+            }
+        }
+    }
+
+    // Upstream has no such file; remembered for negative_ttl by this replica
+    // and, through Redis, by the others
+    pub async fn store_missing(&self, path: &str) {
+        if self.negative_ttl.is_zero() {
+            return;
+        }
+        let object = CachedObject {
+            response: CachedResponse { status: warp::http::StatusCode::NOT_FOUND, headers: Default::default(), body: Bytes::new() },
+            expires_at: unix_now() + self.negative_ttl.as_secs(),
+        };
+        if let Some(memory) = &self.memory {
+            self.write(memory.as_ref(), path, &object).await;
+        }
+        if let Some(metadata) = &self.metadata {
+            let entry = EntryMetadata { status: 404, expires_at: object.expires_at, size: 0, layer: "none".to_string() };
+            if let Err(e) = metadata.record(path, &entry).await {
+                warn!("Shared cache metadata for {} not recorded: {}", path, e);
+            }
+        }
+    }
+
+    // Only paths bound for a shared layer are claimed; other replicas could
+    // not use anything else this replica fetches
+    pub async fn begin_fetch(&self, path: &str) -> FetchTurn {
+        let Some(metadata) = &self.metadata else {
+            return FetchTurn::Fetch(None);
+        };
+        if !self.layers.iter().any(|layer| layer.shared && layer.holds(path)) {
+            return FetchTurn::Fetch(None);
+        }
+        let deadline = Instant::now() + metadata.fetch_wait();
+        loop {
+            match metadata.try_claim(path).await {
+                Ok(true) => return FetchTurn::Fetch(Some(FetchClaim::new(metadata.clone(), path))),
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Fetch of {} not coordinated: {}", path, e);
+                    return FetchTurn::Fetch(None);
+                }
+            }
+            if Instant::now() >= deadline {
+                return FetchTurn::Fetch(None);
+            }
+            tokio::time::sleep(CLAIM_POLL_INTERVAL).await;
+            if let Some(cached) = self.get(path).await {
+                return FetchTurn::Cached(cached);
+            }
+        }
     }
     
     fn determine_ttl(&self, path: &str) -> Duration {
@@ -94,40 +339,77 @@ impl CacheManager {
         }
     }
     
-    async fn extract_response_data(&self, _response: &impl Reply) -> Result<CachedResponse, Box<dyn std::error::Error + Send + Sync>> {
-        // This is a simplified version - in practice, you'd need to properly extract
-        // the response data from the warp Reply
-        // For now, we'll create a placeholder
-        Ok(CachedResponse {
-            status: warp::http::StatusCode::OK,
-            headers: warp::http::HeaderMap::new(),
-            body: Bytes::new(),
-        })
-    }
-    
-    fn create_warp_response(&self, cached: CachedResponse) -> impl Reply {
-        let mut response = warp::reply::Response::new(cached.body.into());
-        *response.headers_mut() = cached.headers;
-        *response.status_mut() = cached.status;
-        response
+    // For content aptg generates itself, such as a Release listing local packages
+    pub async fn invalidate(&self, path: &str) {
+        if let Some(memory) = &self.memory {
+            let _ = memory.delete(path).await;
+        }
+        for layer in self.layers.iter().filter(|layer| layer.holds(path)) {
+            if let Err(e) = layer.backend.delete(path).await {
+                warn!("Cache {} could not remove {}: {}", layer.backend.name(), path, e);
+            }
+        }
+        if let Some(metadata) = &self.metadata {
+            if let Err(e) = metadata.forget(path).await {
+                warn!("Shared cache metadata for {} not removed: {}", path, e);
+            }
+        }
     }
-    
+
+    // Disk and object store entries are removed when found expired
     pub async fn clear(&self) {
-        let mut cache = self.cache.write().await;
-        cache.clear();
+        if let Some(memory) = &self.memory {
+            memory.clear();
+        }
         info!("Cache cleared");
     }
     
     pub async fn cleanup_expired(&self) {
-        let mut cache = self.cache.write().await;
-        let now = Instant::now();
+        if let Some(memory) = &self.memory {
+            let removed = memory.remove_expired(unix_now());
+            info!("Removed {} expired cache entries", removed);
+        }
+    }
         
-        cache.retain(|path, entry| {
-            let is_valid = now.duration_since(entry.created_at) < entry.ttl;
-            if !is_valid {
-                info!("Removing expired cache entry: {}", path);
+    // Expired memory entries would otherwise stay until requested again
+    pub fn spawn_cleanup(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                self.cleanup_expired().await;
             }
-            is_valid
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &'static str) -> CachedResponse {
+        CachedResponse { status: warp::http::StatusCode::OK, headers: warp::http::HeaderMap::new(), body: Bytes::from_static(body.as_bytes()) }
+    }
+
+    #[tokio::test]
+    async fn test_read_through_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CacheConfig { disk_path: Some(dir.path().to_str().unwrap().to_string()), ..CacheConfig::default() };
+        let pool = "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb";
+        let index = "/debian/dists/bookworm/InRelease";
+        let replica = CacheManager::from_config(&config);
+        replica.store(pool, &response("deb")).await;
+        replica.store(index, &response("release")).await;
+        replica.store("/debian/dists/sid/InRelease", &CachedResponse { status: warp::http::StatusCode::NOT_FOUND, ..response("") }).await;
+        assert!(replica.get("/debian/dists/sid/InRelease").await.is_none());
+        replica.store_missing("/debian/pool/main/s/sl/sl_9.99-1_amd64.deb").await;
+        assert_eq!(replica.get("/debian/pool/main/s/sl/sl_9.99-1_amd64.deb").await.unwrap().status, warp::http::StatusCode::NOT_FOUND);
+        
+        // Another process on the same disk finds the pool file, which then
+        // reaches its memory layer, but not the index
+        let restarted = CacheManager::from_config(&config);
+        assert_eq!(&restarted.get(pool).await.unwrap().body[..], b"deb");
+        assert!(restarted.get(index).await.is_none());
+        std::fs::remove_dir_all(dir.path()).unwrap();
+        assert_eq!(&restarted.get(pool).await.unwrap().body[..], b"deb");
+    }
+}
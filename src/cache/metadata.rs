@@ -0,0 +1,245 @@
+use anyhow::{Result, anyhow};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use crate::cache::backend::unix_now;
+use crate::cache::redis::{RedisClient, RespValue};
+
+// Deletes the fetch claim only while this replica still holds it
+const RELEASE_SCRIPT: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('del', KEYS[1]) else return 0 end";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedisMetadataConfig {
+    // redis://[[username]:password@]host[:port][/database]
+    pub url: String,
+    pub password_file: Option<String>,
+    // Every key starts with this, so several deployments can share a server
+    pub key_prefix: String,
+    // How long one replica may hold the upstream fetch of a path; the claim
+    // is released as soon as the fetch is done
+    pub fetch_lock_secs: u64,
+    // How long other replicas wait for that fetch before fetching themselves
+    pub fetch_wait_secs: u64,
+}
+
+impl Default for RedisMetadataConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379/0".to_string(),
+            password_file: None,
+            key_prefix: "aptg:".to_string(),
+            fetch_lock_secs: 60,
+            fetch_wait_secs: 30,
+        }
+    }
+}
+
+// What the fleet knows about a cache key: a response stored in a shared
+// layer, or a 404 from upstream (a negative entry)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryMetadata {
+    pub status: u16,
+    pub expires_at: u64,
+    pub size: u64,
+    // Backend holding the body; "none" for negative entries
+    pub layer: String,
+}
+
+impl EntryMetadata {
+    pub fn is_missing(&self) -> bool {
+        self.status == 404
+    }
+}
+
+// Cache metadata and upstream fetch claims in Redis, shared by every replica
+pub struct SharedMetadata {
+    client: RedisClient,
+    config: RedisMetadataConfig,
+    // Identifies this process's fetch claims
+    replica: String,
+}
+
+impl SharedMetadata {
+    pub fn new(config: &RedisMetadataConfig) -> Result<Self> {
+        let password = match &config.password_file {
+            Some(file) => Some(
+                std::fs::read_to_string(file)
+                    .map_err(|e| anyhow!("Failed to read Redis password file {}: {}", file, e))?
+                    .trim()
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let mut replica = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut replica);
+        Ok(Self {
+            client: RedisClient::from_url(&config.url, password)?,
+            config: config.clone(),
+            replica: hex::encode(replica),
+        })
+    }
+
+    pub fn fetch_wait(&self) -> Duration {
+        Duration::from_secs(self.config.fetch_wait_secs)
+    }
+
+    fn key(&self, kind: &str, path: &str) -> String {
+        format!("{}{}:{}", self.config.key_prefix, kind, path)
+    }
+
+    pub async fn lookup(&self, path: &str) -> Result<Option<EntryMetadata>> {
+        match self.client.command(&[b"GET", self.key("entry", path).as_bytes()]).await? {
+            RespValue::Bulk(Some(value)) => {
+                let entry = serde_json::from_slice(&value).map_err(|e| anyhow!("Invalid cache metadata for {}: {}", path, e))?;
+                Ok(Some(entry))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Kept in Redis until the entry expires
+    pub async fn record(&self, path: &str, entry: &EntryMetadata) -> Result<()> {
+        let ttl_ms = entry.expires_at.saturating_sub(unix_now()) * 1000;
+        if ttl_ms == 0 {
+            return Ok(());
+        }
+        let value = serde_json::to_vec(entry)?;
+        self.client
+            .command(&[b"SET", self.key("entry", path).as_bytes(), &value, b"PX", ttl_ms.to_string().as_bytes()])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn forget(&self, path: &str) -> Result<()> {
+        self.client.command(&[b"DEL", self.key("entry", path).as_bytes()]).await?;
+        Ok(())
+    }
+
+    // True when this replica now holds the fetch of path
+    pub async fn try_claim(&self, path: &str) -> Result<bool> {
+        let lock_ms = (self.config.fetch_lock_secs.max(1) * 1000).to_string();
+        let reply = self.client
+            .command(&[b"SET", self.key("fetch", path).as_bytes(), self.replica.as_bytes(), b"NX", b"PX", lock_ms.as_bytes()])
+            .await?;
+        Ok(reply == RespValue::Simple("OK".to_string()))
+    }
+
+    pub async fn release(&self, path: &str) -> Result<()> {
+        self.client
+            .command(&[b"EVAL", RELEASE_SCRIPT.as_bytes(), b"1", self.key("fetch", path).as_bytes(), self.replica.as_bytes()])
+            .await?;
+        Ok(())
+    }
+}
+
+// Held while this replica fetches a path; dropping it lets waiting replicas
+// go ahead, whether or not the fetch ended up in the cache
+pub struct FetchClaim {
+    metadata: Arc<SharedMetadata>,
+    path: String,
+}
+
+impl FetchClaim {
+    pub fn new(metadata: Arc<SharedMetadata>, path: &str) -> Self {
+        Self { metadata, path: path.to_string() }
+    }
+}
+
+impl Drop for FetchClaim {
+    fn drop(&mut self) {
+        let metadata = self.metadata.clone();
+        let path = std::mem::take(&mut self.path);
+        tokio::spawn(async move {
+            if let Err(e) = metadata.release(&path).await {
+                warn!("Fetch claim on {} not released: {}", path, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // Just enough of Redis for the commands above; expiry is not modelled
+    async fn fake_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let data = Arc::new(std::sync::Mutex::new(HashMap::<Vec<u8>, Vec<u8>>::new()));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let data = data.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    let mut line = String::new();
+                    while stream.read_line(&mut line).await.unwrap_or(0) > 0 {
+                        let count: usize = line.trim()[1..].parse().unwrap();
+                        let mut args = Vec::new();
+                        for _ in 0..count {
+                            line.clear();
+                            stream.read_line(&mut line).await.unwrap();
+                            let mut arg = vec![0u8; line.trim()[1..].parse::<usize>().unwrap() + 2];
+                            stream.read_exact(&mut arg).await.unwrap();
+                            arg.truncate(arg.len() - 2);
+                            args.push(arg);
+                        }
+                        line.clear();
+                        let reply = {
+                            let mut data = data.lock().unwrap();
+                            match args[0].as_slice() {
+                                b"GET" => match data.get(&args[1]) {
+                                    Some(value) => format!("${}\r\n{}\r\n", value.len(), String::from_utf8_lossy(value)),
+                                    None => "$-1\r\n".to_string(),
+                                },
+                                b"SET" if args.iter().any(|a| a == b"NX") && data.contains_key(&args[1]) => "$-1\r\n".to_string(),
+                                b"SET" => {
+                                    data.insert(args[1].clone(), args[2].clone());
+                                    "+OK\r\n".to_string()
+                                }
+                                b"DEL" => format!(":{}\r\n", data.remove(&args[1]).map_or(0, |_| 1)),
+                                b"EVAL" if data.get(&args[3]) == Some(&args[4]) => format!(":{}\r\n", data.remove(&args[3]).map_or(0, |_| 1)),
+                                b"EVAL" => ":0\r\n".to_string(),
+                                _ => "-ERR unknown command\r\n".to_string(),
+                            }
+                        };
+                        stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        format!("redis://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_entries_and_claims() {
+        let config = RedisMetadataConfig { url: fake_redis().await, ..Default::default() };
+        let first = Arc::new(SharedMetadata::new(&config).unwrap());
+        let second = SharedMetadata::new(&config).unwrap();
+        let path = "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb";
+        
+        assert_eq!(first.lookup(path).await.unwrap(), None);
+        let entry = EntryMetadata { status: 200, expires_at: unix_now() + 60, size: 3, layer: "object_store".to_string() };
+        first.record(path, &entry).await.unwrap();
+        assert_eq!(second.lookup(path).await.unwrap(), Some(entry));
+        second.forget(path).await.unwrap();
+        assert_eq!(first.lookup(path).await.unwrap(), None);
+        
+        assert!(first.try_claim(path).await.unwrap());
+        assert!(!second.try_claim(path).await.unwrap());
+        // Only the holder's release counts
+        second.release(path).await.unwrap();
+        assert!(!second.try_claim(path).await.unwrap());
+        drop(FetchClaim::new(first.clone(), path));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(second.try_claim(path).await.unwrap());
+        
+        assert!(RedisClient::from_url("rediss://cache:6380", None).is_err());
+    }
+}
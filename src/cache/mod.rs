@@ -1 +1,5 @@
+pub mod backend;
 pub mod cache;
+pub mod metadata;
+pub mod object_store;
+pub mod redis;
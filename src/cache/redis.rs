@@ -0,0 +1,138 @@
+use anyhow::{Result, anyhow};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+// Idle connections kept for reuse; busier moments open more
+const MAX_IDLE: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum RespValue {
+    Simple(String),
+    Integer(i64),
+    // None for a nil reply, e.g. GET of a missing key or a SET NX that did not set
+    Bulk(Option<Vec<u8>>),
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+}
+
+impl Connection {
+    async fn send(&mut self, args: &[&[u8]]) -> Result<RespValue> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        self.stream.get_mut().write_all(&request).await?;
+        self.read_value().await
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("Redis closed the connection"));
+        }
+        Ok(line.trim_end_matches("\r\n").to_string())
+    }
+
+    // Only the reply types of the commands aptg sends; arrays are not needed
+    async fn read_value(&mut self) -> Result<RespValue> {
+        let line = self.read_line().await?;
+        let (kind, rest) = line.split_at(line.len().min(1));
+        match kind {
+            "+" => Ok(RespValue::Simple(rest.to_string())),
+            "-" => Err(anyhow!("Redis error: {}", rest)),
+            ":" => Ok(RespValue::Integer(rest.parse().map_err(|_| anyhow!("Invalid Redis integer: {}", rest))?)),
+            "$" => {
+                let length: i64 = rest.parse().map_err(|_| anyhow!("Invalid Redis bulk length: {}", rest))?;
+                if length < 0 {
+                    return Ok(RespValue::Bulk(None));
+                }
+                let mut data = vec![0u8; length as usize + 2];
+                self.stream.read_exact(&mut data).await?;
+                data.truncate(length as usize);
+                Ok(RespValue::Bulk(Some(data)))
+            }
+            _ => Err(anyhow!("Unexpected Redis reply: {}", line)),
+        }
+    }
+}
+
+// A small RESP2 client for the few commands shared cache metadata needs.
+// Connections are pooled; one that fails mid-command is dropped
+pub struct RedisClient {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: u32,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl RedisClient {
+    // redis://[[username]:password@]host[:port][/database]
+    pub fn from_url(url: &str, password: Option<String>) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid Redis URL: {}", e))?;
+        if parsed.scheme() != "redis" {
+            return Err(anyhow!("Redis URL must start with redis:// (TLS is not supported)"));
+        }
+        let host = parsed.host_str().ok_or_else(|| anyhow!("Redis URL has no host"))?;
+        let database = match parsed.path().trim_matches('/') {
+            "" => 0,
+            database => database.parse().map_err(|_| anyhow!("Invalid Redis database: {}", database))?,
+        };
+        Ok(Self {
+            address: format!("{}:{}", host, parsed.port().unwrap_or(6379)),
+            username: Some(parsed.username()).filter(|u| !u.is_empty()).map(str::to_string),
+            password: password.or_else(|| parsed.password().map(str::to_string)),
+            database,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Redis at {}: {}", self.address, e))?;
+        let mut connection = Connection { stream: BufReader::new(stream) };
+        if let Some(password) = &self.password {
+            match &self.username {
+                Some(username) => connection.send(&[b"AUTH", username.as_bytes(), password.as_bytes()]).await?,
+                None => connection.send(&[b"AUTH", password.as_bytes()]).await?,
+            };
+        }
+        if self.database != 0 {
+            connection.send(&[b"SELECT", self.database.to_string().as_bytes()]).await?;
+        }
+        Ok(connection)
+    }
+
+    pub async fn command(&self, args: &[&[u8]]) -> Result<RespValue> {
+        let pooled = self.idle.lock().await.pop();
+        let mut connection = match pooled {
+            Some(connection) => connection,
+            None => tokio::time::timeout(COMMAND_TIMEOUT, self.connect())
+                .await
+                .map_err(|_| anyhow!("Timed out connecting to Redis at {}", self.address))??,
+        };
+        let reply = tokio::time::timeout(COMMAND_TIMEOUT, connection.send(args))
+            .await
+            .map_err(|_| anyhow!("Redis command timed out"))?;
+        // An error reply leaves the connection usable; a broken one is dropped
+        let usable = match &reply {
+            Ok(_) => true,
+            Err(e) => e.to_string().starts_with("Redis error:"),
+        };
+        if usable {
+            let mut idle = self.idle.lock().await;
+            if idle.len() < MAX_IDLE {
+                idle.push(connection);
+            }
+        }
+        reply
+    }
+}
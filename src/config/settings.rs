@@ -0,0 +1,370 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use tracing::{info, warn};
+use crate::audit::log::AuditConfig;
+use crate::cache::cache::CacheConfig;
+use crate::geoip::policy::GeoPolicy;
+use crate::metrics::downloads::DownloadStatsConfig;
+use crate::mirror::chaos::ChaosConfig;
+use crate::mirror::local::LocalPackagesConfig;
+use crate::mirror::snapshot::SnapshotConfig;
+use crate::notify::webhook::NotificationsConfig;
+use crate::policy::rules::PolicyConfig;
+use crate::telemetry::otel::TelemetryConfig;
+use crate::server::auth::{AdminConfig, AdminScope};
+use crate::server::headers::SecurityHeadersConfig;
+use crate::server::repo_auth::RepositoryAuthConfig;
+use crate::server::tenant::TenantConfig;
+use crate::tls::pinning::{parse_pin, SpkiHash};
+use crate::tls::simple_server::TlsServerConfig;
+use crate::verify::keyring::VerificationConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryConfig {
+    // First path segment served by aptg, e.g. "ubuntu" for /ubuntu/dists/...
+    pub name: String,
+    // Upstream URL the rest of the path is appended to
+    pub upstream: String,
+    // Accepted upstream public keys (base64 SHA-256 of the SPKI). When set, the
+    // upstream must also present one of them, not just a chain to a trusted CA
+    #[serde(default)]
+    pub pinned_spki: Vec<String>,
+    // Credentials clients must present; without it the repository is public
+    #[serde(default)]
+    pub auth: Option<RepositoryAuthConfig>,
+    // Serve InRelease signed with the [verification.signing] key instead of
+    // the upstream's signature, which clients then no longer need to trust
+    #[serde(default)]
+    pub resign: bool,
+}
+
+impl RepositoryConfig {
+    pub fn spki_pins(&self) -> Result<Vec<SpkiHash>> {
+        if !self.pinned_spki.is_empty() && !self.upstream.starts_with("https://") {
+            return Err(anyhow!("Repository '{}' pins upstream keys but its upstream is not https", self.name));
+        }
+        self.pinned_spki.iter().map(|pin| parse_pin(pin)).collect()
+    }
+}
+
+// One address aptg accepts connections on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    // "0.0.0.0:8080", "[::]:8080", or an address alone to use port / https_port
+    pub address: String,
+    // On an IPv6 address, refuse IPv4 connections so an IPv4 listener can
+    // share the port; otherwise [::] accepts both
+    pub ipv6_only: bool,
+    // Connections the kernel queues before aptg accepts them
+    pub backlog: u32,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0".to_string(),
+            ipv6_only: false,
+            backlog: 1024,
+        }
+    }
+}
+
+impl ListenerConfig {
+    pub fn socket_addr(&self, default_port: u16) -> Result<SocketAddr> {
+        if let Ok(addr) = self.address.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+        let host: IpAddr = self.address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|e| anyhow!("Invalid listen address '{}': {}", self.address, e))?;
+        Ok(SocketAddr::new(host, default_port))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    // Listened on when no [[server.listeners]] are configured
+    pub host: String,
+    pub port: u16,
+    pub https_port: u16,
+    // Serve HTTPS on https_port instead of HTTP on port, using the [tls] section.
+    // Plain HTTP is not offered alongside, since it would bypass client certificates
+    pub enable_https: bool,
+    // Security headers on HTML and JSON responses, per listener
+    pub http_headers: SecurityHeadersConfig,
+    pub https_headers: SecurityHeadersConfig,
+    // Addresses to bind, e.g. "0.0.0.0" and "[::]" with ipv6_only for dual-stack.
+    // All of them serve HTTPS or all plain HTTP, as enable_https says
+    pub listeners: Vec<ListenerConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            https_port: 8443,
+            enable_https: false,
+            http_headers: SecurityHeadersConfig::default(),
+            https_headers: SecurityHeadersConfig::https(),
+            listeners: vec![],
+        }
+    }
+}
+
+impl ServerConfig {
+    // Each listener with the address it binds
+    pub fn listeners(&self) -> Result<Vec<(SocketAddr, ListenerConfig)>> {
+        let port = if self.enable_https { self.https_port } else { self.port };
+        if self.listeners.is_empty() {
+            let host: IpAddr = self.host
+                .parse()
+                .map_err(|e| anyhow!("Invalid server host '{}': {}", self.host, e))?;
+            return Ok(vec![(SocketAddr::new(host, port), ListenerConfig { address: self.host.clone(), ..Default::default() })]);
+        }
+        let mut listeners: Vec<(SocketAddr, ListenerConfig)> = Vec::new();
+        for listener in &self.listeners {
+            let addr = listener.socket_addr(port)?;
+            if listeners.iter().any(|(other, _)| *other == addr) {
+                return Err(anyhow!("Listen address {} is configured twice", addr));
+            }
+            listeners.push((addr, listener.clone()));
+        }
+        Ok(listeners)
+    }
+
+    pub fn security_headers(&self) -> &SecurityHeadersConfig {
+        if self.enable_https { &self.https_headers } else { &self.http_headers }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub repositories: Vec<RepositoryConfig>,
+    // Standalone policy TOML that replaces the [policy] section when set
+    pub policy_file: Option<String>,
+    pub policy_hot_reload: bool,
+    // Add X-Aptg-* headers explaining policy and GeoIP decisions to responses
+    pub decision_headers: bool,
+    // Proxies (addresses or CIDRs) allowed to set X-Forwarded-For / X-Real-IP
+    pub trusted_proxies: Vec<String>,
+    pub server: ServerConfig,
+    pub tls: TlsServerConfig,
+    pub cache: CacheConfig,
+    pub policy: PolicyConfig,
+    pub verification: VerificationConfig,
+    pub geoip: GeoPolicy,
+    pub audit: AuditConfig,
+    pub telemetry: TelemetryConfig,
+    pub admin: AdminConfig,
+    // Customers served under /t/<tenant>/<repository>/..., each with its own
+    // upstreams, policy, quota and audit log
+    pub tenants: Vec<TenantConfig>,
+    // Pinned copies of verified suites, served under /snapshots/<id>/<repository>/...
+    pub snapshots: SnapshotConfig,
+    // Uploaded .deb files served under an extra component of a mirrored suite
+    pub local_packages: LocalPackagesConfig,
+    // Webhooks alerted on operational events such as an upstream going down
+    pub notifications: NotificationsConfig,
+    // Package downloads per day, suite and architecture, for /admin/stats/top-packages
+    pub download_stats: DownloadStatsConfig,
+    // Upstream faults injected for testing, with the chaos feature
+    pub chaos: ChaosConfig,
+    #[serde(skip)]
+    pub config_path: Option<String>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            repositories: vec![RepositoryConfig {
+                name: "debian".to_string(),
+                upstream: "https://deb.debian.org/debian".to_string(),
+                pinned_spki: vec![],
+                auth: None,
+                resign: false,
+            }],
+            policy_file: None,
+            policy_hot_reload: false,
+            decision_headers: false,
+            trusted_proxies: vec![],
+            server: ServerConfig::default(),
+            tls: TlsServerConfig::default(),
+            cache: CacheConfig::default(),
+            policy: PolicyConfig::default(),
+            verification: VerificationConfig::default(),
+            geoip: GeoPolicy::default(),
+            audit: AuditConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            admin: AdminConfig::default(),
+            tenants: vec![],
+            snapshots: SnapshotConfig::default(),
+            local_packages: LocalPackagesConfig::default(),
+            notifications: NotificationsConfig::default(),
+            download_stats: DownloadStatsConfig::default(),
+            chaos: ChaosConfig::default(),
+            config_path: None,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn load_from_file(config_path: &str) -> Result<Self> {
+        let config_content = std::fs::read_to_string(config_path)
+            .map_err(|e| anyhow!("Failed to read config file {}: {}", config_path, e))?;
+        let mut config: AppConfig = toml::from_str(&config_content)
+            .map_err(|e| anyhow!("Failed to parse config file {}: {}", config_path, e))?;
+        config.config_path = Some(config_path.to_string());
+        for repository in &config.repositories {
+            repository.spki_pins()?;
+            if let Some(auth) = &repository.auth {
+                auth.validate(&repository.name)?;
+            }
+        }
+        for mirror in &config.geoip.mirrors {
+            mirror.validate()?;
+            if !config.repositories.iter().any(|r| r.name == mirror.repository) {
+                return Err(anyhow!("GeoIP mirror {} is for unknown repository '{}'", mirror.url, mirror.repository));
+            }
+        }
+        config.geoip.validate()?;
+        for feed in &config.geoip.reputation.feeds {
+            feed.validate()?;
+        }
+        config.server.listeners()?;
+        config.admin.validate()?;
+        for (index, tenant) in config.tenants.iter().enumerate() {
+            tenant.validate()?;
+            if config.tenants[..index].iter().any(|other| other.name == tenant.name) {
+                return Err(anyhow!("Tenant '{}' is configured twice", tenant.name));
+            }
+        }
+        if !config.tenants.is_empty() && config.repositories.iter().any(|r| r.name == "t") {
+            return Err(anyhow!("Repository name 't' is reserved for tenant paths"));
+        }
+        let mut all_repositories = config.repositories.iter().chain(config.tenants.iter().flat_map(|t| &t.repositories));
+        if config.snapshots.enabled && all_repositories.clone().any(|r| r.name == "snapshots") {
+            return Err(anyhow!("Repository name 'snapshots' is reserved for snapshot paths"));
+        }
+        // Only a payload whose upstream signature checked out is ever re-signed
+        if let Some(repository) = all_repositories.find(|r| r.resign) {
+            if config.verification.signing.secret_key_file.is_none() || !config.verification.enable_gpg_verification {
+                return Err(anyhow!(
+                    "Repository '{}' is re-signed, which needs [verification.signing] secret_key_file and enable_gpg_verification",
+                    repository.name
+                ));
+            }
+        }
+        
+        // The merged Release no longer matches the upstream signature
+        if config.local_packages.enabled {
+            config.local_packages.validate()?;
+            let repository = &config.local_packages.repository;
+            if !config.repositories.iter().any(|r| &r.name == repository && r.resign) {
+                return Err(anyhow!("Local packages need repository '{}' to be configured with resign = true", repository));
+            }
+            // Uploads are signed with the site key, so they are never open to anyone
+            if !config.admin.has_scope(AdminScope::Mutate) {
+                return Err(anyhow!("Local packages need an [[admin.tokens]] entry with scope = \"mutate\" to upload with"));
+            }
+        }
+        for webhook in &config.notifications.webhooks {
+            reqwest::Url::parse(&webhook.url).map_err(|e| anyhow!("Invalid URL for webhook '{}': {}", webhook.name, e))?;
+        }
+        config.chaos.validate()?;
+        for keyring in &config.verification.keyrings {
+            keyring.validate()?;
+        }
+        for rule in &config.verification.deb_signatures {
+            rule.validate()?;
+        }
+        
+        if let Some(policy_file) = &config.policy_file {
+            config.policy = PolicyConfig::load_from_file(policy_file)?;
+            info!("Policy loaded from {}", policy_file);
+        }
+        // The same checks as a reload, so startup does not skip what a reload rejects
+        config.policy.validate()?;
+        
+        info!("Configuration loaded from {}", config_path);
+        Ok(config)
+    }
+
+    pub fn load_or_default(config_path: &str) -> Result<Self> {
+        if Path::new(config_path).exists() {
+            Self::load_from_file(config_path)
+        } else {
+            warn!("Config file {} not found, using defaults", config_path);
+            Ok(Self::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verification_keyrings() {
+        let config: AppConfig = toml::from_str(r#"
+[verification]
+gpg_keyring_path = "/etc/debian-archive-keyring.gpg"
+enable_gpg_verification = true
+
+[[verification.keyrings]]
+repository = "ubuntu"
+keyring_path = "/usr/share/keyrings/ubuntu-archive-keyring.gpg"
+"#).unwrap();
+
+        assert!(config.verification.enable_gpg_verification);
+        assert_eq!(config.verification.keyrings.len(), 1);
+        assert!(config.verification.keyrings[0].suites.is_empty());
+    }
+
+    #[test]
+    fn test_missing_config_uses_defaults() {
+        let config = AppConfig::load_or_default("/nonexistent/config.toml").unwrap();
+        assert_eq!(config.verification.gpg_keyring_path, "/etc/debian-archive-keyring.gpg");
+    }
+
+    #[test]
+    fn test_shipped_config_parses() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml");
+        let config = AppConfig::load_from_file(path).unwrap();
+        assert!(config.policy.allow.suites.contains(&"bookworm".to_string()));
+        assert!(!config.policy.advisories.enabled);
+        assert_eq!(config.repositories[0].name, "debian");
+        assert_eq!(config.server.listeners().unwrap()[0].0, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(config.tls.ca_path.as_deref(), Some("certs/ca.pem"));
+    }
+
+    #[test]
+    fn test_listeners() {
+        let mut server: ServerConfig = toml::from_str(r#"
+port = 8080
+[[listeners]]
+address = "0.0.0.0"
+[[listeners]]
+address = "[::]"
+ipv6_only = true
+[[listeners]]
+address = "[::1]:9090"
+"#).unwrap();
+        let listeners = server.listeners().unwrap();
+        let addrs: Vec<SocketAddr> = listeners.iter().map(|(addr, _)| *addr).collect();
+        assert_eq!(addrs, ["0.0.0.0:8080".parse().unwrap(), "[::]:8080".parse().unwrap(), "[::1]:9090".parse().unwrap()]);
+        assert!(listeners[1].1.ipv6_only);
+        
+        server.listeners.push(ListenerConfig { address: "::".to_string(), ..Default::default() });
+        assert!(server.listeners().is_err());
+        server.listeners = vec![ListenerConfig { address: "localhost".to_string(), ..Default::default() }];
+        assert!(server.listeners().is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use hashlink::LruCache;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::geoip::location::LocationInfo;
+
+// apt clients send dozens of requests per update run, so lookups are cached
+// per network prefix; MaxMind data is rarely more precise than a /24, and a
+// /56 keeps IPv6 privacy-extension addresses of one site in a single entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeoCacheConfig {
+    pub enabled: bool,
+    // Maximum number of cached prefixes
+    pub size: usize,
+    pub ttl_secs: u64,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+}
+
+impl Default for GeoCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            size: 10_000,
+            ttl_secs: 3600,
+            ipv4_prefix: 24,
+            ipv6_prefix: 56,
+        }
+    }
+}
+
+struct CachedLookup {
+    stored: Instant,
+    location: Option<LocationInfo>,
+}
+
+pub struct LookupCache {
+    config: GeoCacheConfig,
+    entries: Mutex<LruCache<IpAddr, CachedLookup>>,
+}
+
+impl LookupCache {
+    pub fn new(config: &GeoCacheConfig) -> Option<Self> {
+        (config.enabled && config.size > 0).then(|| Self {
+            config: config.clone(),
+            entries: Mutex::new(LruCache::new(config.size)),
+        })
+    }
+
+    fn key(&self, ip: IpAddr) -> IpAddr {
+        let prefix = match ip {
+            IpAddr::V4(_) => self.config.ipv4_prefix,
+            IpAddr::V6(_) => self.config.ipv6_prefix,
+        };
+        IpNet::new(ip, prefix).map(|net| net.network()).unwrap_or(ip)
+    }
+
+    // Some(None) is a cached miss: the database has no record for the prefix
+    pub fn get(&self, ip: IpAddr, now: Instant) -> Option<Option<LocationInfo>> {
+        let key = self.key(ip);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = entries.get(&key)?;
+        if now.saturating_duration_since(cached.stored) >= Duration::from_secs(self.config.ttl_secs) {
+            entries.remove(&key);
+            return None;
+        }
+        // Neighbours in the prefix share the location, not the address
+        Some(cached.location.clone().map(|location| LocationInfo { ip_address: ip.to_string(), ..location }))
+    }
+
+    pub fn insert(&self, ip: IpAddr, location: Option<LocationInfo>, now: Instant) {
+        let key = self.key(ip);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key, CachedLookup { stored: now, location });
+    }
+
+    // Called when a new database is loaded
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_by_prefix_with_ttl() {
+        let config = GeoCacheConfig { size: 2, ttl_secs: 60, ..Default::default() };
+        let cache = LookupCache::new(&config).unwrap();
+        let now = Instant::now();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        
+        cache.insert(ip("192.0.2.10"), Some(LocationInfo::new("192.0.2.10", "DE", "Germany")), now);
+        let neighbour = cache.get(ip("192.0.2.200"), now).unwrap().unwrap();
+        assert_eq!((neighbour.ip_address.as_str(), neighbour.country_code.as_str()), ("192.0.2.200", "DE"));
+        assert!(cache.get(ip("192.0.3.1"), now).is_none());
+        
+        cache.insert(ip("2001:db8:1:100::1"), None, now);
+        assert!(matches!(cache.get(ip("2001:db8:1:1ff:a1b2:c3d4:e5f6:1"), now), Some(None)));
+        // The least recently used prefix is evicted past the size limit
+        cache.insert(ip("198.51.100.1"), None, now);
+        assert!(cache.get(ip("192.0.2.10"), now).is_none());
+        assert!(cache.get(ip("198.51.100.1"), now + Duration::from_secs(60)).is_none());
+        assert!(LookupCache::new(&GeoCacheConfig { enabled: false, ..config }).is_none());
+    }
+}
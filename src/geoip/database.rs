@@ -9,12 +9,35 @@ use chrono::{DateTime, Utc};
 use crate::geoip::location::LocationInfo;
 use std::collections::BTreeMap;
 // use geoip2::City; // Removed to avoid dependency issues
+// City databases also carry cities, regions and coordinates; Country
+// databases only the country and continent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseEdition {
+    City,
+    Country,
+}
+
+impl DatabaseEdition {
+    // From the metadata type, e.g. GeoLite2-City, GeoIP2-Country or GeoIP2-Enterprise
+    pub fn detect(database_type: &str) -> Result<Self> {
+        if database_type.contains("City") || database_type.contains("Enterprise") {
+            Ok(DatabaseEdition::City)
+        } else if database_type.contains("Country") {
+            Ok(DatabaseEdition::Country)
+        } else {
+            Err(anyhow!("GeoIP2 database type {} has no country data", database_type))
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseInfo {
     pub path: String,
     pub size_bytes: u64,
     pub build_epoch: u32,
     pub database_type: String,
+    pub edition: DatabaseEdition,
     pub languages: Vec<String>,
     pub last_updated: DateTime<Utc>,
     pub record_count: u64,
@@ -25,6 +48,8 @@ struct ModelCity<'a> {
     #[serde(borrow)]
     city: Option<ModelRecord<'a>>,
     #[serde(borrow)]
+    continent: Option<ModelContinent<'a>>,
+    #[serde(borrow)]
     country: Option<ModelCountry<'a>>,
     location: Option<ModelLocation>,
     #[serde(borrow)]
@@ -40,10 +65,17 @@ struct ModelRecord<'a> {
 #[derive(Deserialize, Debug)]
 struct ModelCountry<'a> {
     iso_code: Option<&'a str>,
+    // Only present, and true, for EU member states
+    is_in_european_union: Option<bool>,
     #[serde(borrow)]
     names: Option<BTreeMap<&'a str, &'a str>>,
 }
 
+#[derive(Deserialize, Debug)]
+struct ModelContinent<'a> {
+    code: Option<&'a str>,
+}
+
 #[derive(Deserialize, Debug)]
 struct ModelLocation {
     latitude: Option<f64>,
@@ -72,7 +104,7 @@ impl GeoIpDatabase {
         let info = Self::extract_database_info(&reader, database_path)?;
         
         info!("GeoIP2 database loaded successfully");
-        info!("  Type: {}", info.database_type);
+        info!("  Type: {} ({:?} edition)", info.database_type, info.edition);
         info!("  Size: {} bytes", info.size_bytes);
         info!("  Records: {}", info.record_count);
         info!("  Languages: {:?}", info.languages);
@@ -96,7 +128,21 @@ impl GeoIpDatabase {
                     .map(|s| *s) // Map &&str to &str
                     .unwrap_or("Unknown");
 
-                let location = LocationInfo::new(ip_address, iso_code, country_name);
+                let continent_code = city.continent.as_ref()
+                    .and_then(|c| c.code)
+                    .unwrap_or("");
+                
+                let in_eu = city.country.as_ref()
+                    .and_then(|c| c.is_in_european_union)
+                    .unwrap_or(false);
+                
+                let location = LocationInfo::new(ip_address, iso_code, country_name)
+                    .with_continent(continent_code)
+                    .with_european_union(in_eu);
+                
+                if self.info.edition == DatabaseEdition::Country {
+                    return Ok(Some(location));
+                }
                 
                 let lat = city.location.as_ref().and_then(|l| l.latitude).unwrap_or(0.0);
                 let lon = city.location.as_ref().and_then(|l| l.longitude).unwrap_or(0.0);
@@ -145,9 +191,11 @@ impl GeoIpDatabase {
             path: path.to_string(),
             size_bytes,
             build_epoch: metadata.build_epoch as u32,
-            database_type: "GeoIP2-City".to_string(),
+            database_type: metadata.database_type.clone(),
+            edition: DatabaseEdition::detect(&metadata.database_type)?,
             languages: metadata.languages.iter().map(|l| l.to_string()).collect(),
-            last_updated: Utc::now(), // In a real implementation, you'd parse this from metadata
+            // MaxMind rebuilds weekly, so the build time tells how stale the data is
+            last_updated: DateTime::from_timestamp(metadata.build_epoch as i64, 0).unwrap_or_else(Utc::now),
             record_count: 0, // This would need to be calculated or stored separately
         })
     }
@@ -176,6 +224,54 @@ impl GeoIpDatabase {
     }
 }
 
+// GeoIP2 Anonymous-IP flags. Hosting providers alone do not count as anonymous
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ModelAnonymousIp {
+    is_anonymous: bool,
+    is_anonymous_vpn: bool,
+    is_public_proxy: bool,
+    is_residential_proxy: bool,
+    is_tor_exit_node: bool,
+}
+
+impl ModelAnonymousIp {
+    fn is_anonymous(&self) -> bool {
+        self.is_anonymous || self.is_anonymous_vpn || self.is_public_proxy || self.is_residential_proxy || self.is_tor_exit_node
+    }
+}
+
+// Marks VPN, proxy and Tor clients, which the City and Country databases
+// cannot tell apart
+pub struct AnonymousIpDatabase {
+    reader: Reader<Vec<u8>>,
+    path: String,
+}
+
+impl AnonymousIpDatabase {
+    pub fn new(database_path: &str) -> Result<Self> {
+        let reader = Reader::open_readfile(database_path)
+            .map_err(|e| anyhow!("Failed to load Anonymous-IP database {}: {}", database_path, e))?;
+        if !reader.metadata.database_type.contains("Anonymous-IP") {
+            return Err(anyhow!("{} is a {} database, not Anonymous-IP", database_path, reader.metadata.database_type));
+        }
+        info!("Anonymous-IP database loaded from {}", database_path);
+        Ok(Self { reader, path: database_path.to_string() })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Addresses without a record are not anonymous
+    pub fn is_anonymous(&self, ip: std::net::IpAddr) -> bool {
+        self.reader
+            .lookup::<ModelAnonymousIp>(ip)
+            .map(|record| record.is_anonymous())
+            .unwrap_or(false)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -200,4 +296,29 @@ mod tests {
         let parsed: Result<std::net::IpAddr, _> = ip.parse();
         assert!(parsed.is_ok());
     }
+
+    #[test]
+    fn test_edition_detection() {
+        assert_eq!(DatabaseEdition::detect("GeoLite2-City").unwrap(), DatabaseEdition::City);
+        assert_eq!(DatabaseEdition::detect("GeoIP2-Enterprise").unwrap(), DatabaseEdition::City);
+        assert_eq!(DatabaseEdition::detect("GeoLite2-Country").unwrap(), DatabaseEdition::Country);
+        assert!(DatabaseEdition::detect("GeoLite2-ASN").is_err());
+    }
+
+    #[test]
+    fn test_eu_membership_flag() {
+        let country: ModelCountry = serde_json::from_str(r#"{"iso_code": "DE", "is_in_european_union": true}"#).unwrap();
+        assert_eq!(country.is_in_european_union, Some(true));
+        let country: ModelCountry = serde_json::from_str(r#"{"iso_code": "CH"}"#).unwrap();
+        assert_eq!(country.is_in_european_union, None);
+    }
+
+    #[test]
+    fn test_anonymous_ip_flags() {
+        let record = |json: &str| serde_json::from_str::<ModelAnonymousIp>(json).unwrap().is_anonymous();
+        assert!(record(r#"{"is_anonymous": true, "is_anonymous_vpn": true}"#));
+        assert!(record(r#"{"is_tor_exit_node": true}"#));
+        assert!(!record(r#"{"is_hosting_provider": true}"#));
+        assert!(AnonymousIpDatabase::new("/nonexistent/GeoIP2-Anonymous-IP.mmdb").is_err());
+    }
 }
@@ -0,0 +1,111 @@
+use anyhow::{Result, anyhow};
+use std::net::IpAddr;
+
+// Country-only ranges for when no MaxMind database is available. Enough for
+// country allow/deny rules; cities, continents and coordinates stay unknown
+pub struct EmbeddedCountries {
+    // Sorted, non-overlapping (first, last, country); IPv4 as mapped IPv6
+    ranges: Vec<(u128, u128, String)>,
+}
+
+fn address_key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+impl EmbeddedCountries {
+    pub fn parse(csv: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || anyhow!("Invalid country range on line {}: {}", number + 1, line);
+            let mut fields = line.split(',').map(|field| field.trim().trim_matches('"'));
+            let (Some(first), Some(last), Some(country)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid());
+            };
+            let first: IpAddr = first.parse().map_err(|_| invalid())?;
+            let last: IpAddr = last.parse().map_err(|_| invalid())?;
+            if first.is_ipv4() != last.is_ipv4() || address_key(first) > address_key(last) || country.len() != 2 {
+                return Err(invalid());
+            }
+            ranges.push((address_key(first), address_key(last), country.to_ascii_uppercase()));
+        }
+        ranges.sort_by_key(|(first, _, _)| *first);
+        
+        let mut merged: Vec<(u128, u128, String)> = Vec::with_capacity(ranges.len());
+        for (first, last, country) in ranges {
+            match merged.last_mut() {
+                Some(previous) if first <= previous.1 => {
+                    return Err(anyhow!("Country ranges overlap at {}", IpAddr::from(std::net::Ipv6Addr::from(first))));
+                }
+                Some(previous) if previous.1.checked_add(1) == Some(first) && previous.2 == country => previous.1 = last,
+                _ => merged.push((first, last, country)),
+            }
+        }
+        Ok(Self { ranges: merged })
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let key = address_key(ip.to_canonical());
+        let index = self.ranges.partition_point(|(first, _, _)| *first <= key).checked_sub(1)?;
+        let (_, last, country) = &self.ranges[index];
+        (key <= *last).then_some(country.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // The ranges in src/geoip/data/countries.csv, parsed on first use. Empty
+    // unless aptg was built with the embedded-geoip feature
+    pub fn embedded() -> Option<&'static Self> {
+        #[cfg(feature = "embedded-geoip")]
+        {
+            static EMBEDDED: std::sync::OnceLock<Option<EmbeddedCountries>> = std::sync::OnceLock::new();
+            EMBEDDED
+                .get_or_init(|| match Self::parse(include_str!("data/countries.csv")) {
+                    Ok(countries) if !countries.is_empty() => Some(countries),
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::error!("Embedded GeoIP country data is unusable: {}", e);
+                        None
+                    }
+                })
+                .as_ref()
+        }
+        #[cfg(not(feature = "embedded-geoip"))]
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_merged_ranges() {
+        let csv = "# comment\n1.0.0.0,1.0.0.255,au\n1.0.1.0,1.0.3.255,AU\n\"2.16.0.0\",\"2.16.5.255\",\"DE\"\n2001:db8::,2001:db8:ffff:ffff:ffff:ffff:ffff:ffff,NL\n";
+        let countries = EmbeddedCountries::parse(csv).unwrap();
+        assert_eq!(countries.len(), 3);
+        
+        let lookup = |ip: &str| countries.lookup(ip.parse().unwrap());
+        assert_eq!(lookup("1.0.2.7"), Some("AU"));
+        assert_eq!(lookup("2.16.5.255"), Some("DE"));
+        assert_eq!(lookup("::ffff:2.16.0.1"), Some("DE"));
+        assert_eq!(lookup("2001:db8::1"), Some("NL"));
+        assert_eq!(lookup("2.16.6.0"), None);
+        assert_eq!(lookup("0.255.255.255"), None);
+        
+        assert!(EmbeddedCountries::parse("1.0.0.0,1.0.0.255,AU\n1.0.0.128,1.0.1.255,NZ\n").is_err());
+        assert!(EmbeddedCountries::parse("1.0.0.255,1.0.0.0,AU\n").is_err());
+    }
+}
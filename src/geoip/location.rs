@@ -76,6 +76,11 @@ impl LocationInfo {
         self
     }
 
+    pub fn with_european_union(mut self, in_eu: bool) -> Self {
+        self.is_in_european_union = in_eu;
+        self
+    }
+
     pub fn is_in_country(&self, country_code: &str) -> bool {
         self.country_code == country_code
     }
@@ -218,9 +223,50 @@ pub struct LocationStats {
     pub country_counts: HashMap<String, u64>,
     pub city_counts: HashMap<String, u64>,
     pub continent_counts: HashMap<String, u64>,
+    // GeoIP policy decisions by action label (allow, deny, ratelimit, ...)
+    #[serde(default)]
+    pub action_counts: HashMap<String, u64>,
+    // Where the first client placed in each country was, for the dashboard map
+    #[serde(default)]
+    pub country_positions: HashMap<String, (f64, f64)>,
+    pub last_updated: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NamedCount {
+    pub name: String,
+    pub requests: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapPoint {
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub requests: u64,
+}
+
+// What /admin/geo/stats returns
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationStatsSummary {
+    pub total_requests: u64,
+    pub countries: Vec<NamedCount>,
+    pub cities: Vec<NamedCount>,
+    pub continents: Vec<NamedCount>,
+    pub actions: HashMap<String, u64>,
+    // Every country with a known position, not just the top ones
+    pub map: Vec<MapPoint>,
     pub last_updated: chrono::DateTime<chrono::Utc>,
 }
 
+fn top(counts: &HashMap<String, u64>, limit: usize) -> Vec<(&String, &u64)> {
+    let mut top: Vec<_> = counts.iter().collect();
+    // Ties are broken by name so repeated calls agree
+    top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    top.truncate(limit);
+    top
+}
+
 impl LocationStats {
     pub fn new() -> Self {
         Self {
@@ -228,34 +274,74 @@ impl LocationStats {
             country_counts: HashMap::new(),
             city_counts: HashMap::new(),
             continent_counts: HashMap::new(),
+            action_counts: HashMap::new(),
+            country_positions: HashMap::new(),
             last_updated: chrono::Utc::now(),
         }
     }
 
-    pub fn record_request(&mut self, location: &LocationInfo) {
+    // Cities and continents the database does not know are left out
+    pub fn record_request(&mut self, location: &LocationInfo, action: &str) {
         self.total_requests += 1;
         
         *self.country_counts.entry(location.country_code.clone()).or_insert(0) += 1;
+        // 0,0 is what lookups without coordinates report
+        if location.latitude != 0.0 || location.longitude != 0.0 {
+            self.country_positions.entry(location.country_code.clone()).or_insert((location.latitude, location.longitude));
+        }
         
-        if let Some(ref city) = location.city {
+        if let Some(city) = location.city.as_ref().filter(|city| *city != "Unknown") {
             *self.city_counts.entry(city.clone()).or_insert(0) += 1;
         }
         
-        *self.continent_counts.entry(location.continent_code.clone()).or_insert(0) += 1;
+        if !location.continent_code.is_empty() {
+            *self.continent_counts.entry(location.continent_code.clone()).or_insert(0) += 1;
+        }
+        
+        *self.action_counts.entry(action.to_string()).or_insert(0) += 1;
         
         self.last_updated = chrono::Utc::now();
     }
 
     pub fn get_top_countries(&self, limit: usize) -> Vec<(&String, &u64)> {
-        let mut countries: Vec<_> = self.country_counts.iter().collect();
-        countries.sort_by(|a, b| b.1.cmp(a.1));
-        countries.into_iter().take(limit).collect()
+        top(&self.country_counts, limit)
     }
 
     pub fn get_top_cities(&self, limit: usize) -> Vec<(&String, &u64)> {
-        let mut cities: Vec<_> = self.city_counts.iter().collect();
-        cities.sort_by(|a, b| b.1.cmp(a.1));
-        cities.into_iter().take(limit).collect()
+        top(&self.city_counts, limit)
+    }
+
+    pub fn get_top_continents(&self, limit: usize) -> Vec<(&String, &u64)> {
+        top(&self.continent_counts, limit)
+    }
+
+    pub fn map_points(&self) -> Vec<MapPoint> {
+        let mut points: Vec<MapPoint> = self.country_positions
+            .iter()
+            .map(|(country, (latitude, longitude))| MapPoint {
+                country: country.clone(),
+                latitude: *latitude,
+                longitude: *longitude,
+                requests: self.country_counts.get(country).copied().unwrap_or(0),
+            })
+            .collect();
+        points.sort_by(|a, b| a.country.cmp(&b.country));
+        points
+    }
+
+    pub fn summary(&self, limit: usize) -> LocationStatsSummary {
+        let named = |counts: Vec<(&String, &u64)>| {
+            counts.into_iter().map(|(name, requests)| NamedCount { name: name.clone(), requests: *requests }).collect()
+        };
+        LocationStatsSummary {
+            total_requests: self.total_requests,
+            countries: named(self.get_top_countries(limit)),
+            cities: named(self.get_top_cities(limit)),
+            continents: named(self.get_top_continents(limit)),
+            actions: self.action_counts.clone(),
+            map: self.map_points(),
+            last_updated: self.last_updated,
+        }
     }
 }
 
@@ -0,0 +1,113 @@
+use anyhow::{Result, anyhow};
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::config::settings::AppConfig;
+use crate::geoip::policy::{GeoPolicyEngine, PolicyResult};
+use crate::geoip::reputation::ReputationScorer;
+
+const USAGE: &str = "usage: aptg geoip lookup [--config <config.toml>] [--json] <ip>...";
+
+#[derive(Debug, Default)]
+struct LookupOptions {
+    config_path: Option<String>,
+    json: bool,
+    addresses: Vec<IpAddr>,
+}
+
+impl LookupOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => options.config_path = Some(args.next().cloned().ok_or_else(|| anyhow!("--config needs a value\n{}", USAGE))?),
+                "--json" => options.json = true,
+                _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+                _ => options.addresses.push(arg.parse().map_err(|_| anyhow!("Invalid IP address '{}'", arg))?),
+            }
+        }
+        
+        if options.addresses.is_empty() {
+            return Err(anyhow!(USAGE));
+        }
+        Ok(options)
+    }
+}
+
+pub fn format_result(result: &PolicyResult, risk_score: u8) -> Result<String> {
+    let location = serde_json::to_string_pretty(&result.location)?;
+    Ok(format!(
+        "{}\n  action:     {}\n  rule:       {}\n  reason:     {}\n  risk score: {}\n  location:   {}",
+        result.location.ip_address,
+        result.action,
+        result.rule_name.as_deref().unwrap_or("-"),
+        result.reason,
+        risk_score,
+        location.replace('\n', "\n  "),
+    ))
+}
+
+// Evaluates the configured [geoip] policy for each address as the server
+// would, so a block can be explained without replaying traffic
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = LookupOptions::parse(args)?;
+    let config_path = options.config_path.clone()
+        .unwrap_or_else(|| std::env::var("APTG_CONFIG").unwrap_or_else(|_| "config.toml".to_string()));
+    let mut policy = AppConfig::load_from_file(&config_path)?.geoip;
+    if !policy.enabled {
+        eprintln!("note: GeoIP is disabled in {}; evaluating its rules anyway", config_path);
+        policy.enabled = true;
+    }
+
+    let mut engine = GeoPolicyEngine::new(policy.clone());
+    if !engine.is_enabled() {
+        return Err(anyhow!("GeoIP database {} could not be loaded", policy.database_path));
+    }
+    if !policy.reputation.feeds.is_empty() {
+        let scorer = Arc::new(ReputationScorer::new(&policy.reputation)?);
+        scorer.refresh().await;
+        engine = engine.with_scorer(scorer);
+    }
+
+    for address in &options.addresses {
+        let result = engine.check_request(&address.to_string(), "/")?;
+        if options.json {
+            let mut value = serde_json::to_value(&result)?;
+            value["risk_score"] = engine.risk_score(&result.location).into();
+            println!("{}", value);
+        } else {
+            println!("{}", format_result(&result, engine.risk_score(&result.location))?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geoip::location::LocationInfo;
+    use crate::geoip::policy::GeoAction;
+
+    #[test]
+    fn test_parse_and_format() {
+        let args: Vec<String> = ["--json", "192.0.2.1", "2001:db8::1"].iter().map(|arg| arg.to_string()).collect();
+        let options = LookupOptions::parse(&args).unwrap();
+        assert!(options.json);
+        assert_eq!(options.addresses.len(), 2);
+        assert!(LookupOptions::parse(&["example.org".to_string()]).is_err());
+        assert!(LookupOptions::parse(&[]).is_err());
+        
+        let result = PolicyResult {
+            action: GeoAction::Deny,
+            rule_name: Some("blocked_countries".to_string()),
+            location: LocationInfo::new("192.0.2.1", "CN", "China"),
+            reason: "Matched rule: blocked_countries".to_string(),
+        };
+        let output = format_result(&result, 80).unwrap();
+        assert!(output.starts_with("192.0.2.1\n  action:     Deny\n  rule:       blocked_countries\n"));
+        assert!(output.contains("  risk score: 80\n"));
+        assert!(output.contains("    \"country_code\": \"CN\""));
+    }
+}
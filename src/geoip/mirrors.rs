@@ -0,0 +1,139 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::geoip::location::LocationInfo;
+use crate::mirror::latency::{PathClass, UpstreamLatency};
+
+// How clients matched by a NearestMirror rule reach the mirror
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorMode {
+    // 302 to the mirror; the client downloads from it directly
+    #[default]
+    Redirect,
+    // aptg fetches from the mirror instead of the repository upstream
+    Proxy,
+}
+
+// An upstream mirror of one repository, serving clients in the listed ISO
+// country codes or continent codes (EU, NA, AS, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorEntry {
+    pub repository: String,
+    pub url: String,
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub continents: Vec<String>,
+}
+
+impl MirrorEntry {
+    pub fn validate(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.url)
+            .map_err(|e| anyhow!("Invalid mirror URL {} for {}: {}", self.url, self.repository, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("Mirror URL {} for {} must be http or https", self.url, self.repository));
+        }
+        Ok(())
+    }
+}
+
+pub struct MirrorMap {
+    mode: MirrorMode,
+    mirrors: Vec<MirrorEntry>,
+    latency: Option<Arc<UpstreamLatency>>,
+}
+
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default()
+}
+
+impl MirrorMap {
+    pub fn new(mode: MirrorMode, mirrors: &[MirrorEntry]) -> Self {
+        let mirrors = mirrors
+            .iter()
+            .map(|mirror| MirrorEntry { url: mirror.url.trim_end_matches('/').to_string(), ..mirror.clone() })
+            .collect();
+        Self { mode, mirrors, latency: None }
+    }
+
+    // Pool fetch times measured in proxy mode pick between equally near mirrors
+    pub fn with_latency(mut self, latency: Arc<UpstreamLatency>) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    pub fn mode(&self) -> MirrorMode {
+        self.mode
+    }
+
+    // A country match beats a continent match, and among equally near mirrors
+    // the fastest wins; unmeasured ones go first so they get measured. None
+    // keeps the repository upstream
+    pub fn nearest(&self, repository: &str, location: &LocationInfo) -> Option<&str> {
+        let mirrors = || self.mirrors.iter().filter(|mirror| mirror.repository == repository);
+        let by_country: Vec<&MirrorEntry> = mirrors()
+            .filter(|mirror| mirror.countries.iter().any(|code| code.eq_ignore_ascii_case(&location.country_code)))
+            .collect();
+        let candidates = if by_country.is_empty() {
+            mirrors()
+                .filter(|mirror| mirror.continents.iter().any(|code| code.eq_ignore_ascii_case(&location.continent_code)))
+                .collect()
+        } else {
+            by_country
+        };
+        candidates
+            .into_iter()
+            .min_by(|a, b| self.pool_seconds(a).total_cmp(&self.pool_seconds(b)))
+            .map(|mirror| mirror.url.as_str())
+    }
+
+    fn pool_seconds(&self, mirror: &MirrorEntry) -> f64 {
+        self.latency
+            .as_ref()
+            .and_then(|latency| latency.get(&host(&mirror.url), PathClass::Pool))
+            .map_or(0.0, |latency| latency.seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror(repository: &str, url: &str, countries: &[&str], continents: &[&str]) -> MirrorEntry {
+        MirrorEntry {
+            repository: repository.to_string(),
+            url: url.to_string(),
+            countries: countries.iter().map(|c| c.to_string()).collect(),
+            continents: continents.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_nearest_mirror() {
+        let map = MirrorMap::new(MirrorMode::Redirect, &[
+            mirror("debian", "https://ftp.eu.example.org/debian/", &[], &["EU"]),
+            mirror("debian", "https://ftp.de.example.org/debian", &["de", "AT"], &[]),
+            mirror("ubuntu", "https://de.archive.example.org/ubuntu", &["DE"], &[]),
+        ]);
+        let location = |country: &str, continent: &str| LocationInfo::new("192.0.2.1", country, "").with_continent(continent);
+        
+        assert_eq!(map.nearest("debian", &location("DE", "EU")), Some("https://ftp.de.example.org/debian"));
+        assert_eq!(map.nearest("debian", &location("FR", "EU")), Some("https://ftp.eu.example.org/debian"));
+        assert_eq!(map.nearest("ubuntu", &location("FR", "EU")), None);
+        assert_eq!(map.nearest("debian", &location("US", "NA")), None);
+        assert!(mirror("debian", "ftp://ftp.example.org/debian", &[], &[]).validate().is_err());
+        
+        let latency = Arc::new(UpstreamLatency::default());
+        let map = MirrorMap::new(MirrorMode::Proxy, &[
+            mirror("debian", "https://ftp.de.example.org/debian", &["DE"], &[]),
+            mirror("debian", "https://ftp2.de.example.org/debian", &["DE"], &[]),
+        ])
+        .with_latency(latency.clone());
+        assert_eq!(map.nearest("debian", &location("DE", "EU")), Some("https://ftp.de.example.org/debian"));
+        latency.record("ftp.de.example.org", PathClass::Pool, std::time::Duration::from_secs(3), 1_000_000);
+        assert_eq!(map.nearest("debian", &location("DE", "EU")), Some("https://ftp2.de.example.org/debian"));
+        latency.record("ftp2.de.example.org", PathClass::Pool, std::time::Duration::from_secs(5), 1_000_000);
+        assert_eq!(map.nearest("debian", &location("DE", "EU")), Some("https://ftp.de.example.org/debian"));
+    }
+}
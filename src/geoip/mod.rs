@@ -1,3 +1,10 @@
+pub mod cache;
 pub mod database;
+pub mod embedded;
 pub mod location;
+pub mod lookup;
+pub mod mirrors;
 pub mod policy;
+pub mod reload;
+pub mod reputation;
+pub mod updater;
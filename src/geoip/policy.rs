@@ -1,19 +1,63 @@
 use anyhow::{Result, anyhow};
+use arc_swap::ArcSwapOption;
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
-
-
-use tracing::{info, warn, error};
-use crate::geoip::database::GeoIpDatabase;
-use crate::geoip::location::LocationInfo;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+use tracing::{debug, info, warn, error};
+use crate::geoip::cache::{GeoCacheConfig, LookupCache};
+use crate::geoip::database::{AnonymousIpDatabase, DatabaseEdition, GeoIpDatabase};
+use crate::geoip::embedded::EmbeddedCountries;
+use crate::geoip::location::{LocationInfo, LocationStats, LocationStatsSummary};
+use crate::geoip::mirrors::{MirrorEntry, MirrorMode};
+use crate::geoip::reputation::{CountryRiskScorer, ReputationConfig, RiskScorer};
+use crate::geoip::updater::GeoIpUpdateConfig;
+use crate::metrics::registry::Metrics;
+use crate::policy::priority::{select_rule, PrioritizedRule};
+use crate::policy::rules::parse_network;
 use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GeoPolicy {
     pub enabled: bool,
     pub database_path: String,
+    // Loaded when database_path cannot be, typically a GeoLite2-Country
+    // database; country and continent rules keep working
+    pub fallback_database_path: Option<String>,
+    // GeoIP2 Anonymous-IP database; VPN, proxy and Tor clients it lists match
+    // AnonymousProxy conditions
+    pub anonymous_ip_database_path: Option<String>,
+    // Reload the databases when another tool replaces them on disk
+    pub watch_database: bool,
+    // Answer country lookups from the ranges compiled in with the
+    // embedded-geoip feature while no database is loaded
+    pub embedded_fallback: bool,
     pub rules: Vec<GeoRule>,
+    // Shortcuts for the common case; each becomes a Deny rule ahead of the
+    // configured ones. With allowed_countries, clients GeoIP cannot place are denied
+    pub allowed_countries: Vec<String>,
+    pub blocked_countries: Vec<String>,
+    // Addresses or CIDRs decided before any lookup or rule: denied_networks
+    // are refused and allowed_networks let through. Denied wins when both match
+    pub allowed_networks: Vec<String>,
+    pub denied_networks: Vec<String>,
     pub default_action: GeoAction,
+    // How often the updater checks MaxMind for a new database
     pub update_interval_hours: u64,
+    // How often a database missing at startup is looked for again
+    pub load_retry_secs: u64,
+    pub update: GeoIpUpdateConfig,
+    // Where NearestMirror rules send clients, and whether by redirect or proxy
+    pub mirror_mode: MirrorMode,
+    pub mirrors: Vec<MirrorEntry>,
+    pub cache: GeoCacheConfig,
+    // Feeds that replace the per-country risk heuristic for listed clients
+    pub reputation: ReputationConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,11 +69,61 @@ pub struct GeoRule {
     pub enabled: bool,
 }
 
+impl GeoPolicy {
+    pub fn effective_rules(&self) -> Vec<GeoRule> {
+        let shortcut = |name: &str, condition| GeoRule {
+            name: name.to_string(),
+            condition,
+            action: GeoAction::Deny,
+            priority: u8::MAX,
+            enabled: true,
+        };
+        let codes = |codes: &[String]| codes.iter().map(|code| code.to_ascii_uppercase()).collect();
+        let mut rules = Vec::new();
+        if !self.blocked_countries.is_empty() {
+            rules.push(shortcut("blocked_countries", GeoCondition::CountryCode { codes: codes(&self.blocked_countries) }));
+        }
+        if !self.allowed_countries.is_empty() {
+            rules.push(shortcut("allowed_countries", GeoCondition::NotCountryCode { codes: codes(&self.allowed_countries) }));
+        }
+        rules.extend(self.rules.iter().cloned());
+        rules
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for network in self.allowed_networks.iter().chain(&self.denied_networks) {
+            parse_network(network).map_err(|e| anyhow!("GeoIP network lists: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// Internal and loopback clients are never geo-blocked by default
+fn private_networks() -> Vec<String> {
+    ["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "127.0.0.0/8", "::1/128", "fc00::/7"]
+        .iter()
+        .map(|network| network.to_string())
+        .collect()
+}
+
+impl PrioritizedRule for GeoRule {
+    fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum GeoCondition {
     CountryCode { codes: Vec<String> },
+    NotCountryCode { codes: Vec<String> },
     Continent { codes: Vec<String> },
+    // EU member states, for GDPR-driven routing
+    InEu { member: bool },
     Region { regions: Vec<String> },
     City { cities: Vec<String> },
     CountryGroup { groups: Vec<String> },
@@ -43,6 +137,13 @@ pub enum GeoCondition {
     Custom { field: String, operator: String, value: String },
 }
 
+impl GeoCondition {
+    // Country databases leave these fields empty, so such rules never match
+    pub fn needs_city_data(&self) -> bool {
+        matches!(self, GeoCondition::Region { .. } | GeoCondition::City { .. } | GeoCondition::Distance { .. })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum GeoAction {
@@ -51,6 +152,21 @@ pub enum GeoAction {
     RateLimit { requests_per_minute: u32 },
     LogOnly,
     Redirect { url: String },
+    // Pool files go to the closest entry in the mirror map
+    NearestMirror,
+}
+
+impl GeoAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeoAction::Allow => "allow",
+            GeoAction::Deny => "deny",
+            GeoAction::RateLimit { .. } => "ratelimit",
+            GeoAction::LogOnly => "logonly",
+            GeoAction::Redirect { .. } => "redirect",
+            GeoAction::NearestMirror => "nearest_mirror",
+        }
+    }
 }
 
 impl fmt::Display for GeoAction {
@@ -61,6 +177,7 @@ impl fmt::Display for GeoAction {
             GeoAction::RateLimit { requests_per_minute } => write!(f, "RateLimit({} req/min)", requests_per_minute),
             GeoAction::LogOnly => write!(f, "LogOnly"),
             GeoAction::Redirect { url } => write!(f, "Redirect({})", url),
+            GeoAction::NearestMirror => write!(f, "NearestMirror"),
         }
     }
 }
@@ -80,31 +197,118 @@ pub struct PolicyResult {
 }
 
 pub struct GeoPolicyEngine {
-    database: Option<GeoIpDatabase>,
+    // Swapped in place when the updater installs a new database
+    database: ArcSwapOption<GeoIpDatabase>,
+    anonymous_ip: ArcSwapOption<AnonymousIpDatabase>,
+    embedded: Option<&'static EmbeddedCountries>,
+    cache: Option<LookupCache>,
+    stats: Mutex<LocationStats>,
+    // Decisions per matched rule name, DEFAULT_RULE when none matched
+    rule_matches: Mutex<HashMap<String, u64>>,
+    scorer: Arc<dyn RiskScorer>,
+    allowed_networks: Vec<IpNet>,
+    denied_networks: Vec<IpNet>,
     policy: GeoPolicy,
 }
 
+fn load_database(policy: &GeoPolicy) -> Result<GeoIpDatabase> {
+    let database = match (GeoIpDatabase::new(&policy.database_path), &policy.fallback_database_path) {
+        (Ok(database), _) => database,
+        (Err(e), Some(fallback)) => {
+            warn!("Failed to load GeoIP database: {}; falling back to {}", e, fallback);
+            GeoIpDatabase::new(fallback)?
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    let info = database.get_info();
+    if info.edition == DatabaseEdition::Country {
+        let city_rules: Vec<&str> = policy.rules.iter()
+            .filter(|rule| rule.enabled && rule.condition.needs_city_data())
+            .map(|rule| rule.name.as_str())
+            .collect();
+        if !city_rules.is_empty() {
+            warn!("{} is a {} database without city data; rules {} will not match", info.path, info.database_type, city_rules.join(", "));
+        }
+    }
+    Ok(database)
+}
+
+fn load_anonymous_ip_database(policy: &GeoPolicy) -> Result<Option<Arc<AnonymousIpDatabase>>> {
+    policy.anonymous_ip_database_path
+        .as_deref()
+        .map(|path| AnonymousIpDatabase::new(path).map(Arc::new))
+        .transpose()
+}
+
 impl GeoPolicyEngine {
-    pub fn new(policy: GeoPolicy) -> Self {
+    pub fn new(mut policy: GeoPolicy) -> Self {
+        policy.rules = policy.effective_rules();
         let database = if policy.enabled {
-            match GeoIpDatabase::new(&policy.database_path) {
-                Ok(db) => Some(db),
+            match load_database(&policy) {
+                Ok(db) => Some(Arc::new(db)),
                 Err(e) => {
                     error!("Failed to load GeoIP database: {}", e);
-                    warn!("GeoIP policy will be disabled");
+                    warn!("GeoIP policy is not enforced until the database loads");
                     None
                 }
             }
         } else {
             None
         };
+        let anonymous_ip = if policy.enabled {
+            load_anonymous_ip_database(&policy).unwrap_or_else(|e| {
+                error!("Failed to load Anonymous-IP database: {}", e);
+                None
+            })
+        } else {
+            None
+        };
 
+        let embedded = (policy.enabled && policy.embedded_fallback).then(EmbeddedCountries::embedded).flatten();
+        if let (None, Some(embedded)) = (&database, embedded) {
+            info!("Answering GeoIP country lookups from {} embedded ranges until a database loads", embedded.len());
+        }
+
+        // Invalid entries are rejected when the config is loaded
+        let networks = |networks: &[String]| networks.iter().filter_map(|network| parse_network(network).ok()).collect();
         Self {
-            database,
+            database: ArcSwapOption::new(database),
+            anonymous_ip: ArcSwapOption::new(anonymous_ip),
+            embedded,
+            cache: LookupCache::new(&policy.cache),
+            stats: Mutex::new(LocationStats::new()),
+            rule_matches: Mutex::new(HashMap::new()),
+            scorer: Arc::new(CountryRiskScorer),
+            allowed_networks: networks(&policy.allowed_networks),
+            denied_networks: networks(&policy.denied_networks),
             policy,
         }
     }
 
+    pub fn with_scorer(mut self, scorer: Arc<dyn RiskScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    // Keeps looking for a database that was missing at startup and starts
+    // enforcing once one loads
+    pub fn spawn_load_retry(self: Arc<Self>) {
+        if !self.policy.enabled || self.database.load().is_some() {
+            return;
+        }
+        let interval = Duration::from_secs(self.policy.load_retry_secs.max(1));
+        tokio::spawn(async move {
+            while self.database.load().is_none() {
+                tokio::time::sleep(interval).await;
+                match self.reload_database() {
+                    Ok(()) => info!("GeoIP database loaded, enforcing GeoIP policy"),
+                    Err(e) => debug!("GeoIP database still unavailable: {}", e),
+                }
+            }
+        });
+    }
+
     pub fn check_request(&self, ip_address: &str, _path: &str) -> Result<PolicyResult> {
         if !self.policy.enabled {
             return Ok(PolicyResult {
@@ -115,29 +319,25 @@ impl GeoPolicyEngine {
             });
         }
 
-        let database = self.database.as_ref()
-            .ok_or_else(|| anyhow!("GeoIP database not available"))?;
-
-        let location = database.lookup(ip_address)?
-            .unwrap_or_else(|| LocationInfo::new(ip_address, "Unknown", "Unknown"));
-
-        // Check rules in priority order
-        let mut matching_rule = None;
-        let mut highest_priority = 0;
-
-        for rule in &self.policy.rules {
-            if !rule.enabled {
-                continue;
-            }
+        // Checked before the database, so these hold even while it is missing
+        if let Some(result) = self.check_networks(ip_address) {
+            self.record_request(&result);
+            return Ok(result);
+        }
 
-            if self.evaluate_condition(&rule.condition, &location) {
-                if rule.priority > highest_priority {
-                    matching_rule = Some(rule);
-                    highest_priority = rule.priority;
-                }
-            }
+        let mut location = match self.database.load_full() {
+            Some(database) => self.lookup(&database, ip_address)?,
+            None => self.embedded_lookup(ip_address)?,
+        }
+        .unwrap_or_else(|| LocationInfo::new(ip_address, "Unknown", "Unknown"));
+        // Per address rather than cached per prefix: one VPN exit does not
+        // make its neighbours anonymous
+        if let Some(anonymous_ip) = self.anonymous_ip.load().as_ref() {
+            location.is_anonymous_proxy = ip_address.parse().is_ok_and(|ip| anonymous_ip.is_anonymous(ip));
         }
 
+        let matching_rule = select_rule(&self.policy.rules, |rule| self.evaluate_condition(&rule.condition, &location));
+
         let (action, rule_name, reason) = if let Some(rule) = matching_rule {
             (rule.action.clone(), Some(rule.name.clone()), format!("Matched rule: {}", rule.name))
         } else {
@@ -146,22 +346,85 @@ impl GeoPolicyEngine {
 
         info!("GeoIP policy check for {}: {} - {}", ip_address, action, reason);
 
-        Ok(PolicyResult {
+        let result = PolicyResult {
             action,
             rule_name,
             location,
             reason,
+        };
+        self.record_request(&result);
+        Ok(result)
+    }
+
+    pub fn risk_score(&self, location: &LocationInfo) -> u8 {
+        self.scorer.score(location)
+    }
+
+    pub fn record_request(&self, result: &PolicyResult) {
+        let rule = result.rule_name.as_deref().unwrap_or(DEFAULT_RULE);
+        Metrics::global().geoip_rule_matches.with_label_values(&[rule, result.action.as_str()]).inc();
+        *self.rule_matches.lock().unwrap_or_else(|e| e.into_inner()).entry(rule.to_string()).or_insert(0) += 1;
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).record_request(&result.location, result.action.as_str());
+    }
+
+    pub fn location_stats(&self, limit: usize) -> LocationStatsSummary {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).summary(limit)
+    }
+
+    fn check_networks(&self, ip_address: &str) -> Option<PolicyResult> {
+        let ip: IpAddr = ip_address.parse().ok()?;
+        let (action, list) = if self.denied_networks.iter().any(|network| network.contains(&ip)) {
+            (GeoAction::Deny, "denied_networks")
+        } else if self.allowed_networks.iter().any(|network| network.contains(&ip)) {
+            (GeoAction::Allow, "allowed_networks")
+        } else {
+            return None;
+        };
+        info!("GeoIP policy check for {}: {} - listed in {}", ip_address, action, list);
+        Some(PolicyResult {
+            action,
+            rule_name: Some(list.to_string()),
+            location: LocationInfo::new(ip_address, "Unknown", "Unknown"),
+            reason: format!("Listed in {}", list),
         })
     }
 
+    fn embedded_lookup(&self, ip_address: &str) -> Result<Option<LocationInfo>> {
+        let embedded = self.embedded.ok_or_else(|| anyhow!("GeoIP database not available"))?;
+        let ip: IpAddr = ip_address.parse()
+            .map_err(|e| anyhow!("Invalid IP address {}: {}", ip_address, e))?;
+        Ok(embedded.lookup(ip).map(|country| LocationInfo::new(ip_address, country, country)))
+    }
+
+    fn lookup(&self, database: &GeoIpDatabase, ip_address: &str) -> Result<Option<LocationInfo>> {
+        let Some(cache) = &self.cache else {
+            return database.lookup(ip_address);
+        };
+        let ip: IpAddr = ip_address.parse()
+            .map_err(|e| anyhow!("Invalid IP address {}: {}", ip_address, e))?;
+        let now = Instant::now();
+        if let Some(location) = cache.get(ip, now) {
+            return Ok(location);
+        }
+        let location = database.lookup(ip_address)?;
+        cache.insert(ip, location.clone(), now);
+        Ok(location)
+    }
+
     fn evaluate_condition(&self, condition: &GeoCondition, location: &LocationInfo) -> bool {
         match condition {
             GeoCondition::CountryCode { codes } => {
                 codes.contains(&location.country_code)
             }
+            GeoCondition::NotCountryCode { codes } => {
+                !codes.contains(&location.country_code)
+            }
             GeoCondition::Continent { codes } => {
                 codes.contains(&location.continent_code)
             }
+            GeoCondition::InEu { member } => {
+                *member == location.is_in_eu()
+            }
             GeoCondition::Region { regions } => {
                 if let Some(ref region) = location.region {
                     regions.iter().any(|r| r.to_lowercase() == region.to_lowercase())
@@ -180,7 +443,7 @@ impl GeoPolicyEngine {
                 groups.contains(&location.get_country_grouping())
             }
             GeoCondition::RiskScore { min, max } => {
-                let score = location.get_risk_score();
+                let score = self.scorer.score(location);
                 min.map_or(true, |m| score >= m) && max.map_or(true, |m| score <= m)
             }
             GeoCondition::Distance { latitude, longitude, radius_km } => {
@@ -224,8 +487,9 @@ impl GeoPolicyEngine {
             "postal_code" => location.postal_code.clone().unwrap_or_default(),
             "timezone" => location.timezone.clone().unwrap_or_default(),
             "continent_code" => location.continent_code.clone(),
+            "is_in_european_union" => location.is_in_european_union.to_string(),
             "country_grouping" => location.get_country_grouping(),
-            "risk_score" => location.get_risk_score().to_string(),
+            "risk_score" => self.scorer.score(location).to_string(),
             _ => return false,
         };
 
@@ -243,40 +507,59 @@ impl GeoPolicyEngine {
         }
     }
 
-    pub fn reload_database(&mut self) -> Result<()> {
-        if let Some(ref mut database) = self.database {
-            database.reload()?;
+    // Also loads a database that was missing at startup; lookups in flight
+    // keep the previous one
+    pub fn reload_database(&self) -> Result<()> {
+        if self.policy.enabled {
+            let database = load_database(&self.policy)?;
+            self.database.store(Some(Arc::new(database)));
+            if let Some(anonymous_ip) = load_anonymous_ip_database(&self.policy)? {
+                self.anonymous_ip.store(Some(anonymous_ip));
+            }
+            if let Some(cache) = &self.cache {
+                cache.clear();
+            }
             info!("GeoIP database reloaded successfully");
         }
         Ok(())
     }
 
     pub fn validate_database(&self) -> Result<()> {
-        if let Some(ref database) = self.database {
+        if let Some(database) = self.database.load_full() {
             database.validate_database()?;
         }
         Ok(())
     }
 
-    pub fn get_database_info(&self) -> Option<&crate::geoip::database::DatabaseInfo> {
-        self.database.as_ref().map(|db| db.get_info())
+    pub fn get_database_info(&self) -> Option<crate::geoip::database::DatabaseInfo> {
+        self.database.load().as_ref().map(|db| db.get_info().clone())
     }
 
+    // Also true while only the embedded country ranges answer lookups
     pub fn is_enabled(&self) -> bool {
-        self.policy.enabled && self.database.is_some()
+        self.policy.enabled && (self.database.load().is_some() || self.embedded.is_some())
     }
 
     pub fn get_policy_stats(&self) -> GeoPolicyStats {
+        // Configured rules are listed even before their first match, so a rule
+        // that never fires shows up as 0
+        let mut rule_matches: BTreeMap<String, u64> = self.policy.rules.iter().map(|r| (r.name.clone(), 0)).collect();
+        rule_matches.extend(self.rule_matches.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(rule, count)| (rule.clone(), *count)));
         GeoPolicyStats {
             enabled: self.policy.enabled,
-            database_loaded: self.database.is_some(),
+            database_loaded: self.database.load().is_some(),
             total_rules: self.policy.rules.len(),
             enabled_rules: self.policy.rules.iter().filter(|r| r.enabled).count(),
             default_action: self.policy.default_action.clone(),
+            rule_matches,
+            action_counts: self.stats.lock().unwrap_or_else(|e| e.into_inner()).action_counts.clone(),
         }
     }
 }
 
+// Where get_policy_stats counts decisions no rule matched
+pub const DEFAULT_RULE: &str = "default";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeoPolicyStats {
     pub enabled: bool,
@@ -284,6 +567,9 @@ pub struct GeoPolicyStats {
     pub total_rules: usize,
     pub enabled_rules: usize,
     pub default_action: GeoAction,
+    // Decisions since startup by rule name and by action
+    pub rule_matches: BTreeMap<String, u64>,
+    pub action_counts: HashMap<String, u64>,
 }
 
 impl Default for GeoPolicy {
@@ -291,6 +577,14 @@ impl Default for GeoPolicy {
         Self {
             enabled: false,
             database_path: "geoip/GeoLite2-City.mmdb".to_string(),
+            fallback_database_path: None,
+            anonymous_ip_database_path: None,
+            watch_database: true,
+            embedded_fallback: true,
+            allowed_countries: vec![],
+            blocked_countries: vec![],
+            allowed_networks: private_networks(),
+            denied_networks: vec![],
             rules: vec![
                 GeoRule {
                     name: "Block high-risk countries".to_string(),
@@ -309,6 +603,12 @@ impl Default for GeoPolicy {
             ],
             default_action: GeoAction::Allow,
             update_interval_hours: 24,
+            load_retry_secs: 60,
+            update: GeoIpUpdateConfig::default(),
+            mirror_mode: MirrorMode::default(),
+            mirrors: vec![],
+            cache: GeoCacheConfig::default(),
+            reputation: ReputationConfig::default(),
         }
     }
 }
@@ -334,5 +634,102 @@ mod tests {
         let location = LocationInfo::new("8.8.8.8", "CN", "China");
         let result = engine.evaluate_condition(&policy.rules[0].condition, &location);
         assert!(result);
+
+        let eu = GeoCondition::InEu { member: true };
+        assert!(!engine.evaluate_condition(&eu, &location));
+        assert!(engine.evaluate_condition(&eu, &LocationInfo::new("192.0.2.1", "DE", "Germany").with_european_union(true)));
+    }
+
+    #[test]
+    fn test_country_shortcuts() {
+        let policy = GeoPolicy {
+            rules: vec![],
+            allowed_countries: vec!["de".to_string(), "AT".to_string(), "CH".to_string()],
+            blocked_countries: vec!["CH".to_string()],
+            ..Default::default()
+        };
+        let engine = GeoPolicyEngine::new(policy);
+        let decide = |country: &str| {
+            let location = LocationInfo::new("192.0.2.1", country, "");
+            select_rule(&engine.policy.rules, |rule| engine.evaluate_condition(&rule.condition, &location)).map(|rule| rule.name.clone())
+        };
+
+        assert_eq!(decide("DE"), None);
+        assert_eq!(decide("FR").as_deref(), Some("allowed_countries"));
+        assert_eq!(decide("Unknown").as_deref(), Some("allowed_countries"));
+        assert_eq!(decide("CH").as_deref(), Some("blocked_countries"));
+    }
+
+    #[test]
+    fn test_network_lists_bypass_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = GeoPolicy {
+            enabled: true,
+            database_path: dir.path().join("missing.mmdb").to_str().unwrap().to_string(),
+            denied_networks: vec!["192.168.66.0/24".to_string(), "203.0.113.7".to_string()],
+            ..Default::default()
+        };
+        policy.validate().unwrap();
+        let engine = GeoPolicyEngine::new(policy);
+        let check = |ip: &str| engine.check_request(ip, "/debian/dists/bookworm/InRelease").map(|result| result.action.as_str());
+
+        assert_eq!(check("192.168.1.10").unwrap(), "allow");
+        assert_eq!(check("fd00::1").unwrap(), "allow");
+        assert_eq!(check("192.168.66.1").unwrap(), "deny");
+        assert_eq!(check("203.0.113.7").unwrap(), "deny");
+        // Everyone else still needs the database
+        assert!(check("203.0.113.8").is_err());
+
+        let invalid = GeoPolicy { allowed_networks: vec!["10.0.0.0/33".to_string()], ..Default::default() };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_location_stats() {
+        let engine = GeoPolicyEngine::new(GeoPolicy::default());
+        let result = |country: &str, city: &str, action: GeoAction| {
+            let mut location = LocationInfo::new("192.0.2.1", country, "").with_city(city);
+            location.continent_code = if country == "US" { "NA" } else { "EU" }.to_string();
+            if city == "Berlin" {
+                location = location.with_coordinates(52.52, 13.40);
+            }
+            PolicyResult { action, rule_name: None, location, reason: String::new() }
+        };
+        engine.record_request(&result("DE", "Berlin", GeoAction::Allow));
+        engine.record_request(&result("DE", "Unknown", GeoAction::Allow));
+        engine.record_request(&result("US", "Boston", GeoAction::Deny));
+        engine.record_request(&result("FR", "Paris", GeoAction::Allow));
+
+        let stats = engine.location_stats(2);
+        assert_eq!(stats.total_requests, 4);
+        let names = |counts: &[crate::geoip::location::NamedCount]| counts.iter().map(|c| (c.name.clone(), c.requests)).collect::<Vec<_>>();
+        assert_eq!(names(&stats.countries), vec![("DE".to_string(), 2), ("FR".to_string(), 1)]);
+        assert_eq!(names(&stats.cities), vec![("Berlin".to_string(), 1), ("Boston".to_string(), 1)]);
+        assert_eq!(names(&stats.continents), vec![("EU".to_string(), 3), ("NA".to_string(), 1)]);
+        assert_eq!(stats.actions["allow"], 3);
+        assert_eq!(stats.actions["deny"], 1);
+        assert_eq!(stats.map.len(), 1);
+        assert_eq!((stats.map[0].country.as_str(), stats.map[0].latitude, stats.map[0].requests), ("DE", 52.52, 2));
+    }
+
+    #[test]
+    fn test_rule_match_counts() {
+        let engine = GeoPolicyEngine::new(GeoPolicy::default());
+        let result = |rule: Option<&str>, action: GeoAction| PolicyResult {
+            action,
+            rule_name: rule.map(str::to_string),
+            location: LocationInfo::new("192.0.2.1", "CN", ""),
+            reason: String::new(),
+        };
+        engine.record_request(&result(Some("Block high-risk countries"), GeoAction::Deny));
+        engine.record_request(&result(Some("Block high-risk countries"), GeoAction::Deny));
+        engine.record_request(&result(None, GeoAction::Allow));
+
+        let stats = engine.get_policy_stats();
+        assert_eq!(stats.rule_matches["Block high-risk countries"], 2);
+        assert_eq!(stats.rule_matches["Rate limit suspicious regions"], 0);
+        assert_eq!(stats.rule_matches[DEFAULT_RULE], 1);
+        assert_eq!(stats.action_counts["deny"], 2);
+        assert!(Metrics::global().geoip_rule_matches.with_label_values(&["Block high-risk countries", "deny"]).get() >= 2);
     }
 }
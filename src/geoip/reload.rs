@@ -0,0 +1,112 @@
+use anyhow::{Result, anyhow};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, error};
+use crate::geoip::policy::{GeoPolicy, GeoPolicyEngine};
+
+// Reloads the GeoIP databases when an external updater (geoipupdate, cron,
+// configuration management) replaces them
+pub struct GeoIpReloader {
+    engine: Arc<GeoPolicyEngine>,
+    paths: Vec<String>,
+}
+
+impl GeoIpReloader {
+    pub fn new(policy: &GeoPolicy, engine: Arc<GeoPolicyEngine>) -> Self {
+        let paths = [Some(&policy.database_path), policy.fallback_database_path.as_ref(), policy.anonymous_ip_database_path.as_ref()]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        Self { engine, paths }
+    }
+
+    // The new database is opened and validated before it is swapped in, so
+    // lookups in flight keep the old one and a truncated file is rejected
+    pub fn reload(&self) -> Result<()> {
+        self.engine.reload_database()
+    }
+
+    fn watched_files(&self) -> Result<Vec<(PathBuf, OsString)>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                let file_name = path.file_name().map(|f| f.to_os_string())
+                    .ok_or_else(|| anyhow!("Invalid GeoIP database path {}", path.display()))?;
+                let directory = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                    _ => Path::new(".").to_path_buf(),
+                };
+                Ok((directory, file_name))
+            })
+            .collect()
+    }
+
+    pub fn spawn(self) -> Result<()> {
+        let files = self.watched_files()?;
+        let file_names: HashSet<OsString> = files.iter().map(|(_, name)| name.clone()).collect();
+        let directories: HashSet<PathBuf> = files.into_iter().map(|(directory, _)| directory).collect();
+        
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let relevant = event.kind.is_modify() || event.kind.is_create();
+                if relevant && event.paths.iter().any(|p| p.file_name().is_some_and(|f| file_names.contains(f))) {
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+        // Watch the directories: updaters write a temporary file and rename it,
+        // and the database may not exist yet at startup
+        for directory in &directories {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        }
+        info!("Watching {} for GeoIP database changes", self.paths.join(", "));
+        
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            while rx.recv().await.is_some() {
+                // Databases are tens of megabytes; tools copying in place emit a
+                // stream of writes, so wait for them to stop
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                while rx.try_recv().is_ok() {}
+                
+                if let Err(e) = self.reload() {
+                    error!("Rejected GeoIP database change, keeping current database: {}", e);
+                }
+            }
+        });
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_database_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let database_path = dir.path().join("GeoLite2-City.mmdb");
+        std::fs::write(&database_path, b"partially written").unwrap();
+        let policy = GeoPolicy {
+            enabled: true,
+            database_path: database_path.to_str().unwrap().to_string(),
+            anonymous_ip_database_path: Some("GeoIP2-Anonymous-IP.mmdb".to_string()),
+            ..Default::default()
+        };
+        let reloader = GeoIpReloader::new(&policy, Arc::new(GeoPolicyEngine::new(policy.clone())));
+        
+        assert!(reloader.reload().is_err());
+        assert!(!reloader.engine.is_enabled());
+        let files = reloader.watched_files().unwrap();
+        assert_eq!(files[0], (dir.path().to_path_buf(), OsString::from("GeoLite2-City.mmdb")));
+        assert_eq!(files[1], (PathBuf::from("."), OsString::from("GeoIP2-Anonymous-IP.mmdb")));
+    }
+}
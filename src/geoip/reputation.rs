@@ -0,0 +1,259 @@
+use anyhow::{Result, anyhow};
+use arc_swap::ArcSwap;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::geoip::location::LocationInfo;
+use crate::policy::rules::parse_network;
+
+// Scores how risky a client is, 0 to 100; RiskScore conditions and the
+// risk_score custom field compare against it
+pub trait RiskScorer: Send + Sync {
+    fn score(&self, location: &LocationInfo) -> u8;
+}
+
+// The built-in per-country heuristic, used when no feeds are configured
+pub struct CountryRiskScorer;
+
+impl RiskScorer for CountryRiskScorer {
+    fn score(&self, location: &LocationInfo) -> u8 {
+        location.get_risk_score()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReputationConfig {
+    pub feeds: Vec<ReputationFeed>,
+    pub refresh_interval_hours: u64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            feeds: vec![],
+            refresh_interval_hours: 6,
+        }
+    }
+}
+
+// A blocklist, Tor exit list or cloud-provider range list: one address or
+// CIDR per line. Text after # or ; is ignored, as are lines that hold no address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationFeed {
+    pub name: String,
+    // http(s) URL or local path
+    pub source: String,
+    // Risk score of listed clients
+    #[serde(default = "default_feed_score")]
+    pub score: u8,
+}
+
+fn default_feed_score() -> u8 {
+    100
+}
+
+impl ReputationFeed {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.source.is_empty() {
+            return Err(anyhow!("Reputation feeds need a name and a source"));
+        }
+        if self.score > 100 {
+            return Err(anyhow!("Reputation feed '{}': score {} is above 100", self.name, self.score));
+        }
+        Ok(())
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.starts_with("http://") || self.source.starts_with("https://")
+    }
+}
+
+pub fn parse_feed(body: &str) -> Vec<IpNet> {
+    body.lines()
+        .filter_map(|line| line.split(['#', ';']).next()?.split_whitespace().next())
+        .filter_map(|entry| parse_network(entry).ok())
+        .map(|network| network.trunc())
+        .collect()
+}
+
+// Networks are keyed by their truncated form, so a lookup tries each prefix
+// length present instead of scanning every entry
+#[derive(Default)]
+struct ReputationList {
+    networks: HashMap<IpNet, u8>,
+    prefix_lengths: BTreeSet<(bool, u8)>,
+}
+
+impl ReputationList {
+    fn insert(&mut self, network: IpNet, score: u8) {
+        self.prefix_lengths.insert((network.addr().is_ipv4(), network.prefix_len()));
+        let entry = self.networks.entry(network).or_insert(score);
+        *entry = (*entry).max(score);
+    }
+
+    fn score(&self, ip: IpAddr) -> Option<u8> {
+        self.prefix_lengths
+            .iter()
+            .filter(|(ipv4, _)| *ipv4 == ip.is_ipv4())
+            .filter_map(|(_, prefix_len)| IpNet::new(ip, *prefix_len).ok())
+            .filter_map(|network| self.networks.get(&network.trunc()).copied())
+            .max()
+    }
+}
+
+// Listed clients get the highest score of the feeds listing them; everyone
+// else keeps the country heuristic
+pub struct ReputationScorer {
+    feeds: Vec<ReputationFeed>,
+    // Last good entries per feed, so one failing download keeps the rest
+    loaded: Mutex<Vec<Option<Vec<IpNet>>>>,
+    list: ArcSwap<ReputationList>,
+    fallback: Box<dyn RiskScorer>,
+    client: reqwest::Client,
+}
+
+impl ReputationScorer {
+    pub fn new(config: &ReputationConfig) -> Result<Self> {
+        for feed in &config.feeds {
+            feed.validate()?;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .user_agent("aptg/0.1.0")
+            .build()?;
+        Ok(Self {
+            feeds: config.feeds.clone(),
+            loaded: Mutex::new(vec![None; config.feeds.len()]),
+            list: ArcSwap::from_pointee(ReputationList::default()),
+            fallback: Box::new(CountryRiskScorer),
+            client,
+        })
+    }
+
+    async fn load(&self, feed: &ReputationFeed) -> Result<String> {
+        if !feed.is_remote() {
+            return tokio::fs::read_to_string(&feed.source)
+                .await
+                .map_err(|e| anyhow!("Failed to read reputation feed {}: {}", feed.source, e));
+        }
+        let response = self.client
+            .get(&feed.source)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to download reputation feed {}: {}", feed.source, e))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Download of reputation feed {} failed with {}", feed.source, response.status()));
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| anyhow!("Failed to download reputation feed {}: {}", feed.source, e))
+    }
+
+    // Returns the number of listed networks after the refresh
+    pub async fn refresh(&self) -> usize {
+        let mut fetched = Vec::with_capacity(self.feeds.len());
+        for feed in &self.feeds {
+            match self.load(feed).await {
+                Ok(body) => {
+                    let networks = parse_feed(&body);
+                    info!("Reputation feed '{}' lists {} networks", feed.name, networks.len());
+                    fetched.push(Some(networks));
+                }
+                Err(e) => {
+                    warn!("Reputation feed '{}' not refreshed: {}", feed.name, e);
+                    fetched.push(None);
+                }
+            }
+        }
+        
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list = ReputationList::default();
+        for ((slot, networks), feed) in loaded.iter_mut().zip(fetched).zip(&self.feeds) {
+            if networks.is_some() {
+                *slot = networks;
+            }
+            for network in slot.iter().flatten() {
+                list.insert(*network, feed.score);
+            }
+        }
+        let count = list.networks.len();
+        self.list.store(Arc::new(list));
+        count
+    }
+
+    pub fn listed_score(&self, ip: IpAddr) -> Option<u8> {
+        self.list.load().score(ip)
+    }
+
+    // Loads the feeds at startup, then every interval
+    pub fn spawn(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                self.refresh().await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+impl RiskScorer for ReputationScorer {
+    fn score(&self, location: &LocationInfo) -> u8 {
+        location.ip_address.parse()
+            .ok()
+            .and_then(|ip| self.listed_score(ip))
+            .unwrap_or_else(|| self.fallback.score(location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_feed() {
+        let body = "# Tor exit nodes\n192.0.2.7\n198.51.100.0/24 ; SBL123\n2001:db8::/32\nExitAddress\n\n10.1.2.3/8\n";
+        let networks: Vec<String> = parse_feed(body).iter().map(|network| network.to_string()).collect();
+        assert_eq!(networks, vec!["192.0.2.7/32", "198.51.100.0/24", "2001:db8::/32", "10.0.0.0/8"]);
+    }
+
+    #[tokio::test]
+    async fn test_listed_clients_take_feed_score() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocklist = dir.path().join("blocklist.txt");
+        std::fs::write(&blocklist, "198.51.100.0/24\n2001:db8::/32\n").unwrap();
+        let tor = dir.path().join("tor.txt");
+        std::fs::write(&tor, "198.51.100.9\n").unwrap();
+        let feed = |name: &str, source: &std::path::Path, score| ReputationFeed {
+            name: name.to_string(),
+            source: source.to_string_lossy().into_owned(),
+            score,
+        };
+        let config = ReputationConfig {
+            feeds: vec![
+                feed("blocklist", &blocklist, 70),
+                feed("tor", &tor, 90),
+                feed("missing", &dir.path().join("missing.txt"), 100),
+            ],
+            ..Default::default()
+        };
+        let scorer = ReputationScorer::new(&config).unwrap();
+        assert_eq!(scorer.refresh().await, 3);
+        
+        let score = |ip: &str, country: &str| scorer.score(&LocationInfo::new(ip, country, ""));
+        assert_eq!(score("198.51.100.1", "US"), 70);
+        assert_eq!(score("198.51.100.9", "US"), 90);
+        assert_eq!(score("2001:db8::1", "US"), 70);
+        assert_eq!(score("203.0.113.1", "US"), 40);
+        
+        // A feed that fails later keeps its last entries
+        std::fs::remove_file(&tor).unwrap();
+        assert_eq!(scorer.refresh().await, 3);
+        assert_eq!(score("198.51.100.9", "US"), 90);
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use crate::geoip::database::GeoIpDatabase;
+use crate::geoip::policy::GeoPolicyEngine;
+
+// Downloads a MaxMind edition (GeoLite2-City, GeoLite2-Country, ...) into
+// database_path. The license key is read from the config or a secret file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeoIpUpdateConfig {
+    pub enabled: bool,
+    pub edition_id: String,
+    pub license_key: Option<String>,
+    pub license_key_file: Option<String>,
+    pub download_url: String,
+}
+
+impl Default for GeoIpUpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            edition_id: "GeoLite2-City".to_string(),
+            license_key: None,
+            license_key_file: None,
+            download_url: "https://download.maxmind.com/app/geoip_download".to_string(),
+        }
+    }
+}
+
+impl GeoIpUpdateConfig {
+    pub fn license_key(&self) -> Result<String> {
+        if let Some(license_key) = &self.license_key {
+            return Ok(license_key.clone());
+        }
+        if let Some(license_key_file) = &self.license_key_file {
+            let license_key = std::fs::read_to_string(license_key_file)
+                .map_err(|e| anyhow!("Failed to read MaxMind license key file {}: {}", license_key_file, e))?;
+            return Ok(license_key.trim().to_string());
+        }
+        Err(anyhow!("GeoIP updates need license_key or license_key_file"))
+    }
+}
+
+pub struct GeoIpUpdater {
+    config: GeoIpUpdateConfig,
+    database_path: PathBuf,
+    client: reqwest::Client,
+}
+
+impl GeoIpUpdater {
+    pub fn new(config: GeoIpUpdateConfig, database_path: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(300))
+            .user_agent("aptg/0.1.0")
+            .build()?;
+        Ok(Self { config, database_path: PathBuf::from(database_path), client })
+    }
+
+    // reqwest errors carry the URL, which holds the license key
+    async fn fetch(&self, suffix: &str) -> Result<Vec<u8>> {
+        let license_key = self.config.license_key()?;
+        let response = self.client
+            .get(&self.config.download_url)
+            .query(&[("edition_id", self.config.edition_id.as_str()), ("license_key", &license_key), ("suffix", suffix)])
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to download {} {}: {}", self.config.edition_id, suffix, e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Download of {} {} failed with {}", self.config.edition_id, suffix, response.status()));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| anyhow!("Failed to download {} {}: {}", self.config.edition_id, suffix, e.without_url()))?;
+        Ok(body.to_vec())
+    }
+
+    // Returns whether a new database was installed. The published checksum is
+    // compared with the one stored beside the database first, so an unchanged
+    // edition is not downloaded again
+    pub async fn update(&self) -> Result<bool> {
+        let expected = parse_checksum(&self.fetch("tar.gz.sha256").await?)?;
+        let checksum_path = self.database_path.with_extension("mmdb.sha256");
+        let installed = std::fs::read_to_string(&checksum_path).unwrap_or_default();
+        if self.database_path.exists() && installed.trim() == expected {
+            debug!("GeoIP database {} is up to date", self.database_path.display());
+            return Ok(false);
+        }
+        
+        let tarball = self.fetch("tar.gz").await?;
+        let actual = hex::encode(Sha256::digest(&tarball));
+        if actual != expected {
+            return Err(anyhow!("Checksum mismatch for {} download: expected {}, got {}", self.config.edition_id, expected, actual));
+        }
+        let database = extract_database(&tarball, &self.config.edition_id)?;
+        self.install(&database)?;
+        std::fs::write(&checksum_path, &expected)
+            .map_err(|e| anyhow!("Failed to write {}: {}", checksum_path.display(), e))?;
+        
+        info!("Installed {} database {} ({} bytes)", self.config.edition_id, self.database_path.display(), database.len());
+        Ok(true)
+    }
+
+    // Written beside the live file and opened before the rename, so lookups
+    // never see a partial or corrupt database
+    fn install(&self, database: &[u8]) -> Result<()> {
+        if let Some(dir) = self.database_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary = self.database_path.with_extension("mmdb.tmp");
+        std::fs::write(&temporary, database)
+            .map_err(|e| anyhow!("Failed to write {}: {}", temporary.display(), e))?;
+        if let Err(e) = GeoIpDatabase::new(&temporary.to_string_lossy()) {
+            let _ = std::fs::remove_file(&temporary);
+            return Err(anyhow!("Downloaded {} database is unusable: {}", self.config.edition_id, e));
+        }
+        std::fs::rename(&temporary, &self.database_path)
+            .map_err(|e| anyhow!("Failed to replace {}: {}", self.database_path.display(), e))?;
+        Ok(())
+    }
+
+    // Checks at startup, then every interval; a new database is swapped into
+    // the engine without a restart
+    pub fn spawn(self, engine: Arc<GeoPolicyEngine>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                match self.update().await {
+                    Ok(true) => {
+                        if let Err(e) = engine.reload_database() {
+                            warn!("Failed to load updated GeoIP database: {}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => warn!("GeoIP database update failed: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+// MaxMind publishes "<sha256>  <archive name>"
+fn parse_checksum(body: &[u8]) -> Result<String> {
+    let checksum = String::from_utf8_lossy(body)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if checksum.len() != 64 || hex::decode(&checksum).is_err() {
+        return Err(anyhow!("Invalid SHA-256 checksum file"));
+    }
+    Ok(checksum)
+}
+
+// The archive holds a dated directory with the .mmdb plus license files
+pub fn extract_database(tarball: &[u8], edition_id: &str) -> Result<Vec<u8>> {
+    let name = format!("{}.mmdb", edition_id);
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name().is_some_and(|file_name| file_name == name.as_str()) {
+            let mut database = Vec::new();
+            entry.read_to_end(&mut database)?;
+            return Ok(database);
+        }
+    }
+    Err(anyhow!("No {} in the downloaded archive", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::collections::HashMap;
+    use warp::Filter;
+
+    fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_extract_database() {
+        let archive = tarball(&[
+            ("GeoLite2-City_20240102/COPYRIGHT.txt", b"MaxMind"),
+            ("GeoLite2-City_20240102/GeoLite2-City.mmdb", b"database"),
+        ]);
+        assert_eq!(extract_database(&archive, "GeoLite2-City").unwrap(), b"database");
+        assert!(extract_database(&archive, "GeoLite2-Country").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_downloads_leave_database_untouched() {
+        let archive = tarball(&[("GeoLite2-City_20240102/GeoLite2-City.mmdb", b"not a database")]);
+        let checksum = hex::encode(Sha256::digest(&archive));
+        let published = Arc::new(std::sync::Mutex::new(String::new()));
+        let served = published.clone();
+        let routes = warp::query::<HashMap<String, String>>().map(move |query: HashMap<String, String>| {
+            assert_eq!(query.get("license_key").map(String::as_str), Some("s3cret"));
+            match query.get("suffix").map(String::as_str) {
+                Some("tar.gz.sha256") => served.lock().unwrap().clone().into_bytes(),
+                _ => archive.clone(),
+            }
+        });
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        
+        let dir = tempfile::tempdir().unwrap();
+        let database_path = dir.path().join("GeoLite2-City.mmdb");
+        let config = GeoIpUpdateConfig {
+            enabled: true,
+            license_key: Some("s3cret".to_string()),
+            download_url: format!("http://{}/app/geoip_download", addr),
+            ..Default::default()
+        };
+        let updater = GeoIpUpdater::new(config, database_path.to_str().unwrap()).unwrap();
+        
+        *published.lock().unwrap() = format!("{}  GeoLite2-City_20240102.tar.gz\n", "0".repeat(64));
+        let error = updater.update().await.unwrap_err().to_string();
+        assert!(error.contains("Checksum mismatch"), "{}", error);
+        // The checksum matches but the archive holds no usable database
+        *published.lock().unwrap() = format!("{}  GeoLite2-City_20240102.tar.gz\n", checksum);
+        assert!(updater.update().await.is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
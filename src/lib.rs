@@ -1,3 +1,7 @@
+//! The aptg proxy as a library. `RouterBuilder` assembles the warp routes the
+//! binary serves; a service embedding them can pass in its own cache, policy,
+//! audit logger or `Upstream` in place of the ones built from `AppConfig`.
+
 pub mod server;
 pub mod mirror;
 pub mod verify;
@@ -6,3 +10,17 @@ pub mod cache;
 pub mod audit;
 pub mod tls;
 pub mod geoip;
+pub mod config;
+pub mod metrics;
+pub mod notify;
+pub mod telemetry;
+pub mod bench;
+
+pub use audit::log::AuditLogger;
+pub use cache::backend::CacheBackend;
+pub use cache::cache::CacheManager;
+pub use config::settings::AppConfig;
+pub use mirror::fetch::{MirrorFetcher, Upstream, UpstreamResponse};
+pub use policy::reload::SharedPolicy;
+pub use policy::rules::PolicyEngine;
+pub use server::router::RouterBuilder;
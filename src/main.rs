@@ -1,31 +1,45 @@
 use anyhow::Result;
-use std::net::SocketAddr;
+use aptg::{audit, config, geoip, mirror, policy, server, telemetry, tls};
+use std::sync::Arc;
 use tracing::info;
-use tracing_subscriber;
-
-mod server;
-mod mirror;
-mod verify;
-mod policy;
-mod cache;
-mod audit;
-mod tls;
-mod geoip;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["policy", "test", ..] => return policy::tester::run(&args[2..]).await,
+        ["geoip", "lookup", ..] => return geoip::lookup::run(&args[2..]).await,
+        ["admin", "token", ..] => return server::auth::run(&args[2..]),
+        ["export", ..] => return mirror::export::run(&args[1..]).await,
+        [command, ..] => return Err(anyhow::anyhow!("Unknown command '{}' (available: policy test, geoip lookup, admin token, export)", command)),
+        [] => {}
+    }
+
+    // The subscriber depends on the config, so loading logs through a temporary one
+    let config_path = std::env::var("APTG_CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+    let config = tracing::subscriber::with_default(tracing_subscriber::fmt().finish(), || {
+        config::settings::AppConfig::load_or_default(&config_path)
+    })?;
+    let _telemetry = telemetry::otel::init(&config.telemetry)?;
     
     info!("Starting aptg");
+
+    let audit = Arc::new(audit::log::AuditLogger::from_config(&config.audit));
+    let tls_server = config.server.enable_https.then(|| tls::simple_server::TlsServer::new(config.tls.clone())).transpose()?;
+    let mut routes = server::router::RouterBuilder::new(&config).with_audit(audit.clone());
+    if let Some(server) = &tls_server {
+        routes = routes.with_tls(server.reloader());
+    }
+    let routes = routes.build()?;
+    let listeners = config.server
+        .listeners()?
+        .iter()
+        .map(|(addr, options)| server::listener::bind(*addr, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(server) = tls_server {
+        return server.with_audit(audit).serve(listeners, routes).await;
+    }
     
-    let routes = server::router::build_routes();
-    let addr: SocketAddr = ([0, 0, 0, 0], 8080).into();
-    
-    info!("Server listening on {}", addr);
-    
-    warp::serve(routes)
-        .run(addr)
-        .await;
-    
-    Ok(())
+    server::listener::serve(listeners, routes).await
 }
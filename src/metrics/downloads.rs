@@ -0,0 +1,288 @@
+use anyhow::{Result, anyhow};
+use chrono::{Days, NaiveDate, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, error};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadStatsConfig {
+    pub enabled: bool,
+    pub path: String,
+    // Daily counts older than this are deleted; 0 keeps everything
+    pub retention_days: u32,
+    // Counts are kept in memory and written out this often
+    pub flush_interval_secs: u64,
+}
+
+impl Default for DownloadStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/var/lib/aptg/downloads.db".to_string(),
+            retention_days: 365,
+            flush_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DownloadKey {
+    day: NaiveDate,
+    repository: String,
+    package: String,
+    suite: String,
+    architecture: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DownloadCount {
+    downloads: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TopPackagesQuery {
+    pub days: u32,
+    pub limit: usize,
+    pub repository: Option<String>,
+    pub suite: Option<String>,
+    pub architecture: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DownloadTotal {
+    pub name: String,
+    pub downloads: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopPackages {
+    // First and last day counted, inclusive
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub downloads: u64,
+    pub bytes: u64,
+    pub packages: Vec<DownloadTotal>,
+    pub suites: Vec<DownloadTotal>,
+    pub architectures: Vec<DownloadTotal>,
+}
+
+// A window such as 7d or 2w, in days
+pub fn parse_window(window: &str) -> Option<u32> {
+    let (count, unit) = window.split_at(window.len().checked_sub(1)?);
+    let count: u32 = count.parse().ok().filter(|count| *count > 0)?;
+    match unit {
+        "d" => Some(count),
+        "w" => count.checked_mul(7),
+        _ => None,
+    }
+}
+
+// Package downloads per day, repository, suite and architecture, kept in
+// SQLite. Counting only touches memory; a background task writes the counts
+// out so serving never waits on the database
+pub struct DownloadStats {
+    config: DownloadStatsConfig,
+    pending: Mutex<HashMap<DownloadKey, DownloadCount>>,
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl DownloadStats {
+    pub fn open(config: &DownloadStatsConfig) -> Result<Self> {
+        let path = Path::new(&config.path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create download statistics directory {}: {}", parent.display(), e))?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS downloads (
+                 day TEXT NOT NULL,
+                 repository TEXT NOT NULL,
+                 package TEXT NOT NULL,
+                 suite TEXT NOT NULL,
+                 architecture TEXT NOT NULL,
+                 downloads INTEGER NOT NULL,
+                 bytes INTEGER NOT NULL,
+                 PRIMARY KEY (day, repository, package, suite, architecture)
+             );",
+        )?;
+        info!("Storing download statistics in {}", path.display());
+        Ok(Self {
+            config: config.clone(),
+            pending: Mutex::new(HashMap::new()),
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // One served package; the suite is empty when no known index lists the file
+    pub fn record(&self, repository: &str, package: &str, suite: &str, architecture: &str, bytes: u64) {
+        let key = DownloadKey {
+            day: Utc::now().date_naive(),
+            repository: repository.to_string(),
+            package: package.to_string(),
+            suite: suite.to_string(),
+            architecture: architecture.to_string(),
+        };
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let count = pending.entry(key).or_default();
+        count.downloads += 1;
+        count.bytes += bytes;
+    }
+
+    // Writes out the counts recorded so far
+    pub async fn flush(&self) -> Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let transaction = conn.transaction()?;
+            {
+                let mut statement = transaction.prepare_cached(
+                    "INSERT INTO downloads (day, repository, package, suite, architecture, downloads, bytes)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT (day, repository, package, suite, architecture)
+                     DO UPDATE SET downloads = downloads + excluded.downloads, bytes = bytes + excluded.bytes",
+                )?;
+                for (key, count) in &pending {
+                    statement.execute((
+                        key.day.to_string(),
+                        &key.repository,
+                        &key.package,
+                        &key.suite,
+                        &key.architecture,
+                        count.downloads as i64,
+                        count.bytes as i64,
+                    ))?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn prune(&self) -> Result<usize> {
+        if self.config.retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = Utc::now().date_naive() - Days::new(u64::from(self.config.retention_days));
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(conn.execute("DELETE FROM downloads WHERE day < ?1", [cutoff.to_string()])?)
+        })
+        .await?
+    }
+
+    pub fn spawn(self: &Arc<Self>) {
+        let stats = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(stats.config.flush_interval_secs.max(1)));
+            let mut pruned_on = None;
+            loop {
+                interval.tick().await;
+                if let Err(e) = stats.flush().await {
+                    error!("Failed to store download statistics: {}", e);
+                }
+                let today = Utc::now().date_naive();
+                if pruned_on != Some(today) {
+                    match stats.prune().await {
+                        Ok(removed) if removed > 0 => info!("Pruned {} daily download counts", removed),
+                        Ok(_) => {}
+                        Err(e) => error!("Failed to prune download statistics: {}", e),
+                    }
+                    pruned_on = Some(today);
+                }
+            }
+        });
+    }
+
+    // Most downloaded packages over the last query.days days, today included,
+    // with totals per suite and architecture over the same downloads
+    pub async fn top_packages(&self, query: &TopPackagesQuery) -> Result<TopPackages> {
+        self.flush().await?;
+        let to = Utc::now().date_naive();
+        let from = to - Days::new(u64::from(query.days.max(1) - 1));
+        let query = query.clone();
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let filter = "day >= ?1 AND (?2 IS NULL OR repository = ?2) AND (?3 IS NULL OR suite = ?3) AND (?4 IS NULL OR architecture = ?4)";
+            let params = (from.to_string(), &query.repository, &query.suite, &query.architecture);
+            let totals = |column: &str, limit: Option<usize>| -> Result<Vec<DownloadTotal>> {
+                let sql = format!(
+                    "SELECT {column}, SUM(downloads), SUM(bytes) FROM downloads WHERE {filter}
+                     GROUP BY {column} ORDER BY SUM(downloads) DESC, {column} LIMIT {}",
+                    limit.map_or(-1, |limit| limit as i64),
+                );
+                let mut statement = conn.prepare(&sql)?;
+                let rows = statement.query_map(params.clone(), |row| {
+                    Ok(DownloadTotal { name: row.get(0)?, downloads: row.get::<_, i64>(1)? as u64, bytes: row.get::<_, i64>(2)? as u64 })
+                })?;
+                Ok(rows.collect::<rusqlite::Result<_>>()?)
+            };
+            let packages = totals("package", Some(query.limit))?;
+            let suites = totals("suite", None)?;
+            let architectures = totals("architecture", None)?;
+            Ok(TopPackages {
+                from,
+                to,
+                downloads: suites.iter().map(|total| total.downloads).sum(),
+                bytes: suites.iter().map(|total| total.bytes).sum(),
+                packages,
+                suites,
+                architectures,
+            })
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counts_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DownloadStatsConfig { enabled: true, path: dir.path().join("downloads.db").to_str().unwrap().to_string(), ..Default::default() };
+        let stats = DownloadStats::open(&config).unwrap();
+        stats.record("/debian", "openssl", "bookworm", "amd64", 1000);
+        stats.record("/debian", "openssl", "bookworm", "arm64", 1000);
+        stats.record("/debian", "openssl", "bookworm", "amd64", 1000);
+        stats.record("/debian", "curl", "bookworm", "amd64", 500);
+        stats.flush().await.unwrap();
+        drop(stats);
+        
+        let stats = DownloadStats::open(&config).unwrap();
+        stats.record("/debian", "curl", "bookworm", "amd64", 500);
+        let top = stats.top_packages(&TopPackagesQuery { days: 7, limit: 1, ..Default::default() }).await.unwrap();
+        assert_eq!(top.downloads, 5);
+        assert_eq!(top.packages, vec![DownloadTotal { name: "openssl".to_string(), downloads: 3, bytes: 3000 }]);
+        assert_eq!(top.architectures[0], DownloadTotal { name: "amd64".to_string(), downloads: 4, bytes: 3000 });
+        assert_eq!(top.suites.len(), 1);
+        
+        let arm = TopPackagesQuery { days: 1, limit: 10, architecture: Some("arm64".to_string()), ..Default::default() };
+        assert_eq!(stats.top_packages(&arm).await.unwrap().downloads, 1);
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("7d"), Some(7));
+        assert_eq!(parse_window("2w"), Some(14));
+        assert_eq!(parse_window("0d"), None);
+        assert_eq!(parse_window("24h"), None);
+        assert_eq!(parse_window(""), None);
+    }
+}
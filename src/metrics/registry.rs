@@ -0,0 +1,157 @@
+use anyhow::Result;
+use prometheus::{Encoder, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+use std::sync::OnceLock;
+
+// Seconds; the upper buckets cover large package downloads
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+// Bytes per second, 64 KiB/s to 1 GiB/s
+const RATE_BUCKETS: &[f64] = &[65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0, 67108864.0, 268435456.0, 1073741824.0];
+
+pub struct Metrics {
+    registry: Registry,
+    // mode is "enforced" or "dry_run"; rule is the policy rule kind
+    pub policy_violations: IntCounterVec,
+    // scope is "global", "client", "concurrency" or "geoip"
+    pub rate_limited: IntCounterVec,
+    // Every audit event logged, whether or not a sink records it
+    pub audit_events: IntCounterVec,
+    // Events a streaming sink could not accept or deliver
+    pub audit_events_dropped: IntCounterVec,
+    // Repository requests by response status code
+    pub request_duration: HistogramVec,
+    pub upstream_fetch_duration: Histogram,
+    // Successful upstream fetches by host and path class ("index" or "pool")
+    pub upstream_host_fetch_duration: HistogramVec,
+    pub upstream_transfer_rate: HistogramVec,
+    // Days until each served TLS certificate expires, by certificate file
+    pub certificate_expiry_days: GaugeVec,
+    // Completed handshakes by negotiated protocol version and cipher suite
+    pub tls_handshakes: IntCounterVec,
+    // reason is "protocol_version", "cipher_suite", "client_auth", "alert", ...
+    pub tls_handshake_failures: IntCounterVec,
+    // GeoIP decisions by matched rule ("default" when none matched) and action
+    pub geoip_rule_matches: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+        let policy_violations = IntCounterVec::new(
+            Opts::new("aptg_policy_violations_total", "Policy violations by enforcement mode and rule kind"),
+            &["mode", "rule"],
+        )?;
+        let rate_limited = IntCounterVec::new(
+            Opts::new("aptg_rate_limited_requests_total", "Requests rejected by the rate limiter"),
+            &["scope"],
+        )?;
+        let audit_events = IntCounterVec::new(
+            Opts::new("aptg_audit_events_total", "Audit events by event type and status"),
+            &["type", "status"],
+        )?;
+        let audit_events_dropped = IntCounterVec::new(
+            Opts::new("aptg_audit_events_dropped_total", "Audit events dropped by a streaming sink"),
+            &["sink"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("aptg_request_duration_seconds", "Time to answer repository requests")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["status"],
+        )?;
+        let upstream_fetch_duration = Histogram::with_opts(
+            HistogramOpts::new("aptg_upstream_fetch_duration_seconds", "Time spent fetching from upstream mirrors")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+        )?;
+        let upstream_host_fetch_duration = HistogramVec::new(
+            HistogramOpts::new("aptg_upstream_host_fetch_duration_seconds", "Successful upstream fetch time by host and path class")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["host", "class"],
+        )?;
+        let upstream_transfer_rate = HistogramVec::new(
+            HistogramOpts::new("aptg_upstream_transfer_bytes_per_second", "Upstream transfer rate by host and path class")
+                .buckets(RATE_BUCKETS.to_vec()),
+            &["host", "class"],
+        )?;
+        let certificate_expiry_days = GaugeVec::new(
+            Opts::new("aptg_tls_certificate_expiry_days", "Days until a served TLS certificate expires"),
+            &["certificate"],
+        )?;
+        let tls_handshakes = IntCounterVec::new(
+            Opts::new("aptg_tls_handshakes_total", "Completed TLS handshakes by protocol version and cipher suite"),
+            &["protocol", "cipher_suite"],
+        )?;
+        let tls_handshake_failures = IntCounterVec::new(
+            Opts::new("aptg_tls_handshake_failures_total", "Failed TLS handshakes by reason"),
+            &["reason"],
+        )?;
+        let geoip_rule_matches = IntCounterVec::new(
+            Opts::new("aptg_geoip_rule_matches_total", "GeoIP policy decisions by matched rule and action"),
+            &["rule", "action"],
+        )?;
+        registry.register(Box::new(policy_violations.clone()))?;
+        registry.register(Box::new(rate_limited.clone()))?;
+        registry.register(Box::new(audit_events.clone()))?;
+        registry.register(Box::new(audit_events_dropped.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(upstream_fetch_duration.clone()))?;
+        registry.register(Box::new(upstream_host_fetch_duration.clone()))?;
+        registry.register(Box::new(upstream_transfer_rate.clone()))?;
+        registry.register(Box::new(certificate_expiry_days.clone()))?;
+        registry.register(Box::new(tls_handshakes.clone()))?;
+        registry.register(Box::new(tls_handshake_failures.clone()))?;
+        registry.register(Box::new(geoip_rule_matches.clone()))?;
+        
+        Ok(Self {
+            registry,
+            policy_violations,
+            rate_limited,
+            audit_events,
+            audit_events_dropped,
+            request_duration,
+            upstream_fetch_duration,
+            upstream_host_fetch_duration,
+            upstream_transfer_rate,
+            certificate_expiry_days,
+            tls_handshakes,
+            tls_handshake_failures,
+            geoip_rule_matches,
+        })
+    }
+
+    pub fn global() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(|| Metrics::new().expect("metric definitions are valid"))
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_violations_are_rendered() {
+        let metrics = Metrics::new().unwrap();
+        metrics.policy_violations.with_label_values(&["dry_run", "deny"]).inc();
+        
+        let output = metrics.render().unwrap();
+        assert!(output.contains("aptg_policy_violations_total{mode=\"dry_run\",rule=\"deny\"} 1"));
+    }
+
+    #[test]
+    fn test_latency_histograms_are_rendered() {
+        let metrics = Metrics::new().unwrap();
+        metrics.request_duration.with_label_values(&["200"]).observe(0.3);
+        metrics.upstream_fetch_duration.observe(12.0);
+        
+        let output = metrics.render().unwrap();
+        assert!(output.contains("aptg_request_duration_seconds_bucket{status=\"200\",le=\"0.5\"} 1"));
+        assert!(output.contains("aptg_upstream_fetch_duration_seconds_bucket{le=\"10\"} 0"));
+        assert!(output.contains("aptg_upstream_fetch_duration_seconds_bucket{le=\"30\"} 1"));
+    }
+}
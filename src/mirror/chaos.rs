@@ -0,0 +1,223 @@
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use crate::mirror::fetch::{FetchFuture, Upstream, UpstreamResponse, UpstreamStatus};
+use crate::mirror::latency::UpstreamLatency;
+
+// Faults injected into upstream fetches, to check that verification and
+// upstream failure handling behave as designed. Rates are 0.0 to 1.0 and
+// rolled per fetch; only honoured when aptg is built with the chaos feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    // Delay added before a fetch
+    pub latency_rate: f64,
+    pub latency_ms: u64,
+    // The fetch is not made; upstream answers error_status instead
+    pub error_rate: f64,
+    pub error_status: u16,
+    // Only the first half of the body is passed on
+    pub truncate_rate: f64,
+    // One byte of the body is flipped, so its hash no longer matches
+    pub corrupt_rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latency_rate: 0.0,
+            latency_ms: 2000,
+            error_rate: 0.0,
+            error_status: 503,
+            truncate_rate: 0.0,
+            corrupt_rate: 0.0,
+        }
+    }
+}
+
+impl ChaosConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && !cfg!(feature = "chaos") {
+            return Err(anyhow!("[chaos] requires aptg built with the chaos feature"));
+        }
+        let rates = [
+            ("latency_rate", self.latency_rate),
+            ("error_rate", self.error_rate),
+            ("truncate_rate", self.truncate_rate),
+            ("corrupt_rate", self.corrupt_rate),
+        ];
+        if let Some((name, _)) = rates.iter().find(|(_, rate)| !(0.0..=1.0).contains(rate)) {
+            return Err(anyhow!("[chaos] {} must be between 0 and 1", name));
+        }
+        if !(500..600).contains(&self.error_status) {
+            return Err(anyhow!("[chaos] error_status must be a 5xx status, not {}", self.error_status));
+        }
+        Ok(())
+    }
+
+    // Whether upstreams are to be wrapped in a ChaosUpstream
+    pub fn is_active(&self) -> bool {
+        cfg!(feature = "chaos") && self.enabled
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Truncate,
+    Corrupt,
+}
+
+impl Fault {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Fault::Truncate => "truncating",
+            Fault::Corrupt => "corrupting",
+        }
+    }
+}
+
+// Wraps an upstream and injects the configured faults into its fetches
+pub struct ChaosUpstream {
+    inner: Arc<dyn Upstream>,
+    config: ChaosConfig,
+}
+
+impl ChaosUpstream {
+    pub fn new(inner: Arc<dyn Upstream>, config: ChaosConfig) -> Self {
+        Self { inner, config }
+    }
+
+    fn roll(rate: f64) -> bool {
+        rate > 0.0 && rand::thread_rng().gen_bool(rate.min(1.0))
+    }
+
+    fn body_fault(&self) -> Option<Fault> {
+        if Self::roll(self.config.truncate_rate) {
+            Some(Fault::Truncate)
+        } else if Self::roll(self.config.corrupt_rate) {
+            Some(Fault::Corrupt)
+        } else {
+            None
+        }
+    }
+
+    async fn inject<'a>(&'a self, path: &'a str, fetch: FetchFuture<'a>) -> Result<UpstreamResponse> {
+        if Self::roll(self.config.error_rate) {
+            warn!("Chaos: answering {} for {}", self.config.error_status, path);
+            let status = warp::http::StatusCode::from_u16(self.config.error_status)?;
+            return Err(UpstreamStatus(status).into());
+        }
+        if Self::roll(self.config.latency_rate) {
+            warn!("Chaos: delaying {} by {}ms", path, self.config.latency_ms);
+            tokio::time::sleep(Duration::from_millis(self.config.latency_ms)).await;
+        }
+        let fault = self.body_fault();
+        let mut response = fetch.await?;
+        if let Some(fault) = fault.filter(|_| !response.body.is_empty()) {
+            warn!("Chaos: {} the body of {}", fault.as_str(), path);
+            response.body = damage(&response.body, fault);
+            // Left in place it would describe the original body
+            response.headers.remove(warp::http::header::CONTENT_LENGTH);
+        }
+        Ok(response)
+    }
+}
+
+fn damage(body: &Bytes, fault: Fault) -> Bytes {
+    match fault {
+        Fault::Truncate => body.slice(..body.len() / 2),
+        Fault::Corrupt => {
+            let mut damaged = body.to_vec();
+            let index = rand::thread_rng().gen_range(0..damaged.len());
+            damaged[index] ^= 0xff;
+            Bytes::from(damaged)
+        }
+    }
+}
+
+impl Upstream for ChaosUpstream {
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(self.inject(path, self.inner.fetch(path)))
+    }
+
+    fn fetch_from<'a>(&'a self, mirror: &'a str, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(self.inject(path, self.inner.fetch_from(mirror, path)))
+    }
+
+    fn latency(&self) -> Arc<UpstreamLatency> {
+        self.inner.latency()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct FixedUpstream {
+        fetches: AtomicUsize,
+        latency: Arc<UpstreamLatency>,
+    }
+
+    impl Upstream for FixedUpstream {
+        fn fetch<'a>(&'a self, _path: &'a str) -> FetchFuture<'a> {
+            Box::pin(async move {
+                self.fetches.fetch_add(1, Ordering::SeqCst);
+                let mut headers = warp::http::HeaderMap::new();
+                headers.insert(warp::http::header::CONTENT_LENGTH, "8".parse().unwrap());
+                Ok(UpstreamResponse { status: warp::http::StatusCode::OK, headers, body: Bytes::from_static(b"Package:") })
+            })
+        }
+        
+        fn fetch_from<'a>(&'a self, _mirror: &'a str, path: &'a str) -> FetchFuture<'a> {
+            self.fetch(path)
+        }
+        
+        fn latency(&self) -> Arc<UpstreamLatency> {
+            self.latency.clone()
+        }
+    }
+
+    fn chaos(config: ChaosConfig) -> (Arc<FixedUpstream>, ChaosUpstream) {
+        let inner = Arc::new(FixedUpstream::default());
+        (inner.clone(), ChaosUpstream::new(inner, ChaosConfig { enabled: true, ..config }))
+    }
+
+    #[tokio::test]
+    async fn test_injected_faults() {
+        let path = "/debian/dists/bookworm/main/binary-amd64/Packages";
+        let (inner, upstream) = chaos(ChaosConfig { error_rate: 1.0, ..Default::default() });
+        let error = upstream.fetch(path).await.err().unwrap();
+        assert!(matches!(error.downcast_ref::<UpstreamStatus>(), Some(UpstreamStatus(status)) if status.as_u16() == 503));
+        assert_eq!(inner.fetches.load(Ordering::SeqCst), 0);
+        
+        let (_, upstream) = chaos(ChaosConfig { truncate_rate: 1.0, ..Default::default() });
+        let response = upstream.fetch(path).await.unwrap();
+        assert_eq!(&response.body[..], b"Pack");
+        assert!(response.headers.get(warp::http::header::CONTENT_LENGTH).is_none());
+        
+        let (_, upstream) = chaos(ChaosConfig { corrupt_rate: 1.0, ..Default::default() });
+        let response = upstream.fetch_from("http://mirror.example/debian", path).await.unwrap();
+        assert_eq!(response.body.len(), 8);
+        assert_ne!(&response.body[..], b"Package:");
+        
+        let (inner, upstream) = chaos(ChaosConfig::default());
+        assert_eq!(&upstream.fetch(path).await.unwrap().body[..], b"Package:");
+        assert_eq!(inner.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ChaosConfig::default().validate().is_ok());
+        assert!(ChaosConfig { corrupt_rate: 1.5, ..Default::default() }.validate().is_err());
+        assert!(ChaosConfig { error_status: 404, ..Default::default() }.validate().is_err());
+        assert_eq!(ChaosConfig { enabled: true, ..Default::default() }.validate().is_ok(), cfg!(feature = "chaos"));
+    }
+}
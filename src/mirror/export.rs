@@ -0,0 +1,339 @@
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use crate::verify::hashes::{DigestAlgorithm, FileDigests, HashVerifier};
+use crate::verify::index::{IndexParser, PoolEntry};
+
+const USAGE: &str = "usage: aptg export --dest <dir> [--url <aptg base url>] [--repository <name>]
+                  [--suite <suite>[,...]] [--component <component>[,...]] [--arch <arch>[,...]] [--source]
+
+Fetches the suites through a running aptg, so everything exported has passed its
+verification, and lays them out under <dest>/<repository> as on a Debian mirror.
+Files already there and unchanged are kept, so the export can be re-run.";
+
+// Release files of a suite; an archive carries InRelease, Release and Release.gpg, or some of them
+const RELEASE_FILES: [&str; 3] = ["InRelease", "Release", "Release.gpg"];
+
+#[derive(Debug)]
+struct ExportOptions {
+    dest: PathBuf,
+    url: String,
+    repository: String,
+    suites: Vec<String>,
+    components: Vec<String>,
+    architectures: Vec<String>,
+    source: bool,
+}
+
+fn list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+impl ExportOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut dest = None;
+        let mut options = Self {
+            dest: PathBuf::new(),
+            url: "http://127.0.0.1:8080".to_string(),
+            repository: "debian".to_string(),
+            suites: vec!["bookworm".to_string()],
+            components: vec!["main".to_string()],
+            architectures: vec!["amd64".to_string()],
+            source: false,
+        };
+        let mut args = args.iter();
+        
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--dest" => dest = Some(PathBuf::from(value()?)),
+                "--url" => options.url = value()?.trim_end_matches('/').to_string(),
+                "--repository" => options.repository = value()?,
+                "--suite" => options.suites = list(&value()?),
+                "--component" => options.components = list(&value()?),
+                "--arch" => options.architectures = list(&value()?),
+                "--source" => options.source = true,
+                "--help" | "-h" => return Err(anyhow!(USAGE)),
+                _ => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+            }
+        }
+        
+        options.dest = dest.ok_or_else(|| anyhow!("--dest is required\n{}", USAGE))?;
+        if options.suites.is_empty() || options.components.is_empty() || options.architectures.is_empty() {
+            return Err(anyhow!("--suite, --component and --arch need at least one value"));
+        }
+        Ok(options)
+    }
+
+    // Whether apt reads this index of a suite for the requested components
+    // and architectures; relative to the suite directory, as Release lists it
+    fn wants_index(&self, relative: &str) -> bool {
+        let Some((component, rest)) = relative.split_once('/') else {
+            return false;
+        };
+        if !self.components.iter().any(|wanted| wanted == component) || rest.contains("/by-hash/") {
+            return false;
+        }
+        self.architectures.iter().map(String::as_str).chain(["all"]).any(|arch| rest.starts_with(&format!("binary-{}/", arch)))
+            || rest.starts_with("i18n/Translation-en")
+            || (self.source && rest.starts_with("source/"))
+    }
+}
+
+// Paths from indexes end up under the destination, so they must stay inside it
+fn checked(relative: &str) -> Result<&Path> {
+    let path = Path::new(relative);
+    if relative.is_empty() || !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(anyhow!("Refusing to write outside the mirror: {}", relative));
+    }
+    Ok(path)
+}
+
+fn verify_listed(data: &[u8], listed: &FileDigests) -> Result<()> {
+    if data.len() as u64 != listed.size {
+        return Err(anyhow!("{} bytes where Release lists {}", data.len(), listed.size));
+    }
+    let (algorithm, digest) = listed.strongest().ok_or_else(|| anyhow!("Release lists no digest"))?;
+    if !algorithm.compute(data).eq_ignore_ascii_case(digest) {
+        return Err(anyhow!("{} does not match Release", algorithm.field_name()));
+    }
+    Ok(())
+}
+
+fn is_current(file: &Path, entry: &PoolEntry) -> bool {
+    std::fs::metadata(file).is_ok_and(|metadata| metadata.len() == entry.size)
+        && std::fs::read(file).is_ok_and(|data| DigestAlgorithm::Sha256.compute(&data).eq_ignore_ascii_case(&entry.sha256))
+}
+
+// Replaced by rename, so rsync or apt reading the mirror never sees half a file
+fn write_file(file: &Path, data: &[u8]) -> Result<()> {
+    if let Some(directory) = file.parent() {
+        std::fs::create_dir_all(directory).map_err(|e| anyhow!("Failed to create {}: {}", directory.display(), e))?;
+    }
+    let partial = file.with_extension(format!("partial-{}", rand::random::<u32>()));
+    if let Err(e) = std::fs::write(&partial, data) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(anyhow!("Failed to write {}: {}", partial.display(), e));
+    }
+    std::fs::rename(&partial, file).map_err(|e| anyhow!("Failed to rename {}: {}", partial.display(), e))
+}
+
+struct Exporter {
+    client: reqwest::Client,
+    // Base URL of the repository on aptg, e.g. http://127.0.0.1:8080/debian
+    base: String,
+    // <dest>/<repository>
+    root: PathBuf,
+    exported: usize,
+    unchanged: usize,
+    bytes: u64,
+}
+
+impl Exporter {
+    fn new(options: &ExportOptions) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(600))
+                .user_agent("aptg-export/0.1.0")
+                .build()?,
+            base: format!("{}/{}", options.url, options.repository),
+            root: options.dest.join(&options.repository),
+            exported: 0,
+            unchanged: 0,
+            bytes: 0,
+        })
+    }
+
+    // None when aptg has no such file
+    async fn get(&self, relative: &str) -> Result<Option<Bytes>> {
+        let url = format!("{}/{}", self.base, relative);
+        let response = self.client.get(&url).send().await.map_err(|e| anyhow!("{}: {}", url, e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow!("{} answered {}", url, response.status()));
+        }
+        Ok(Some(response.bytes().await?))
+    }
+
+    fn write(&mut self, relative: &str, data: &[u8]) -> Result<()> {
+        let file = self.root.join(checked(relative)?);
+        if std::fs::read(&file).is_ok_and(|existing| existing == data) {
+            self.unchanged += 1;
+            return Ok(());
+        }
+        write_file(&file, data)?;
+        self.exported += 1;
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+
+    // The Release files and wanted indexes of a suite, checked against its
+    // Release; the pool files its Packages and Sources list are added to pool
+    async fn suite(&self, suite: &str, options: &ExportOptions, pool: &mut BTreeMap<String, PoolEntry>) -> Result<Vec<(String, Bytes)>> {
+        let dists = format!("dists/{}", suite);
+        let mut files = Vec::new();
+        for name in RELEASE_FILES {
+            if let Some(data) = self.get(&format!("{}/{}", dists, name)).await? {
+                files.push((format!("{}/{}", dists, name), data));
+            }
+        }
+        let release = files
+            .iter()
+            .find(|(path, _)| !path.ends_with(".gpg"))
+            .ok_or_else(|| anyhow!("aptg has neither InRelease nor Release"))?;
+        let listed: HashMap<String, FileDigests> = HashVerifier::parse_release_digests(&String::from_utf8_lossy(&release.1))?;
+        let mut wanted: Vec<&String> = listed.keys().filter(|relative| options.wants_index(relative)).collect();
+        wanted.sort();
+        
+        // One variant of each Packages or Sources index is enough to list the pool
+        let mut parsed = HashSet::new();
+        for relative in wanted {
+            // Release also lists compressions the archive does not carry
+            let Some(data) = self.get(&format!("{}/{}", dists, relative)).await? else {
+                continue;
+            };
+            verify_listed(&data, &listed[relative]).map_err(|e| anyhow!("{}/{}: {}", dists, relative, e))?;
+            let stem = relative.trim_end_matches(".xz").trim_end_matches(".gz");
+            let kind = stem.rsplit('/').next().unwrap_or("");
+            if (kind == "Packages" || kind == "Sources") && parsed.insert(stem.to_string()) {
+                let content = IndexParser::decompress(relative, &data)?;
+                let entries = if kind == "Sources" { IndexParser::parse_sources(&content)? } else { IndexParser::parse_packages(&content)? };
+                pool.extend(entries);
+            }
+            files.push((format!("{}/{}", dists, relative), data));
+        }
+        if parsed.is_empty() {
+            return Err(anyhow!("No Packages index for {} on {}", options.components.join(","), options.architectures.join(",")));
+        }
+        Ok(files)
+    }
+
+    async fn pool_file(&mut self, filename: &str, entry: &PoolEntry) -> Result<()> {
+        let file = self.root.join(checked(filename)?);
+        if is_current(&file, entry) {
+            self.unchanged += 1;
+            return Ok(());
+        }
+        let data = self.get(filename).await?.ok_or_else(|| anyhow!("aptg answered 404"))?;
+        if data.len() as u64 != entry.size || !DigestAlgorithm::Sha256.compute(&data).eq_ignore_ascii_case(&entry.sha256) {
+            return Err(anyhow!("Does not match its index"));
+        }
+        write_file(&file, &data)?;
+        self.exported += 1;
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+}
+
+// Copies suites as served by aptg into a directory laid out like a Debian
+// mirror, for rsync or removable media to an air-gapped site. The dists files
+// go last, and only once every pool file is in place, so the mirror never
+// lists a package it does not have
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = ExportOptions::parse(args)?;
+    let mut exporter = Exporter::new(&options)?;
+    let mut failures = Vec::new();
+
+    let mut pool = BTreeMap::new();
+    let mut dists = Vec::new();
+    for suite in &options.suites {
+        match exporter.suite(suite, &options, &mut pool).await {
+            Ok(files) => dists.extend(files),
+            Err(e) => failures.push(format!("dists/{}: {}", suite, e)),
+        }
+    }
+    println!("Exporting {} pool files to {}", pool.len(), exporter.root.display());
+    for (filename, entry) in &pool {
+        if let Err(e) = exporter.pool_file(filename, entry).await {
+            failures.push(format!("{}: {}", filename, e));
+        }
+    }
+
+    if !failures.is_empty() {
+        for failure in &failures {
+            eprintln!("{}", failure);
+        }
+        return Err(anyhow!("{} files could not be exported; the dists directory was left as it was", failures.len()));
+    }
+    for (relative, data) in &dists {
+        exporter.write(relative, data)?;
+    }
+    println!(
+        "{} files written ({:.1} MiB), {} already up to date",
+        exporter.exported, exporter.bytes as f64 / (1024.0 * 1024.0), exporter.unchanged
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_wanted_indexes() {
+        let options = ExportOptions::parse(&args(&["--dest", "/srv/mirror", "--component", "main,contrib", "--arch", "arm64"])).unwrap();
+        assert_eq!(options.components, ["main", "contrib"]);
+        for wanted in ["main/binary-arm64/Packages.xz", "contrib/binary-all/Packages.gz", "main/i18n/Translation-en.bz2"] {
+            assert!(options.wants_index(wanted), "{}", wanted);
+        }
+        for unwanted in ["main/binary-amd64/Packages.xz", "non-free/binary-arm64/Packages.xz", "main/source/Sources.xz", "main/binary-arm64/by-hash/SHA256/ab", "Contents-arm64.gz"] {
+            assert!(!options.wants_index(unwanted), "{}", unwanted);
+        }
+        assert!(ExportOptions::parse(&args(&["--suite", "bookworm"])).is_err());
+        assert!(checked("pool/main/s/sl/sl_5.02-1_amd64.deb").is_ok());
+        assert!(checked("pool/../../etc/passwd").is_err());
+        assert!(checked("/etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let deb = b"!<arch>\nsl".to_vec();
+        let packages = format!(
+            "Package: sl\nFilename: pool/main/s/sl/sl_5.02-1_amd64.deb\nSize: {}\nSHA256: {}\n",
+            deb.len(), DigestAlgorithm::Sha256.compute(&deb)
+        );
+        // Packages.xz is listed but missing, as the uncompressed index often is
+        let release = format!(
+            "Suite: stable\nSHA256:\n {} {} main/binary-amd64/Packages\n {} 10 main/binary-amd64/Packages.xz\n",
+            DigestAlgorithm::Sha256.compute(packages.as_bytes()), packages.len(), "0".repeat(64)
+        );
+        let files: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::from([
+            ("/debian/dists/bookworm/InRelease".to_string(), release.into_bytes()),
+            ("/debian/dists/bookworm/main/binary-amd64/Packages".to_string(), packages.into_bytes()),
+            ("/debian/pool/main/s/sl/sl_5.02-1_amd64.deb".to_string(), deb.clone()),
+        ])));
+        let served = files.clone();
+        let routes = warp::path::full().map(move |path: warp::path::FullPath| match served.lock().unwrap().get(path.as_str()) {
+            Some(body) => warp::http::Response::builder().body(body.clone()).unwrap(),
+            None => warp::http::Response::builder().status(404).body(Vec::new()).unwrap(),
+        });
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        
+        let dest = tempfile::tempdir().unwrap();
+        let export = args(&["--url", &format!("http://{}", addr), "--dest", dest.path().to_str().unwrap()]);
+        run(&export).await.unwrap();
+        let mirror = dest.path().join("debian");
+        assert_eq!(std::fs::read(mirror.join("pool/main/s/sl/sl_5.02-1_amd64.deb")).unwrap(), deb);
+        assert!(mirror.join("dists/bookworm/InRelease").exists());
+        assert!(mirror.join("dists/bookworm/main/binary-amd64/Packages").exists());
+        assert!(!mirror.join("dists/bookworm/main/binary-amd64/Packages.xz").exists());
+        
+        // A pool file that does not match keeps the old dists in place
+        std::fs::remove_file(mirror.join("pool/main/s/sl/sl_5.02-1_amd64.deb")).unwrap();
+        std::fs::remove_dir_all(mirror.join("dists")).unwrap();
+        files.lock().unwrap().insert("/debian/pool/main/s/sl/sl_5.02-1_amd64.deb".to_string(), b"tampered".to_vec());
+        assert!(run(&export).await.is_err());
+        assert!(!mirror.join("dists").exists());
+    }
+}
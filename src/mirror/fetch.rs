@@ -1,47 +1,223 @@
 use anyhow::{Result, anyhow};
+use bytes::Bytes;
 use reqwest::Client;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use warp::Reply;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::info;
+use crate::cache::cache::CachedResponse;
+use crate::config::settings::RepositoryConfig;
+use crate::mirror::latency::{PathClass, UpstreamLatency};
+use crate::telemetry::trace_context::TraceContext;
+use crate::tls::pinning::{pinned_client_config, SpkiHash};
+
+pub struct UpstreamResponse {
+    pub status: warp::http::StatusCode,
+    pub headers: warp::http::HeaderMap,
+    pub body: Bytes,
+}
+
+impl Reply for UpstreamResponse {
+    fn into_response(self) -> warp::reply::Response {
+        let mut response = warp::reply::Response::new(self.body.into());
+        *response.headers_mut() = self.headers;
+        *response.status_mut() = self.status;
+        response
+    }
+}
+
+// A non-success status from upstream, kept so a 404 can be told apart from
+// connection failures
+#[derive(Debug)]
+pub struct UpstreamStatus(pub warp::http::StatusCode);
+
+impl std::fmt::Display for UpstreamStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upstream returned status: {}", self.0)
+    }
+}
+
+impl std::error::Error for UpstreamStatus {}
+
+impl From<&UpstreamResponse> for CachedResponse {
+    fn from(response: &UpstreamResponse) -> Self {
+        Self { status: response.status, headers: response.headers.clone(), body: response.body.clone() }
+    }
+}
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<UpstreamResponse>> + Send + 'a>>;
+
+// Where repository content comes from. Paths start with the repository name,
+// e.g. /debian/dists/bookworm/InRelease; a non-success status is an
+// UpstreamStatus error
+pub trait Upstream: Send + Sync {
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a>;
+    // The path from a GeoIP mirror, whose base URL replaces the repository upstream
+    fn fetch_from<'a>(&'a self, mirror: &'a str, path: &'a str) -> FetchFuture<'a>;
+    // Shared with GeoIP mirror selection and the dashboard
+    fn latency(&self) -> Arc<UpstreamLatency>;
+}
 
 pub struct MirrorFetcher {
     client: Client,
-    upstream_base: String,
+    // Repository name -> upstream base URL
+    upstreams: HashMap<String, String>,
+    latency: Arc<UpstreamLatency>,
+}
+
+// Upstream host -> pinned keys; repositories sharing a host share their pins
+fn upstream_pins(repositories: &[RepositoryConfig]) -> Result<HashMap<String, Vec<SpkiHash>>> {
+    let mut pins: HashMap<String, Vec<SpkiHash>> = HashMap::new();
+    for repository in repositories.iter().filter(|r| !r.pinned_spki.is_empty()) {
+        let url = reqwest::Url::parse(&repository.upstream)
+            .map_err(|e| anyhow!("Invalid upstream URL {}: {}", repository.upstream, e))?;
+        let host = url.host_str()
+            .ok_or_else(|| anyhow!("Upstream URL {} has no host", repository.upstream))?;
+        pins.entry(host.to_string()).or_default().extend(repository.spki_pins()?);
+    }
+    Ok(pins)
 }
 
 impl MirrorFetcher {
-    pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("aptg/0.1.0")
-            .build()
-            .expect("Failed to create HTTP client");
+    pub fn from_repositories(repositories: &[RepositoryConfig]) -> Self {
+        let mut builder = Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .user_agent("aptg/0.1.0");
+        let pins = upstream_pins(repositories).expect("Invalid upstream SPKI pins");
+        if !pins.is_empty() {
+            builder = builder.use_preconfigured_tls(pinned_client_config(pins));
+        }
+        let client = builder.build().expect("Failed to create HTTP client");
+        let upstreams = repositories
+            .iter()
+            .map(|r| (r.name.clone(), r.upstream.trim_end_matches('/').to_string()))
+            .collect();
             
         Self {
             client,
-            upstream_base: "https://deb.debian.org".to_string(),
+            upstreams,
+            latency: Arc::new(UpstreamLatency::default()),
         }
     }
-    
-    pub async fn fetch(&self, path: &str) -> Result<impl Reply> {
-        let url = format!("{}{}", self.upstream_base, path);
+
+    // Shares one latency table between fetchers, e.g. those of several tenants
+    pub fn with_latency(mut self, latency: Arc<UpstreamLatency>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    fn upstream_url(&self, path: &str) -> Result<String> {
+        // Example: /ubuntu/dists/noble/InRelease -> http://archive.ubuntu.com/ubuntu/dists/noble/InRelease
+        let (repository, rest) = path.trim_start_matches('/').split_once('/').unwrap_or((path, ""));
+        let upstream = self.upstreams.get(repository)
+            .ok_or_else(|| anyhow!("No upstream configured for repository '{}'", repository))?;
+        Ok(format!("{}/{}", upstream, rest))
+    }
+
+    async fn fetch_url(&self, url: &str, class: PathClass) -> Result<UpstreamResponse> {
         info!("Fetching from upstream: {}", url);
+        let host = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default();
+        let started = Instant::now();
         
-        let response = self.client.get(&url).send().await?;
+        let fetched = self.download(url).await;
+        match &fetched {
+            Ok(response) => self.latency.record(&host, class, started.elapsed(), response.body.len()),
+            Err(_) => self.latency.record_failure(&host, class, FETCH_TIMEOUT),
+        }
+        fetched
+    }
+
+    async fn download(&self, url: &str) -> Result<UpstreamResponse> {
+        let mut request = self.client.get(url);
+        if let Some(context) = TraceContext::current() {
+            let context = context.child();
+            let traceparent = context.traceparent();
+            tracing::Span::current().record("traceparent", traceparent.as_str());
+            request = request.header("traceparent", traceparent);
+            if let Some(tracestate) = context.tracestate {
+                request = request.header("tracestate", tracestate);
+            }
+        }
+        let response = request.send().await?;
         
         if !response.status().is_success() {
-            return Err(anyhow!("Upstream returned status: {}", response.status()));
+            return Err(UpstreamStatus(response.status()).into());
         }
         
-        // Convert to warp response
         let status = response.status();
         let headers = response.headers().clone();
-        let bytes = response.bytes().await?;
+        let body = response.bytes().await?;
+        
+        Ok(UpstreamResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl Upstream for MirrorFetcher {
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move { self.fetch_url(&self.upstream_url(path)?, PathClass::from_path(path)).await })
+    }
+
+    fn fetch_from<'a>(&'a self, mirror: &'a str, path: &'a str) -> FetchFuture<'a> {
+        let (_, rest) = path.trim_start_matches('/').split_once('/').unwrap_or((path, ""));
+        let url = format!("{}/{}", mirror, rest);
+        Box::pin(async move { self.fetch_url(&url, PathClass::from_path(path)).await })
+    }
+
+    fn latency(&self) -> Arc<UpstreamLatency> {
+        self.latency.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_url_per_repository() {
+        let fetcher = MirrorFetcher::from_repositories(&[
+            RepositoryConfig { name: "debian".to_string(), upstream: "https://deb.debian.org/debian".to_string(), pinned_spki: vec![], auth: None, resign: false },
+            RepositoryConfig { name: "ubuntu".to_string(), upstream: "http://archive.ubuntu.com/ubuntu/".to_string(), pinned_spki: vec![], auth: None, resign: false },
+        ]);
         
-        let mut warp_response = warp::reply::Response::new(bytes.into());
-        *warp_response.headers_mut() = headers;
-        *warp_response.status_mut() = status;
+        assert_eq!(
+            fetcher.upstream_url("/debian/dists/bookworm/InRelease").unwrap(),
+            "https://deb.debian.org/debian/dists/bookworm/InRelease"
+        );
+        assert_eq!(
+            fetcher.upstream_url("/ubuntu/pool/main/a/apt/apt_2.7.14_amd64.deb").unwrap(),
+            "http://archive.ubuntu.com/ubuntu/pool/main/a/apt/apt_2.7.14_amd64.deb"
+        );
+        assert!(fetcher.upstream_url("/vendor/dists/stable/InRelease").is_err());
+    }
+
+    #[test]
+    fn test_pins_grouped_by_host() {
+        let pin = |byte: u8| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [byte; 32]);
+        let repository = |name: &str, upstream: &str, pins: Vec<String>| RepositoryConfig {
+            name: name.to_string(),
+            upstream: upstream.to_string(),
+            pinned_spki: pins,
+            auth: None,
+            resign: false,
+        };
+        let pins = upstream_pins(&[
+            repository("debian", "https://deb.debian.org/debian", vec![pin(1)]),
+            repository("security", "https://deb.debian.org/debian-security", vec![pin(2)]),
+            repository("ubuntu", "http://archive.ubuntu.com/ubuntu", vec![]),
+        ]).unwrap();
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins["deb.debian.org"], vec![[1u8; 32], [2u8; 32]]);
         
-        Ok(warp_response)
+        // A pin on plain HTTP would never be checked
+        assert!(upstream_pins(&[repository("ubuntu", "http://archive.ubuntu.com/ubuntu", vec![pin(1)])]).is_err());
     }
 }
@@ -0,0 +1,122 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::metrics::registry::Metrics;
+
+// Weight of the newest sample in the moving averages
+const SMOOTHING: f64 = 0.2;
+
+// Indexes are small and latency-bound; pool files are large and
+// throughput-bound, so they are tracked apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathClass {
+    Index,
+    Pool,
+}
+
+impl PathClass {
+    pub fn from_path(path: &str) -> Self {
+        if path.contains("/pool/") { PathClass::Pool } else { PathClass::Index }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathClass::Index => "index",
+            PathClass::Pool => "pool",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct HostLatency {
+    pub fetches: u64,
+    pub failures: u64,
+    // Exponentially weighted moving averages
+    pub seconds: f64,
+    pub bytes_per_second: f64,
+}
+
+impl HostLatency {
+    fn observe(&mut self, seconds: f64, bytes_per_second: Option<f64>) {
+        let first = self.fetches == 0;
+        let smooth = |average: f64, sample: f64| if first { sample } else { average + SMOOTHING * (sample - average) };
+        self.seconds = smooth(self.seconds, seconds);
+        if let Some(rate) = bytes_per_second {
+            self.bytes_per_second = smooth(self.bytes_per_second, rate);
+        }
+        self.fetches += 1;
+    }
+}
+
+// Fetch latency and transfer rate per upstream host and path class, exported
+// to Prometheus and consulted when choosing between equally near mirrors
+#[derive(Default)]
+pub struct UpstreamLatency {
+    hosts: Mutex<HashMap<(String, PathClass), HostLatency>>,
+}
+
+impl UpstreamLatency {
+    pub fn record(&self, host: &str, class: PathClass, elapsed: Duration, bytes: usize) {
+        let seconds = elapsed.as_secs_f64();
+        let rate = (seconds > 0.0 && bytes > 0).then(|| bytes as f64 / seconds);
+        let labels = [host, class.as_str()];
+        let metrics = Metrics::global();
+        metrics.upstream_host_fetch_duration.with_label_values(&labels).observe(seconds);
+        if let Some(rate) = rate {
+            metrics.upstream_transfer_rate.with_label_values(&labels).observe(rate);
+        }
+        self.update(host, class, |latency| latency.observe(seconds, rate));
+    }
+
+    // Failed fetches count as slow as the penalty so a broken mirror is avoided
+    pub fn record_failure(&self, host: &str, class: PathClass, penalty: Duration) {
+        self.update(host, class, |latency| {
+            latency.observe(penalty.as_secs_f64(), None);
+            latency.failures += 1;
+        });
+    }
+
+    fn update(&self, host: &str, class: PathClass, update: impl FnOnce(&mut HostLatency)) {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        update(hosts.entry((host.to_string(), class)).or_default());
+    }
+
+    pub fn get(&self, host: &str, class: PathClass) -> Option<HostLatency> {
+        self.hosts.lock().unwrap_or_else(|e| e.into_inner()).get(&(host.to_string(), class)).copied()
+    }
+
+    // Every host fetched from so far, by host name then class
+    pub fn hosts(&self) -> Vec<(String, PathClass, HostLatency)> {
+        let hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut hosts: Vec<_> = hosts.iter().map(|((host, class), latency)| (host.clone(), *class, *latency)).collect();
+        hosts.sort_by(|a, b| (&a.0, a.1.as_str()).cmp(&(&b.0, b.1.as_str())));
+        hosts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moving_averages_per_host_and_class() {
+        let latency = UpstreamLatency::default();
+        latency.record("deb.debian.org", PathClass::Pool, Duration::from_secs(2), 4_000_000);
+        latency.record("deb.debian.org", PathClass::Pool, Duration::from_secs(1), 4_000_000);
+        latency.record_failure("deb.debian.org", PathClass::Index, Duration::from_secs(30));
+        
+        let pool = latency.get("deb.debian.org", PathClass::Pool).unwrap();
+        assert_eq!(pool.fetches, 2);
+        assert!((pool.seconds - 1.8).abs() < 1e-9);
+        assert!((pool.bytes_per_second - 2_400_000.0).abs() < 1e-6);
+        assert_eq!(latency.get("deb.debian.org", PathClass::Index).unwrap().seconds, 30.0);
+        assert_eq!(latency.get("deb.debian.org", PathClass::Index).unwrap().failures, 1);
+        assert!(latency.get("ftp.de.debian.org", PathClass::Pool).is_none());
+        assert_eq!(PathClass::from_path("/debian/pool/main/a/apt/apt_2.6.1_amd64.deb"), PathClass::Pool);
+        assert_eq!(PathClass::from_path("/debian/dists/bookworm/InRelease"), PathClass::Index);
+        
+        let output = Metrics::global().render().unwrap();
+        assert!(output.contains("aptg_upstream_transfer_bytes_per_second_count{class=\"pool\",host=\"deb.debian.org\"}"));
+    }
+}
@@ -0,0 +1,399 @@
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use crate::cache::cache::CacheManager;
+use crate::verify::control::DebControl;
+use crate::verify::hashes::DigestAlgorithm;
+
+// Files in the incoming directory younger than this may still be being copied
+const INCOMING_SETTLE_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalPackagesConfig {
+    pub enabled: bool,
+    pub directory: String,
+    // Mirrored repository the packages are served in; it must be re-signed,
+    // since its Release then lists the local indices too
+    pub repository: String,
+    pub component: String,
+    pub suites: Vec<String>,
+    // Packages dropped here are imported and the files removed; ones that
+    // cannot be read are renamed to <name>.rejected
+    pub incoming: Option<String>,
+    pub scan_interval_secs: u64,
+    pub max_upload_mb: u64,
+}
+
+impl Default for LocalPackagesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "/var/lib/aptg/local".to_string(),
+            repository: "debian".to_string(),
+            component: "local".to_string(),
+            suites: vec![],
+            incoming: None,
+            scan_interval_secs: 30,
+            max_upload_mb: 512,
+        }
+    }
+}
+
+impl LocalPackagesConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !is_name(&self.component) {
+            return Err(anyhow!("Invalid local packages component '{}'", self.component));
+        }
+        if self.suites.is_empty() || !self.suites.iter().all(|suite| is_name(suite)) {
+            return Err(anyhow!("Local packages need at least one valid suite"));
+        }
+        Ok(())
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+')) && !name.starts_with('.')
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalPackage {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+    // Relative to the repository root, as in Packages
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(skip)]
+    control: Arc<DebControl>,
+}
+
+// Internal packages served under their own component of a mirrored suite.
+// Packages indices are generated on request and merged into the suite's
+// Release, which is then re-signed with the local key
+pub struct LocalRepository {
+    config: LocalPackagesConfig,
+    // By filename, so generated indices list packages in a stable order
+    packages: RwLock<BTreeMap<String, LocalPackage>>,
+}
+
+impl LocalRepository {
+    pub fn new(config: LocalPackagesConfig) -> Self {
+        Self {
+            config,
+            packages: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.config.max_upload_mb * 1024 * 1024
+    }
+
+    // pool/<component>/<prefix>/<package>/<package>_<version>_<arch>.deb, with the
+    // version's epoch left out as in the Debian archive
+    fn pool_filename(&self, control: &DebControl) -> Result<String> {
+        let (package, architecture) = (control.package(), control.architecture());
+        let version = control.version().split_once(':').map_or(control.version(), |(_, version)| version);
+        let valid = |value: &str| value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-' | '~'));
+        if !is_name(package) || !valid(version) || !is_name(architecture) {
+            return Err(anyhow!("Invalid package name, version or architecture in {} {}", package, control.version()));
+        }
+        let prefix = if package.starts_with("lib") && package.len() > 3 { &package[..4] } else { &package[..1] };
+        Ok(format!("pool/{}/{}/{}/{}_{}_{}.deb", self.config.component, prefix, package, package, version, architecture))
+    }
+
+    fn file(&self, filename: &str) -> PathBuf {
+        Path::new(&self.config.directory).join(filename)
+    }
+
+    async fn add(&self, control: DebControl, data: &[u8]) -> Result<LocalPackage> {
+        let filename = self.pool_filename(&control)?;
+        let package = LocalPackage {
+            package: control.package().to_string(),
+            version: control.version().to_string(),
+            architecture: control.architecture().to_string(),
+            filename: filename.clone(),
+            size: data.len() as u64,
+            sha256: DigestAlgorithm::Sha256.compute(data),
+            control: Arc::new(control),
+        };
+        self.packages.write().await.insert(filename, package.clone());
+        Ok(package)
+    }
+
+    // Reads back the packages stored by earlier runs
+    pub async fn load(&self) -> Result<usize> {
+        let mut pending = vec![self.file("pool")];
+        let mut count = 0;
+        while let Some(directory) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(anyhow!("Failed to read {}: {}", directory.display(), e)),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|extension| extension == "deb") {
+                    let data = tokio::fs::read(&path).await?;
+                    match DebControl::from_deb(&data) {
+                        Ok(control) => {
+                            self.add(control, &data).await?;
+                            count += 1;
+                        }
+                        Err(e) => warn!("Skipping local package {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+        info!("Loaded {} local packages", count);
+        Ok(count)
+    }
+
+    // Replaces any package with the same name, version and architecture
+    pub async fn import(&self, data: &[u8]) -> Result<LocalPackage> {
+        let control = DebControl::from_deb(data)?;
+        let file = self.file(&self.pool_filename(&control)?);
+        let directory = file.parent().expect("pool files are in a directory");
+        tokio::fs::create_dir_all(directory).await.map_err(|e| anyhow!("Failed to create {}: {}", directory.display(), e))?;
+        let partial = file.with_extension(format!("partial-{}", rand::random::<u32>()));
+        tokio::fs::write(&partial, data).await.map_err(|e| anyhow!("Failed to write {}: {}", partial.display(), e))?;
+        tokio::fs::rename(&partial, &file).await.map_err(|e| anyhow!("Failed to rename {}: {}", partial.display(), e))?;
+        
+        let package = self.add(control, data).await?;
+        info!("Imported local package {}", package.filename);
+        Ok(package)
+    }
+
+    // By file name, e.g. hello_1.2-3_amd64.deb
+    pub async fn remove(&self, name: &str) -> Result<Option<LocalPackage>> {
+        let mut packages = self.packages.write().await;
+        let Some(filename) = packages.keys().find(|filename| filename.rsplit('/').next() == Some(name)).cloned() else {
+            return Ok(None);
+        };
+        tokio::fs::remove_file(self.file(&filename)).await.map_err(|e| anyhow!("Failed to remove {}: {}", filename, e))?;
+        info!("Removed local package {}", filename);
+        Ok(packages.remove(&filename))
+    }
+
+    pub async fn list(&self) -> Vec<LocalPackage> {
+        self.packages.read().await.values().cloned().collect()
+    }
+
+    // Repository paths whose Release lists the local indices, for cache invalidation
+    pub fn release_paths(&self) -> Vec<String> {
+        self.config.suites
+            .iter()
+            .flat_map(|suite| ["InRelease", "Release"].map(|file| format!("/{}/dists/{}/{}", self.config.repository, suite, file)))
+            .collect()
+    }
+
+    fn relative<'a>(&self, repository_path: &'a str) -> Option<&'a str> {
+        repository_path.strip_prefix('/')?.strip_prefix(self.config.repository.as_str())?.strip_prefix('/')
+    }
+
+    // For policy checks and audit events on uploaded packages
+    pub async fn control(&self, repository_path: &str) -> Option<Arc<DebControl>> {
+        let filename = self.relative(repository_path)?;
+        self.packages.read().await.get(filename).map(|package| package.control.clone())
+    }
+
+    // Packages and Packages.gz for one architecture, relative to the suite
+    // directory. Architecture: all packages are listed for every architecture
+    fn indices(&self, packages: &BTreeMap<String, LocalPackage>, architecture: &str) -> Vec<(String, Vec<u8>)> {
+        let mut index = String::new();
+        for package in packages.values().filter(|p| p.architecture == architecture || p.architecture == "all") {
+            index.push_str(&package.control.to_stanza());
+            index.push_str(&format!("Filename: {}\nSize: {}\nSHA256: {}\n\n", package.filename, package.size, package.sha256));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder.write_all(index.as_bytes()).and_then(|_| encoder.finish()).unwrap_or_default();
+        let directory = format!("{}/binary-{}", self.config.component, architecture);
+        vec![(format!("{}/Packages", directory), index.into_bytes()), (format!("{}/Packages.gz", directory), compressed)]
+    }
+
+    // A pool file, a generated index or one of its by-hash names; None for
+    // paths outside the local component
+    pub async fn serve(&self, repository_path: &str) -> Option<Result<Bytes>> {
+        let relative = self.relative(repository_path)?;
+        let packages = self.packages.read().await;
+        if relative.starts_with(&format!("pool/{}/", self.config.component)) {
+            let package = packages.get(relative)?;
+            return Some(tokio::fs::read(self.file(&package.filename)).await.map(Bytes::from).map_err(|e| anyhow!("Failed to read {}: {}", package.filename, e)));
+        }
+        
+        let (suite, rest) = relative.strip_prefix("dists/")?.split_once('/')?;
+        let architecture = rest.strip_prefix(&format!("{}/binary-", self.config.component))?.split('/').next()?;
+        if !self.config.suites.iter().any(|configured| configured == suite) {
+            return None;
+        }
+        let indices = self.indices(&packages, architecture);
+        let by_hash = rest.rsplit_once("/by-hash/SHA256/").map(|(_, hash)| hash);
+        indices
+            .into_iter()
+            .find(|(name, data)| match by_hash {
+                Some(hash) => DigestAlgorithm::Sha256.compute(data) == hash,
+                None => name == rest,
+            })
+            .map(|(_, data)| Ok(Bytes::from(data)))
+    }
+
+    // The Release text of a configured suite with the local component added, and
+    // the index files it now lists. None for other repositories and suites
+    pub async fn merge_release(&self, repository_path: &str, release: &str) -> Option<(String, Vec<(String, Vec<u8>)>)> {
+        let relative = self.relative(repository_path)?;
+        let suite = relative.strip_prefix("dists/")?.split_once('/')?.0;
+        if !self.config.suites.iter().any(|configured| configured == suite) {
+            return None;
+        }
+        let architectures: Vec<&str> = release
+            .lines()
+            .find_map(|line| line.strip_prefix("Architectures:"))
+            .map(|value| value.split_whitespace().collect())
+            .unwrap_or_default();
+        let packages = self.packages.read().await;
+        let files: Vec<(String, Vec<u8>)> = architectures.iter().flat_map(|architecture| self.indices(&packages, architecture)).collect();
+        let entries: String = files
+            .iter()
+            .map(|(name, data)| format!(" {} {:>16} {}\n", DigestAlgorithm::Sha256.compute(data), data.len(), name))
+            .collect();
+        
+        let mut merged = String::with_capacity(release.len() + entries.len());
+        let mut in_sha256 = false;
+        for line in release.lines() {
+            if in_sha256 && !line.starts_with(char::is_whitespace) {
+                merged.push_str(&entries);
+                in_sha256 = false;
+            }
+            match line.strip_prefix("Components:") {
+                Some(components) if !components.split_whitespace().any(|c| c == self.config.component) => {
+                    merged.push_str(&format!("Components:{} {}\n", components, self.config.component));
+                }
+                _ => {
+                    merged.push_str(line);
+                    merged.push('\n');
+                }
+            }
+            in_sha256 |= line == "SHA256:";
+        }
+        if in_sha256 {
+            merged.push_str(&entries);
+        } else if !release.lines().any(|line| line == "SHA256:") {
+            merged.push_str("SHA256:\n");
+            merged.push_str(&entries);
+        }
+        Some((merged, files))
+    }
+
+    // Loads stored packages, then imports from the incoming directory if one is set
+    pub fn spawn(self: Arc<Self>, cache: Arc<CacheManager>) {
+        tokio::spawn(async move {
+            if let Err(e) = self.load().await {
+                warn!("Local packages not loaded: {}", e);
+            }
+            let Some(incoming) = self.config.incoming.clone() else {
+                return;
+            };
+            loop {
+                match self.scan_incoming(Path::new(&incoming)).await {
+                    Ok(0) => {}
+                    Ok(_) => {
+                        for path in self.release_paths() {
+                            cache.invalidate(&path).await;
+                        }
+                    }
+                    Err(e) => warn!("Failed to scan {}: {}", incoming, e),
+                }
+                tokio::time::sleep(Duration::from_secs(self.config.scan_interval_secs.max(1))).await;
+            }
+        });
+    }
+
+    async fn scan_incoming(&self, incoming: &Path) -> Result<usize> {
+        let mut imported = 0;
+        let mut entries = tokio::fs::read_dir(incoming).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            let settled = metadata.modified().ok().and_then(|modified| SystemTime::now().duration_since(modified).ok()).is_some_and(|age| age >= INCOMING_SETTLE_TIME);
+            if !metadata.is_file() || path.extension().is_none_or(|extension| extension != "deb") || !settled {
+                continue;
+            }
+            let data = tokio::fs::read(&path).await?;
+            match self.import(&data).await {
+                Ok(_) => {
+                    tokio::fs::remove_file(&path).await?;
+                    imported += 1;
+                }
+                Err(e) => {
+                    warn!("Rejected {}: {}", path.display(), e);
+                    tokio::fs::rename(&path, path.with_extension("deb.rejected")).await?;
+                }
+            }
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE: &str = "Suite: stable
+Codename: bookworm
+Architectures: all amd64
+Components: main contrib
+SHA256:
+ 0123 100 main/binary-amd64/Packages
+Acquire-By-Hash: yes
+";
+
+    #[tokio::test]
+    async fn test_indices_and_merged_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = LocalRepository::new(LocalPackagesConfig {
+            enabled: true,
+            directory: dir.path().to_str().unwrap().to_string(),
+            suites: vec!["bookworm".to_string()],
+            ..Default::default()
+        });
+        let control = DebControl::parse("Package: libhello\nVersion: 1:1.2-3\nArchitecture: amd64\nSection: libs\n").unwrap();
+        let package = local.add(control, b"deb").await.unwrap();
+        assert_eq!(package.filename, "pool/local/libh/libhello/libhello_1.2-3_amd64.deb");
+        let control = local.control("/debian/pool/local/libh/libhello/libhello_1.2-3_amd64.deb").await.unwrap();
+        assert_eq!(control.get("Section"), Some("libs"));
+        
+        let (merged, files) = local.merge_release("/debian/dists/bookworm/InRelease", RELEASE).await.unwrap();
+        assert!(merged.contains("Components: main contrib local\n"));
+        assert_eq!(files.len(), 4);
+        let packages = local.serve("/debian/dists/bookworm/local/binary-amd64/Packages").await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&packages).contains("Filename: pool/local/libh/libhello/libhello_1.2-3_amd64.deb\n"));
+        let entry = format!(" {} {:>16} local/binary-amd64/Packages\n", DigestAlgorithm::Sha256.compute(&packages), packages.len());
+        let position = |text: &str| merged.find(text).unwrap();
+        assert!(position(" 0123 100 main") < position(&entry) && position(&entry) < position("Acquire-By-Hash"));
+        assert!(merged.ends_with("Acquire-By-Hash: yes\n"));
+        
+        let by_hash = format!("/debian/dists/bookworm/local/binary-amd64/by-hash/SHA256/{}", DigestAlgorithm::Sha256.compute(&packages));
+        assert_eq!(local.serve(&by_hash).await.unwrap().unwrap(), packages);
+        let all = local.serve("/debian/dists/bookworm/local/binary-all/Packages").await.unwrap().unwrap();
+        assert!(all.is_empty());
+        assert!(local.serve("/debian/dists/trixie/local/binary-amd64/Packages").await.is_none());
+        assert!(local.serve("/debian/dists/bookworm/main/binary-amd64/Packages").await.is_none());
+        assert!(local.merge_release("/debian/dists/trixie/InRelease", RELEASE).await.is_none());
+    }
+}
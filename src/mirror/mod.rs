@@ -1,3 +1,8 @@
 pub mod fetch;
+pub mod latency;
 pub mod cache;
 pub mod path;
+pub mod snapshot;
+pub mod local;
+pub mod chaos;
+pub mod export;
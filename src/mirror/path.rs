@@ -3,19 +3,23 @@ pub struct PathParser;
 
 impl PathParser {
     pub fn parse_debian_path(path: &str) -> Result<DebianPath, String> {
-        if !path.starts_with("/debian/") {
-            return Err("Invalid Debian path".to_string());
-        }
-        
-        let remaining = &path[8..]; // Remove "/debian/"
+        // Any repository laid out like the Debian archive: /debian/..., /ubuntu/...
+        let (repository, remaining) = path
+            .strip_prefix('/')
+            .and_then(|p| p.split_once('/'))
+            .filter(|(repository, _)| !repository.is_empty())
+            .ok_or_else(|| "Invalid Debian path".to_string())?;
         
-        if remaining.starts_with("dists/") {
+        let mut debian_path = if remaining.starts_with("dists/") {
             Self::parse_release_path(remaining)
         } else if remaining.starts_with("pool/") {
             Self::parse_package_path(remaining)
         } else {
             Err("Unknown Debian path type".to_string())
-        }
+        }?;
+        
+        debian_path.repository = repository.to_string();
+        Ok(debian_path)
     }
     
     fn parse_release_path(path: &str) -> Result<DebianPath, String> {
@@ -29,6 +33,10 @@ impl PathParser {
         
         let suite = parts[1].to_string(); // bookworm, bullseye, etc.
         
+        if let Some(installer) = Self::parse_installer_path(&parts) {
+            return Ok(installer);
+        }
+        
         // Handle different path structures
         let (component, architecture, filename) = if parts.len() == 2 {
             // /debian/dists/bookworm/InRelease
@@ -66,6 +74,7 @@ impl PathParser {
         };
         
         Ok(DebianPath {
+            repository: String::new(),
             path_type: PathType::Release,
             suite,
             component,
@@ -74,6 +83,25 @@ impl PathParser {
         })
     }
     
+    fn parse_installer_path(parts: &[&str]) -> Option<DebianPath> {
+        // Example: dists/bookworm/main/installer-amd64/current/images/netboot/netboot.tar.gz
+        // Example: dists/bookworm/main/debian-installer/binary-amd64/Packages.gz
+        let architecture = match parts.get(3..5)? {
+            [installer, _] if installer.starts_with("installer-") => installer.to_string(),
+            ["debian-installer", binary] => binary.to_string(),
+            _ => return None,
+        };
+        
+        Some(DebianPath {
+            repository: String::new(),
+            path_type: PathType::Installer,
+            suite: parts[1].to_string(),
+            component: Some(parts[2].to_string()),
+            architecture: Some(architecture),
+            filename: parts.last().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+        })
+    }
+
     fn parse_package_path(path: &str) -> Result<DebianPath, String> {
         // Example: pool/main/a/apt/apt_2.6.1_amd64.deb (after removing /debian/)
         let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
@@ -88,6 +116,7 @@ impl PathParser {
         let filename = parts.last().map(|s| s.to_string());
         
         Ok(DebianPath {
+            repository: String::new(),
             path_type: PathType::Package,
             suite: String::new(), // Packages don't have suite in path
             component,
@@ -99,6 +128,7 @@ impl PathParser {
 
 #[derive(Debug, Clone)]
 pub struct DebianPath {
+    pub repository: String,
     pub path_type: PathType,
     pub suite: String,
     pub component: Option<String>,
@@ -110,6 +140,7 @@ pub struct DebianPath {
 pub enum PathType {
     Release,    // Release files, Packages indices
     Package,    // .deb files
+    Installer,  // debian-installer images and udeb indices
 }
 
 #[cfg(test)]
@@ -122,6 +153,7 @@ mod tests {
         let result = PathParser::parse_debian_path(path).unwrap();
         
         assert_eq!(result.path_type, PathType::Release);
+        assert_eq!(result.repository, "debian");
         assert_eq!(result.suite, "bookworm");
         assert!(result.component.is_none());
     }
@@ -150,8 +182,24 @@ mod tests {
 
     #[test]
     fn test_invalid_path() {
-        assert!(PathParser::parse_debian_path("/ubuntu/dists/noble/Release").is_err());
+        assert!(PathParser::parse_debian_path("/ubuntu").is_err());
+        assert!(PathParser::parse_debian_path("//dists/noble/Release").is_err());
         assert!(PathParser::parse_debian_path("/debian/invalid/path").is_err());
+        assert_eq!(PathParser::parse_debian_path("/ubuntu/dists/noble/Release").unwrap().repository, "ubuntu");
+    }
+
+    #[test]
+    fn test_parse_installer_paths() {
+        let netboot = PathParser::parse_debian_path("/debian/dists/bookworm/main/installer-amd64/current/images/netboot/netboot.tar.gz").unwrap();
+        assert_eq!(netboot.path_type, PathType::Installer);
+        assert_eq!(netboot.component.as_deref(), Some("main"));
+        assert_eq!(netboot.architecture.as_deref(), Some("installer-amd64"));
+        assert_eq!(netboot.filename.as_deref(), Some("netboot.tar.gz"));
+        
+        let udebs = PathParser::parse_debian_path("/debian/dists/bookworm/main/debian-installer/binary-arm64/Packages.xz").unwrap();
+        assert_eq!(udebs.path_type, PathType::Installer);
+        assert_eq!(udebs.component.as_deref(), Some("main"));
+        assert_eq!(udebs.architecture.as_deref(), Some("binary-arm64"));
     }
 
     #[test]
@@ -0,0 +1,293 @@
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use crate::verify::hashes::HashVerifier;
+use crate::verify::index::PackageIndexStore;
+
+// Written next to the staged Release files of a suite; apt never asks for it
+const VERIFIED_RELEASE: &str = ".verified-release.json";
+const RECORD: &str = "snapshot.json";
+const MAX_ID_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    // Holds the latest verified indices ("staging") and every captured snapshot
+    pub directory: String,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "/var/lib/aptg/snapshots".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    // Suite directories such as /debian/dists/bookworm
+    pub suites: Vec<String>,
+    // Full request paths of the captured Release and index files
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VerifiedRelease {
+    // InRelease or Release, whichever passed signature verification last
+    filename: String,
+    payload: String,
+}
+
+// Point-in-time copies of the verified Release and index files of a suite,
+// served under /snapshots/<id>/ so clients can pin to a reproducible state.
+// Pool files are not copied; they are immutable and served as usual
+pub struct SnapshotStore {
+    config: SnapshotConfig,
+    // Serializes captures so two cannot claim the same ID
+    lock: Mutex<()>,
+}
+
+impl SnapshotStore {
+    pub fn new(config: SnapshotConfig) -> Self {
+        Self {
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    // Letters, digits, '.', '-' and '_', not starting with '.'
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_ID_LENGTH
+            && !id.starts_with('.')
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    }
+
+    // Maps a request path below root, refusing anything that could leave it
+    fn local_path(root: &Path, path: &str) -> Option<PathBuf> {
+        let relative = path.strip_prefix('/')?;
+        if relative.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+            return None;
+        }
+        Some(root.join(relative))
+    }
+
+    fn staging(&self) -> PathBuf {
+        Path::new(&self.config.directory).join("staging")
+    }
+
+    fn snapshot_dir(&self, id: &str) -> PathBuf {
+        Path::new(&self.config.directory).join("captured").join(id)
+    }
+
+    async fn write_file(file: &Path, data: &[u8]) -> Result<()> {
+        let directory = file.parent().ok_or_else(|| anyhow!("{} has no parent directory", file.display()))?;
+        tokio::fs::create_dir_all(directory).await.map_err(|e| anyhow!("Failed to create {}: {}", directory.display(), e))?;
+        let partial = file.with_extension(format!("partial-{}", rand::random::<u32>()));
+        tokio::fs::write(&partial, data).await.map_err(|e| anyhow!("Failed to write {}: {}", partial.display(), e))?;
+        tokio::fs::rename(&partial, file).await.map_err(|e| anyhow!("Failed to rename {}: {}", partial.display(), e))
+    }
+
+    // Keeps the latest copy of a Release, Release.gpg or index file for the
+    // next capture. payload is the cleartext of a Release whose signature was
+    // verified; indices are checked against it when captured, not here
+    pub async fn stage(&self, path: &str, data: &[u8], payload: Option<&str>) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let staging = self.staging();
+        let file = Self::local_path(&staging, path).ok_or_else(|| anyhow!("Cannot stage {}", path))?;
+        Self::write_file(&file, data).await?;
+        if let Some(payload) = payload {
+            let (suite_dir, filename) = PackageIndexStore::split_suite_path(path).ok_or_else(|| anyhow!("Not a Release path: {}", path))?;
+            let verified = VerifiedRelease { filename: filename.to_string(), payload: payload.to_string() };
+            let record = Self::local_path(&staging, &format!("{}/{}", suite_dir, VERIFIED_RELEASE))
+                .ok_or_else(|| anyhow!("Cannot stage {}", path))?;
+            Self::write_file(&record, &serde_json::to_vec(&verified)?).await?;
+        }
+        Ok(())
+    }
+
+    // Staged files of one suite that match its verified Release, as request paths
+    async fn verified_files(&self, suite_dir: &str) -> Result<Vec<String>> {
+        let root = Self::local_path(&self.staging(), suite_dir).ok_or_else(|| anyhow!("Invalid suite {}", suite_dir))?;
+        let verified = tokio::fs::read(root.join(VERIFIED_RELEASE))
+            .await
+            .map_err(|_| anyhow!("No verified Release has been served for {}", suite_dir))?;
+        let verified: VerifiedRelease = serde_json::from_slice(&verified)?;
+        let digests = HashVerifier::parse_release_digests(&verified.payload)?;
+        
+        let mut files = vec![format!("{}/{}", suite_dir, verified.filename)];
+        if verified.filename == "Release" && root.join("Release.gpg").exists() {
+            files.push(format!("{}/Release.gpg", suite_dir));
+        }
+        let mut pending = vec![root.clone()];
+        while let Some(directory) = pending.pop() {
+            let mut entries = tokio::fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let file = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(file);
+                    continue;
+                }
+                let Some(relative) = file.strip_prefix(&root).ok().and_then(|r| r.to_str()) else {
+                    continue;
+                };
+                // Indices staged before the suite's latest Release may no longer match it
+                if digests.contains_key(relative) {
+                    let data = tokio::fs::read(&file).await?;
+                    match HashVerifier::verify_file_against_digests(&data, relative, &digests) {
+                        Ok(true) => files.push(format!("{}/{}", suite_dir, relative)),
+                        _ => warn!("Not capturing {}/{}: it does not match the verified Release", suite_dir, relative),
+                    }
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    pub async fn capture(&self, id: &str, suites: &[String]) -> Result<SnapshotRecord> {
+        if !Self::is_valid_id(id) {
+            return Err(anyhow!("Invalid snapshot ID '{}'", id));
+        }
+        if suites.is_empty() {
+            return Err(anyhow!("A snapshot needs at least one suite"));
+        }
+        let _guard = self.lock.lock().await;
+        let directory = self.snapshot_dir(id);
+        if directory.exists() {
+            return Err(anyhow!("Snapshot '{}' already exists", id));
+        }
+        
+        let mut files = Vec::new();
+        for suite in suites {
+            if PackageIndexStore::split_suite_path(&format!("{}/", suite)).map(|(suite_dir, _)| suite_dir) != Some(suite.as_str()) {
+                return Err(anyhow!("Not a suite directory: {}", suite));
+            }
+            files.extend(self.verified_files(suite).await?);
+        }
+        
+        let record = SnapshotRecord { id: id.to_string(), created_at: Utc::now(), suites: suites.to_vec(), files };
+        if let Err(e) = self.copy_files(&directory, &record).await {
+            let _ = tokio::fs::remove_dir_all(&directory).await;
+            return Err(e);
+        }
+        info!("Captured snapshot {} with {} files", id, record.files.len());
+        Ok(record)
+    }
+
+    async fn copy_files(&self, directory: &Path, record: &SnapshotRecord) -> Result<()> {
+        // Staged files are only ever replaced by rename, so linking them is safe
+        let staging = self.staging();
+        for path in &record.files {
+            let source = Self::local_path(&staging, path).ok_or_else(|| anyhow!("Invalid path {}", path))?;
+            let target = Self::local_path(&directory.join("files"), path).ok_or_else(|| anyhow!("Invalid path {}", path))?;
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            if tokio::fs::hard_link(&source, &target).await.is_err() {
+                tokio::fs::copy(&source, &target).await.map_err(|e| anyhow!("Failed to copy {}: {}", source.display(), e))?;
+            }
+        }
+        // Written last, so a failed capture is never listed or served
+        Self::write_file(&directory.join(RECORD), &serde_json::to_vec_pretty(record)?).await
+    }
+
+    pub async fn get(&self, id: &str) -> Option<SnapshotRecord> {
+        if !self.config.enabled || !Self::is_valid_id(id) {
+            return None;
+        }
+        let data = tokio::fs::read(self.snapshot_dir(id).join(RECORD)).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    pub async fn list(&self) -> Result<Vec<SnapshotRecord>> {
+        let mut records = Vec::new();
+        let mut dir = match tokio::fs::read_dir(Path::new(&self.config.directory).join("captured")).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(records),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = dir.next_entry().await? {
+            if let Some(record) = self.get(&entry.file_name().to_string_lossy()).await {
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|r| r.created_at);
+        Ok(records)
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        if self.get(id).await.is_none() {
+            return Ok(false);
+        }
+        let _guard = self.lock.lock().await;
+        tokio::fs::remove_dir_all(self.snapshot_dir(id)).await?;
+        info!("Deleted snapshot {}", id);
+        Ok(true)
+    }
+
+    // None for paths the snapshot did not capture
+    pub async fn read(&self, id: &str, path: &str) -> Option<Bytes> {
+        let record = self.get(id).await?;
+        if !record.files.iter().any(|file| file == path) {
+            return None;
+        }
+        let file = Self::local_path(&self.snapshot_dir(id).join("files"), path)?;
+        tokio::fs::read(file).await.ok().map(Bytes::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::hashes::DigestAlgorithm;
+
+    #[tokio::test]
+    async fn test_capture_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(SnapshotConfig { enabled: true, directory: dir.path().to_str().unwrap().to_string() });
+        let suites = vec!["/debian/dists/bookworm".to_string()];
+        let packages = b"Package: hello\n".to_vec();
+        let release = format!(
+            "Suite: stable\nSHA256:\n {} {} main/binary-amd64/Packages\n",
+            DigestAlgorithm::Sha256.compute(&packages),
+            packages.len()
+        );
+        assert!(store.capture("2026-10-16", &suites).await.is_err());
+        
+        store.stage("/debian/dists/bookworm/InRelease", b"signed", Some(&release)).await.unwrap();
+        store.stage("/debian/dists/bookworm/main/binary-amd64/Packages", &packages, None).await.unwrap();
+        store.stage("/debian/dists/bookworm/main/binary-arm64/Packages", b"not in the Release", None).await.unwrap();
+        let record = store.capture("2026-10-16", &suites).await.unwrap();
+        assert_eq!(record.files, vec![
+            "/debian/dists/bookworm/InRelease".to_string(),
+            "/debian/dists/bookworm/main/binary-amd64/Packages".to_string(),
+        ]);
+        assert!(store.capture("2026-10-16", &suites).await.is_err());
+        assert!(store.capture("../escape", &suites).await.is_err());
+        
+        // Later fetches do not change what the snapshot serves
+        store.stage("/debian/dists/bookworm/main/binary-amd64/Packages", b"Package: hello-newer\n", None).await.unwrap();
+        let served = store.read("2026-10-16", "/debian/dists/bookworm/main/binary-amd64/Packages").await.unwrap();
+        assert_eq!(&served[..], &packages[..]);
+        assert!(store.read("2026-10-16", "/debian/dists/bookworm/main/binary-arm64/Packages").await.is_none());
+        assert_eq!(store.list().await.unwrap().len(), 1);
+        assert!(store.delete("2026-10-16").await.unwrap());
+        assert!(store.get("2026-10-16").await.is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::geoip::policy::GeoPolicyEngine;
+
+// The GeoIP database is rebuilt weekly upstream; checking daily is enough
+const GEOIP_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    VerificationFailed,
+    UpstreamDown,
+    UpstreamRecovered,
+    CacheStorageFull,
+    CertificateExpiring,
+    GeoipStale,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::VerificationFailed => "verification_failed",
+            NotificationKind::UpstreamDown => "upstream_down",
+            NotificationKind::UpstreamRecovered => "upstream_recovered",
+            NotificationKind::CacheStorageFull => "cache_storage_full",
+            NotificationKind::CertificateExpiring => "certificate_expiring",
+            NotificationKind::GeoipStale => "geoip_stale",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            NotificationKind::VerificationFailed => "Verification failed",
+            NotificationKind::UpstreamDown => "Upstream down",
+            NotificationKind::UpstreamRecovered => "Upstream recovered",
+            NotificationKind::CacheStorageFull => "Cache storage full",
+            NotificationKind::CertificateExpiring => "Certificate expiring",
+            NotificationKind::GeoipStale => "GeoIP database stale",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    // Incoming webhook of a Slack (or Mattermost) channel
+    Slack,
+    // Incoming webhook connector of a Teams channel
+    Teams,
+    // A Notification object as-is
+    #[default]
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    // Kinds sent to this webhook; all when empty
+    pub events: Vec<NotificationKind>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            url: String::new(),
+            format: WebhookFormat::Json,
+            events: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub webhooks: Vec<WebhookConfig>,
+    // The same kind of event about the same subject is sent at most once per interval
+    pub repeat_interval_secs: u64,
+    // Consecutive failed fetches, other than 4xx answers, before an upstream counts as down
+    pub upstream_failures: u32,
+    pub geoip_max_age_days: i64,
+    pub timeout_ms: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: vec![],
+            repeat_interval_secs: 3600,
+            upstream_failures: 5,
+            geoip_max_age_days: 30,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: NotificationKind,
+    pub title: &'static str,
+    // What the event is about: a repository, upstream, cache layer or file
+    pub subject: String,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Notification {
+    fn payload(&self, format: WebhookFormat) -> serde_json::Value {
+        let text = format!("{}: {}", self.title, self.message);
+        match format {
+            WebhookFormat::Slack => serde_json::json!({"text": format!("[aptg] {}", text)}),
+            WebhookFormat::Teams => serde_json::json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": text,
+                "title": format!("[aptg] {}", self.title),
+                "text": self.message,
+            }),
+            WebhookFormat::Json => serde_json::to_value(self).unwrap_or_default(),
+        }
+    }
+}
+
+// Sends operational alerts to chat and other webhooks. Unlike the audit
+// stream, only a few kinds of events are sent, and repeats are held back
+pub struct Notifier {
+    config: NotificationsConfig,
+    client: reqwest::Client,
+    // When each (kind, subject) was last sent
+    sent: Mutex<HashMap<(NotificationKind, String), Instant>>,
+    // Consecutive failed fetches per upstream
+    upstreams: Mutex<HashMap<String, u32>>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationsConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .user_agent("aptg/0.1.0")
+            .build()
+            .expect("Failed to create HTTP client");
+        Self {
+            config,
+            client,
+            sent: Mutex::new(HashMap::new()),
+            upstreams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.webhooks.is_empty()
+    }
+
+    // False while the same notification is held back as a repeat
+    fn due(&self, kind: NotificationKind, subject: &str) -> bool {
+        let repeat = Duration::from_secs(self.config.repeat_interval_secs);
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (kind, subject.to_string());
+        if sent.get(&key).is_some_and(|last| last.elapsed() < repeat) {
+            return false;
+        }
+        sent.retain(|_, last| last.elapsed() < repeat);
+        sent.insert(key, Instant::now());
+        true
+    }
+
+    // Never waits; each webhook is posted to from its own task
+    pub fn notify(&self, kind: NotificationKind, subject: &str, message: String) {
+        if !self.is_enabled() || !self.due(kind, subject) {
+            return;
+        }
+        let notification = Notification {
+            event: kind,
+            title: kind.title(),
+            subject: subject.to_string(),
+            message,
+            timestamp: Utc::now(),
+        };
+        for webhook in self.config.webhooks.iter().filter(|w| w.events.is_empty() || w.events.contains(&kind)) {
+            let request = self.client.post(&webhook.url).json(&notification.payload(webhook.format));
+            let name = webhook.name.clone();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => info!("Sent {} notification to webhook '{}'", kind.as_str(), name),
+                    Err(e) => warn!("Webhook '{}' failed: {}", name, e.without_url()),
+                }
+            });
+        }
+    }
+
+    pub fn verification_failed(&self, repository: &str, path: &str, reason: &str) {
+        self.notify(NotificationKind::VerificationFailed, repository, format!("{}: {}", path, reason));
+    }
+
+    // The notification due for an upstream after a fetch, if any
+    fn record_upstream(&self, upstream: &str, failed: bool) -> Option<NotificationKind> {
+        let threshold = self.config.upstream_failures.max(1);
+        let mut upstreams = self.upstreams.lock().unwrap_or_else(|e| e.into_inner());
+        if failed {
+            let failures = upstreams.entry(upstream.to_string()).or_default();
+            *failures += 1;
+            (*failures == threshold).then_some(NotificationKind::UpstreamDown)
+        } else {
+            let failures = upstreams.remove(upstream).unwrap_or_default();
+            (failures >= threshold).then_some(NotificationKind::UpstreamRecovered)
+        }
+    }
+
+    pub fn upstream_fetched(&self, upstream: &str) {
+        if self.record_upstream(upstream, false).is_some() {
+            self.notify(NotificationKind::UpstreamRecovered, upstream, format!("{} is answering again", upstream));
+        }
+    }
+
+    pub fn upstream_failed(&self, upstream: &str, error: &anyhow::Error) {
+        if self.record_upstream(upstream, true).is_some() {
+            let message = format!("{} failed {} fetches in a row, the last with: {}", upstream, self.config.upstream_failures.max(1), error);
+            self.notify(NotificationKind::UpstreamDown, upstream, message);
+        }
+    }
+
+    // Checks the age of the loaded GeoIP database once a day
+    pub fn watch_geoip(self: &Arc<Self>, engine: Arc<GeoPolicyEngine>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Some(info) = engine.get_database_info() {
+                    let days = Utc::now().signed_duration_since(info.last_updated).num_days();
+                    if days > notifier.config.geoip_max_age_days {
+                        let message = format!("{} was built {} days ago", info.path, days);
+                        notifier.notify(NotificationKind::GeoipStale, &info.path, message);
+                    }
+                }
+                tokio::time::sleep(GEOIP_CHECK_INTERVAL).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_and_upstream_state() {
+        let notifier = Notifier::new(NotificationsConfig { upstream_failures: 2, ..Default::default() });
+        assert!(notifier.due(NotificationKind::UpstreamDown, "/debian"));
+        assert!(!notifier.due(NotificationKind::UpstreamDown, "/debian"));
+        assert!(notifier.due(NotificationKind::UpstreamDown, "/ubuntu"));
+        assert!(notifier.due(NotificationKind::UpstreamRecovered, "/debian"));
+        
+        assert_eq!(notifier.record_upstream("/debian", false), None);
+        assert_eq!(notifier.record_upstream("/debian", true), None);
+        assert_eq!(notifier.record_upstream("/debian", true), Some(NotificationKind::UpstreamDown));
+        assert_eq!(notifier.record_upstream("/debian", true), None);
+        assert_eq!(notifier.record_upstream("/debian", false), Some(NotificationKind::UpstreamRecovered));
+        assert_eq!(notifier.record_upstream("/debian", false), None);
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let notification = Notification {
+            event: NotificationKind::CertificateExpiring,
+            title: NotificationKind::CertificateExpiring.title(),
+            subject: "/etc/aptg/server.pem".to_string(),
+            message: "/etc/aptg/server.pem expires in 6 days".to_string(),
+            timestamp: Utc::now(),
+        };
+        assert_eq!(notification.payload(WebhookFormat::Slack)["text"], "[aptg] Certificate expiring: /etc/aptg/server.pem expires in 6 days");
+        assert_eq!(notification.payload(WebhookFormat::Teams)["title"], "[aptg] Certificate expiring");
+        let json = notification.payload(WebhookFormat::Json);
+        assert_eq!(json["event"], "certificate_expiring");
+        assert_eq!(json["subject"], "/etc/aptg/server.pem");
+        
+        let config: NotificationsConfig = toml::from_str("[[webhooks]]\nurl = \"https://hooks.example.org/x\"\nformat = \"teams\"\nevents = [\"upstream_down\", \"geoip_stale\"]\n").unwrap();
+        assert_eq!(config.webhooks[0].format, WebhookFormat::Teams);
+        assert_eq!(config.webhooks[0].events, vec![NotificationKind::UpstreamDown, NotificationKind::GeoipStale]);
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, error};
+use crate::policy::version::{compare_versions, DebFilename};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdvisoryFormat {
+    // OSV JSON: a single vulnerability object or an array of them
+    #[default]
+    Osv,
+    // One "<advisory-id> <package> [< | <= | = version]" entry per line
+    Denylist,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdvisoryConfig {
+    pub enabled: bool,
+    // http(s) URL or a local file path
+    pub feed_url: String,
+    pub format: AdvisoryFormat,
+    // OSV records are kept only for this Debian release, e.g. "Debian:12"
+    pub ecosystem: String,
+    pub refresh_interval_secs: u64,
+    // false logs affected packages without blocking them
+    pub enforce: bool,
+}
+
+impl Default for AdvisoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feed_url: String::new(),
+            format: AdvisoryFormat::Osv,
+            ecosystem: "Debian:12".to_string(),
+            refresh_interval_secs: 3600,
+            enforce: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AffectedRange {
+    pub introduced: Option<String>,
+    pub fixed: Option<String>,
+    pub last_affected: Option<String>,
+}
+
+impl AffectedRange {
+    fn contains(&self, version: &str) -> bool {
+        let introduced = self.introduced.as_deref().filter(|v| *v != "0");
+        introduced.is_none_or(|v| compare_versions(version, v) != Ordering::Less)
+            && self.fixed.as_deref().is_none_or(|v| compare_versions(version, v) == Ordering::Less)
+            && self.last_affected.as_deref().is_none_or(|v| compare_versions(version, v) != Ordering::Greater)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advisory {
+    pub id: String,
+    pub package: String,
+    pub ranges: Vec<AffectedRange>,
+    pub versions: Vec<String>,
+}
+
+impl Advisory {
+    pub fn affects(&self, version: &str) -> bool {
+        self.versions.iter().any(|v| compare_versions(version, v) == Ordering::Equal)
+            || self.ranges.iter().any(|range| range.contains(version))
+    }
+}
+
+#[derive(Deserialize)]
+struct OsvVulnerability {
+    id: String,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+}
+
+#[derive(Deserialize)]
+struct OsvAffected {
+    package: OsvPackage,
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OsvPackage {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct OsvRange {
+    #[serde(rename = "type")]
+    range_type: String,
+    #[serde(default)]
+    events: Vec<HashMap<String, String>>,
+}
+
+impl OsvRange {
+    // Events are ordered; each introduced opens a range that the next fixed or
+    // last_affected closes, e.g. introduced 0, fixed 1.2, introduced 2.0, fixed 2.3
+    fn affected_ranges(&self) -> Vec<AffectedRange> {
+        let mut ranges = Vec::new();
+        let mut open: Option<AffectedRange> = None;
+        for event in &self.events {
+            if let Some(version) = event.get("introduced") {
+                ranges.extend(open.take());
+                open = Some(AffectedRange { introduced: Some(version.clone()), ..Default::default() });
+            } else if let Some(version) = event.get("fixed") {
+                ranges.push(AffectedRange { fixed: Some(version.clone()), ..open.take().unwrap_or_default() });
+            } else if let Some(version) = event.get("last_affected") {
+                ranges.push(AffectedRange { last_affected: Some(version.clone()), ..open.take().unwrap_or_default() });
+            }
+        }
+        // Introduced without a fix affects every later version
+        ranges.extend(open);
+        ranges
+    }
+}
+
+pub struct AdvisoryStore {
+    advisories: RwLock<HashMap<String, Vec<Advisory>>>,
+}
+
+impl AdvisoryStore {
+    pub fn new() -> Self {
+        Self {
+            advisories: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn replace(&self, advisories: Vec<Advisory>) {
+        let mut by_package: HashMap<String, Vec<Advisory>> = HashMap::new();
+        for advisory in advisories {
+            by_package.entry(advisory.package.clone()).or_default().push(advisory);
+        }
+        
+        if let Ok(mut current) = self.advisories.write() {
+            *current = by_package;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.advisories.read().map(|a| a.values().map(Vec::len).sum()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the ID of the first advisory affecting the package version
+    pub fn find(&self, deb: &DebFilename) -> Option<String> {
+        let advisories = self.advisories.read().ok()?;
+        advisories
+            .get(&deb.name)?
+            .iter()
+            .find(|advisory| advisory.affects(&deb.version))
+            .map(|advisory| advisory.id.clone())
+    }
+
+    // Every advisory affecting the package version
+    pub fn affecting(&self, package: &str, version: &str) -> Vec<String> {
+        let Ok(advisories) = self.advisories.read() else {
+            return vec![];
+        };
+        advisories
+            .get(package)
+            .map(|advisories| advisories.iter().filter(|a| a.affects(version)).map(|a| a.id.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for AdvisoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct AdvisoryFeed {
+    config: AdvisoryConfig,
+    store: Arc<AdvisoryStore>,
+}
+
+impl AdvisoryFeed {
+    pub fn new(config: AdvisoryConfig, store: Arc<AdvisoryStore>) -> Self {
+        Self { config, store }
+    }
+
+    // Denylist entries carry no ecosystem and always apply
+    pub fn parse(format: AdvisoryFormat, content: &str, ecosystem: &str) -> Result<Vec<Advisory>> {
+        match format {
+            AdvisoryFormat::Osv => Self::parse_osv(content, ecosystem),
+            AdvisoryFormat::Denylist => Self::parse_denylist(content),
+        }
+    }
+
+    fn parse_osv(content: &str, ecosystem: &str) -> Result<Vec<Advisory>> {
+        let vulnerabilities: Vec<OsvVulnerability> = if content.trim_start().starts_with('[') {
+            serde_json::from_str(content)?
+        } else {
+            vec![serde_json::from_str(content)?]
+        };
+        
+        let mut advisories = Vec::new();
+        for vulnerability in vulnerabilities {
+            // Each Debian release is its own ecosystem with its own fixed versions,
+            // so a range for "Debian:11" says nothing about "Debian:12"
+            for affected in vulnerability.affected.into_iter().filter(|a| a.package.ecosystem == ecosystem) {
+                let ranges = affected
+                    .ranges
+                    .iter()
+                    .filter(|range| range.range_type == "ECOSYSTEM")
+                    .flat_map(OsvRange::affected_ranges)
+                    .collect();
+                
+                advisories.push(Advisory {
+                    id: vulnerability.id.clone(),
+                    package: affected.package.name,
+                    ranges,
+                    versions: affected.versions,
+                });
+            }
+        }
+        
+        Ok(advisories)
+    }
+
+    fn parse_denylist(content: &str) -> Result<Vec<Advisory>> {
+        let mut advisories = Vec::new();
+        
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let (range, versions) = match parts.as_slice() {
+                [_, _] => (AffectedRange::default(), vec![]),
+                [_, _, "<", version] => (AffectedRange { fixed: Some(version.to_string()), ..Default::default() }, vec![]),
+                [_, _, "<=", version] => (AffectedRange { last_affected: Some(version.to_string()), ..Default::default() }, vec![]),
+                [_, _, "=", version] => (AffectedRange::default(), vec![version.to_string()]),
+                _ => return Err(anyhow!("Invalid denylist entry '{}'", line)),
+            };
+            
+            advisories.push(Advisory {
+                id: parts[0].to_string(),
+                package: parts[1].to_string(),
+                ranges: if versions.is_empty() { vec![range] } else { vec![] },
+                versions,
+            });
+        }
+        
+        Ok(advisories)
+    }
+
+    async fn download(&self) -> Result<String> {
+        let url = &self.config.feed_url;
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let response = reqwest::get(url).await?.error_for_status()?;
+            Ok(response.text().await?)
+        } else {
+            Ok(tokio::fs::read_to_string(url.trim_start_matches("file://")).await?)
+        }
+    }
+
+    pub async fn refresh(&self) -> Result<usize> {
+        let content = self.download().await?;
+        let advisories = Self::parse(self.config.format, &content, &self.config.ecosystem)?;
+        let count = advisories.len();
+        
+        self.store.replace(advisories);
+        info!("Loaded {} advisories from {}", count, self.config.feed_url);
+        Ok(count)
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.config.refresh_interval_secs.max(60)));
+            loop {
+                interval.tick().await;
+                // Keep the previous advisories when a refresh fails
+                if let Err(e) = self.refresh().await {
+                    error!("Failed to refresh advisory feed {}: {}", self.config.feed_url, e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OSV: &str = r#"[{
+        "id": "DSA-5532-1",
+        "affected": [{
+            "package": {"ecosystem": "Debian:12", "name": "openssl"},
+            "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.11-1~deb12u2"}]}]
+        }, {
+            "package": {"ecosystem": "PyPI", "name": "openssl"},
+            "versions": ["1.0"]
+        }]
+    }]"#;
+
+    fn deb(filename: &str) -> DebFilename {
+        DebFilename::parse(filename).unwrap()
+    }
+
+    #[test]
+    fn test_osv_ranges() {
+        let store = AdvisoryStore::default();
+        assert!(store.is_empty());
+        store.replace(AdvisoryFeed::parse(AdvisoryFormat::Osv, OSV, "Debian:12").unwrap());
+        
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.find(&deb("openssl_3.0.11-1~deb12u1_amd64.deb")), Some("DSA-5532-1".to_string()));
+        assert_eq!(store.find(&deb("openssl_3.0.11-1~deb12u2_amd64.deb")), None);
+    }
+
+    #[test]
+    fn test_osv_ranges_per_release() {
+        let osv = r#"{
+            "id": "DSA-5532-1",
+            "affected": [{
+                "package": {"ecosystem": "Debian:11", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.12-1~deb11u1"}]}]
+            }, {
+                "package": {"ecosystem": "Debian:12", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.11-1~deb12u2"}]}]
+            }]
+        }"#;
+        let bookworm = AdvisoryStore::new();
+        bookworm.replace(AdvisoryFeed::parse(AdvisoryFormat::Osv, osv, "Debian:12").unwrap());
+        let bullseye = AdvisoryStore::new();
+        bullseye.replace(AdvisoryFeed::parse(AdvisoryFormat::Osv, osv, "Debian:11").unwrap());
+        
+        // Fixed in bookworm, though older than the bullseye fix
+        assert_eq!(bookworm.len(), 1);
+        assert_eq!(bookworm.find(&deb("openssl_3.0.11-1~deb12u2_amd64.deb")), None);
+        assert!(bookworm.find(&deb("openssl_3.0.11-1~deb12u1_amd64.deb")).is_some());
+        assert!(bullseye.find(&deb("openssl_3.0.11-1~deb12u2_amd64.deb")).is_some());
+        assert_eq!(bullseye.find(&deb("openssl_3.0.12-1~deb11u1_amd64.deb")), None);
+    }
+
+    #[test]
+    fn test_osv_several_ranges() {
+        let osv = r#"{
+            "id": "DLA-1234-1",
+            "affected": [{
+                "package": {"ecosystem": "Debian", "name": "libfoo"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [
+                    {"introduced": "0"}, {"fixed": "1.2-1"}, {"introduced": "2.0-1"}, {"fixed": "2.3-1"}, {"introduced": "3.0-1"}
+                ]}]
+            }]
+        }"#;
+        let advisories = AdvisoryFeed::parse(AdvisoryFormat::Osv, osv, "Debian").unwrap();
+        assert_eq!(advisories[0].ranges.len(), 3);
+        let store = AdvisoryStore::new();
+        store.replace(advisories);
+        
+        for (version, affected) in [("1.1-1", true), ("1.2-1", false), ("1.9-1", false), ("2.0-1", true), ("2.3-1", false), ("3.1-1", true)] {
+            assert_eq!(store.find(&deb(&format!("libfoo_{}_amd64.deb", version))).is_some(), affected, "{}", version);
+        }
+    }
+
+    #[test]
+    fn test_denylist_entries() {
+        let denylist = "# local advisories\nSEC-1 libfoo < 1.2-3\nSEC-2 libbar = 2.0-1\nSEC-3 badpkg\n";
+        let store = AdvisoryStore::new();
+        store.replace(AdvisoryFeed::parse(AdvisoryFormat::Denylist, denylist, "Debian:12").unwrap());
+        
+        assert_eq!(store.find(&deb("libfoo_1.2-2_amd64.deb")), Some("SEC-1".to_string()));
+        assert_eq!(store.find(&deb("libfoo_1.2-3_amd64.deb")), None);
+        assert_eq!(store.find(&deb("libbar_2.0-1_amd64.deb")), Some("SEC-2".to_string()));
+        assert_eq!(store.find(&deb("badpkg_9.9_all.deb")), Some("SEC-3".to_string()));
+        assert!(AdvisoryFeed::parse(AdvisoryFormat::Denylist, "SEC-4 pkg ~ 1.0", "Debian:12").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+use crate::geoip::location::LocationInfo;
+use crate::mirror::path::{PathParser, PathType};
+use crate::tls::identity::ClientIdentity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExternalFormat {
+    // Request context posted as-is; answer is an ExternalAnswer object
+    #[default]
+    Webhook,
+    // Context wrapped in {"input": ...}; answer read from "result" (an object or a bool)
+    Opa,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureMode {
+    // Serve the request when the endpoint errors or times out
+    Open,
+    #[default]
+    Closed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExternalPolicyConfig {
+    pub enabled: bool,
+    // e.g. http://localhost:8181/v1/data/aptg/decision for OPA
+    pub url: String,
+    pub format: ExternalFormat,
+    pub timeout_ms: u64,
+    pub failure_mode: FailureMode,
+    pub bearer_token: Option<String>,
+}
+
+impl Default for ExternalPolicyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            format: ExternalFormat::Webhook,
+            timeout_ms: 500,
+            failure_mode: FailureMode::Closed,
+            bearer_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalRequest {
+    pub path: String,
+    pub method: String,
+    pub repository: Option<String>,
+    pub path_type: Option<&'static str>,
+    pub suite: Option<String>,
+    pub component: Option<String>,
+    pub architecture: Option<String>,
+    pub filename: Option<String>,
+    pub section: Option<String>,
+    pub client_ip: Option<String>,
+    pub geo: Option<LocationInfo>,
+    // Set by the router on mutual TLS connections
+    pub client_identity: Option<ClientIdentity>,
+    // Set by the router for /t/<tenant>/ requests; path is then within the tenant
+    pub tenant: Option<String>,
+}
+
+impl ExternalRequest {
+    pub fn new(path: &str, method: &str, client_ip: Option<&str>, section: Option<&str>, geo: Option<LocationInfo>) -> Self {
+        let parsed = PathParser::parse_debian_path(path).ok();
+        Self {
+            path: path.to_string(),
+            method: method.to_string(),
+            repository: parsed.as_ref().map(|p| p.repository.clone()),
+            path_type: parsed.as_ref().map(|p| match p.path_type {
+                PathType::Release => "release",
+                PathType::Package => "package",
+                PathType::Installer => "installer",
+            }),
+            suite: parsed.as_ref().map(|p| p.suite.clone()).filter(|s| !s.is_empty()),
+            component: parsed.as_ref().and_then(|p| p.component.clone()),
+            architecture: parsed.as_ref().and_then(|p| p.architecture.clone()),
+            filename: parsed.as_ref().and_then(|p| p.filename.clone()),
+            section: section.map(str::to_string),
+            client_ip: client_ip.map(str::to_string),
+            geo,
+            client_identity: None,
+            tenant: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum ExternalAnswer {
+    Allow {
+        rule: Option<String>,
+    },
+    Deny {
+        rule: Option<String>,
+        reason: Option<String>,
+    },
+    RateLimit {
+        rule: Option<String>,
+        retry_after_secs: Option<u64>,
+    },
+}
+
+impl ExternalAnswer {
+    pub fn parse(format: ExternalFormat, body: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(body)?;
+        let answer = match format {
+            ExternalFormat::Webhook => value,
+            ExternalFormat::Opa => value
+                .get("result")
+                .cloned()
+                .ok_or_else(|| anyhow!("OPA response has no result (undefined decision)"))?,
+        };
+        
+        match answer {
+            serde_json::Value::Bool(true) => Ok(Self::Allow { rule: None }),
+            serde_json::Value::Bool(false) => Ok(Self::Deny { rule: None, reason: None }),
+            answer => Ok(serde_json::from_value(answer)?),
+        }
+    }
+}
+
+pub struct ExternalPolicy {
+    config: ExternalPolicyConfig,
+    client: reqwest::Client,
+}
+
+impl ExternalPolicy {
+    pub fn new(config: ExternalPolicyConfig) -> Result<Self> {
+        reqwest::Url::parse(&config.url).map_err(|e| anyhow!("Invalid external policy URL '{}': {}", config.url, e))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self { config, client })
+    }
+
+    async fn query(&self, request: &ExternalRequest) -> Result<ExternalAnswer> {
+        let body = match self.config.format {
+            ExternalFormat::Webhook => serde_json::to_value(request)?,
+            ExternalFormat::Opa => serde_json::json!({ "input": request }),
+        };
+        
+        let mut builder = self.client.post(&self.config.url).json(&body);
+        if let Some(token) = &self.config.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+        let response = builder.send().await?.error_for_status()?;
+        ExternalAnswer::parse(self.config.format, &response.text().await?)
+    }
+
+    // Never fails: endpoint errors are resolved with the configured failure mode
+    pub async fn check(&self, request: &ExternalRequest) -> ExternalAnswer {
+        match self.query(request).await {
+            Ok(answer) => answer,
+            Err(e) => {
+                warn!("External policy {} failed for {}: {}", self.config.url, request.path, e);
+                match self.config.failure_mode {
+                    FailureMode::Open => ExternalAnswer::Allow { rule: Some("external.fail-open".to_string()) },
+                    FailureMode::Closed => ExternalAnswer::Deny {
+                        rule: Some("external.fail-closed".to_string()),
+                        reason: Some(format!("External policy unavailable: {}", e)),
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answers() {
+        assert_eq!(
+            ExternalAnswer::parse(ExternalFormat::Webhook, r#"{"decision": "deny", "rule": "no-games", "reason": "games"}"#).unwrap(),
+            ExternalAnswer::Deny { rule: Some("no-games".to_string()), reason: Some("games".to_string()) }
+        );
+        assert_eq!(
+            ExternalAnswer::parse(ExternalFormat::Opa, r#"{"result": {"decision": "rate_limit", "retry_after_secs": 30}}"#).unwrap(),
+            ExternalAnswer::RateLimit { rule: None, retry_after_secs: Some(30) }
+        );
+        assert_eq!(
+            ExternalAnswer::parse(ExternalFormat::Opa, r#"{"result": true}"#).unwrap(),
+            ExternalAnswer::Allow { rule: None }
+        );
+        assert!(ExternalAnswer::parse(ExternalFormat::Opa, "{}").is_err());
+        assert!(ExternalAnswer::parse(ExternalFormat::Webhook, r#"{"decision": "maybe"}"#).is_err());
+    }
+
+    #[test]
+    fn test_request_context() {
+        let request = ExternalRequest::new("/debian/pool/main/a/apt/apt_2.6.1_amd64.deb", "GET", Some("10.0.0.1"), Some("admin"), None);
+        
+        assert_eq!(request.repository.as_deref(), Some("debian"));
+        assert_eq!(request.path_type, Some("package"));
+        assert_eq!(request.suite, None);
+        assert_eq!(request.filename.as_deref(), Some("apt_2.6.1_amd64.deb"));
+    }
+
+    #[tokio::test]
+    async fn test_failure_modes() {
+        let request = ExternalRequest::new("/debian/dists/bookworm/InRelease", "GET", None, None, None);
+        let config = ExternalPolicyConfig {
+            enabled: true,
+            // Nothing listens on the discard port
+            url: "http://127.0.0.1:9/decision".to_string(),
+            ..ExternalPolicyConfig::default()
+        };
+        
+        let closed = ExternalPolicy::new(config.clone()).unwrap();
+        assert!(matches!(closed.check(&request).await, ExternalAnswer::Deny { .. }));
+        
+        let open = ExternalPolicy::new(ExternalPolicyConfig { failure_mode: FailureMode::Open, ..config }).unwrap();
+        assert!(matches!(open.check(&request).await, ExternalAnswer::Allow { .. }));
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{Result, anyhow};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+use std::collections::HashSet;
+use tracing::error;
+
+// Matches package names against a mix of patterns:
+//   "apt"                 exact name
+//   "nvidia-*"            glob (any of * ? [ present)
+//   "^linux-image-.*-rt"  regex (leading ^)
+#[derive(Debug, Clone)]
+pub struct PackageMatcher {
+    exact: HashSet<String>,
+    globs: GlobSet,
+    regexes: RegexSet,
+}
+
+impl Default for PackageMatcher {
+    fn default() -> Self {
+        Self {
+            exact: HashSet::new(),
+            globs: GlobSet::empty(),
+            regexes: RegexSet::empty(),
+        }
+    }
+}
+
+impl PackageMatcher {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut exact = HashSet::new();
+        let mut globs = GlobSetBuilder::new();
+        let mut regexes = Vec::new();
+        
+        for pattern in patterns {
+            if pattern.starts_with('^') {
+                regexes.push(pattern.clone());
+            } else if pattern.contains(['*', '?', '[']) {
+                globs.add(Glob::new(pattern).map_err(|e| anyhow!("Invalid glob '{}': {}", pattern, e))?);
+            } else {
+                exact.insert(pattern.clone());
+            }
+        }
+        
+        Ok(Self {
+            exact,
+            globs: globs.build()?,
+            regexes: RegexSet::new(&regexes).map_err(|e| anyhow!("Invalid regex: {}", e))?,
+        })
+    }
+
+    // Like new(), but drops (and logs) invalid patterns instead of failing
+    pub fn new_lenient(patterns: &[String]) -> Self {
+        let valid: Vec<String> = patterns
+            .iter()
+            .filter(|pattern| match Self::new(std::slice::from_ref(*pattern)) {
+                Ok(_) => true,
+                Err(e) => {
+                    error!("Ignoring package pattern: {}", e);
+                    false
+                }
+            })
+            .cloned()
+            .collect();
+        
+        Self::new(&valid).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.globs.is_empty() && self.regexes.is_empty()
+    }
+
+    pub fn is_match(&self, package_name: &str) -> bool {
+        self.exact.contains(package_name)
+            || self.globs.is_match(package_name)
+            || self.regexes.is_match(package_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_pattern_kinds() {
+        let matcher = PackageMatcher::new(&patterns(&["apt", "nvidia-*", "^linux-image-.*-rt"])).unwrap();
+        
+        assert!(matcher.is_match("apt"));
+        assert!(!matcher.is_match("apt-utils"));
+        assert!(matcher.is_match("nvidia-driver"));
+        assert!(matcher.is_match("linux-image-6.1.0-13-rt-amd64"));
+        assert!(!matcher.is_match("linux-image-amd64"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(PackageMatcher::new(&patterns(&["^linux-(image"])).is_err());
+        
+        let lenient = PackageMatcher::new_lenient(&patterns(&["^linux-(image", "nvidia-*"]));
+        assert!(lenient.is_match("nvidia-smi"));
+        assert!(!lenient.is_empty());
+    }
+}
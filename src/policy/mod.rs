@@ -1 +1,10 @@
+pub mod advisories;
+pub mod external;
+pub mod matcher;
+pub mod osv;
+pub mod priority;
+pub mod reload;
+pub mod roles;
 pub mod rules;
+pub mod tester;
+pub mod version;
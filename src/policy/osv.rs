@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, error};
+use crate::policy::advisories::{AdvisoryFeed, AdvisoryFormat, AdvisoryStore};
+use crate::policy::external::FailureMode;
+
+// Answers kept before expired ones are dropped, and all of them if none has expired
+const MAX_CACHED_RESULTS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsvAction {
+    // Serve the package; findings go to the audit log and decision headers
+    #[default]
+    Annotate,
+    Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OsvConfig {
+    pub enabled: bool,
+    pub action: OsvAction,
+    pub api_url: String,
+    // OSV records Debian vulnerabilities per release, e.g. "Debian:12"
+    pub ecosystem: String,
+    // Directory of OSV JSON records, such as the unzipped Debian export,
+    // queried instead of the API. Records of other ecosystems are skipped
+    pub database: Option<String>,
+    pub refresh_interval_secs: u64,
+    pub timeout_ms: u64,
+    // How long an answer is reused for the same package version
+    pub cache_ttl_secs: u64,
+    // Vulnerability IDs never reported, e.g. disputed CVEs
+    pub ignore: Vec<String>,
+    // Whether packages are served while OSV cannot be asked
+    pub failure_mode: FailureMode,
+}
+
+impl Default for OsvConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: OsvAction::Annotate,
+            api_url: "https://api.osv.dev/v1/query".to_string(),
+            ecosystem: "Debian:12".to_string(),
+            database: None,
+            refresh_interval_secs: 3600,
+            timeout_ms: 2000,
+            cache_ttl_secs: 3600,
+            ignore: vec![],
+            failure_mode: FailureMode::Open,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OsvQuery<'a> {
+    version: &'a str,
+    package: OsvQueryPackage<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OsvQueryPackage<'a> {
+    name: &'a str,
+    ecosystem: &'a str,
+}
+
+// The API answers {} when nothing is known
+#[derive(Debug, Default, Deserialize)]
+struct OsvResponse {
+    #[serde(default)]
+    vulns: Vec<OsvFinding>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvFinding {
+    id: String,
+}
+
+// When a package version was looked up and the vulnerability IDs found
+type ScanResult = (Instant, Arc<Vec<String>>);
+
+// Looks up the vulnerabilities of each package version served, on OSV.dev or
+// in a local copy of its records
+pub struct OsvScanner {
+    config: OsvConfig,
+    client: reqwest::Client,
+    database: Option<Arc<AdvisoryStore>>,
+    results: Mutex<HashMap<(String, String), ScanResult>>,
+}
+
+impl OsvScanner {
+    pub fn new(config: OsvConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            database: config.database.is_some().then(|| Arc::new(AdvisoryStore::new())),
+            config,
+            client,
+            results: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn blocks(&self) -> bool {
+        self.config.action == OsvAction::Block
+    }
+
+    pub fn fails_closed(&self) -> bool {
+        self.config.failure_mode == FailureMode::Closed
+    }
+
+    async fn load_database(&self, directory: &str, store: &AdvisoryStore) -> Result<usize> {
+        let mut entries = tokio::fs::read_dir(directory).await.map_err(|e| anyhow!("Failed to read {}: {}", directory, e))?;
+        let mut advisories = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                let content = tokio::fs::read_to_string(&path).await?;
+                advisories.extend(AdvisoryFeed::parse(AdvisoryFormat::Osv, &content, &self.config.ecosystem).map_err(|e| anyhow!("{}: {}", path.display(), e))?);
+            }
+        }
+        let count = advisories.len();
+        store.replace(advisories);
+        // Answers from the previous records may be out of date
+        self.results.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        info!("Loaded {} OSV records from {}", count, directory);
+        Ok(count)
+    }
+
+    // Loads the local database now and again every refresh interval
+    pub fn spawn(self: Arc<Self>) {
+        let (Some(directory), Some(store)) = (self.config.database.clone(), self.database.clone()) else {
+            return;
+        };
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.config.refresh_interval_secs.max(60)));
+            loop {
+                interval.tick().await;
+                // Keep the previous records when a reload fails
+                if let Err(e) = self.load_database(&directory, &store).await {
+                    error!("Failed to load OSV database: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn query(&self, package: &str, version: &str) -> Result<Vec<String>> {
+        if let Some(store) = &self.database {
+            return Ok(store.affecting(package, version));
+        }
+        let mut ids = Vec::new();
+        let mut page_token = None;
+        loop {
+            let query = OsvQuery {
+                version,
+                package: OsvQueryPackage { name: package, ecosystem: &self.config.ecosystem },
+                page_token,
+            };
+            let response = self.client.post(&self.config.api_url).json(&query).send().await?.error_for_status()?;
+            let response: OsvResponse = serde_json::from_str(&response.text().await?)?;
+            ids.extend(response.vulns.into_iter().map(|finding| finding.id));
+            match response.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => return Ok(ids),
+            }
+        }
+    }
+
+    // IDs of the vulnerabilities affecting the (source) package version, minus ignored ones
+    pub async fn scan(&self, package: &str, version: &str) -> Result<Arc<Vec<String>>> {
+        let key = (package.to_string(), version.to_string());
+        let ttl = Duration::from_secs(self.config.cache_ttl_secs);
+        if let Some((checked, ids)) = self.results.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            if checked.elapsed() < ttl {
+                return Ok(ids.clone());
+            }
+        }
+        
+        let mut ids = self.query(package, version).await?;
+        ids.retain(|id| !self.config.ignore.contains(id));
+        ids.sort();
+        ids.dedup();
+        let ids = Arc::new(ids);
+        
+        let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        if results.len() >= MAX_CACHED_RESULTS {
+            results.retain(|_, (checked, _)| checked.elapsed() < ttl);
+            if results.len() >= MAX_CACHED_RESULTS {
+                results.clear();
+            }
+        }
+        results.insert(key, (Instant::now(), ids.clone()));
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scan_local_database() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("DSA-5532-1.json"), r#"{
+            "id": "DSA-5532-1",
+            "affected": [{
+                "package": {"ecosystem": "Debian:12", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.11-1~deb12u2"}]}]
+            }]
+        }"#).unwrap();
+        std::fs::write(dir.path().join("DEBIAN-CVE-2023-0001.json"), r#"{
+            "id": "DEBIAN-CVE-2023-0001",
+            "affected": [{"package": {"ecosystem": "Debian:12", "name": "openssl"}, "versions": ["3.0.11-1~deb12u1"]}]
+        }"#).unwrap();
+        // Only bullseye is affected by this one
+        std::fs::write(dir.path().join("DLA-3530-1.json"), r#"{
+            "id": "DLA-3530-1",
+            "affected": [{
+                "package": {"ecosystem": "Debian:11", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.12-1~deb11u1"}]}]
+            }]
+        }"#).unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a record").unwrap();
+        
+        let database = dir.path().to_str().unwrap().to_string();
+        let scanner = OsvScanner::new(OsvConfig {
+            enabled: true,
+            database: Some(database.clone()),
+            ignore: vec!["DEBIAN-CVE-2023-0001".to_string()],
+            ..OsvConfig::default()
+        })
+        .unwrap();
+        let store = scanner.database.clone().unwrap();
+        assert_eq!(scanner.load_database(&database, &store).await.unwrap(), 2);
+        
+        assert_eq!(*scanner.scan("openssl", "3.0.11-1~deb12u1").await.unwrap(), vec!["DSA-5532-1".to_string()]);
+        assert!(scanner.scan("openssl", "3.0.11-1~deb12u2").await.unwrap().is_empty());
+        assert!(scanner.scan("libssl3", "3.0.11-1~deb12u1").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_api_response() {
+        let response: OsvResponse = serde_json::from_str(r#"{"vulns": [{"id": "DSA-5532-1", "modified": "2023-10-24T00:00:00Z"}], "next_page_token": "abc"}"#).unwrap();
+        assert_eq!(response.vulns[0].id, "DSA-5532-1");
+        assert_eq!(response.next_page_token.as_deref(), Some("abc"));
+        assert!(serde_json::from_str::<OsvResponse>("{}").unwrap().vulns.is_empty());
+    }
+}
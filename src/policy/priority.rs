@@ -0,0 +1,53 @@
+// Rule selection shared by the path policy and the GeoIP policy engines:
+// among enabled rules that match, the highest priority wins and ties go to
+// the rule listed first.
+pub trait PrioritizedRule {
+    fn priority(&self) -> u8;
+    fn is_enabled(&self) -> bool;
+}
+
+pub fn select_rule<'a, R, I, F>(rules: I, mut matches: F) -> Option<&'a R>
+where
+    R: PrioritizedRule + 'a,
+    I: IntoIterator<Item = &'a R>,
+    F: FnMut(&R) -> bool,
+{
+    let mut selected: Option<&'a R> = None;
+    for rule in rules {
+        if !rule.is_enabled() || selected.is_some_and(|s| s.priority() >= rule.priority()) {
+            continue;
+        }
+        if matches(rule) {
+            selected = Some(rule);
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rule(&'static str, u8, bool);
+
+    impl PrioritizedRule for Rule {
+        fn priority(&self) -> u8 {
+            self.1
+        }
+        
+        fn is_enabled(&self) -> bool {
+            self.2
+        }
+    }
+
+    #[test]
+    fn test_highest_priority_then_first_listed() {
+        let rules = [Rule("low", 0, true), Rule("high", 50, true), Rule("tie", 50, true), Rule("off", 99, false)];
+        
+        assert_eq!(select_rule(&rules, |_| true).map(|r| r.0), Some("high"));
+        assert_eq!(select_rule(&rules, |r| r.0 != "high").map(|r| r.0), Some("tie"));
+        // Priority 0 is a real priority, not "unset"
+        assert_eq!(select_rule(&rules, |r| r.0 == "low").map(|r| r.0), Some("low"));
+        assert!(select_rule(&rules, |r| r.0 == "off").is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Result, anyhow};
+use arc_swap::ArcSwap;
+use notify::{RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, error};
+use crate::config::settings::AppConfig;
+use crate::policy::rules::{PolicyConfig, PolicyEngine};
+
+pub type SharedPolicy = Arc<ArcSwap<PolicyEngine>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicySource {
+    // The [policy] section of the main configuration file
+    AppConfig(String),
+    // A standalone policy file referenced by policy_file
+    PolicyFile(String),
+}
+
+impl PolicySource {
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        match (&config.policy_file, &config.config_path) {
+            (Some(policy_file), _) => Some(Self::PolicyFile(policy_file.clone())),
+            (None, Some(config_path)) => Some(Self::AppConfig(config_path.clone())),
+            (None, None) => None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Self::AppConfig(path) | Self::PolicyFile(path) => path,
+        }
+    }
+
+    fn load(&self) -> Result<PolicyConfig> {
+        match self {
+            Self::AppConfig(path) => Ok(AppConfig::load_from_file(path)?.policy),
+            Self::PolicyFile(path) => PolicyConfig::load_from_file(path),
+        }
+    }
+}
+
+pub struct PolicyReloader {
+    source: PolicySource,
+    policy: SharedPolicy,
+}
+
+impl PolicyReloader {
+    pub fn new(source: PolicySource, policy: SharedPolicy) -> Self {
+        Self { source, policy }
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let config = self.source.load()?;
+        // The advisory store is carried over; feed settings only apply after a restart
+        let engine = PolicyEngine::try_from_config(config, self.policy.load().advisory_store())?;
+        
+        self.policy.store(Arc::new(engine));
+        info!("Policy reloaded from {}", self.source.path());
+        Ok(())
+    }
+
+    pub fn spawn(self) -> Result<()> {
+        let path = Path::new(self.source.path()).to_path_buf();
+        let file_name = path.file_name().map(|f| f.to_os_string())
+            .ok_or_else(|| anyhow!("Invalid policy path {}", path.display()))?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => Path::new(".").to_path_buf(),
+        };
+        
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let relevant = event.kind.is_modify() || event.kind.is_create();
+                if relevant && event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+        // Watch the directory: editors and config management replace files by rename
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        info!("Watching {} for policy changes", path.display());
+        
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            while rx.recv().await.is_some() {
+                // A single save usually produces several events; let them settle
+                tokio::time::sleep(Duration::from_millis(250)).await;
+                while rx.try_recv().is_ok() {}
+                
+                if let Err(e) = self.reload() {
+                    error!("Rejected policy change in {}, keeping current policy: {}", self.source.path(), e);
+                }
+            }
+        });
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+[allow]
+suites = ["bookworm"]
+components = ["main"]
+architectures = ["amd64"]
+
+[deny]
+architectures = []
+packages = []
+
+[limits]
+max_deb_size_mb = 500
+max_request_rate_per_minute = 100
+"#;
+
+    #[test]
+    fn test_reload_swaps_valid_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, POLICY).unwrap();
+        
+        let policy: SharedPolicy = Arc::new(ArcSwap::from_pointee(PolicyEngine::new()));
+        let reloader = PolicyReloader::new(PolicySource::PolicyFile(path.to_str().unwrap().to_string()), policy.clone());
+        let contrib = "/debian/dists/bookworm/contrib/binary-amd64/Packages.gz";
+        
+        assert!(policy.load().check_path(contrib).is_ok());
+        reloader.reload().unwrap();
+        assert!(policy.load().check_path(contrib).is_err());
+    }
+
+    #[test]
+    fn test_invalid_policy_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, POLICY.replace("packages = []", "packages = [\"^broken(\"]")).unwrap();
+        
+        let policy: SharedPolicy = Arc::new(ArcSwap::from_pointee(PolicyEngine::new()));
+        let reloader = PolicyReloader::new(PolicySource::PolicyFile(path.to_str().unwrap().to_string()), policy.clone());
+        
+        assert!(reloader.reload().is_err());
+        assert!(policy.load().check_path("/debian/dists/bookworm/contrib/binary-amd64/Packages.gz").is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::policy::matcher::PackageMatcher;
+use crate::tls::identity::ClientIdentity;
+
+// Grants a role to mutual TLS clients whose certificate matches any of the
+// listed attributes. Subjects, SANs and OUs take exact values, globs
+// (builder-*) or regexes starting with ^; fingerprints are SHA-256 hex, with
+// or without colons. A certificate may hold several roles
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoleMapping {
+    pub name: String,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub sans: Vec<String>,
+    #[serde(default)]
+    pub organizational_units: Vec<String>,
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.replace(':', "").to_ascii_lowercase()
+}
+
+impl RoleMapping {
+    pub fn validate(&self) -> Result<()> {
+        for patterns in [&self.subjects, &self.sans, &self.organizational_units] {
+            PackageMatcher::new(patterns).map_err(|e| anyhow!("Role '{}': {}", self.name, e))?;
+        }
+        for fingerprint in &self.fingerprints {
+            let normalized = normalize_fingerprint(fingerprint);
+            if normalized.len() != 64 || hex::decode(&normalized).is_err() {
+                return Err(anyhow!("Role '{}': invalid SHA-256 fingerprint '{}'", self.name, fingerprint));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct CompiledRole {
+    name: String,
+    subjects: PackageMatcher,
+    sans: PackageMatcher,
+    organizational_units: PackageMatcher,
+    fingerprints: Vec<String>,
+}
+
+impl CompiledRole {
+    fn matches(&self, identity: &ClientIdentity) -> bool {
+        self.subjects.is_match(&identity.subject)
+            || identity.sans.iter().any(|san| self.sans.is_match(san))
+            || identity.organizational_units.iter().any(|ou| self.organizational_units.is_match(ou))
+            || self.fingerprints.contains(&identity.fingerprint)
+    }
+}
+
+pub struct RoleMapper {
+    roles: Vec<CompiledRole>,
+}
+
+impl RoleMapper {
+    pub fn new(mappings: &[RoleMapping]) -> Self {
+        let roles = mappings
+            .iter()
+            .map(|mapping| CompiledRole {
+                name: mapping.name.clone(),
+                subjects: PackageMatcher::new_lenient(&mapping.subjects),
+                sans: PackageMatcher::new_lenient(&mapping.sans),
+                organizational_units: PackageMatcher::new_lenient(&mapping.organizational_units),
+                fingerprints: mapping.fingerprints.iter().map(|f| normalize_fingerprint(f)).collect(),
+            })
+            .collect();
+        Self { roles }
+    }
+
+    // Requests without a client certificate hold no roles
+    pub fn roles_for(&self, identity: Option<&ClientIdentity>) -> Vec<String> {
+        let Some(identity) = identity else {
+            return Vec::new();
+        };
+        self.roles
+            .iter()
+            .filter(|role| role.matches(identity))
+            .map(|role| role.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(subject: &str, ou: &str) -> ClientIdentity {
+        ClientIdentity {
+            subject: subject.to_string(),
+            fingerprint: "ab".repeat(32),
+            sans: vec![format!("{}.ci.example.org", subject)],
+            organizational_units: vec![ou.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_roles_from_certificate_attributes() {
+        let mappings = vec![
+            RoleMapping {
+                name: "builder".to_string(),
+                subjects: vec!["builder-*".to_string()],
+                sans: vec![],
+                organizational_units: vec!["Release Engineering".to_string()],
+                fingerprints: vec![],
+            },
+            RoleMapping {
+                name: "ci".to_string(),
+                subjects: vec![],
+                sans: vec!["^.*\\.ci\\.example\\.org$".to_string()],
+                organizational_units: vec![],
+                fingerprints: vec![],
+            },
+            RoleMapping {
+                name: "admin".to_string(),
+                subjects: vec![],
+                sans: vec![],
+                organizational_units: vec![],
+                fingerprints: vec!["AB:".repeat(31) + "AB"],
+            },
+        ];
+        let mapper = RoleMapper::new(&mappings);
+        
+        assert_eq!(mapper.roles_for(Some(&identity("builder-01", "QA"))), vec!["builder", "ci", "admin"]);
+        let mut other = identity("laptop", "Release Engineering");
+        other.fingerprint = "cd".repeat(32);
+        other.sans.clear();
+        assert_eq!(mapper.roles_for(Some(&other)), vec!["builder"]);
+        assert!(mapper.roles_for(None).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_fingerprint_rejected() {
+        let mapping = RoleMapping {
+            name: "admin".to_string(),
+            subjects: vec![],
+            sans: vec![],
+            organizational_units: vec![],
+            fingerprints: vec!["abcd".to_string()],
+        };
+        assert!(mapping.validate().is_err());
+    }
+}
@@ -1,15 +1,130 @@
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use ipnet::IpNet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
 use crate::mirror::path::{PathParser, DebianPath, PathType};
-use tracing::info;
+use crate::policy::advisories::{AdvisoryConfig, AdvisoryStore};
+use crate::policy::external::ExternalPolicyConfig;
+use crate::policy::matcher::PackageMatcher;
+use crate::policy::osv::OsvConfig;
+use crate::policy::priority::{select_rule, PrioritizedRule};
+use crate::policy::roles::{RoleMapper, RoleMapping};
+use crate::policy::version::{DebFilename, VersionRule};
+use crate::tls::identity::ClientIdentity;
+use crate::verify::control::DebControl;
+use tracing::{info, error};
 use warp::http::Method;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PolicyConfig {
+    // When false, every violation is only logged and counted (dry-run)
+    #[serde(default = "default_enforce")]
+    pub enforce: bool,
     pub allow: AllowPolicy,
     pub deny: DenyPolicy,
     pub limits: LimitsPolicy,
+    // Explicit rules decide before the allow/deny sets (see PathRule)
+    #[serde(default)]
+    pub rules: Vec<PathRule>,
+    // Checked in order before the global rules; first matching subnet wins
+    #[serde(default)]
+    pub clients: Vec<ClientPolicy>,
+    #[serde(default)]
+    pub advisories: AdvisoryConfig,
+    // Consulted after the local rules and GeoIP; read once at startup
+    #[serde(default)]
+    pub external: ExternalPolicyConfig,
+    // Vulnerability lookups for served packages; read once at startup
+    #[serde(default)]
+    pub osv: OsvConfig,
+    // Rule sets for other repositories, selected by the first path segment;
+    // paths of repositories without an entry use the rules above
+    #[serde(default)]
+    pub repositories: Vec<RepositoryPolicy>,
+    // Roles granted to mutual TLS clients by certificate; rules select them with `roles`
+    #[serde(default)]
+    pub roles: Vec<RoleMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RepositoryPolicy {
+    pub name: String,
+    // Missing sections fall back to the global ones
+    pub allow: Option<AllowPolicy>,
+    pub deny: Option<DenyPolicy>,
+    pub rules: Option<Vec<PathRule>>,
+    pub clients: Option<Vec<ClientPolicy>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientPolicy {
+    pub name: String,
+    pub networks: Vec<String>,
+    // Skip path rules entirely for these clients (e.g. a trusted build farm)
+    #[serde(default)]
+    pub unrestricted: bool,
+    // Overrides the global enforce setting for these clients
+    #[serde(default)]
+    pub enforce: Option<bool>,
+    // Missing sections fall back to the global allow/deny rules
+    pub allow: Option<AllowPolicy>,
+    pub deny: Option<DenyPolicy>,
+    pub rules: Option<Vec<PathRule>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Deny,
+}
+
+// An explicit rule. The enabled rule with the highest priority that matches
+// decides (ties go to the rule listed first, as for GeoIP rules):
+//   allow - the request skips the allow/deny sets; advisories still apply
+//   deny  - the request is denied
+// When no rule matches, the allow/deny sets are evaluated in fixed order:
+// installer images, suite, component, denied architecture, allowed architecture, denied package,
+// package allow list, advisories, denied versions, version pins, sections.
+// Conditions left empty match anything; a condition on a field the request does
+// not carry (e.g. suites for pool files) never matches. Maintainer, Priority and
+// Depends come from the package's control file, which is only known once the
+// package has been fetched: the request that fetches it is checked again
+// before it is served.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PathRule {
+    pub name: String,
+    pub action: RuleAction,
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default = "default_enforce")]
+    pub enabled: bool,
+    #[serde(default = "default_enforce")]
+    pub enforce: bool,
+    #[serde(default)]
+    pub suites: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<String>,
+    #[serde(default)]
+    pub architectures: Vec<String>,
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub sections: Vec<String>,
+    // Maintainer patterns, e.g. "*<security@debian.org>"
+    #[serde(default)]
+    pub maintainers: Vec<String>,
+    #[serde(default)]
+    pub priorities: Vec<String>,
+    // Patterns for packages named in Depends or Pre-Depends
+    #[serde(default)]
+    pub depends: Vec<String>,
+    // Roles from [[policy.roles]]; requests without a client certificate never match
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -17,47 +132,316 @@ pub struct AllowPolicy {
     pub suites: Vec<String>,
     pub components: Vec<String>,
     pub architectures: Vec<String>,
+    // When non-empty, only packages matching one of these patterns are served
+    #[serde(default)]
+    pub packages: Vec<String>,
+    // Version pins such as "linux-image-amd64 = 6.1.55-1"; a package covered by
+    // any pin must satisfy at least one of them
+    #[serde(default)]
+    pub versions: Vec<String>,
+    // Archive sections from the package indices (e.g. "python"); empty allows all
+    #[serde(default)]
+    pub sections: Vec<String>,
+    // debian-installer images (netboot, cdrom) and udeb indices under dists/
+    #[serde(default = "default_enforce")]
+    pub installer: bool,
+    #[serde(default = "default_enforce")]
+    pub enforce: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DenyPolicy {
     pub architectures: Vec<String>,
+    // Exact names, globs (nvidia-*) or regexes starting with ^
     pub packages: Vec<String>,
+    // "openssl < 3.0.11" constraints or .deb filename globs like "*_1.2.3-1_*"
+    #[serde(default)]
+    pub versions: Vec<String>,
+    #[serde(default)]
+    pub sections: Vec<String>,
+    #[serde(default = "default_enforce")]
+    pub enforce: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LimitsPolicy {
     pub max_deb_size_mb: u64,
+    // Token-bucket rates; 0 disables the bucket. Bursts default to one minute's worth
     pub max_request_rate_per_minute: u32,
+    #[serde(default)]
+    pub burst: Option<u32>,
+    #[serde(default)]
+    pub max_request_rate_per_ip_per_minute: u32,
+    #[serde(default)]
+    pub per_ip_burst: Option<u32>,
+    // Upstream transfers a single client IP may have in flight; 0 disables the limit
+    #[serde(default)]
+    pub max_concurrent_downloads_per_ip: usize,
+}
+
+fn default_enforce() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Allow,
+    Deny,
+    Advisory,
+    Rule,
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+            Self::Advisory => "advisory",
+            Self::Rule => "rule",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+    pub kind: RuleKind,
+    // Name of an explicit rule, the rule set (e.g. "deny.packages") or the advisory ID
+    pub rule: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PolicyDecision {
+    // Explicit allow rule or client policy that let the request through
+    pub matched_rule: Option<String>,
+    // Violations of rules that are not enforced; log them but serve the request
+    pub dry_run: Vec<PolicyViolation>,
+    // Roles the client certificate was mapped to
+    pub roles: Vec<String>,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+impl PolicyConfig {
+    pub fn load_from_file(config_path: &str) -> Result<Self> {
+        let config_content = std::fs::read_to_string(config_path)
+            .map_err(|e| anyhow!("Failed to read policy file {}: {}", config_path, e))?;
+        toml::from_str(&config_content)
+            .map_err(|e| anyhow!("Failed to parse policy file {}: {}", config_path, e))
+    }
+
+    // Strict counterpart of the lenient compilation done in PolicyEngine::from_config
+    pub fn validate(&self) -> Result<()> {
+        Self::validate_rules(Some(&self.allow), Some(&self.deny))?;
+        Self::validate_path_rules(&self.rules)?;
+        
+        for client in &self.clients {
+            for network in &client.networks {
+                parse_network(network).map_err(|e| anyhow!("Client policy '{}': {}", client.name, e))?;
+            }
+            Self::validate_rules(client.allow.as_ref(), client.deny.as_ref())
+                .and_then(|_| Self::validate_path_rules(client.rules.as_deref().unwrap_or_default()))
+                .map_err(|e| anyhow!("Client policy '{}': {}", client.name, e))?;
+        }
+        
+        for repository in &self.repositories {
+            Self::build_repository_config(repository, self)
+                .validate()
+                .map_err(|e| anyhow!("Repository policy '{}': {}", repository.name, e))?;
+        }
+        for role in &self.roles {
+            role.validate()?;
+        }
+        Ok(())
+    }
+
+    fn validate_rules(allow: Option<&AllowPolicy>, deny: Option<&DenyPolicy>) -> Result<()> {
+        if let Some(allow) = allow {
+            PackageMatcher::new(&allow.packages)?;
+            for rule in &allow.versions {
+                VersionRule::parse(rule)?;
+            }
+        }
+        if let Some(deny) = deny {
+            PackageMatcher::new(&deny.packages)?;
+            for rule in &deny.versions {
+                VersionRule::parse(rule)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn build_repository_config(repository: &RepositoryPolicy, global: &PolicyConfig) -> PolicyConfig {
+        PolicyConfig {
+            enforce: global.enforce,
+            allow: repository.allow.clone().unwrap_or_else(|| global.allow.clone()),
+            deny: repository.deny.clone().unwrap_or_else(|| global.deny.clone()),
+            rules: repository.rules.clone().unwrap_or_else(|| global.rules.clone()),
+            limits: global.limits.clone(),
+            clients: repository.clients.clone().unwrap_or_else(|| global.clients.clone()),
+            advisories: global.advisories.clone(),
+            external: global.external.clone(),
+            osv: global.osv.clone(),
+            repositories: vec![],
+            roles: vec![],
+        }
+    }
+
+    fn validate_path_rules(rules: &[PathRule]) -> Result<()> {
+        for rule in rules {
+            PackageMatcher::new(&rule.packages)
+                .and_then(|_| PackageMatcher::new(&rule.maintainers))
+                .and_then(|_| PackageMatcher::new(&rule.depends))
+                .map_err(|e| anyhow!("Rule '{}': {}", rule.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+// Index directories are named binary-<arch>; rules may use either form
+fn bare_arch(arch: &str) -> &str {
+    arch.strip_prefix("binary-").or_else(|| arch.strip_prefix("installer-")).unwrap_or(arch)
+}
+
+// Sections outside main carry their component, e.g. "contrib/games" -> "games"
+fn bare_section(section: &str) -> &str {
+    section.rsplit('/').next().unwrap_or(section)
+}
+
+pub fn parse_network(network: &str) -> Result<IpNet> {
+    // Bare addresses are treated as single-host networks
+    network
+        .parse::<IpNet>()
+        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|e| anyhow!("Invalid network '{}': {}", network, e))
 }
 
 impl Default for PolicyConfig {
     fn default() -> Self {
         Self {
+            enforce: true,
             allow: AllowPolicy {
                 suites: vec!["bookworm".to_string(), "bullseye".to_string()],
                 components: vec!["main".to_string(), "contrib".to_string(), "non-free".to_string()],
                 architectures: vec!["amd64".to_string(), "arm64".to_string(), "binary-amd64".to_string()],
+                packages: vec![],
+                versions: vec![],
+                sections: vec![],
+                installer: true,
+                enforce: true,
             },
             deny: DenyPolicy {
                 architectures: vec!["i386".to_string()],
                 packages: vec![],
+                versions: vec![],
+                sections: vec![],
+                enforce: true,
             },
+            rules: vec![],
             limits: LimitsPolicy {
                 max_deb_size_mb: 500,
                 max_request_rate_per_minute: 100,
+                burst: None,
+                max_request_rate_per_ip_per_minute: 0,
+                per_ip_burst: None,
+                max_concurrent_downloads_per_ip: 0,
             },
+            clients: vec![],
+            advisories: AdvisoryConfig::default(),
+            external: ExternalPolicyConfig::default(),
+            osv: OsvConfig::default(),
+            repositories: vec![],
+            roles: vec![],
+        }
+    }
+}
+
+struct CompiledRule {
+    rule: PathRule,
+    packages: PackageMatcher,
+    maintainers: PackageMatcher,
+    depends: PackageMatcher,
+}
+
+// What a request is known to be about, for matching PathRule conditions
+struct RequestFacts<'a> {
+    suite: Option<&'a str>,
+    component: Option<&'a str>,
+    architecture: Option<&'a str>,
+    package: Option<&'a str>,
+    section: Option<&'a str>,
+    control: Option<&'a DebControl>,
+    roles: &'a [String],
+}
+
+impl CompiledRule {
+    fn new(rule: &PathRule) -> Self {
+        Self {
+            packages: PackageMatcher::new_lenient(&rule.packages),
+            maintainers: PackageMatcher::new_lenient(&rule.maintainers),
+            depends: PackageMatcher::new_lenient(&rule.depends),
+            rule: rule.clone(),
+        }
+    }
+
+    fn matches(&self, facts: &RequestFacts) -> bool {
+        fn condition(values: &[String], value: Option<&str>, normalize: fn(&str) -> &str) -> bool {
+            values.is_empty() || value.is_some_and(|v| values.iter().any(|x| normalize(x) == normalize(v)))
         }
+        
+        condition(&self.rule.suites, facts.suite, str::trim)
+            && condition(&self.rule.components, facts.component, str::trim)
+            && condition(&self.rule.architectures, facts.architecture, bare_arch)
+            && condition(&self.rule.sections, facts.section, bare_section)
+            && (self.packages.is_empty() || facts.package.is_some_and(|p| self.packages.is_match(p)))
+            && condition(&self.rule.priorities, facts.control.and_then(|c| c.get("Priority")), str::trim)
+            && (self.maintainers.is_empty() || facts.control.and_then(|c| c.get("Maintainer")).is_some_and(|m| self.maintainers.is_match(m)))
+            && (self.depends.is_empty() || facts.control.is_some_and(|c| c.depends().iter().any(|d| self.depends.is_match(d))))
+            && (self.rule.roles.is_empty() || self.rule.roles.iter().any(|role| facts.roles.contains(role)))
+    }
+}
+
+impl PrioritizedRule for CompiledRule {
+    fn priority(&self) -> u8 {
+        self.rule.priority
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.rule.enabled
     }
 }
 
+struct ClientOverride {
+    name: String,
+    networks: Vec<IpNet>,
+    unrestricted: bool,
+    engine: PolicyEngine,
+}
+
 pub struct PolicyEngine {
     config: PolicyConfig,
+    // Only the top-level engine maps certificates; the roles are passed down
+    roles: RoleMapper,
+    client_overrides: Vec<ClientOverride>,
+    repositories: HashMap<String, PolicyEngine>,
+    rules: Vec<CompiledRule>,
     allowed_suites: HashSet<String>,
     allowed_components: HashSet<String>,
     allowed_architectures: HashSet<String>,
     denied_architectures: HashSet<String>,
-    denied_packages: HashSet<String>,
+    allowed_sections: HashSet<String>,
+    denied_sections: HashSet<String>,
+    allowed_packages: PackageMatcher,
+    denied_packages: PackageMatcher,
+    pinned_versions: Vec<VersionRule>,
+    denied_versions: Vec<VersionRule>,
+    advisories: Arc<AdvisoryStore>,
 }
 
 impl PolicyEngine {
@@ -67,61 +451,306 @@ impl PolicyEngine {
     }
     
     pub fn from_config(config: PolicyConfig) -> Self {
+        Self::with_advisory_store(config, Arc::new(AdvisoryStore::new()))
+    }
+
+    // Rejects invalid rules instead of skipping them; used when swapping policies at runtime
+    pub fn try_from_config(config: PolicyConfig, advisories: Arc<AdvisoryStore>) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_advisory_store(config, advisories))
+    }
+
+    // Client overrides share the advisory store so a single feed refresh covers them
+    fn with_advisory_store(config: PolicyConfig, advisories: Arc<AdvisoryStore>) -> Self {
         let allowed_suites: HashSet<String> = config.allow.suites.iter().cloned().collect();
         let allowed_components: HashSet<String> = config.allow.components.iter().cloned().collect();
         let allowed_architectures: HashSet<String> = config.allow.architectures.iter().cloned().collect();
         let denied_architectures: HashSet<String> = config.deny.architectures.iter().cloned().collect();
-        let denied_packages: HashSet<String> = config.deny.packages.iter().cloned().collect();
+        let allowed_sections: HashSet<String> = config.allow.sections.iter().map(|s| bare_section(s).to_string()).collect();
+        let denied_sections: HashSet<String> = config.deny.sections.iter().map(|s| bare_section(s).to_string()).collect();
+        let allowed_packages = PackageMatcher::new_lenient(&config.allow.packages);
+        let denied_packages = PackageMatcher::new_lenient(&config.deny.packages);
+        let pinned_versions = Self::parse_version_rules(&config.allow.versions);
+        let denied_versions = Self::parse_version_rules(&config.deny.versions);
+        let rules = config.rules.iter().map(CompiledRule::new).collect();
+        let roles = RoleMapper::new(&config.roles);
+        let client_overrides = config
+            .clients
+            .iter()
+            .map(|client| Self::build_override(client, &config, advisories.clone()))
+            .collect();
+        let repositories = config
+            .repositories
+            .iter()
+            .map(|repository| {
+                let repository_config = PolicyConfig::build_repository_config(repository, &config);
+                (repository.name.clone(), Self::with_advisory_store(repository_config, advisories.clone()))
+            })
+            .collect();
         
         Self {
             config,
+            roles,
+            client_overrides,
+            repositories,
+            rules,
             allowed_suites,
             allowed_components,
             allowed_architectures,
             denied_architectures,
+            allowed_sections,
+            denied_sections,
+            allowed_packages,
             denied_packages,
+            pinned_versions,
+            denied_versions,
+            advisories,
         }
     }
+
+    fn parse_version_rules(rules: &[String]) -> Vec<VersionRule> {
+        rules
+            .iter()
+            .filter_map(|rule| match VersionRule::parse(rule) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    error!("Ignoring version rule: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
     
-    pub fn check_request(&self, path: &str, method: &Method) -> bool {
+    fn build_override(client: &ClientPolicy, global: &PolicyConfig, advisories: Arc<AdvisoryStore>) -> ClientOverride {
+        let networks = client
+            .networks
+            .iter()
+            .filter_map(|network| match parse_network(network) {
+                Ok(net) => Some(net),
+                Err(e) => {
+                    error!("Ignoring network in client policy '{}': {}", client.name, e);
+                    None
+                }
+            })
+            .collect();
+        
+        let engine = Self::with_advisory_store(PolicyConfig {
+            enforce: client.enforce.unwrap_or(global.enforce),
+            allow: client.allow.clone().unwrap_or_else(|| global.allow.clone()),
+            deny: client.deny.clone().unwrap_or_else(|| global.deny.clone()),
+            rules: client.rules.clone().unwrap_or_else(|| global.rules.clone()),
+            limits: global.limits.clone(),
+            clients: vec![],
+            advisories: global.advisories.clone(),
+            external: global.external.clone(),
+            osv: global.osv.clone(),
+            repositories: vec![],
+            roles: vec![],
+        }, advisories);
+        
+        ClientOverride {
+            name: client.name.clone(),
+            networks,
+            unrestricted: client.unrestricted,
+            engine,
+        }
+    }
+
+    // The engine for the repository named by the first path segment
+    fn engine_for(&self, path: &str) -> &PolicyEngine {
+        let repository = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        self.repositories.get(repository).unwrap_or(self)
+    }
+
+    fn override_for(&self, client_ip: Option<&str>) -> Option<&ClientOverride> {
+        let ip = client_ip?.trim().parse::<IpAddr>().ok()?;
+        self.client_overrides.iter().find(|o| o.networks.iter().any(|net| net.contains(&ip)))
+    }
+
+    pub fn advisory_store(&self) -> Arc<AdvisoryStore> {
+        self.advisories.clone()
+    }
+
+    pub fn advisory_config(&self) -> &AdvisoryConfig {
+        &self.config.advisories
+    }
+
+    // section comes from the package indices and control from the package
+    // itself; either is None when not (yet) known
+    pub fn check_request(
+        &self,
+        path: &str,
+        method: &Method,
+        client_ip: Option<&str>,
+        client_identity: Option<&ClientIdentity>,
+        section: Option<&str>,
+        control: Option<&DebControl>,
+    ) -> Result<PolicyDecision> {
         if method != Method::GET && method != Method::HEAD {
-            return false;
+            return Err(anyhow!("Method {} is not allowed", method));
+        }
+        let roles = self.roles.roles_for(client_identity);
+        let mut dry_run = Vec::new();
+        let section = section.or(control.and_then(|c| c.get("Section")));
+        let matched_rule = self.engine_for(path).evaluate_for_client(path, client_ip, &roles, section, control, &mut dry_run)?;
+        Ok(PolicyDecision { matched_rule, dry_run, roles })
+    }
+
+    pub fn check_path_for_client(&self, path: &str, client_ip: Option<&str>) -> Result<()> {
+        self.engine_for(path).evaluate_for_client(path, client_ip, &[], None, None, &mut Vec::new()).map(|_| ())
+    }
+
+    fn evaluate_for_client(
+        &self,
+        path: &str,
+        client_ip: Option<&str>,
+        roles: &[String],
+        section: Option<&str>,
+        control: Option<&DebControl>,
+        dry_run: &mut Vec<PolicyViolation>,
+    ) -> Result<Option<String>> {
+        match self.override_for(client_ip) {
+            Some(client) if client.unrestricted => {
+                info!("Client policy '{}' allows {} without path rules", client.name, path);
+                Ok(Some(format!("clients.{}", client.name)))
+            }
+            Some(client) => {
+                info!("Applying client policy '{}' to {}", client.name, path);
+                client.engine.evaluate(path, roles, section, control, dry_run)
+            }
+            None => self.evaluate(path, roles, section, control, dry_run),
         }
-        self.check_path(path).is_ok()
     }
 
     pub fn check_path(&self, path: &str) -> Result<()> {
+        self.engine_for(path).evaluate(path, &[], None, None, &mut Vec::new()).map(|_| ())
+    }
+
+    // Ok carries the name of the explicit allow rule that decided, if any
+    fn evaluate(
+        &self,
+        path: &str,
+        roles: &[String],
+        section: Option<&str>,
+        control: Option<&DebControl>,
+        dry_run: &mut Vec<PolicyViolation>,
+    ) -> Result<Option<String>> {
         info!("Checking policy for path: {}", path);
         
         let debian_path = PathParser::parse_debian_path(path)
             .map_err(|e| anyhow!("Invalid Debian path: {}", e))?;
+        let deb = debian_path.filename.as_deref().and_then(DebFilename::parse);
+        
+        if let Some(rule) = self.matching_rule(&debian_path, deb.as_ref(), roles, section, control) {
+            match rule.action {
+                RuleAction::Allow => {
+                    info!("Rule '{}' allows {}", rule.name, path);
+                    if let Some(deb) = &deb {
+                        self.check_advisories(deb, control, dry_run)?;
+                    }
+                    return Ok(Some(rule.name.clone()));
+                }
+                RuleAction::Deny => {
+                    let reason = format!("Denied by rule '{}'", rule.name);
+                    self.record(self.config.enforce && rule.enforce, RuleKind::Rule, &rule.name, reason, dry_run)?;
+                    // A dry-run deny rule leaves the decision to the allow/deny sets
+                }
+            }
+        }
         
         match debian_path.path_type {
-            PathType::Release => self.check_release_policy(&debian_path),
-            PathType::Package => self.check_package_policy(&debian_path),
+            PathType::Release => self.check_release_policy(&debian_path, dry_run)?,
+            PathType::Installer => {
+                if !self.config.allow.installer {
+                    self.violation(RuleKind::Allow, "allow.installer", "Installer images are not allowed".to_string(), dry_run)?;
+                }
+                self.check_release_policy(&debian_path, dry_run)?;
+            }
+            PathType::Package => {
+                self.check_package_policy(&debian_path, control, dry_run)?;
+                self.check_section_policy(section, dry_run)?;
+            }
         }
+        Ok(None)
     }
     
-    fn check_release_policy(&self, path: &DebianPath) -> Result<()> {
+    fn matching_rule(
+        &self,
+        path: &DebianPath,
+        deb: Option<&DebFilename>,
+        roles: &[String],
+        section: Option<&str>,
+        control: Option<&DebControl>,
+    ) -> Option<&PathRule> {
+        let package = match deb {
+            Some(deb) => Some(deb.name.clone()),
+            None => path.filename.as_deref().and_then(|f| self.extract_package_name(f)),
+        };
+        let facts = RequestFacts {
+            suite: Some(path.suite.as_str()).filter(|s| !s.is_empty()),
+            component: path.component.as_deref(),
+            architecture: path.architecture.as_deref().or(deb.map(|d| d.architecture.as_str())),
+            package: package.as_deref(),
+            section,
+            control,
+            roles,
+        };
+        
+        select_rule(&self.rules, |rule| rule.matches(&facts)).map(|compiled| &compiled.rule)
+    }
+
+    fn is_enforced(&self, kind: RuleKind) -> bool {
+        self.config.enforce && match kind {
+            RuleKind::Allow => self.config.allow.enforce,
+            RuleKind::Deny => self.config.deny.enforce,
+            RuleKind::Advisory => self.config.advisories.enforce,
+            RuleKind::Rule => true,
+        }
+    }
+
+    fn violation(&self, kind: RuleKind, rule: &str, reason: String, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
+        self.record(self.is_enforced(kind), kind, rule, reason, dry_run)
+    }
+
+    // Enforced violations fail the check; dry-run ones are collected and
+    // evaluation continues so they cannot mask a later enforced rule
+    fn record(
+        &self,
+        enforced: bool,
+        kind: RuleKind,
+        rule: &str,
+        reason: String,
+        dry_run: &mut Vec<PolicyViolation>,
+    ) -> Result<()> {
+        let violation = PolicyViolation { kind, rule: rule.to_string(), reason };
+        if enforced {
+            return Err(violation.into());
+        }
+        dry_run.push(violation);
+        Ok(())
+    }
+
+    fn check_release_policy(&self, path: &DebianPath, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
         // Check suite
         if !self.allowed_suites.contains(&path.suite) {
-            return Err(anyhow!("Suite '{}' is not allowed", path.suite));
+            self.violation(RuleKind::Allow, "allow.suites", format!("Suite '{}' is not allowed", path.suite), dry_run)?;
         }
         
         // Check component if specified
         if let Some(ref component) = path.component {
             if !self.allowed_components.contains(component) {
-                return Err(anyhow!("Component '{}' is not allowed", component));
+                self.violation(RuleKind::Allow, "allow.components", format!("Component '{}' is not allowed", component), dry_run)?;
             }
         }
         
         // Check architecture if specified
         if let Some(ref arch) = path.architecture {
-            if self.denied_architectures.contains(arch) {
-                return Err(anyhow!("Architecture '{}' is explicitly denied", arch));
+            let bare_arch = bare_arch(arch);
+            if self.denied_architectures.contains(arch) || self.denied_architectures.contains(bare_arch) {
+                self.violation(RuleKind::Deny, "deny.architectures", format!("Architecture '{}' is explicitly denied", arch), dry_run)?;
             }
-            if !self.allowed_architectures.contains(arch) {
-                return Err(anyhow!("Architecture '{}' is not allowed", arch));
+            if !self.allowed_architectures.contains(arch) && !self.allowed_architectures.contains(bare_arch) {
+                self.violation(RuleKind::Allow, "allow.architectures", format!("Architecture '{}' is not allowed", arch), dry_run)?;
             }
         }
         
@@ -133,25 +762,86 @@ impl PolicyEngine {
         Ok(())
     }
     
-    fn check_package_policy(&self, path: &DebianPath) -> Result<()> {
+    fn check_package_policy(&self, path: &DebianPath, control: Option<&DebControl>, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
         // Check component if specified
         if let Some(ref component) = path.component {
             if !self.allowed_components.contains(component) {
-                return Err(anyhow!("Component '{}' is not allowed", component));
+                self.violation(RuleKind::Allow, "allow.components", format!("Component '{}' is not allowed", component), dry_run)?;
             }
         }
         
         // Check package name if denied
         if let Some(ref filename) = path.filename {
             if let Some(package_name) = self.extract_package_name(filename) {
-                if self.denied_packages.contains(&package_name) {
-                    return Err(anyhow!("Package '{}' is explicitly denied", package_name));
+                if self.denied_packages.is_match(&package_name) {
+                    self.violation(RuleKind::Deny, "deny.packages", format!("Package '{}' is explicitly denied", package_name), dry_run)?;
                 }
+                if !self.allowed_packages.is_empty() && !self.allowed_packages.is_match(&package_name) {
+                    self.violation(RuleKind::Allow, "allow.packages", format!("Package '{}' is not in the allow list", package_name), dry_run)?;
+                }
+            }
+            
+            if let Some(deb) = DebFilename::parse(filename) {
+                self.check_version_policy(&deb, filename, control, dry_run)?;
             }
         }
         
         Ok(())
     }
+
+    fn check_section_policy(&self, section: Option<&str>, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
+        let Some(section) = section.map(bare_section) else {
+            // Pool files not listed in a fetched index cannot satisfy a section allow list
+            if !self.allowed_sections.is_empty() {
+                self.violation(RuleKind::Allow, "allow.sections", "Section is unknown and an allow list is configured".to_string(), dry_run)?;
+            }
+            return Ok(());
+        };
+        
+        if self.denied_sections.contains(section) {
+            self.violation(RuleKind::Deny, "deny.sections", format!("Section '{}' is explicitly denied", section), dry_run)?;
+        }
+        if !self.allowed_sections.is_empty() && !self.allowed_sections.contains(section) {
+            self.violation(RuleKind::Allow, "allow.sections", format!("Section '{}' is not allowed", section), dry_run)?;
+        }
+        
+        Ok(())
+    }
+
+    // Debian advisories name the source package, which only the control file
+    // gives; until the package is fetched its binary name is checked alone
+    fn check_advisories(&self, deb: &DebFilename, control: Option<&DebControl>, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
+        let source = control.map(DebControl::source).filter(|(name, _)| *name != deb.name);
+        let affected = self.advisories
+            .find(deb)
+            .map(|advisory| (advisory, deb.name.as_str(), deb.version.as_str()))
+            .or_else(|| {
+                let (name, version) = source?;
+                self.advisories.affecting(name, version).into_iter().next().map(|advisory| (advisory, name, version))
+            });
+        if let Some((advisory, package, version)) = affected {
+            let reason = format!("Package '{}' version {} is affected by {}", package, version, advisory);
+            self.violation(RuleKind::Advisory, &advisory, reason, dry_run)?;
+        }
+        Ok(())
+    }
+
+    fn check_version_policy(&self, deb: &DebFilename, filename: &str, control: Option<&DebControl>, dry_run: &mut Vec<PolicyViolation>) -> Result<()> {
+        self.check_advisories(deb, control, dry_run)?;
+        
+        if let Some(rule) = self.denied_versions.iter().find(|rule| rule.matches(deb, filename)) {
+            let reason = format!("Package '{}' version {} is denied by rule '{}'", deb.name, deb.version, rule.source);
+            self.violation(RuleKind::Deny, "deny.versions", reason, dry_run)?;
+        }
+        
+        let mut pins = self.pinned_versions.iter().filter(|rule| rule.applies_to(&deb.name)).peekable();
+        if pins.peek().is_some() && !pins.any(|rule| rule.matches(deb, filename)) {
+            let reason = format!("Package '{}' version {} does not match its pinned versions", deb.name, deb.version);
+            self.violation(RuleKind::Allow, "allow.versions", reason, dry_run)?;
+        }
+        
+        Ok(())
+    }
     
     fn extract_package_name(&self, filename: &str) -> Option<String> {
         // Extract package name from .deb filename
@@ -178,10 +868,9 @@ impl PolicyEngine {
     }
     
     pub fn load_config_from_file(&mut self, config_path: &str) -> Result<()> {
-        let config_content = std::fs::read_to_string(config_path)?;
-        let config: PolicyConfig = toml::from_str(&config_content)?;
+        let config = PolicyConfig::load_from_file(config_path)?;
         
-        *self = Self::from_config(config);
+        *self = Self::try_from_config(config, self.advisory_store())?;
         info!("Policy configuration loaded from {}", config_path);
         Ok(())
     }
@@ -190,6 +879,7 @@ impl PolicyEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::policy::advisories::{AdvisoryFeed, AdvisoryFormat};
     
     #[test]
     fn test_policy_engine_creation() {
@@ -233,4 +923,296 @@ mod tests {
         let result = engine.check_path("/debian/pool/main/a/apt/apt_2.6.1_amd64.deb");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_package_patterns() {
+        let mut config = PolicyConfig::default();
+        config.deny.packages = vec!["nvidia-*".to_string(), "^linux-image-.*-rt".to_string()];
+        config.allow.packages = vec!["linux-*".to_string(), "nvidia-*".to_string(), "apt".to_string()];
+        let engine = PolicyEngine::from_config(config);
+        
+        assert!(engine.check_path("/debian/pool/main/a/apt/apt_2.6.1_amd64.deb").is_ok());
+        assert!(engine.check_path("/debian/pool/non-free/n/nvidia-graphics-drivers/nvidia-driver_525.125.06-1_amd64.deb").is_err());
+        assert!(engine.check_path("/debian/pool/main/l/linux-signed-amd64/linux-image-6.1.0-13-rt-amd64_6.1.55-1_amd64.deb").is_err());
+        assert!(engine.check_path("/debian/pool/main/l/linux-signed-amd64/linux-image-6.1.0-13-amd64_6.1.55-1_amd64.deb").is_ok());
+        assert!(engine.check_path("/debian/pool/main/c/curl/curl_7.88.1-10_amd64.deb").is_err());
+    }
+
+    #[test]
+    fn test_client_network_overrides() {
+        let mut config = PolicyConfig::default();
+        config.clients = vec![
+            ClientPolicy {
+                name: "build-farm".to_string(),
+                networks: vec!["10.20.0.0/16".to_string()],
+                unrestricted: true,
+                enforce: None,
+                allow: None,
+                deny: None,
+                rules: None,
+            },
+            ClientPolicy {
+                name: "guest".to_string(),
+                networks: vec!["192.168.100.0/24".to_string(), "2001:db8::/32".to_string()],
+                unrestricted: false,
+                enforce: None,
+                allow: Some(AllowPolicy {
+                    components: vec!["main".to_string()],
+                    ..PolicyConfig::default().allow
+                }),
+                deny: None,
+                rules: None,
+            },
+        ];
+        let engine = PolicyEngine::from_config(config);
+        let contrib = "/debian/dists/bookworm/contrib/binary-amd64/Packages.gz";
+        let sid = "/debian/dists/sid/main/binary-amd64/Packages.gz";
+        
+        assert!(engine.check_path_for_client(contrib, None).is_ok());
+        assert!(engine.check_path_for_client(contrib, Some("192.168.100.7")).is_err());
+        assert!(engine.check_path_for_client(contrib, Some("2001:db8::1")).is_err());
+        assert!(engine.check_path_for_client(sid, Some("10.20.3.4")).is_ok());
+        assert!(engine.check_path_for_client(sid, Some("172.16.0.1")).is_err());
+    }
+
+    #[test]
+    fn test_version_rules() {
+        let mut config = PolicyConfig::default();
+        config.deny.versions = vec!["openssl < 3.0.11".to_string(), "*_1.2.3-1_*".to_string()];
+        config.allow.versions = vec!["linux-image-amd64 = 6.1.55-1".to_string()];
+        let engine = PolicyEngine::from_config(config);
+        
+        assert!(engine.check_path("/debian/pool/main/o/openssl/openssl_3.0.9-1_amd64.deb").is_err());
+        assert!(engine.check_path("/debian/pool/main/o/openssl/openssl_3.0.11-1~deb12u2_amd64.deb").is_ok());
+        assert!(engine.check_path("/debian/pool/main/f/foo/foo_1.2.3-1_arm64.deb").is_err());
+        assert!(engine.check_path("/debian/pool/main/l/linux-signed-amd64/linux-image-amd64_6.1.55-1_amd64.deb").is_ok());
+        assert!(engine.check_path("/debian/pool/main/l/linux-signed-amd64/linux-image-amd64_6.1.66-1_amd64.deb").is_err());
+    }
+
+    #[test]
+    fn test_advisory_denial() {
+        let engine = PolicyEngine::new();
+        let advisories = AdvisoryFeed::parse(AdvisoryFormat::Denylist, "DSA-5532-1 openssl < 3.0.11-1~deb12u2", "Debian:12").unwrap();
+        engine.advisory_store().replace(advisories);
+        
+        let error = engine.check_path("/debian/pool/main/o/openssl/openssl_3.0.9-1_amd64.deb").unwrap_err();
+        assert!(error.to_string().contains("DSA-5532-1"));
+        assert!(engine.check_path("/debian/pool/main/o/openssl/openssl_3.0.11-1~deb12u2_amd64.deb").is_ok());
+        
+        // libssl3 is built from openssl, which only its control file says
+        let libssl = "/debian/pool/main/o/openssl/libssl3_3.0.9-1_amd64.deb";
+        assert!(engine.check_request(libssl, &Method::GET, None, None, None, None).is_ok());
+        let control = DebControl::parse("Package: libssl3\nSource: openssl\nVersion: 3.0.9-1\nArchitecture: amd64\n").unwrap();
+        let error = engine.check_request(libssl, &Method::GET, None, None, None, Some(&control)).unwrap_err();
+        assert!(error.to_string().contains("Package 'openssl' version 3.0.9-1 is affected by DSA-5532-1"));
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_rules() {
+        let mut config = PolicyConfig::default();
+        assert!(config.validate().is_ok());
+        
+        config.deny.packages = vec!["^linux-(image".to_string()];
+        assert!(config.validate().is_err());
+        assert!(PolicyEngine::try_from_config(config, Arc::new(AdvisoryStore::new())).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_rules() {
+        let mut config = PolicyConfig::default();
+        config.deny.packages = vec!["nvidia-*".to_string()];
+        config.deny.enforce = false;
+        let engine = PolicyEngine::from_config(config);
+        let nvidia = "/debian/pool/non-free/n/nvidia-graphics-drivers/nvidia-driver_525.125.06-1_amd64.deb";
+        
+        let dry_run = engine.check_request(nvidia, &Method::GET, None, None, None, None).unwrap().dry_run;
+        assert_eq!(dry_run.len(), 1);
+        assert_eq!(dry_run[0].kind, RuleKind::Deny);
+        assert_eq!(dry_run[0].rule, "deny.packages");
+        // Allow rules are still enforced
+        assert!(engine.check_request("/debian/dists/sid/main/binary-amd64/Packages.gz", &Method::GET, None, None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_global_dry_run_keeps_evaluating() {
+        let mut config = PolicyConfig::default();
+        config.enforce = false;
+        config.clients = vec![ClientPolicy {
+            name: "production".to_string(),
+            networks: vec!["10.0.0.0/8".to_string()],
+            unrestricted: false,
+            enforce: Some(true),
+            allow: None,
+            deny: None,
+            rules: None,
+        }];
+        let engine = PolicyEngine::from_config(config);
+        let i386_sid = "/debian/dists/sid/main/binary-i386/Packages.gz";
+        
+        let dry_run = engine.check_request(i386_sid, &Method::GET, Some("192.168.1.10"), None, None, None).unwrap().dry_run;
+        assert_eq!(dry_run.len(), 3);
+        assert!(engine.check_request(i386_sid, &Method::GET, Some("10.1.2.3"), None, None, None).is_err());
+        assert!(engine.check_request(i386_sid, &Method::POST, None, None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_section_rules() {
+        let mut config = PolicyConfig::default();
+        config.deny.sections = vec!["games".to_string()];
+        let engine = PolicyEngine::from_config(config.clone());
+        let pool = "/debian/pool/main/f/frozen-bubble/frozen-bubble_2.212-11_amd64.deb";
+        
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("games"), None).is_err());
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("contrib/games"), None).is_err());
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("devel"), None).is_ok());
+        assert!(engine.check_request(pool, &Method::GET, None, None, None, None).is_ok());
+        
+        config.allow.sections = vec!["python".to_string()];
+        let engine = PolicyEngine::from_config(config);
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("python"), None).is_ok());
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("devel"), None).is_err());
+        assert!(engine.check_request(pool, &Method::GET, None, None, None, None).is_err());
+        // Index files have no section
+        assert!(engine.check_request("/debian/dists/bookworm/main/binary-amd64/Packages.gz", &Method::GET, None, None, None, None).is_ok());
+    }
+
+    fn rule(name: &str, action: RuleAction, priority: u8) -> PathRule {
+        PathRule {
+            name: name.to_string(),
+            action,
+            priority,
+            enabled: true,
+            enforce: true,
+            suites: vec![],
+            components: vec![],
+            architectures: vec![],
+            packages: vec![],
+            sections: vec![],
+            maintainers: vec![],
+            priorities: vec![],
+            depends: vec![],
+            roles: vec![],
+        }
+    }
+
+    #[test]
+    fn test_explicit_rule_priority() {
+        let mut config = PolicyConfig::default();
+        config.rules = vec![
+            PathRule { packages: vec!["nvidia-*".to_string()], ..rule("no-nvidia", RuleAction::Deny, 10) },
+            PathRule { packages: vec!["nvidia-smi".to_string()], ..rule("smi-ok", RuleAction::Allow, 20) },
+            PathRule { architectures: vec!["i386".to_string()], ..rule("legacy-i386", RuleAction::Allow, 5) },
+        ];
+        let engine = PolicyEngine::from_config(config);
+        
+        let error = engine.check_path("/debian/pool/non-free/n/nvidia/nvidia-driver_525.125.06-1_amd64.deb").unwrap_err();
+        assert_eq!(error.to_string(), "Denied by rule 'no-nvidia'");
+        let smi = "/debian/pool/non-free/n/nvidia/nvidia-smi_525.125.06-1_amd64.deb";
+        let decision = engine.check_request(smi, &Method::GET, None, None, None, None).unwrap();
+        assert_eq!(decision.matched_rule.as_deref(), Some("smi-ok"));
+        // The allow rule overrides the implicit architecture deny
+        assert!(engine.check_path("/debian/dists/bookworm/main/binary-i386/Packages.gz").is_ok());
+        assert!(engine.check_path("/debian/pool/main/a/apt/apt_2.6.1_i386.deb").is_ok());
+    }
+
+    #[test]
+    fn test_certificate_roles() {
+        let mut config = PolicyConfig::default();
+        config.allow.suites.push("experimental".to_string());
+        config.roles = vec![RoleMapping {
+            name: "builder".to_string(),
+            subjects: vec!["builder-*".to_string()],
+            sans: vec![],
+            organizational_units: vec![],
+            fingerprints: vec![],
+        }];
+        config.rules = vec![
+            PathRule { suites: vec!["experimental".to_string()], ..rule("experimental-locked", RuleAction::Deny, 10) },
+            PathRule {
+                suites: vec!["experimental".to_string()],
+                roles: vec!["builder".to_string()],
+                ..rule("builders-experimental", RuleAction::Allow, 20)
+            },
+        ];
+        let engine = PolicyEngine::from_config(config);
+        let identity = |subject: &str| ClientIdentity {
+            subject: subject.to_string(),
+            fingerprint: "ab".repeat(32),
+            sans: vec![],
+            organizational_units: vec![],
+        };
+        let experimental = "/debian/dists/experimental/main/binary-amd64/Packages.gz";
+        
+        let decision = engine.check_request(experimental, &Method::GET, None, Some(&identity("builder-01")), None, None).unwrap();
+        assert_eq!(decision.matched_rule.as_deref(), Some("builders-experimental"));
+        assert_eq!(decision.roles, vec!["builder"]);
+        assert!(engine.check_request(experimental, &Method::GET, None, Some(&identity("laptop")), None, None).is_err());
+        assert!(engine.check_request(experimental, &Method::GET, None, None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_control_field_rules() {
+        let mut config = PolicyConfig::default();
+        config.deny.sections = vec!["games".to_string()];
+        config.rules = vec![
+            PathRule { depends: vec!["libssl1.*".to_string()], ..rule("old-openssl", RuleAction::Deny, 10) },
+            PathRule { maintainers: vec!["*@example.org>".to_string()], ..rule("vendor", RuleAction::Allow, 20) },
+            PathRule { priorities: vec!["extra".to_string()], ..rule("no-extra", RuleAction::Deny, 5) },
+        ];
+        let engine = PolicyEngine::from_config(config);
+        let pool = "/debian/pool/main/t/tool/tool_1.0-1_amd64.deb";
+        let control = |fields: &str| DebControl::parse(&format!("Package: tool\nVersion: 1.0-1\nArchitecture: amd64\n{}", fields)).unwrap();
+        
+        // Unknown until fetched, so no control rule matches yet
+        assert!(engine.check_request(pool, &Method::GET, None, None, None, None).is_ok());
+        let error = engine.check_request(pool, &Method::GET, None, None, None, Some(&control("Depends: libc6, libssl1.1 (>= 1.1.1)\n"))).unwrap_err();
+        assert_eq!(error.to_string(), "Denied by rule 'old-openssl'");
+        let vendor = control("Maintainer: Tools <tools@example.org>\nDepends: libssl1.1\n");
+        let decision = engine.check_request(pool, &Method::GET, None, None, None, Some(&vendor)).unwrap();
+        assert_eq!(decision.matched_rule.as_deref(), Some("vendor"));
+        assert!(engine.check_request(pool, &Method::GET, None, None, None, Some(&control("Priority: extra\n"))).is_err());
+        // The control file's section stands in for one from the indices
+        assert!(engine.check_request(pool, &Method::GET, None, None, None, Some(&control("Section: games\n"))).is_err());
+        assert!(engine.check_request(pool, &Method::GET, None, None, Some("utils"), Some(&control("Section: games\n"))).is_ok());
+    }
+
+    #[test]
+    fn test_installer_toggle() {
+        let netboot = "/debian/dists/bookworm/main/installer-amd64/current/images/netboot/netboot.tar.gz";
+        let i386 = "/debian/dists/bookworm/main/installer-i386/current/images/netboot/netboot.tar.gz";
+        let engine = PolicyEngine::new();
+        assert!(engine.check_path(netboot).is_ok());
+        assert!(engine.check_path(i386).is_err());
+        
+        let mut config = PolicyConfig::default();
+        config.allow.installer = false;
+        let engine = PolicyEngine::from_config(config);
+        let err = engine.check_path(netboot).unwrap_err();
+        assert_eq!(err.downcast_ref::<PolicyViolation>().unwrap().rule, "allow.installer");
+        assert!(engine.check_path("/debian/dists/bookworm/main/binary-amd64/Packages.gz").is_ok());
+    }
+
+    #[test]
+    fn test_repository_policies() {
+        let mut config = PolicyConfig::default();
+        config.repositories = vec![RepositoryPolicy {
+            name: "ubuntu".to_string(),
+            allow: Some(AllowPolicy {
+                suites: vec!["noble".to_string(), "noble-security".to_string()],
+                components: vec!["main".to_string(), "universe".to_string()],
+                ..PolicyConfig::default().allow
+            }),
+            deny: None,
+            rules: None,
+            clients: None,
+        }];
+        let engine = PolicyEngine::from_config(config);
+        
+        assert!(engine.check_path("/ubuntu/dists/noble/universe/binary-amd64/Packages.xz").is_ok());
+        assert!(engine.check_path("/ubuntu/dists/bookworm/main/binary-amd64/Packages.xz").is_err());
+        // Global deny rules still apply when the repository does not override them
+        assert!(engine.check_path("/ubuntu/dists/noble/main/binary-i386/Packages.xz").is_err());
+        assert!(engine.check_path("/debian/dists/bookworm/main/binary-amd64/Packages.gz").is_ok());
+        assert!(engine.check_path("/debian/dists/noble/main/binary-amd64/Packages.gz").is_err());
+    }
 }
@@ -0,0 +1,192 @@
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use warp::http::Method;
+use crate::config::settings::AppConfig;
+use crate::policy::advisories::{AdvisoryFeed, AdvisoryStore};
+use crate::policy::rules::{PolicyConfig, PolicyEngine, PolicyViolation};
+use crate::verify::control::DebControl;
+
+const USAGE: &str = "usage: aptg policy test <policy.toml> [--client <ip>] [--section <name>] [--deb <package.deb>] [--log <access.log>] [path...]";
+
+#[derive(Debug, Default)]
+struct TestOptions {
+    policy_path: String,
+    client_ip: Option<String>,
+    section: Option<String>,
+    // Its control file is used for rules on Maintainer, Priority and Depends
+    deb: Option<String>,
+    log_files: Vec<String>,
+    paths: Vec<String>,
+}
+
+impl TestOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--client" => options.client_ip = Some(value()?),
+                "--section" => options.section = Some(value()?),
+                "--deb" => options.deb = Some(value()?),
+                "--log" => options.log_files.push(value()?),
+                _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {}\n{}", arg, USAGE)),
+                _ if options.policy_path.is_empty() => options.policy_path = arg.clone(),
+                _ => options.paths.push(arg.clone()),
+            }
+        }
+        
+        if options.policy_path.is_empty() {
+            return Err(anyhow!(USAGE));
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug)]
+pub enum TestOutcome {
+    // The explicit allow rule, if one decided
+    Allow(Option<String>),
+    // Allowed, but rules in dry-run mode would have denied it
+    DryRun(Vec<PolicyViolation>),
+    Deny { rule: String, reason: String },
+}
+
+impl TestOutcome {
+    pub fn evaluate(engine: &PolicyEngine, path: &str, client_ip: Option<&str>, section: Option<&str>, control: Option<&DebControl>) -> Self {
+        match engine.check_request(path, &Method::GET, client_ip, None, section, control) {
+            Ok(decision) if decision.dry_run.is_empty() => Self::Allow(decision.matched_rule),
+            Ok(decision) => Self::DryRun(decision.dry_run),
+            Err(e) => Self::Deny {
+                rule: e.downcast_ref::<PolicyViolation>().map_or("request".to_string(), |v| v.rule.clone()),
+                reason: e.to_string(),
+            },
+        }
+    }
+
+    pub fn format(&self, path: &str) -> String {
+        match self {
+            Self::Allow(None) => format!("ALLOW    {}", path),
+            Self::Allow(Some(rule)) => format!("ALLOW    {} ([{}])", path, rule),
+            Self::DryRun(violations) => {
+                let reasons: Vec<String> = violations.iter().map(|v| format!("[{}] {}", v.rule, v.reason)).collect();
+                format!("DRY-RUN  {} ({})", path, reasons.join("; "))
+            }
+            Self::Deny { rule, reason } => format!("DENY     {} ([{}] {})", path, rule, reason),
+        }
+    }
+}
+
+// Accepts the main configuration file (uses its [policy] or policy_file) or a standalone policy
+pub fn load_policy(path: &str) -> Result<PolicyConfig> {
+    let content = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    let value: toml::Value = toml::from_str(&content).map_err(|e| anyhow!("Failed to parse {}: {}", path, e))?;
+
+    if value.get("policy").is_some() || value.get("policy_file").is_some() {
+        Ok(AppConfig::load_from_file(path)?.policy)
+    } else {
+        PolicyConfig::load_from_file(path)
+    }
+}
+
+// Takes the request path from common/combined log lines ("GET /debian/... HTTP/1.1")
+// or treats the line as a bare path
+pub fn path_from_log_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    let target = match line.split('"').nth(1) {
+        Some(request) => request.split_whitespace().nth(1)?,
+        None => line.split_whitespace().next()?,
+    };
+
+    // Proxy-style requests carry the full URL
+    let target = match target.split_once("://") {
+        Some((_, rest)) => &rest[rest.find('/')?..],
+        None => target,
+    };
+    let path = target.split(['?', '#']).next().unwrap_or(target);
+    path.starts_with('/').then(|| path.to_string())
+}
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = TestOptions::parse(args)?;
+    let config = load_policy(&options.policy_path)?;
+
+    let advisories = Arc::new(AdvisoryStore::new());
+    if config.advisories.enabled {
+        if let Err(e) = AdvisoryFeed::new(config.advisories.clone(), advisories.clone()).refresh().await {
+            eprintln!("warning: advisory feed not loaded: {}", e);
+        }
+    }
+    let engine = PolicyEngine::try_from_config(config, advisories)?;
+
+    let control = match &options.deb {
+        Some(deb) => {
+            let data = std::fs::read(deb).map_err(|e| anyhow!("Failed to read {}: {}", deb, e))?;
+            Some(DebControl::from_deb(&data).map_err(|e| anyhow!("{}: {}", deb, e))?)
+        }
+        None => None,
+    };
+
+    let mut paths = options.paths.clone();
+    for log_file in &options.log_files {
+        let content = std::fs::read_to_string(log_file).map_err(|e| anyhow!("Failed to read {}: {}", log_file, e))?;
+        paths.extend(content.lines().filter_map(path_from_log_line));
+    }
+    if paths.is_empty() {
+        return Err(anyhow!("No request paths given\n{}", USAGE));
+    }
+
+    for path in &paths {
+        let outcome = TestOutcome::evaluate(&engine, path, options.client_ip.as_deref(), options.section.as_deref(), control.as_ref());
+        println!("{}", outcome.format(path));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_from_access_log() {
+        let combined = r#"10.0.0.5 - - [16/Oct/2026:10:00:00 +0000] "GET /debian/dists/bookworm/InRelease HTTP/1.1" 200 151 "-" "Debian APT-HTTP/1.3""#;
+        assert_eq!(path_from_log_line(combined).as_deref(), Some("/debian/dists/bookworm/InRelease"));
+        
+        let proxied = r#"- - - "GET http://mirror.local/debian/pool/main/a/apt/apt_2.6.1_amd64.deb?x=1 HTTP/1.1" 200"#;
+        assert_eq!(path_from_log_line(proxied).as_deref(), Some("/debian/pool/main/a/apt/apt_2.6.1_amd64.deb"));
+        
+        assert_eq!(path_from_log_line("/debian/dists/sid/Release").as_deref(), Some("/debian/dists/sid/Release"));
+        assert_eq!(path_from_log_line("# comment"), None);
+    }
+
+    #[test]
+    fn test_outcomes_name_the_rule() {
+        let engine = PolicyEngine::new();
+        
+        let allowed = TestOutcome::evaluate(&engine, "/debian/dists/bookworm/main/binary-amd64/Packages.gz", None, None, None);
+        assert!(matches!(allowed, TestOutcome::Allow(None)));
+        
+        let denied = TestOutcome::evaluate(&engine, "/debian/dists/bookworm/main/binary-i386/Packages.gz", None, None, None);
+        assert_eq!(
+            denied.format("/i386"),
+            "DENY     /i386 ([deny.architectures] Architecture 'binary-i386' is explicitly denied)"
+        );
+    }
+
+    #[test]
+    fn test_option_parsing() {
+        let args: Vec<String> = ["policy.toml", "--client", "10.0.0.1", "/debian/a", "--log", "access.log"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let options = TestOptions::parse(&args).unwrap();
+        
+        assert_eq!(options.policy_path, "policy.toml");
+        assert_eq!(options.client_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(options.paths, vec!["/debian/a".to_string()]);
+        assert_eq!(options.log_files, vec!["access.log".to_string()]);
+        assert!(TestOptions::parse(&["--bogus".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{Result, anyhow};
+use globset::{Glob, GlobMatcher};
+use std::cmp::Ordering;
+use crate::policy::matcher::PackageMatcher;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebFilename {
+    pub name: String,
+    pub version: String,
+    pub architecture: String,
+}
+
+impl DebFilename {
+    pub fn parse(filename: &str) -> Option<Self> {
+        // Example: openssl_3.0.11-1~deb12u2_amd64.deb (epochs are encoded as %3a)
+        let stem = filename.strip_suffix(".deb").or_else(|| filename.strip_suffix(".udeb"))?;
+        let mut parts = stem.splitn(3, '_');
+        let name = parts.next()?.to_string();
+        let version = parts.next()?.replace("%3a", ":").replace("%3A", ":");
+        let architecture = parts.next()?.to_string();
+        Some(Self { name, version, architecture })
+    }
+}
+
+// Debian version ordering as implemented by dpkg (epoch:upstream-revision)
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (epoch_a, upstream_a, revision_a) = split_version(a);
+    let (epoch_b, upstream_b, revision_b) = split_version(b);
+
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| compare_fragment(upstream_a, upstream_b))
+        .then_with(|| compare_fragment(revision_a, revision_b))
+}
+
+fn split_version(version: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+        None => (0, version),
+    };
+    match rest.rsplit_once('-') {
+        Some((upstream, revision)) => (epoch, upstream, revision),
+        None => (epoch, rest, ""),
+    }
+}
+
+fn char_order(c: Option<u8>) -> i32 {
+    // '~' sorts before everything, even the end of the string; letters before other symbols
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(b'~') => -1,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+fn compare_fragment(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit prefix, compared character by character
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let (ac, bc) = (char_order(a.get(i).copied()), char_order(b.get(j).copied()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        
+        // Numeric run, compared by value
+        while i < a.len() && a[i] == b'0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == b'0' {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while i < a.len() && a[i].is_ascii_digit() && j < b.len() && b[j].is_ascii_digit() {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if i < a.len() && a[i].is_ascii_digit() {
+            return Ordering::Greater;
+        }
+        if j < b.len() && b[j].is_ascii_digit() {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VersionOp {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl VersionOp {
+    fn parse(op: &str) -> Option<Self> {
+        // "<" and ">" are strict here, unlike dpkg's deprecated meaning
+        match op {
+            "<<" | "<" => Some(Self::Less),
+            "<=" => Some(Self::LessOrEqual),
+            "=" | "==" => Some(Self::Equal),
+            ">=" => Some(Self::GreaterOrEqual),
+            ">>" | ">" => Some(Self::Greater),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            Self::Less => ordering == Ordering::Less,
+            Self::LessOrEqual => ordering != Ordering::Greater,
+            Self::Equal => ordering == Ordering::Equal,
+            Self::GreaterOrEqual => ordering != Ordering::Less,
+            Self::Greater => ordering == Ordering::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum VersionRuleKind {
+    // "openssl < 3.0.11" or "linux-image-* >= 6.1"
+    Constraint { package: PackageMatcher, op: VersionOp, version: String },
+    // "*_1.2.3-1_*" matched against the whole .deb filename
+    Filename(GlobMatcher),
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionRule {
+    pub source: String,
+    kind: VersionRuleKind,
+}
+
+impl VersionRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let parts: Vec<&str> = rule.split_whitespace().collect();
+        let kind = match parts.as_slice() {
+            [package, op, version] => VersionRuleKind::Constraint {
+                package: PackageMatcher::new(&[package.to_string()])?,
+                op: VersionOp::parse(op).ok_or_else(|| anyhow!("Unknown version operator '{}' in '{}'", op, rule))?,
+                version: version.to_string(),
+            },
+            [pattern] => VersionRuleKind::Filename(
+                Glob::new(pattern)
+                    .map_err(|e| anyhow!("Invalid filename pattern '{}': {}", pattern, e))?
+                    .compile_matcher(),
+            ),
+            _ => return Err(anyhow!("Invalid version rule '{}'", rule)),
+        };
+        
+        Ok(Self { source: rule.to_string(), kind })
+    }
+
+    // Whether a pin constrains this package at all
+    pub fn applies_to(&self, package_name: &str) -> bool {
+        match &self.kind {
+            VersionRuleKind::Constraint { package, .. } => package.is_match(package_name),
+            VersionRuleKind::Filename(_) => false,
+        }
+    }
+
+    pub fn matches(&self, deb: &DebFilename, filename: &str) -> bool {
+        match &self.kind {
+            VersionRuleKind::Constraint { package, op, version } => {
+                package.is_match(&deb.name) && op.matches(compare_versions(&deb.version, version))
+            }
+            VersionRuleKind::Filename(glob) => glob.is_match(filename),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debian_version_ordering() {
+        assert_eq!(compare_versions("3.0.11-1", "3.0.9-1"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0~rc1-1", "1.0-1"), Ordering::Less);
+        assert_eq!(compare_versions("1:0.9", "2.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0-1", "1.0-1+deb12u1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0a", "1.0+"), Ordering::Less);
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("2.10-3", "2.10-3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_deb_filename_parsing() {
+        let deb = DebFilename::parse("openssl_1%3a3.0.11-1~deb12u2_amd64.deb").unwrap();
+        assert_eq!(deb.name, "openssl");
+        assert_eq!(deb.version, "1:3.0.11-1~deb12u2");
+        assert_eq!(deb.architecture, "amd64");
+        assert!(DebFilename::parse("Packages.gz").is_none());
+    }
+
+    #[test]
+    fn test_version_rules() {
+        let filename = "openssl_3.0.9-1_amd64.deb";
+        let deb = DebFilename::parse(filename).unwrap();
+        
+        assert!(VersionRule::parse("openssl < 3.0.11").unwrap().matches(&deb, filename));
+        assert!(!VersionRule::parse("openssl >= 3.0.11").unwrap().matches(&deb, filename));
+        assert!(VersionRule::parse("*_3.0.9-1_*").unwrap().matches(&deb, filename));
+        assert!(VersionRule::parse("openssl ~ 3.0").is_err());
+    }
+}
@@ -0,0 +1,728 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use tracing::error;
+use warp::{Filter, Reply, Rejection};
+use crate::audit::log::{AuditLogger, AuditStatus};
+use crate::audit::siem::EventFormat;
+use crate::audit::store::AuditQuery;
+use crate::geoip::policy::GeoPolicyEngine;
+use crate::cache::cache::CacheManager;
+use crate::metrics::downloads::{parse_window, DownloadStats, TopPackagesQuery};
+use crate::mirror::local::LocalRepository;
+use crate::mirror::snapshot::SnapshotStore;
+use crate::server::auth::{require_token, AdminAuth, AdminScope};
+use crate::server::reload::{Subsystem, SubsystemReloader};
+
+const DEFAULT_EVENT_LIMIT: usize = 100;
+const MAX_EVENT_LIMIT: usize = 1000;
+const DEFAULT_GEO_STATS_LIMIT: usize = 10;
+const DEFAULT_TOP_PACKAGES_LIMIT: usize = 20;
+const MAX_TOP_PACKAGES_LIMIT: usize = 1000;
+
+fn with_audit<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || item.clone())
+}
+
+// Shared by both endpoints; the export ignores limit and offset, the events
+// listing ignores format
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    // RFC 3339 timestamps; either one lists oldest first instead of newest first.
+    // Missing bounds are open
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    // Every event logged for one request
+    request_id: Option<String>,
+    client_ip: Option<IpAddr>,
+    // Matches events whose path starts with this, e.g. /debian/pool/main/s/sl/
+    path: Option<String>,
+    status: Option<String>,
+    // json, cef or leef
+    format: Option<EventFormat>,
+}
+
+impl EventsQuery {
+    fn to_audit_query(&self) -> Result<AuditQuery, Box<dyn Reply + Send>> {
+        Ok(AuditQuery {
+            from: self.from,
+            to: self.to,
+            event_type: variant_param(self.event_type.as_deref(), "event type")?,
+            request_id: self.request_id.clone(),
+            client_ip: self.client_ip,
+            path_prefix: self.path.clone(),
+            status: variant_param::<AuditStatus>(self.status.as_deref(), "status")?,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotRequest {
+    // Defaults to the capture time, e.g. 20261016T120000Z
+    id: Option<String>,
+    // Suite directories, e.g. /debian/dists/bookworm or /t/acme/debian/dists/bookworm
+    suites: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopPackagesParams {
+    // e.g. 7d or 4w, counted in whole days up to today
+    window: Option<String>,
+    limit: Option<usize>,
+    // As counted, e.g. /debian or /t/acme/debian
+    repository: Option<String>,
+    suite: Option<String>,
+    architecture: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeoStatsQuery {
+    // Entries per top list
+    limit: Option<usize>,
+}
+
+pub fn admin_routes(audit: Arc<AuditLogger>, geo: Arc<GeoPolicyEngine>, auth: Arc<AdminAuth>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let events = warp::path!("admin" / "audit" / "events")
+        .and(warp::get())
+        .and(warp::query::<EventsQuery>())
+        .and(with_audit(audit.clone()))
+        .and_then(handle_audit_events);
+
+    let export = warp::path!("admin" / "audit" / "export")
+        .and(warp::get())
+        .and(warp::query::<EventsQuery>())
+        .and(with_audit(audit))
+        .and_then(handle_audit_export);
+
+    let geo_stats = warp::path!("admin" / "geo" / "stats")
+        .and(warp::get())
+        .and(warp::query::<GeoStatsQuery>())
+        .and(with_audit(geo.clone()))
+        .and_then(handle_geo_stats);
+
+    let geo_reload = warp::path!("admin" / "geo" / "reload")
+        .and(warp::post())
+        .and(require_token(auth, AdminScope::Mutate))
+        .and(with_audit(geo))
+        .and_then(handle_geo_reload);
+
+    events.or(export).or(geo_stats).or(geo_reload)
+}
+
+pub fn snapshot_routes(snapshots: Arc<SnapshotStore>, auth: Arc<AdminAuth>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "snapshots")
+        .and(warp::get())
+        .and(with_audit(snapshots.clone()))
+        .and_then(handle_snapshot_list);
+
+    let capture = warp::path!("admin" / "snapshots")
+        .and(warp::post())
+        .and(require_token(auth.clone(), AdminScope::Mutate))
+        .and(warp::body::json::<SnapshotRequest>())
+        .and(with_audit(snapshots.clone()))
+        .and_then(handle_snapshot_capture);
+
+    let delete = warp::path!("admin" / "snapshots" / String)
+        .and(warp::delete())
+        .and(require_token(auth, AdminScope::Mutate))
+        .and(with_audit(snapshots))
+        .and_then(handle_snapshot_delete);
+
+    list.or(capture).or(delete)
+}
+
+pub fn download_stats_routes(stats: Option<Arc<DownloadStats>>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("admin" / "stats" / "top-packages")
+        .and(warp::get())
+        .and(warp::query::<TopPackagesParams>())
+        .and(with_audit(stats))
+        .and_then(handle_top_packages)
+}
+
+// POST /admin/reload/<subsystem>; the outcome is audited besides the admin request itself
+pub fn reload_routes(reloader: Arc<SubsystemReloader>, audit: Arc<AuditLogger>, auth: Arc<AdminAuth>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("admin" / "reload" / String)
+        .and(warp::post())
+        .and(require_token(auth, AdminScope::Mutate))
+        .and(with_audit(reloader))
+        .and(with_audit(audit))
+        .and_then(handle_reload)
+}
+
+// Uploads take the .deb as the raw request body; changes end up in a Release
+// re-signed with the site key
+pub fn local_package_routes(local: Arc<LocalRepository>, cache: Arc<CacheManager>, auth: Arc<AdminAuth>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "packages")
+        .and(warp::get())
+        .and(with_audit(local.clone()))
+        .and_then(handle_package_list);
+
+    let upload = warp::path!("admin" / "packages")
+        .and(warp::put().or(warp::post()).unify())
+        .and(require_token(auth.clone(), AdminScope::Mutate))
+        .and(warp::body::content_length_limit(local.max_upload_bytes()))
+        .and(warp::body::bytes())
+        .and(with_audit(local.clone()))
+        .and(with_audit(cache.clone()))
+        .and_then(handle_package_upload);
+
+    let delete = warp::path!("admin" / "packages" / String)
+        .and(warp::delete())
+        .and(require_token(auth, AdminScope::Mutate))
+        .and(with_audit(local))
+        .and(with_audit(cache))
+        .and_then(handle_package_delete);
+
+    list.or(upload).or(delete)
+}
+
+pub(crate) fn error_reply(message: &str, status: StatusCode) -> Box<dyn Reply + Send> {
+    Box::new(warp::reply::with_status(warp::reply::json(&serde_json::json!({"error": message})), status))
+}
+
+fn parse_variant<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+fn variant_param<T: DeserializeOwned>(name: Option<&str>, kind: &str) -> Result<Option<T>, Box<dyn Reply + Send>> {
+    match name {
+        Some(name) => parse_variant(name)
+            .map(Some)
+            .ok_or_else(|| error_reply(&format!("Unknown {} '{}'", kind, name), StatusCode::BAD_REQUEST)),
+        None => Ok(None),
+    }
+}
+
+async fn handle_audit_events(query: EventsQuery, audit: Arc<AuditLogger>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !audit.has_store() {
+        return Ok(error_reply("Audit store is not enabled", StatusCode::NOT_FOUND));
+    }
+    let mut audit_query = match query.to_audit_query() {
+        Ok(audit_query) => audit_query,
+        Err(reply) => return Ok(reply),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_EVENT_LIMIT).min(MAX_EVENT_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    audit_query.limit = Some(limit);
+    audit_query.offset = offset;
+    audit_query.newest_first = query.from.is_none() && query.to.is_none();
+
+    match audit.query_events(&audit_query).await {
+        Ok(events) => {
+            // A full page may have more behind it
+            let next_offset = if events.len() == limit { Some(offset + limit) } else { None };
+            Ok(Box::new(warp::reply::json(&serde_json::json!({"events": events, "next_offset": next_offset}))))
+        }
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+// One event per line, oldest first, streamed as rows are read
+async fn handle_audit_export(query: EventsQuery, audit: Arc<AuditLogger>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !audit.has_store() {
+        return Ok(error_reply("Audit store is not enabled", StatusCode::NOT_FOUND));
+    }
+    let audit_query = match query.to_audit_query() {
+        Ok(audit_query) => audit_query,
+        Err(reply) => return Ok(reply),
+    };
+    let mut events = match audit.stream_events(&audit_query).await {
+        Ok(events) => events,
+        Err(e) => return Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+    let format = query.format.unwrap_or_default();
+    let (mut sender, body) = warp::hyper::Body::channel();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if event.as_ref().is_ok_and(|event| !format.includes(event)) {
+                continue;
+            }
+            let line = match event.and_then(|event| format.format(&event)) {
+                Ok(line) => line + "\n",
+                Err(e) => {
+                    // Headers are already sent; abort so the client sees an incomplete export
+                    error!("Audit export failed: {}", e);
+                    sender.abort();
+                    return;
+                }
+            };
+            if sender.send_data(line.into()).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(Box::new(warp::reply::with_header(
+        warp::reply::Response::new(body),
+        "content-type",
+        format.content_type(),
+    )))
+}
+
+// Top countries, cities and continents plus decisions per GeoIP action and
+// rule since startup
+async fn handle_geo_stats(query: GeoStatsQuery, geo: Arc<GeoPolicyEngine>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let policy = geo.get_policy_stats();
+    if !policy.enabled {
+        return Ok(error_reply("GeoIP is not enabled", StatusCode::NOT_FOUND));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_GEO_STATS_LIMIT);
+    let mut stats = serde_json::to_value(geo.location_stats(limit)).unwrap_or_default();
+    stats["rules"] = serde_json::to_value(&policy.rule_matches).unwrap_or_default();
+    Ok(Box::new(warp::reply::json(&stats)))
+}
+
+// Loads the configured databases again, e.g. once one has been provisioned
+async fn handle_geo_reload(geo: Arc<GeoPolicyEngine>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !geo.get_policy_stats().enabled {
+        return Ok(error_reply("GeoIP is not enabled", StatusCode::NOT_FOUND));
+    }
+    match geo.reload_database() {
+        Ok(()) => Ok(Box::new(warp::reply::json(&serde_json::json!({"reloaded": true, "database": geo.get_database_info()})))),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::SERVICE_UNAVAILABLE)),
+    }
+}
+
+async fn handle_reload(name: String, reloader: Arc<SubsystemReloader>, audit: Arc<AuditLogger>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let Some(subsystem) = Subsystem::parse(&name) else {
+        let known: Vec<&str> = Subsystem::ALL.iter().map(Subsystem::as_str).collect();
+        return Ok(error_reply(&format!("Unknown subsystem '{}', expected one of {}", name, known.join(", ")), StatusCode::NOT_FOUND));
+    };
+    if !reloader.is_enabled(subsystem) {
+        return Ok(error_reply(&format!("{} reload is not enabled", subsystem.as_str()), StatusCode::NOT_FOUND));
+    }
+    // Reloads read files and may talk to a PKCS#11 module
+    let result = tokio::task::spawn_blocking({
+        let reloader = reloader.clone();
+        move || reloader.reload(subsystem)
+    })
+    .await
+    .unwrap_or_else(|e| Err(anyhow::anyhow!("Reload task failed: {}", e)));
+    let error = result.as_ref().err().map(|e| e.to_string());
+    audit.log_subsystem_reload(subsystem.as_str(), error.as_deref()).await;
+    match result {
+        Ok(mut details) => {
+            details["reloaded"] = serde_json::Value::String(subsystem.as_str().to_string());
+            Ok(Box::new(warp::reply::json(&details)))
+        }
+        // The running configuration is kept
+        Err(e) => Ok(error_reply(&format!("Reloading {} failed: {}", subsystem.as_str(), e), StatusCode::SERVICE_UNAVAILABLE)),
+    }
+}
+
+async fn handle_top_packages(params: TopPackagesParams, stats: Option<Arc<DownloadStats>>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let Some(stats) = stats else {
+        return Ok(error_reply("Download statistics are not enabled", StatusCode::NOT_FOUND));
+    };
+    let window = params.window.unwrap_or_else(|| "7d".to_string());
+    let Some(days) = parse_window(&window) else {
+        return Ok(error_reply(&format!("Invalid window '{}', e.g. 7d or 4w", window), StatusCode::BAD_REQUEST));
+    };
+    let query = TopPackagesQuery {
+        days,
+        limit: params.limit.unwrap_or(DEFAULT_TOP_PACKAGES_LIMIT).min(MAX_TOP_PACKAGES_LIMIT),
+        repository: params.repository,
+        suite: params.suite,
+        architecture: params.architecture,
+    };
+    match stats.top_packages(&query).await {
+        Ok(top) => {
+            let mut body = serde_json::to_value(top).unwrap_or_default();
+            body["window"] = serde_json::Value::String(window);
+            Ok(Box::new(warp::reply::json(&body)))
+        }
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+async fn handle_snapshot_list(snapshots: Arc<SnapshotStore>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !snapshots.is_enabled() {
+        return Ok(error_reply("Snapshots are not enabled", StatusCode::NOT_FOUND));
+    }
+    match snapshots.list().await {
+        Ok(records) => Ok(Box::new(warp::reply::json(&serde_json::json!({"snapshots": records})))),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+// Captures the Release and index files most recently verified for each suite
+async fn handle_snapshot_capture(request: SnapshotRequest, snapshots: Arc<SnapshotStore>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !snapshots.is_enabled() {
+        return Ok(error_reply("Snapshots are not enabled", StatusCode::NOT_FOUND));
+    }
+    let id = request.id.unwrap_or_else(|| Utc::now().format("%Y%m%dT%H%M%SZ").to_string());
+    match snapshots.capture(&id, &request.suites).await {
+        Ok(record) => Ok(Box::new(warp::reply::with_status(warp::reply::json(&record), StatusCode::CREATED))),
+        Err(e) if snapshots.get(&id).await.is_some() => Ok(error_reply(&e.to_string(), StatusCode::CONFLICT)),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::BAD_REQUEST)),
+    }
+}
+
+async fn handle_snapshot_delete(id: String, snapshots: Arc<SnapshotStore>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    match snapshots.delete(&id).await {
+        Ok(true) => Ok(Box::new(warp::reply::json(&serde_json::json!({"deleted": id})))),
+        Ok(false) => Ok(error_reply("Unknown snapshot", StatusCode::NOT_FOUND)),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+async fn handle_package_list(local: Arc<LocalRepository>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !local.is_enabled() {
+        return Ok(error_reply("Local packages are not enabled", StatusCode::NOT_FOUND));
+    }
+    Ok(Box::new(warp::reply::json(&serde_json::json!({"packages": local.list().await}))))
+}
+
+// The re-signed Release files list the local indices, so the cached ones go stale
+async fn invalidate_releases(local: &LocalRepository, cache: &CacheManager) {
+    for path in local.release_paths() {
+        cache.invalidate(&path).await;
+    }
+}
+
+async fn handle_package_upload(body: bytes::Bytes, local: Arc<LocalRepository>, cache: Arc<CacheManager>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    if !local.is_enabled() {
+        return Ok(error_reply("Local packages are not enabled", StatusCode::NOT_FOUND));
+    }
+    match local.import(&body).await {
+        Ok(package) => {
+            invalidate_releases(&local, &cache).await;
+            Ok(Box::new(warp::reply::with_status(warp::reply::json(&package), StatusCode::CREATED)))
+        }
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::BAD_REQUEST)),
+    }
+}
+
+async fn handle_package_delete(name: String, local: Arc<LocalRepository>, cache: Arc<CacheManager>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    match local.remove(&name).await {
+        Ok(Some(package)) => {
+            invalidate_releases(&local, &cache).await;
+            Ok(Box::new(warp::reply::json(&serde_json::json!({"deleted": package.filename}))))
+        }
+        Ok(None) => Ok(error_reply("Unknown package", StatusCode::NOT_FOUND)),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::log::{AuditEventType, RequestContext};
+    use crate::geoip::policy::GeoPolicy;
+
+    fn geo() -> Arc<GeoPolicyEngine> {
+        Arc::new(GeoPolicyEngine::new(GeoPolicy::default()))
+    }
+
+    fn no_tokens() -> Arc<AdminAuth> {
+        Arc::new(AdminAuth::new(&crate::server::auth::AdminConfig::default()))
+    }
+
+    // Accepts "Bearer mutate-secret"
+    fn ops() -> Arc<AdminAuth> {
+        use crate::server::auth::{hash_token, AdminConfig, AdminToken};
+        let tokens = vec![AdminToken { name: "ops".to_string(), sha256: hash_token("mutate-secret"), scope: AdminScope::Mutate }];
+        Arc::new(AdminAuth::new(&AdminConfig { tokens }))
+    }
+
+    #[tokio::test]
+    async fn test_events_require_store() {
+        let routes = admin_routes(Arc::new(AuditLogger::new()), geo(), no_tokens());
+        let response = warp::test::request().path("/admin/audit/events").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_export_streams_ndjson() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::audit::log::AuditConfig::default();
+        config.store.enabled = true;
+        config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&config));
+        audit.log_cache_hit(&RequestContext::default(), "/debian/dists/bookworm/InRelease").await;
+        audit.log_policy_violation(&RequestContext::default(), "/debian/pool/main/s/sl/sl_5.02-1_amd64.deb", "denied").await;
+        let routes = admin_routes(audit, geo(), no_tokens());
+
+        let response = warp::test::request().path("/admin/audit/export?type=CacheHit").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert_eq!(body.lines().count(), 1);
+        assert!(body.contains("\"CacheHit\""));
+
+        let response = warp::test::request().path("/admin/audit/export?format=cef").reply(&routes).await;
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert_eq!(body.lines().count(), 1);
+        assert!(body.starts_with("CEF:0|aptg|aptg|"));
+
+        let response = warp::test::request().path("/admin/audit/export?type=Nope").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_events_filters_and_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::audit::log::AuditConfig::default();
+        config.store.enabled = true;
+        config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&config));
+        let client = RequestContext::new(Some("192.0.2.7".parse().unwrap()));
+        for arch in ["amd64", "arm64", "i386"] {
+            audit.log_cache_hit(&client, &format!("/debian/pool/main/s/sl/sl_5.02-1_{}.deb", arch)).await;
+        }
+        audit.log_cache_hit(&RequestContext::default(), "/debian/pool/main/s/sl/sl_5.02-1_armhf.deb").await;
+        let routes = admin_routes(audit, geo(), no_tokens());
+
+        let response = warp::test::request()
+            .path("/admin/audit/events?client_ip=192.0.2.7&path=/debian/pool/main/s/sl/&status=Info&limit=2")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 2);
+        assert_eq!(body["events"][0]["path"], "/debian/pool/main/s/sl/sl_5.02-1_i386.deb");
+        assert_eq!(body["next_offset"], 2);
+
+        let response = warp::test::request().path("/admin/audit/events?client_ip=192.0.2.7&offset=2").reply(&routes).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 1);
+        assert!(body["next_offset"].is_null());
+
+        let response = warp::test::request().path(&format!("/admin/audit/events?request_id={}", client.id)).reply(&routes).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 3);
+
+        let response = warp::test::request().path("/admin/audit/events?status=Bad").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_geo_stats() {
+        let routes = admin_routes(Arc::new(AuditLogger::new()), geo(), no_tokens());
+        let response = warp::test::request().path("/admin/geo/stats").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Arc::new(GeoPolicyEngine::new(GeoPolicy {
+            enabled: true,
+            database_path: dir.path().join("missing.mmdb").to_str().unwrap().to_string(),
+            ..Default::default()
+        }));
+        for country in ["DE", "DE", "FR"] {
+            engine.record_request(&crate::geoip::policy::PolicyResult {
+                action: crate::geoip::policy::GeoAction::Allow,
+                rule_name: None,
+                location: crate::geoip::location::LocationInfo::new("192.0.2.1", country, ""),
+                reason: String::new(),
+            });
+        }
+        let routes = admin_routes(Arc::new(AuditLogger::new()), engine, no_tokens());
+
+        let response = warp::test::request().path("/admin/geo/stats?limit=1").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["total_requests"], 3);
+        assert_eq!(body["countries"], serde_json::json!([{"name": "DE", "requests": 2}]));
+        assert_eq!(body["actions"]["allow"], 3);
+        assert_eq!(body["rules"]["default"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_geo_reload_reports_missing_database() {
+        let reload = || warp::test::request().method("POST").path("/admin/geo/reload").header("authorization", "Bearer mutate-secret");
+        let routes = admin_routes(Arc::new(AuditLogger::new()), geo(), ops());
+        let response = reload().reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Arc::new(GeoPolicyEngine::new(GeoPolicy {
+            enabled: true,
+            database_path: dir.path().join("missing.mmdb").to_str().unwrap().to_string(),
+            ..Default::default()
+        }));
+        let routes = admin_routes(Arc::new(AuditLogger::new()), engine, ops());
+        let response = reload().reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("missing.mmdb"));
+    }
+
+    #[tokio::test]
+    async fn test_top_packages() {
+        let routes = download_stats_routes(None);
+        let response = warp::test::request().path("/admin/stats/top-packages").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let dir = tempfile::tempdir().unwrap();
+        let stats = DownloadStats::open(&crate::metrics::downloads::DownloadStatsConfig {
+            enabled: true,
+            path: dir.path().join("downloads.db").to_str().unwrap().to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        stats.record("/debian", "sl", "bookworm", "amd64", 100);
+        let routes = download_stats_routes(Some(Arc::new(stats)));
+        let response = warp::test::request().path("/admin/stats/top-packages?window=2w&suite=bookworm").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["window"], "2w");
+        assert_eq!(body["packages"], serde_json::json!([{"name": "sl", "downloads": 1, "bytes": 100}]));
+
+        let response = warp::test::request().path("/admin/stats/top-packages?window=1y").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_capture_errors() {
+        let routes = snapshot_routes(Arc::new(SnapshotStore::new(Default::default())), ops());
+        let response = warp::test::request().path("/admin/snapshots").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(crate::mirror::snapshot::SnapshotConfig {
+            enabled: true,
+            directory: dir.path().to_str().unwrap().to_string(),
+        });
+        let routes = snapshot_routes(Arc::new(store), ops());
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/snapshots")
+            .header("authorization", "Bearer mutate-secret")
+            .json(&serde_json::json!({"suites": ["/debian/dists/bookworm"]}))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("No verified Release"));
+    }
+
+    #[tokio::test]
+    async fn test_changes_refused_without_tokens() {
+        use crate::server::auth::handle_admin_denied;
+
+        let routes = admin_routes(Arc::new(AuditLogger::new()), geo(), no_tokens())
+            .or(snapshot_routes(Arc::new(SnapshotStore::new(Default::default())), no_tokens()))
+            .recover(handle_admin_denied);
+        let response = warp::test::request().method("POST").path("/admin/geo/reload").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = warp::test::request().method("DELETE").path("/admin/snapshots/20261016T120000Z").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // Reads stay open; snapshots are not enabled here
+        let response = warp::test::request().path("/admin/snapshots").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_package_changes_need_mutate_token() {
+        use crate::server::auth::{hash_token, handle_admin_denied, AdminConfig, AdminToken};
+
+        let local = Arc::new(LocalRepository::new(Default::default()));
+        let cache = Arc::new(CacheManager::new());
+        let routes = local_package_routes(local.clone(), cache.clone(), Arc::new(AdminAuth::new(&AdminConfig::default())))
+            .recover(handle_admin_denied);
+        let response = warp::test::request().method("PUT").path("/admin/packages").body("!<arch>").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = warp::test::request().method("DELETE").path("/admin/packages/sl_5.02-1_amd64.deb").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let tokens = vec![
+            AdminToken { name: "grafana".to_string(), sha256: hash_token("read-secret"), scope: AdminScope::Read },
+            AdminToken { name: "ops".to_string(), sha256: hash_token("mutate-secret"), scope: AdminScope::Mutate },
+        ];
+        let routes = local_package_routes(local, cache, Arc::new(AdminAuth::new(&AdminConfig { tokens }))).recover(handle_admin_denied);
+        let upload = |token: &str| {
+            warp::test::request().method("PUT").path("/admin/packages").header("authorization", format!("Bearer {}", token)).body("!<arch>")
+        };
+        assert_eq!(upload("read-secret").reply(&routes).await.status(), StatusCode::FORBIDDEN);
+        // Past authorization; local packages are not enabled here
+        assert_eq!(upload("mutate-secret").reply(&routes).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_reload_subsystems() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let policy_path = dir.path().join("policy.toml");
+        let keyring_path = dir.path().join("archive.gpg");
+        std::fs::write(&keyring_path, b"").unwrap();
+        let write_config = |extra: &str| {
+            std::fs::write(&config_path, format!(
+                "policy_file = {:?}\n{}\n[verification]\ngpg_keyring_path = {:?}\n",
+                policy_path.to_str().unwrap(), extra, keyring_path.to_str().unwrap(),
+            )).unwrap();
+        };
+        let policy = |suites: &str, packages: &str| format!("[allow]\nsuites = [{}]\ncomponents = [\"main\"]\narchitectures = [\"amd64\"]\n[deny]\narchitectures = []\npackages = [{}]\n[limits]\nmax_deb_size_mb = 500\nmax_request_rate_per_minute = 100\n", suites, packages);
+        write_config("");
+        std::fs::write(&policy_path, policy("\"bookworm\"", "")).unwrap();
+
+        let mut audit_config = crate::audit::log::AuditConfig::default();
+        audit_config.store.enabled = true;
+        audit_config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&audit_config));
+        let config = crate::config::settings::AppConfig::load_from_file(config_path.to_str().unwrap()).unwrap();
+        let engine: crate::policy::reload::SharedPolicy = Arc::new(arc_swap::ArcSwap::from_pointee(crate::policy::rules::PolicyEngine::from_config(config.policy.clone())));
+        let keyrings = Arc::new(arc_swap::ArcSwap::from_pointee(crate::verify::keyring::KeyringMap::from_config(&config.verification)));
+        let reloader = SubsystemReloader::new(&config, engine.clone(), keyrings, geo(), None);
+        let routes = reload_routes(Arc::new(reloader), audit.clone(), ops());
+        let reload = |subsystem: &str| {
+            let routes = routes.clone();
+            let path = format!("/admin/reload/{}", subsystem);
+            async move {
+                let response = warp::test::request().method("POST").path(&path).header("authorization", "Bearer mutate-secret").reply(&routes).await;
+                (response.status(), serde_json::from_slice::<serde_json::Value>(response.body()).unwrap())
+            }
+        };
+        let trixie = "/debian/dists/trixie/InRelease";
+
+        std::fs::write(&policy_path, policy("\"bookworm\", \"trixie\"", "")).unwrap();
+        assert!(engine.load().check_path(trixie).is_err());
+        let (status, body) = reload("policy").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["reloaded"], "policy");
+        assert!(engine.load().check_path(trixie).is_ok());
+
+        // A rejected policy keeps the running one
+        std::fs::write(&policy_path, policy("\"bookworm\"", "\"^broken(\"")).unwrap();
+        let (status, body) = reload("config").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body["error"].as_str().unwrap().starts_with("Reloading config failed"));
+        assert!(engine.load().check_path(trixie).is_ok());
+
+        std::fs::write(&policy_path, policy("\"bookworm\"", "")).unwrap();
+        write_config("decision_headers = true");
+        let (status, body) = reload("config").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["restart_required"], serde_json::json!(["decision_headers"]));
+        assert!(engine.load().check_path(trixie).is_err());
+
+        let (status, body) = reload("keyring").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["keyrings"], serde_json::json!([keyring_path.to_str().unwrap()]));
+        std::fs::remove_file(&keyring_path).unwrap();
+        assert_eq!(reload("keyring").await.0, StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(reload("tls").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(reload("geoip").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(reload("cache").await.0, StatusCode::NOT_FOUND);
+
+        let query = AuditQuery { event_type: Some(AuditEventType::SubsystemReload), ..Default::default() };
+        let events = audit.query_events(&query).await.unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events.iter().filter(|event| event.status == AuditStatus::Failed).count(), 2);
+    }
+
+    #[test]
+    fn test_parse_variant() {
+        assert!(matches!(parse_variant("PolicyViolation"), Some(AuditEventType::PolicyViolation)));
+        assert!(parse_variant::<AuditEventType>("policy_violation").is_none());
+        assert!(matches!(parse_variant("Error"), Some(AuditStatus::Error)));
+    }
+}
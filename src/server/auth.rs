@@ -0,0 +1,265 @@
+use anyhow::{Result, anyhow};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use warp::http::{Method, StatusCode};
+use warp::path::FullPath;
+use warp::{Filter, Reply, Rejection};
+use crate::audit::log::AuditLogger;
+use crate::server::client_ip::{client_ip, TrustedProxies};
+use crate::server::dashboard::PUBLIC_PATHS;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminScope {
+    // GET endpoints: audit queries, statistics, the dashboard
+    #[default]
+    Read,
+    // Everything else, e.g. POST /admin/geo/reload; includes read
+    Mutate,
+}
+
+impl AdminScope {
+    fn for_method(method: &Method) -> Self {
+        if method == Method::GET || method == Method::HEAD {
+            AdminScope::Read
+        } else {
+            AdminScope::Mutate
+        }
+    }
+}
+
+// Only the SHA-256 of a token is configured, so the config file does not
+// hold usable credentials. `aptg admin token` prints a new token and its hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminToken {
+    pub name: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub scope: AdminScope,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    // Without tokens the /admin endpoints are open, as before tokens existed
+    pub tokens: Vec<AdminToken>,
+}
+
+impl AdminConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (index, token) in self.tokens.iter().enumerate() {
+            if token.name.is_empty() {
+                return Err(anyhow!("Admin token {} has no name", index + 1));
+            }
+            if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow!("Admin token '{}': sha256 must be 64 hex digits", token.name));
+            }
+            if self.tokens[..index].iter().any(|other| other.name == token.name) {
+                return Err(anyhow!("Admin token name '{}' is used twice", token.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_scope(&self, scope: AdminScope) -> bool {
+        self.tokens.iter().any(|token| token.scope >= scope)
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Debug)]
+pub struct AdminDenied {
+    status: StatusCode,
+    reason: &'static str,
+}
+
+impl warp::reject::Reject for AdminDenied {}
+
+pub struct AdminAuth {
+    // Token hash -> (name, scope)
+    tokens: HashMap<String, (String, AdminScope)>,
+}
+
+impl AdminAuth {
+    pub fn new(config: &AdminConfig) -> Self {
+        let tokens = config.tokens
+            .iter()
+            .map(|token| (token.sha256.to_ascii_lowercase(), (token.name.clone(), token.scope)))
+            .collect();
+        Self { tokens }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    // The name of the token used, or None when no tokens are configured and
+    // only read access is asked for
+    pub fn authorize(&self, authorization: Option<&str>, required: AdminScope) -> Result<Option<String>, AdminDenied> {
+        if !self.is_enabled() && required == AdminScope::Read {
+            return Ok(None);
+        }
+        self.authorize_token(authorization, required).map(Some)
+    }
+
+    // Like authorize, but without tokens nothing is allowed
+    pub fn authorize_token(&self, authorization: Option<&str>, required: AdminScope) -> Result<String, AdminDenied> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or(AdminDenied { status: StatusCode::UNAUTHORIZED, reason: "Bearer token required" })?;
+        // Hashes are compared, so lookup timing says nothing about the token
+        let (name, scope) = self.tokens
+            .get(&hash_token(token))
+            .ok_or(AdminDenied { status: StatusCode::UNAUTHORIZED, reason: "Unknown token" })?;
+        if *scope < required {
+            return Err(AdminDenied { status: StatusCode::FORBIDDEN, reason: "Token is read-only" });
+        }
+        Ok(name.clone())
+    }
+}
+
+// For every endpoint that changes state, e.g. reloads or uploads that end up
+// re-signed: a token with the scope is needed even when no tokens are configured
+pub fn require_token(auth: Arc<AdminAuth>, required: AdminScope) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let result = auth.authorize_token(authorization.as_deref(), required).map(|_| ()).map_err(warp::reject::custom);
+            async move { result }
+        })
+        .untuple_one()
+}
+
+// Guards every /admin path and audits each admin request, allowed or not.
+// Other paths are rejected as not found so the next route gets them
+pub fn admin_auth(auth: Arc<AdminAuth>, audit: Arc<AuditLogger>, proxies: Arc<TrustedProxies>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(warp::method())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(client_ip(proxies))
+        .and_then(move |path: FullPath, method: Method, authorization: Option<String>, client_ip: Option<IpAddr>| {
+            let auth = auth.clone();
+            let audit = audit.clone();
+            async move {
+                if path.as_str() != "/admin" && !path.as_str().starts_with("/admin/") {
+                    return Err(warp::reject::not_found());
+                }
+                if method == Method::GET && PUBLIC_PATHS.contains(&path.as_str()) {
+                    return Ok(());
+                }
+                match auth.authorize(authorization.as_deref(), AdminScope::for_method(&method)) {
+                    Ok(token) => {
+                        audit.log_admin_action(client_ip, &method, path.as_str(), token.as_deref(), None).await;
+                        Ok(())
+                    }
+                    Err(denied) => {
+                        audit.log_admin_action(client_ip, &method, path.as_str(), None, Some(denied.reason)).await;
+                        Err(warp::reject::custom(denied))
+                    }
+                }
+            }
+        })
+        .untuple_one()
+}
+
+pub async fn handle_admin_denied(rejection: Rejection) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let Some(denied) = rejection.find::<AdminDenied>() else {
+        return Err(rejection);
+    };
+    let reply = warp::reply::with_status(warp::reply::json(&serde_json::json!({"error": denied.reason})), denied.status);
+    Ok(Box::new(warp::reply::with_header(reply, "www-authenticate", "Bearer realm=\"aptg admin\"")))
+}
+
+// `aptg admin token`: a random token for the client and the hash for config.toml
+pub fn run(args: &[String]) -> Result<()> {
+    if !args.is_empty() {
+        return Err(anyhow!("usage: aptg admin token"));
+    }
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    println!("token:  {}", token);
+    println!("sha256: {}", hash_token(&token));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> AdminAuth {
+        AdminAuth::new(&AdminConfig {
+            tokens: vec![
+                AdminToken { name: "grafana".to_string(), sha256: hash_token("read-secret"), scope: AdminScope::Read },
+                AdminToken { name: "ops".to_string(), sha256: hash_token("mutate-secret").to_uppercase(), scope: AdminScope::Mutate },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_scopes() {
+        let auth = auth();
+        assert_eq!(auth.authorize(Some("Bearer read-secret"), AdminScope::Read).unwrap().as_deref(), Some("grafana"));
+        assert_eq!(auth.authorize(Some("Bearer read-secret"), AdminScope::Mutate).unwrap_err().status, StatusCode::FORBIDDEN);
+        assert_eq!(auth.authorize(Some("Bearer mutate-secret"), AdminScope::Mutate).unwrap().as_deref(), Some("ops"));
+        assert_eq!(auth.authorize(Some("Bearer wrong"), AdminScope::Read).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        assert_eq!(auth.authorize(Some("Basic cmVhZC1zZWNyZXQ="), AdminScope::Read).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        assert_eq!(auth.authorize(None, AdminScope::Read).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        assert_eq!(AdminAuth::new(&AdminConfig::default()).authorize(None, AdminScope::Read).unwrap(), None);
+        assert_eq!(AdminAuth::new(&AdminConfig::default()).authorize(None, AdminScope::Mutate).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        assert!(AdminAuth::new(&AdminConfig::default()).authorize_token(None, AdminScope::Mutate).is_err());
+        assert_eq!(auth.authorize_token(Some("Bearer read-secret"), AdminScope::Mutate).unwrap_err().status, StatusCode::FORBIDDEN);
+        
+        let config = AdminConfig { tokens: vec![AdminToken { name: "ops".to_string(), sha256: "abc".to_string(), scope: AdminScope::Read }] };
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filter_guards_admin_paths_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::audit::log::AuditConfig::default();
+        config.store.enabled = true;
+        config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&config));
+        let routes = admin_auth(Arc::new(auth()), audit.clone(), Arc::new(TrustedProxies::default()))
+            .map(|| "ok")
+            .recover(handle_admin_denied);
+        
+        let response = warp::test::request().path("/admin/geo/stats").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer realm=\"aptg admin\"");
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/geo/reload")
+            .header("authorization", "Bearer read-secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = warp::test::request()
+            .path("/admin/geo/stats")
+            .header("authorization", "Bearer read-secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.body(), "ok");
+        let response = warp::test::request().path("/admin/ui").reply(&routes).await;
+        assert_eq!(response.body(), "ok");
+        let response = warp::test::request().path("/administrator").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        
+        let query = crate::audit::store::AuditQuery { event_type: Some(crate::audit::log::AuditEventType::AdminAction), ..Default::default() };
+        let events = audit.query_events(&query).await.unwrap();
+        let logged: Vec<_> = events.iter().map(|e| (e.path.as_str(), e.status.as_str(), e.client_identity.as_deref())).collect();
+        assert_eq!(logged, vec![
+            ("/admin/geo/stats", "failed", None),
+            ("/admin/geo/reload", "failed", None),
+            ("/admin/geo/stats", "success", Some("token:grafana")),
+        ]);
+    }
+}
@@ -0,0 +1,155 @@
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::error;
+use warp::http::HeaderMap;
+use warp::Filter;
+use crate::tls::identity::ClientIdentity;
+use crate::tls::simple_server::ConnectionInfo;
+
+// Peers whose X-Forwarded-For / X-Real-IP headers are believed. Requests from
+// anyone else are attributed to the socket address, so clients cannot pick
+// the address policies, rate limits and audit events see.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    // Invalid entries are logged and skipped, leaving the proxy untrusted
+    pub fn from_config(networks: &[String]) -> Self {
+        let networks = networks
+            .iter()
+            .filter_map(|network| {
+                let parsed = network
+                    .parse::<IpNet>()
+                    .or_else(|_| network.parse::<IpAddr>().map(IpNet::from));
+                if parsed.is_err() {
+                    error!("Ignoring invalid trusted proxy '{}'", network);
+                }
+                parsed.ok()
+            })
+            .collect();
+        Self { networks }
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    pub fn resolve(&self, remote: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let remote = remote.map(|addr| addr.ip().to_canonical());
+        if !remote.is_some_and(|ip| self.is_trusted(&ip)) {
+            return remote;
+        }
+        
+        // Walk the chain from the nearest hop; the first untrusted address is the client
+        let forwarded: Vec<IpAddr> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(parse_forwarded)
+            .collect();
+        if let Some(first) = forwarded.first() {
+            return Some(*forwarded.iter().rev().find(|ip| !self.is_trusted(ip)).unwrap_or(first));
+        }
+        
+        headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_forwarded)
+            .or(remote)
+    }
+}
+
+// Proxies write "2001:db8::1", "[2001:db8::1]:443" or "192.0.2.1:443".
+// IPv4-mapped addresses from dual-stack sockets become plain IPv4, so they
+// match IPv4 trusted proxies, GeoIP records and audit queries
+fn parse_forwarded(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    let ip = match value.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => value.parse::<SocketAddr>().ok()?.ip(),
+    };
+    Some(ip.to_canonical())
+}
+
+// warp only knows the peer of connections it accepted itself; the HTTPS server
+// passes it along with the request instead
+fn remote_addr() -> impl Filter<Extract = (Option<SocketAddr>,), Error = std::convert::Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::ext::optional::<ConnectionInfo>())
+        .map(|remote: Option<SocketAddr>, connection: Option<ConnectionInfo>| remote.or(connection.map(|c| c.remote_addr)))
+}
+
+pub fn client_ip(proxies: Arc<TrustedProxies>) -> impl Filter<Extract = (Option<IpAddr>,), Error = std::convert::Infallible> + Clone {
+    remote_addr()
+        .and(warp::header::headers_cloned())
+        .map(move |remote: Option<SocketAddr>, headers: HeaderMap| proxies.resolve(remote, &headers))
+}
+
+// The verified client certificate, on HTTPS connections that presented one
+pub fn client_identity() -> impl Filter<Extract = (Option<ClientIdentity>,), Error = std::convert::Infallible> + Clone {
+    warp::ext::optional::<ConnectionInfo>().map(|connection: Option<ConnectionInfo>| connection.and_then(|c| c.identity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        headers
+    }
+
+    fn addr(ip: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::new(ip.parse().unwrap(), 40000))
+    }
+
+    #[test]
+    fn test_untrusted_peers_cannot_spoof() {
+        let proxies = TrustedProxies::from_config(&["10.0.0.0/8".to_string()]);
+        let resolved = proxies.resolve(addr("203.0.113.9"), &headers("198.51.100.1"));
+        assert_eq!(resolved, Some("203.0.113.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_forwarded_chain_through_trusted_proxies() {
+        let proxies = TrustedProxies::from_config(&["10.0.0.0/8".to_string(), "192.0.2.1".to_string()]);
+        // Client-supplied garbage on the left is ignored
+        let resolved = proxies.resolve(addr("10.0.0.5"), &headers("1.2.3.4, 198.51.100.7, 192.0.2.1"));
+        assert_eq!(resolved, Some("198.51.100.7".parse().unwrap()));
+        
+        let mut real_ip = HeaderMap::new();
+        real_ip.insert("x-real-ip", "198.51.100.8".parse().unwrap());
+        assert_eq!(proxies.resolve(addr("10.0.0.5"), &real_ip), Some("198.51.100.8".parse().unwrap()));
+        assert_eq!(proxies.resolve(None, &real_ip), None);
+    }
+
+    #[tokio::test]
+    async fn test_tls_connection_peer() {
+        let proxies = Arc::new(TrustedProxies::default());
+        let identity = ClientIdentity { subject: "builder-01".to_string(), fingerprint: "ab".repeat(32), sans: vec![], organizational_units: vec![] };
+        let connection = ConnectionInfo { remote_addr: addr("192.0.2.4").unwrap(), identity: Some(identity.clone()) };
+        let filter = client_ip(proxies).and(client_identity());
+        
+        let (ip, found) = warp::test::request().extension(connection).filter(&filter).await.unwrap();
+        assert_eq!(ip, Some("192.0.2.4".parse().unwrap()));
+        assert_eq!(found, Some(identity));
+        let (ip, found) = warp::test::request().filter(&filter).await.unwrap();
+        assert_eq!(ip, None);
+        assert_eq!(found, None);
+    }
+
+    #[test]
+    fn test_ipv6_and_mapped_addresses() {
+        let proxies = TrustedProxies::from_config(&["10.0.0.0/8".to_string(), "2001:db8:ffff::/48".to_string()]);
+        // A dual-stack listener reports IPv4 peers as ::ffff:a.b.c.d
+        let resolved = proxies.resolve(addr("::ffff:10.0.0.5"), &headers("[2001:db8:1::7]:51234, 2001:db8:ffff::1"));
+        assert_eq!(resolved, Some("2001:db8:1::7".parse().unwrap()));
+        assert_eq!(proxies.resolve(addr("2001:db8:ffff::2"), &headers("198.51.100.7:443")), Some("198.51.100.7".parse().unwrap()));
+        assert_eq!(proxies.resolve(addr("::ffff:203.0.113.9"), &HeaderMap::new()), Some("203.0.113.9".parse().unwrap()));
+    }
+}
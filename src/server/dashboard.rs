@@ -0,0 +1,267 @@
+use anyhow::Result;
+use prometheus::core::Collector;
+use prometheus::proto::{MetricFamily, MetricType};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Reply, Rejection};
+use crate::audit::log::{AuditEvent, AuditEventType, AuditLogger};
+use crate::audit::store::AuditQuery;
+use crate::geoip::location::{LocationStatsSummary, NamedCount};
+use crate::geoip::policy::GeoPolicyEngine;
+use crate::metrics::registry::Metrics;
+use crate::mirror::latency::{HostLatency, UpstreamLatency};
+use crate::server::admin::error_reply;
+
+const INDEX: &str = include_str!("dashboard/index.html");
+const SCRIPT: &str = include_str!("dashboard/dashboard.js");
+const STYLE: &str = include_str!("dashboard/dashboard.css");
+// The page only loads its own script and stylesheet and polls the summary
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'self'; style-src 'self'; connect-src 'self'; frame-ancestors 'none'";
+// The page shell and its assets carry no data, so a browser can load them
+// without a token; the script then sends the token for the summary
+pub const PUBLIC_PATHS: &[&str] = &["/admin/ui", "/admin/ui/dashboard.js", "/admin/ui/dashboard.css"];
+// Most recent stored events counted for the top packages
+const PACKAGE_SAMPLE: usize = 5000;
+const TOP_LIMIT: usize = 10;
+const RECENT_FAILURES: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct CacheSummary {
+    pub hits: u64,
+    // Cache misses answered from upstream
+    pub fetches: u64,
+    pub fetch_errors: u64,
+    pub hit_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerificationSummary {
+    pub failed: u64,
+    pub unexpected_signer: u64,
+    pub unverified_denied: u64,
+    // Empty without the audit store
+    pub recent: Vec<AuditEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpstreamHealth {
+    pub host: String,
+    pub class: &'static str,
+    #[serde(flatten)]
+    pub latency: HostLatency,
+}
+
+// Totals since startup; the page derives rates from successive polls
+#[derive(Debug, Serialize)]
+pub struct DashboardSummary {
+    // Repository requests by response status
+    pub requests: BTreeMap<String, u64>,
+    pub cache: CacheSummary,
+    pub verification: VerificationSummary,
+    // Counted over the most recent stored events; empty without the audit store
+    pub top_packages: Vec<NamedCount>,
+    // None when GeoIP is disabled
+    pub geo: Option<LocationStatsSummary>,
+    pub upstreams: Vec<UpstreamHealth>,
+}
+
+// Read-only view over the metrics, the audit store, GeoIP statistics and
+// upstream latency, served under /admin/ui
+pub struct Dashboard {
+    audit: Arc<AuditLogger>,
+    geo: Arc<GeoPolicyEngine>,
+    upstream: Arc<UpstreamLatency>,
+}
+
+// Sums counters, or histogram sample counts, by one label
+fn totals_by_label(families: Vec<MetricFamily>, label: &str) -> BTreeMap<String, u64> {
+    let mut totals = BTreeMap::new();
+    for family in &families {
+        for metric in family.get_metric() {
+            let Some(value) = metric.get_label().iter().find(|pair| pair.get_name() == label) else {
+                continue;
+            };
+            let count = match family.get_field_type() {
+                MetricType::COUNTER => metric.get_counter().get_value() as u64,
+                MetricType::HISTOGRAM => metric.get_histogram().get_sample_count(),
+                _ => continue,
+            };
+            *totals.entry(value.get_value().to_string()).or_insert(0) += count;
+        }
+    }
+    totals
+}
+
+// sl for /debian/pool/main/s/sl/sl_5.02-1_amd64.deb
+fn package_name(path: &str) -> Option<&str> {
+    let file = path.split_once("/pool/")?.1.rsplit('/').next()?;
+    if !file.ends_with(".deb") && !file.ends_with(".udeb") {
+        return None;
+    }
+    file.split('_').next().filter(|name| !name.is_empty())
+}
+
+impl Dashboard {
+    pub fn new(audit: Arc<AuditLogger>, geo: Arc<GeoPolicyEngine>, upstream: Arc<UpstreamLatency>) -> Self {
+        Self { audit, geo, upstream }
+    }
+
+    pub async fn summary(&self) -> Result<DashboardSummary> {
+        let metrics = Metrics::global();
+        let events = totals_by_label(metrics.audit_events.collect(), "type");
+        let count = |event_type: AuditEventType| events.get(event_type.as_str()).copied().unwrap_or(0);
+        let (hits, fetches) = (count(AuditEventType::CacheHit), count(AuditEventType::FetchSuccess));
+        
+        let (recent, top_packages) = if self.audit.has_store() {
+            (self.recent_failures().await?, self.top_packages().await?)
+        } else {
+            (vec![], vec![])
+        };
+        let geo = self.geo.get_policy_stats().enabled.then(|| self.geo.location_stats(TOP_LIMIT));
+        let upstreams = self.upstream
+            .hosts()
+            .into_iter()
+            .map(|(host, class, latency)| UpstreamHealth { host, class: class.as_str(), latency })
+            .collect();
+        
+        Ok(DashboardSummary {
+            requests: totals_by_label(metrics.request_duration.collect(), "status"),
+            cache: CacheSummary {
+                hits,
+                fetches,
+                fetch_errors: count(AuditEventType::FetchError),
+                hit_ratio: (hits + fetches > 0).then(|| hits as f64 / (hits + fetches) as f64),
+            },
+            verification: VerificationSummary {
+                failed: count(AuditEventType::VerificationFailed),
+                unexpected_signer: count(AuditEventType::UnexpectedSigner),
+                unverified_denied: count(AuditEventType::UnverifiedContentDenied),
+                recent,
+            },
+            top_packages,
+            geo,
+            upstreams,
+        })
+    }
+
+    async fn recent_failures(&self) -> Result<Vec<AuditEvent>> {
+        self.audit
+            .query_events(&AuditQuery {
+                event_type: Some(AuditEventType::VerificationFailed),
+                limit: Some(RECENT_FAILURES),
+                newest_first: true,
+                ..Default::default()
+            })
+            .await
+    }
+
+    // Packages served, from the cache or upstream
+    async fn top_packages(&self) -> Result<Vec<NamedCount>> {
+        let events = self.audit
+            .query_events(&AuditQuery { limit: Some(PACKAGE_SAMPLE), newest_first: true, ..Default::default() })
+            .await?;
+        let mut counts: HashMap<&str, u64> = HashMap::new();
+        for event in &events {
+            if !matches!(event.event_type, AuditEventType::CacheHit | AuditEventType::FetchSuccess) {
+                continue;
+            }
+            if let Some(name) = package_name(&event.path) {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+        }
+        let mut top: Vec<NamedCount> = counts
+            .into_iter()
+            .map(|(name, requests)| NamedCount { name: name.to_string(), requests })
+            .collect();
+        top.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.name.cmp(&b.name)));
+        top.truncate(TOP_LIMIT);
+        Ok(top)
+    }
+}
+
+fn with_dashboard(dashboard: Arc<Dashboard>) -> impl Filter<Extract = (Arc<Dashboard>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || dashboard.clone())
+}
+
+pub fn dashboard_routes(dashboard: Arc<Dashboard>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let page = warp::path!("admin" / "ui")
+        .and(warp::get())
+        .map(|| warp::reply::with_header(warp::reply::html(INDEX), "content-security-policy", CONTENT_SECURITY_POLICY));
+
+    let script = warp::path!("admin" / "ui" / "dashboard.js")
+        .and(warp::get())
+        .map(|| warp::reply::with_header(SCRIPT, "content-type", "text/javascript; charset=utf-8"));
+
+    let style = warp::path!("admin" / "ui" / "dashboard.css")
+        .and(warp::get())
+        .map(|| warp::reply::with_header(STYLE, "content-type", "text/css; charset=utf-8"));
+
+    let summary = warp::path!("admin" / "ui" / "summary")
+        .and(warp::get())
+        .and(with_dashboard(dashboard))
+        .and_then(handle_summary);
+
+    page.or(script).or(style).or(summary)
+}
+
+async fn handle_summary(dashboard: Arc<Dashboard>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    match dashboard.summary().await {
+        Ok(summary) => Ok(Box::new(warp::reply::json(&summary))),
+        Err(e) => Ok(error_reply(&e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::log::RequestContext;
+    use crate::geoip::policy::GeoPolicy;
+    use crate::mirror::latency::PathClass;
+    use std::time::Duration;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(package_name("/debian/pool/main/s/sl/sl_5.02-1_amd64.deb"), Some("sl"));
+        assert_eq!(package_name("/debian/pool/main/d/debian-installer/di-utils_1.140_amd64.udeb"), Some("di-utils"));
+        assert_eq!(package_name("/debian/pool/main/s/sl/sl_5.02-1.dsc"), None);
+        assert_eq!(package_name("/debian/dists/bookworm/InRelease"), None);
+    }
+
+    #[tokio::test]
+    async fn test_page_and_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = crate::audit::log::AuditConfig::default();
+        config.store.enabled = true;
+        config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&config));
+        let request = RequestContext::default();
+        for arch in ["amd64", "arm64"] {
+            audit.log_cache_hit(&request, &format!("/debian/pool/main/s/sl/sl_5.02-1_{}.deb", arch)).await;
+        }
+        audit.log_fetch_success(&request, "/debian/pool/main/a/apt/apt_2.6.1_amd64.deb", Duration::from_millis(80), 1024).await;
+        audit.log_cache_hit(&request, "/debian/dists/bookworm/InRelease").await;
+        audit.log_verification_failed(&request, "/debian/dists/bookworm/InRelease", "bad signature").await;
+        let upstream = Arc::new(UpstreamLatency::default());
+        upstream.record_failure("deb.debian.org", PathClass::Index, Duration::from_secs(30));
+        let dashboard = Arc::new(Dashboard::new(audit, Arc::new(GeoPolicyEngine::new(GeoPolicy::default())), upstream));
+        let routes = dashboard_routes(dashboard);
+        
+        let response = warp::test::request().path("/admin/ui").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-security-policy"].to_str().unwrap().contains("script-src 'self'"));
+        assert!(String::from_utf8_lossy(response.body()).contains("/admin/ui/dashboard.js"));
+        
+        let response = warp::test::request().path("/admin/ui/summary").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["top_packages"], serde_json::json!([{"name": "sl", "requests": 2}, {"name": "apt", "requests": 1}]));
+        assert_eq!(body["verification"]["recent"][0]["message"], "GPG verification failed: bad signature");
+        assert!(body["cache"]["hits"].as_u64().unwrap() >= 3);
+        assert!(body["geo"].is_null());
+        assert_eq!(body["upstreams"][0]["host"], "deb.debian.org");
+        assert_eq!(body["upstreams"][0]["class"], "index");
+        assert_eq!(body["upstreams"][0]["failures"], 1);
+    }
+}
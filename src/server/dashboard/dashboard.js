@@ -0,0 +1,187 @@
+// Polls /admin/ui/summary and renders it. Values from the server are only
+// ever set as text, never as markup
+"use strict";
+
+const POLL_MS = 5000;
+const HISTORY = 120;
+const SVG = "http://www.w3.org/2000/svg";
+
+let previous = null;
+const rates = [];
+// Kept for the browser session only; needed when [[admin.tokens]] are configured
+let token = sessionStorage.getItem("aptg-admin-token");
+
+function element(name, text, className) {
+  const node = document.createElement(name);
+  if (text !== undefined) {
+    node.textContent = text;
+  }
+  if (className) {
+    node.className = className;
+  }
+  return node;
+}
+
+function fill(id, header, rows) {
+  const table = document.getElementById(id);
+  table.replaceChildren();
+  if (header) {
+    const tr = element("tr");
+    header.forEach((title) => tr.append(element("th", title)));
+    table.append(tr);
+  }
+  rows.forEach((cells) => {
+    const tr = element("tr");
+    cells.forEach((cell) => {
+      tr.append(typeof cell === "number" ? element("td", cell.toLocaleString(), "number") : cell instanceof Node ? cell : element("td", cell));
+    });
+    table.append(tr);
+  });
+}
+
+function cell(text, className) {
+  return element("td", text, className);
+}
+
+function renderRequests(summary, now) {
+  const total = Object.values(summary.requests).reduce((sum, count) => sum + count, 0);
+  if (previous) {
+    const seconds = (now - previous.time) / 1000;
+    rates.push(Math.max(0, total - previous.total) / seconds);
+    if (rates.length > HISTORY) {
+      rates.shift();
+    }
+    document.getElementById("request-rate").textContent = rates[rates.length - 1].toFixed(1);
+  }
+  previous = { time: now, total };
+
+  const history = document.getElementById("request-history");
+  history.replaceChildren();
+  const peak = Math.max(1, ...rates);
+  const line = document.createElementNS(SVG, "polyline");
+  line.setAttribute("points", rates.map((rate, i) => `${i},${30 - (rate / peak) * 28}`).join(" "));
+  history.setAttribute("viewBox", `0 0 ${Math.max(1, rates.length - 1)} 30`);
+  history.append(line);
+
+  fill("request-statuses", ["Status", "Requests"], Object.entries(summary.requests).map(([status, count]) => [status, count]));
+}
+
+function renderCache(cache) {
+  const ratio = cache.hit_ratio === null ? "-" : `${(cache.hit_ratio * 100).toFixed(1)}%`;
+  document.getElementById("hit-ratio").textContent = ratio;
+  fill("cache", null, [
+    ["Hits", cache.hits],
+    ["Fetched upstream", cache.fetches],
+    ["Fetch errors", cache.fetch_errors],
+  ]);
+}
+
+function renderGeo(geo) {
+  const map = document.getElementById("geo-map");
+  map.replaceChildren();
+  for (let x = 0; x <= 360; x += 30) {
+    map.append(gridLine(x, 0, x, 180));
+  }
+  for (let y = 0; y <= 180; y += 30) {
+    map.append(gridLine(0, y, 360, y));
+  }
+  if (!geo) {
+    fill("top-countries", null, [["GeoIP is not enabled"]]);
+    return;
+  }
+  const peak = Math.max(1, ...geo.map.map((point) => point.requests));
+  geo.map.forEach((point) => {
+    // Equirectangular: longitude -180..180 and latitude 90..-90 onto 360x180
+    const circle = document.createElementNS(SVG, "circle");
+    circle.setAttribute("cx", point.longitude + 180);
+    circle.setAttribute("cy", 90 - point.latitude);
+    circle.setAttribute("r", 1.5 + Math.sqrt(point.requests / peak) * 8);
+    const title = document.createElementNS(SVG, "title");
+    title.textContent = `${point.country}: ${point.requests.toLocaleString()} requests`;
+    circle.append(title);
+    map.append(circle);
+  });
+  fill("top-countries", ["Country", "Requests"], geo.countries.map((country) => [country.name, country.requests]));
+}
+
+function gridLine(x1, y1, x2, y2) {
+  const line = document.createElementNS(SVG, "line");
+  line.setAttribute("x1", x1);
+  line.setAttribute("y1", y1);
+  line.setAttribute("x2", x2);
+  line.setAttribute("y2", y2);
+  return line;
+}
+
+function renderVerification(verification) {
+  fill("verification", null, [
+    ["Signature failures", verification.failed],
+    ["Unexpected signers", verification.unexpected_signer],
+    ["Unverified content denied", verification.unverified_denied],
+  ]);
+  fill(
+    "recent-failures",
+    verification.recent.length ? ["Time", "Path", "Reason"] : null,
+    verification.recent.map((event) => [new Date(event.timestamp).toLocaleString(), event.path, event.message || ""]),
+  );
+}
+
+function health(upstream) {
+  if (upstream.fetches > 0 && upstream.failures / upstream.fetches > 0.5) {
+    return cell("failing", "failing");
+  }
+  return upstream.seconds > 5 ? cell("slow", "slow") : cell("ok", "ok");
+}
+
+function renderUpstreams(upstreams) {
+  fill(
+    "upstreams",
+    ["Host", "Class", "Health", "Fetches", "Failures", "Avg seconds", "Avg MB/s"],
+    upstreams.map((upstream) => [
+      upstream.host,
+      upstream.class,
+      health(upstream),
+      upstream.fetches,
+      upstream.failures,
+      cell(upstream.seconds.toFixed(2), "number"),
+      cell((upstream.bytes_per_second / 1e6).toFixed(2), "number"),
+    ]),
+  );
+}
+
+async function refresh() {
+  try {
+    const headers = token ? { Authorization: `Bearer ${token}` } : {};
+    const response = await fetch("/admin/ui/summary", { cache: "no-store", headers });
+    const login = document.getElementById("login");
+    login.hidden = response.status !== 401;
+    if (response.status === 401) {
+      document.getElementById("updated").textContent = token ? "token rejected" : "sign in to load data";
+      return;
+    }
+    if (!response.ok) {
+      throw new Error(`summary returned ${response.status}`);
+    }
+    const summary = await response.json();
+    const now = Date.now();
+    renderRequests(summary, now);
+    renderCache(summary.cache);
+    fill("top-packages", ["Package", "Downloads"], summary.top_packages.map((top) => [top.name, top.requests]));
+    renderGeo(summary.geo);
+    renderVerification(summary.verification);
+    renderUpstreams(summary.upstreams);
+    document.getElementById("updated").textContent = `updated ${new Date(now).toLocaleTimeString()}`;
+  } catch (error) {
+    document.getElementById("updated").textContent = `update failed: ${error.message}`;
+  }
+}
+
+document.getElementById("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  token = document.getElementById("token").value.trim();
+  sessionStorage.setItem("aptg-admin-token", token);
+  refresh();
+});
+
+refresh();
+setInterval(refresh, POLL_MS);
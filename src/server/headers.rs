@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use warp::http::header::{HeaderValue, CONTENT_TYPE};
+use warp::reply::Response;
+use warp::Reply;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    // Strict-Transport-Security max-age; 0 leaves the header out
+    pub hsts_max_age_secs: u64,
+    pub hsts_include_subdomains: bool,
+    // Empty leaves the header out
+    pub content_security_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hsts_max_age_secs: 0,
+            hsts_include_subdomains: false,
+            content_security_policy: "default-src 'none'; frame-ancestors 'none'".to_string(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    // Browsers ignore HSTS over plain HTTP, so only the HTTPS listener sends it by default
+    pub fn https() -> Self {
+        Self { hsts_max_age_secs: 31536000, ..Self::default() }
+    }
+
+    // Package and index downloads are left untouched; only pages and API
+    // responses a browser might render get the headers. A page that sets its
+    // own Content-Security-Policy keeps it
+    pub fn apply(&self, reply: impl Reply) -> Response {
+        let mut response = reply.into_response();
+        let renderable = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/html") || v.starts_with("application/json"));
+        if !self.enabled || !renderable {
+            return response;
+        }
+        
+        let headers = response.headers_mut();
+        headers.insert("x-content-type-options", HeaderValue::from_static("nosniff"));
+        if self.hsts_max_age_secs > 0 {
+            let mut hsts = format!("max-age={}", self.hsts_max_age_secs);
+            if self.hsts_include_subdomains {
+                hsts.push_str("; includeSubDomains");
+            }
+            if let Ok(value) = HeaderValue::from_str(&hsts) {
+                headers.insert("strict-transport-security", value);
+            }
+        }
+        if !self.content_security_policy.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&self.content_security_policy) {
+                headers.entry("content-security-policy").or_insert(value);
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_on_json_only() {
+        let headers = SecurityHeadersConfig::https();
+        let response = headers.apply(warp::reply::json(&serde_json::json!({"status": "ok"})));
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(response.headers()["strict-transport-security"], "max-age=31536000");
+        assert_eq!(response.headers()["content-security-policy"], "default-src 'none'; frame-ancestors 'none'");
+        
+        let page = warp::reply::with_header(warp::reply::html("<p>"), "content-security-policy", "script-src 'self'");
+        assert_eq!(headers.apply(page).headers()["content-security-policy"], "script-src 'self'");
+        
+        let package = warp::reply::with_header(vec![0u8; 4], "content-type", "application/vnd.debian.binary-package");
+        assert!(headers.apply(package).headers().get("x-content-type-options").is_none());
+    }
+
+    #[test]
+    fn test_plain_http_defaults() {
+        let headers = SecurityHeadersConfig { content_security_policy: String::new(), ..Default::default() };
+        let response = headers.apply(warp::reply::json(&serde_json::json!({})));
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+        assert!(response.headers().get("strict-transport-security").is_none());
+        assert!(response.headers().get("content-security-policy").is_none());
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{Result, anyhow};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tracing::info;
+use warp::hyper::server::conn::AddrStream;
+use warp::hyper::service::{make_service_fn, service_fn, Service};
+use warp::hyper::{Body, Request};
+use warp::{Filter, Rejection, Reply};
+use crate::config::settings::ListenerConfig;
+use crate::tls::simple_server::ConnectionInfo;
+
+// A listening socket with the listener's options applied, ready for tokio
+pub fn bind(addr: SocketAddr, options: &ListenerConfig) -> Result<std::net::TcpListener> {
+    let bind = || -> std::io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        // Set either way, so whether [::] takes IPv4 does not depend on net.ipv6.bindv6only
+        if addr.is_ipv6() {
+            socket.set_only_v6(options.ipv6_only)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(options.backlog.min(i32::MAX as u32) as i32)?;
+        Ok(socket.into())
+    };
+    bind().map_err(|e| anyhow!("Failed to bind {}: {}", addr, e))
+}
+
+// Serves plain HTTP on every listener until one of them fails. Each request
+// carries a ConnectionInfo extension, as on the TLS listeners
+pub async fn serve<F>(listeners: Vec<std::net::TcpListener>, routes: F) -> Result<()>
+where
+    F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        let service = warp::service(routes.clone());
+        let make_service = make_service_fn(move |stream: &AddrStream| {
+            let connection = ConnectionInfo { remote_addr: stream.remote_addr(), identity: None };
+            let service = service.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |mut request: Request<Body>| {
+                    request.extensions_mut().insert(connection.clone());
+                    service.clone().call(request)
+                }))
+            }
+        });
+        let server = warp::hyper::Server::from_tcp(listener)?.serve(make_service);
+        info!("Server listening on {}", addr);
+        servers.spawn(server);
+    }
+    while let Some(result) = servers.join_next().await {
+        result?.map_err(|e| anyhow!("Server failed: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dual_stack_listeners() {
+        let v4 = bind("127.0.0.1:0".parse().unwrap(), &ListenerConfig::default()).unwrap();
+        let port = v4.local_addr().unwrap().port();
+        // Hosts without IPv6 cannot take part
+        let Ok(v6) = bind(SocketAddr::new("::1".parse().unwrap(), port), &ListenerConfig { ipv6_only: true, ..Default::default() }) else {
+            return;
+        };
+        let routes = warp::path("healthz").and(warp::addr::remote()).and(warp::ext::optional::<ConnectionInfo>()).map(
+            |remote: Option<SocketAddr>, connection: Option<ConnectionInfo>| remote.or(connection.map(|c| c.remote_addr)).unwrap().ip().to_string(),
+        );
+        tokio::spawn(serve(vec![v4, v6], routes));
+        
+        let client = reqwest::Client::new();
+        for (host, peer) in [("127.0.0.1", "127.0.0.1"), ("[::1]", "::1")] {
+            let body = client.get(format!("http://{}:{}/healthz", host, port)).send().await.unwrap().text().await.unwrap();
+            assert_eq!(body, peer);
+        }
+    }
+}
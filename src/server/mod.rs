@@ -1 +1,11 @@
+pub mod admin;
+pub mod auth;
+pub mod client_ip;
+pub mod dashboard;
+pub mod headers;
+pub mod listener;
+pub mod ratelimit;
+pub mod reload;
+pub mod repo_auth;
 pub mod router;
+pub mod tenant;
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+use crate::metrics::registry::Metrics;
+use crate::policy::rules::LimitsPolicy;
+use crate::server::client_ip::{client_ip, TrustedProxies};
+
+// Idle per-IP buckets are dropped once the table grows past this size
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate_per_minute: u32, burst: u32, now: Instant) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: rate_per_minute as f64 / 60.0,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    // Err carries how long until the next token is available
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec))
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    pub scope: &'static str,
+    pub retry_after: Duration,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+pub struct RateLimiter {
+    global: Option<Mutex<TokenBucket>>,
+    per_ip: Option<(u32, u32)>,
+    clients: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn from_limits(limits: &LimitsPolicy) -> Self {
+        let now = Instant::now();
+        let global = (limits.max_request_rate_per_minute > 0).then(|| {
+            let rate = limits.max_request_rate_per_minute;
+            Mutex::new(TokenBucket::new(rate, limits.burst.unwrap_or(rate), now))
+        });
+        let per_ip = (limits.max_request_rate_per_ip_per_minute > 0).then(|| {
+            let rate = limits.max_request_rate_per_ip_per_minute;
+            (rate, limits.per_ip_burst.unwrap_or(rate))
+        });
+        
+        Self {
+            global,
+            per_ip,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || self.per_ip.is_some()
+    }
+
+    pub fn check(&self, client_ip: Option<IpAddr>, now: Instant) -> Result<(), RateLimited> {
+        // Per-IP first so one noisy client does not drain the global bucket
+        if let (Some((rate, burst)), Some(ip)) = (self.per_ip, client_ip) {
+            let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+            if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&ip) {
+                clients.retain(|_, bucket| !bucket.is_full(now));
+            }
+            clients
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(rate, burst, now))
+                .try_acquire(now)
+                .map_err(|retry_after| RateLimited { scope: "client", retry_after })?;
+        }
+        
+        if let Some(global) = &self.global {
+            global
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .try_acquire(now)
+                .map_err(|retry_after| RateLimited { scope: "global", retry_after })?;
+        }
+        
+        Ok(())
+    }
+}
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// Approximates a one-minute sliding window by weighting the previous fixed
+// window's count by how much of it still overlaps
+#[derive(Debug, Clone)]
+struct SlidingWindow {
+    start: Instant,
+    current: u32,
+    previous: u32,
+}
+
+impl SlidingWindow {
+    fn new(now: Instant) -> Self {
+        Self { start: now, current: 0, previous: 0 }
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= WINDOW * 2 {
+            *self = Self::new(now);
+        } else if elapsed >= WINDOW {
+            self.previous = self.current;
+            self.current = 0;
+            self.start += WINDOW;
+        }
+    }
+
+    // Only admitted requests are counted; Err carries a suggested back-off
+    fn try_count(&mut self, limit: u32, now: Instant) -> Result<(), Duration> {
+        self.advance(now);
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        let window = WINDOW.as_secs_f64();
+        let overlap = 1.0 - elapsed / window;
+        if self.previous as f64 * overlap + self.current as f64 + 1.0 <= limit as f64 {
+            self.current += 1;
+            return Ok(());
+        }
+        // Wait for the previous window to slide out far enough, or for this one to end
+        let room = limit as f64 - 1.0 - self.current as f64;
+        let wait = if room >= 0.0 && self.previous > 0 {
+            window * (1.0 - room / self.previous as f64) - elapsed
+        } else {
+            window - elapsed
+        };
+        Err(Duration::from_secs_f64(wait.max(0.0)))
+    }
+
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.advance(now);
+        self.current == 0 && self.previous == 0
+    }
+}
+
+// Per-IP request counts for GeoIP RateLimit rules. Each rule keeps its own
+// counters, so a client matching two rules is limited by whichever it hits
+#[derive(Debug, Default)]
+pub struct SlidingWindowLimiter {
+    windows: Mutex<HashMap<(String, IpAddr), SlidingWindow>>,
+}
+
+impl SlidingWindowLimiter {
+    pub fn check(&self, rule: &str, ip: IpAddr, requests_per_minute: u32, now: Instant) -> Result<(), RateLimited> {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (rule.to_string(), ip);
+        if windows.len() >= MAX_TRACKED_CLIENTS && !windows.contains_key(&key) {
+            windows.retain(|_, window| !window.is_idle(now));
+        }
+        windows
+            .entry(key)
+            .or_insert_with(|| SlidingWindow::new(now))
+            .try_count(requests_per_minute, now)
+            .map_err(|retry_after| RateLimited { scope: "geoip", retry_after })
+    }
+}
+
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    max_per_client: usize,
+    active: Mutex<HashMap<IpAddr, usize>>,
+}
+
+// Held for the duration of an upstream transfer; releases the slot on drop
+#[derive(Debug)]
+pub struct DownloadPermit {
+    limiter: Arc<ConcurrencyLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn from_limits(limits: &LimitsPolicy) -> Self {
+        Self {
+            max_per_client: limits.max_concurrent_downloads_per_ip,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_per_client > 0
+    }
+
+    // Requests without a known client address are not limited
+    pub fn try_acquire(self: &Arc<Self>, client_ip: Option<IpAddr>) -> Result<Option<DownloadPermit>, RateLimited> {
+        let Some(ip) = client_ip.filter(|_| self.is_enabled()) else {
+            return Ok(None);
+        };
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let count = active.entry(ip).or_insert(0);
+        if *count >= self.max_per_client {
+            // Transfers have no predictable end; suggest a short back-off
+            return Err(RateLimited { scope: "concurrency", retry_after: Duration::from_secs(1) });
+        }
+        *count += 1;
+        
+        Ok(Some(DownloadPermit { limiter: self.clone(), ip }))
+    }
+}
+
+pub fn rate_limit(limiter: Arc<RateLimiter>, proxies: Arc<TrustedProxies>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    client_ip(proxies)
+        .and_then(move |client_ip: Option<IpAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                if !limiter.is_enabled() {
+                    return Ok(());
+                }
+                limiter.check(client_ip, Instant::now()).map_err(|limited| {
+                    Metrics::global().rate_limited.with_label_values(&[limited.scope]).inc();
+                    warp::reject::custom(limited)
+                })
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(global: u32, per_ip: u32, burst: Option<u32>) -> LimitsPolicy {
+        LimitsPolicy {
+            max_deb_size_mb: 500,
+            max_request_rate_per_minute: global,
+            burst,
+            max_request_rate_per_ip_per_minute: per_ip,
+            per_ip_burst: burst,
+            max_concurrent_downloads_per_ip: 0,
+        }
+    }
+
+    #[test]
+    fn test_token_bucket_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, 2, start);
+        
+        assert!(bucket.try_acquire(start).is_ok());
+        assert!(bucket.try_acquire(start).is_ok());
+        let retry_after = bucket.try_acquire(start).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(1));
+        assert!(bucket.try_acquire(start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_per_ip_buckets_are_independent() {
+        let limiter = RateLimiter::from_limits(&limits(0, 60, Some(1)));
+        let now = Instant::now();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        
+        assert!(limiter.check(Some(a), now).is_ok());
+        assert_eq!(limiter.check(Some(a), now).unwrap_err().scope, "client");
+        assert!(limiter.check(Some(b), now).is_ok());
+        // Requests without a known client address only hit the global bucket
+        assert!(limiter.check(None, now).is_ok());
+    }
+
+    #[test]
+    fn test_global_burst() {
+        let limiter = RateLimiter::from_limits(&limits(60, 0, Some(3)));
+        let now = Instant::now();
+        
+        for _ in 0..3 {
+            assert!(limiter.check(None, now).is_ok());
+        }
+        assert_eq!(limiter.check(None, now).unwrap_err().scope, "global");
+        assert!(!RateLimiter::from_limits(&limits(0, 0, None)).is_enabled());
+    }
+
+    #[test]
+    fn test_concurrent_downloads_per_ip() {
+        let limiter = Arc::new(ConcurrencyLimiter::from_limits(&LimitsPolicy {
+            max_concurrent_downloads_per_ip: 2,
+            ..limits(0, 0, None)
+        }));
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        
+        let first = limiter.try_acquire(Some(a)).unwrap();
+        let _second = limiter.try_acquire(Some(a)).unwrap();
+        assert_eq!(limiter.try_acquire(Some(a)).unwrap_err().scope, "concurrency");
+        assert!(limiter.try_acquire(Some(b)).unwrap().is_some());
+        assert!(limiter.try_acquire(None).unwrap().is_none());
+        
+        drop(first);
+        let _third = limiter.try_acquire(Some(a)).unwrap();
+        assert!(limiter.try_acquire(Some(a)).is_err());
+    }
+
+    #[test]
+    fn test_sliding_window_per_rule_and_ip() {
+        let limiter = SlidingWindowLimiter::default();
+        let start = Instant::now();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        
+        for _ in 0..3 {
+            assert!(limiter.check("cn", a, 3, start).is_ok());
+        }
+        let limited = limiter.check("cn", a, 3, start).unwrap_err();
+        assert_eq!(limited.scope, "geoip");
+        assert!(limited.retry_after <= WINDOW);
+        assert!(limiter.check("cn", b, 3, start).is_ok());
+        assert!(limiter.check("proxies", a, 3, start).is_ok());
+        
+        // Half a minute into the next window, half of the previous count still applies
+        let later = start + WINDOW + WINDOW / 2;
+        assert!(limiter.check("cn", a, 3, later).is_ok());
+        assert!(limiter.check("cn", a, 3, later).is_err());
+        assert!(limiter.check("cn", a, 3, start + WINDOW * 3).is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{Result, anyhow};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+use crate::config::settings::AppConfig;
+use crate::geoip::policy::GeoPolicyEngine;
+use crate::policy::reload::{PolicyReloader, PolicySource, SharedPolicy};
+use crate::policy::rules::PolicyEngine;
+use crate::tls::reload::CertReloader;
+use crate::verify::keyring::{KeyringMap, SharedKeyrings, VerificationConfig};
+
+// Verification settings applied by a keyring reload; the rest of
+// [verification] is read once at startup
+const KEYRING_FIELDS: [&str; 5] = ["gpg_keyring_path", "additional_keyrings", "keyring_glob", "keyrings", "gpg_input"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Policy,
+    GeoIp,
+    Keyring,
+    Tls,
+    Config,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 5] = [Self::Policy, Self::GeoIp, Self::Keyring, Self::Tls, Self::Config];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Policy => "policy",
+            Self::GeoIp => "geoip",
+            Self::Keyring => "keyring",
+            Self::Tls => "tls",
+            Self::Config => "config",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|subsystem| subsystem.as_str() == name)
+    }
+}
+
+// Reloads one subsystem on demand, for /admin/reload/<subsystem>. Each
+// reload builds and checks the new state before swapping it in, so a
+// rejected file leaves the running one untouched
+pub struct SubsystemReloader {
+    // As last loaded, the source of policy and keyring reloads
+    config: Mutex<AppConfig>,
+    policy: SharedPolicy,
+    keyrings: SharedKeyrings,
+    geo: Arc<GeoPolicyEngine>,
+    // Only set when aptg terminates TLS itself
+    tls: Option<CertReloader>,
+}
+
+impl SubsystemReloader {
+    pub fn new(config: &AppConfig, policy: SharedPolicy, keyrings: SharedKeyrings, geo: Arc<GeoPolicyEngine>, tls: Option<CertReloader>) -> Self {
+        Self {
+            config: Mutex::new(config.clone()),
+            policy,
+            keyrings,
+            geo,
+            tls,
+        }
+    }
+
+    pub fn is_enabled(&self, subsystem: Subsystem) -> bool {
+        match subsystem {
+            Subsystem::GeoIp => self.geo.get_policy_stats().enabled,
+            Subsystem::Tls => self.tls.is_some(),
+            Subsystem::Policy | Subsystem::Keyring | Subsystem::Config => true,
+        }
+    }
+
+    // Details of what was loaded, for the response
+    pub fn reload(&self, subsystem: Subsystem) -> Result<Value> {
+        match subsystem {
+            Subsystem::Policy => self.reload_policy(),
+            Subsystem::GeoIp => {
+                self.geo.reload_database()?;
+                Ok(json!({"database": self.geo.get_database_info()}))
+            }
+            Subsystem::Keyring => self.reload_keyrings(),
+            Subsystem::Tls => {
+                self.tls.as_ref().ok_or_else(|| anyhow!("aptg does not terminate TLS"))?.reload()?;
+                Ok(json!({}))
+            }
+            Subsystem::Config => self.reload_config(),
+        }
+    }
+
+    fn config_path(&self) -> Result<String> {
+        let config = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        config.config_path.clone().ok_or_else(|| anyhow!("No configuration file was loaded"))
+    }
+
+    fn reload_policy(&self) -> Result<Value> {
+        let source = PolicySource::from_config(&self.config.lock().unwrap_or_else(|e| e.into_inner()))
+            .ok_or_else(|| anyhow!("No configuration file was loaded"))?;
+        PolicyReloader::new(source.clone(), self.policy.clone()).reload()?;
+        Ok(json!({"source": source.path()}))
+    }
+
+    fn reload_keyrings(&self) -> Result<Value> {
+        let config = AppConfig::load_from_file(&self.config_path()?)?;
+        let keyrings = Self::load_keyrings(&config.verification)?;
+        let paths = keyrings.keyring_paths();
+        self.keyrings.store(Arc::new(keyrings));
+        self.config.lock().unwrap_or_else(|e| e.into_inner()).verification = config.verification;
+        info!("Keyrings reloaded");
+        Ok(json!({"keyrings": paths}))
+    }
+
+    fn load_keyrings(verification: &VerificationConfig) -> Result<KeyringMap> {
+        let keyrings = KeyringMap::from_config(verification);
+        // gpgv would only fail on the next Release file
+        if verification.enable_gpg_verification {
+            if let Some(missing) = keyrings.keyring_paths().into_iter().find(|path| !Path::new(path).exists()) {
+                return Err(anyhow!("Keyring {} does not exist", missing));
+            }
+        }
+        Ok(keyrings)
+    }
+
+    // Applies the policy and keyrings of the configuration file; changes to
+    // other sections are listed as needing a restart
+    fn reload_config(&self) -> Result<Value> {
+        let path = self.config_path()?;
+        let config = AppConfig::load_from_file(&path)?;
+        let keyrings = Self::load_keyrings(&config.verification)?;
+        // The advisory store is carried over, as for a policy file change
+        let engine = PolicyEngine::try_from_config(config.policy.clone(), self.policy.load().advisory_store())?;
+        
+        let mut current = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        let restart_required = Self::restart_required(&current, &config)?;
+        self.policy.store(Arc::new(engine));
+        self.keyrings.store(Arc::new(keyrings));
+        *current = config;
+        info!("Configuration reloaded from {}", path);
+        Ok(json!({"source": path, "applied": ["policy", "keyring"], "restart_required": restart_required}))
+    }
+
+    // Top-level sections that differ, other than the ones a reload applies
+    fn restart_required(current: &AppConfig, new: &AppConfig) -> Result<Vec<String>> {
+        let (Value::Object(mut current), Value::Object(mut new)) = (serde_json::to_value(current)?, serde_json::to_value(new)?) else {
+            return Err(anyhow!("Configuration is not a table"));
+        };
+        for section in [&mut current, &mut new] {
+            section.remove("policy");
+            section.remove("policy_file");
+            if let Some(Value::Object(verification)) = section.get_mut("verification") {
+                for field in KEYRING_FIELDS {
+                    verification.remove(field);
+                }
+            }
+        }
+        Ok(new.into_iter().filter(|(name, value)| current.get(name) != Some(value)).map(|(name, _)| name).collect())
+    }
+}
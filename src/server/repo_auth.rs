@@ -0,0 +1,536 @@
+use anyhow::{Result, anyhow};
+use arc_swap::ArcSwap;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use md5::{Digest as _, Md5};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use crate::config::settings::RepositoryConfig;
+
+// Tokens a few seconds past exp, or issued by a clock slightly ahead, still pass
+const CLOCK_LEEWAY_SECS: u64 = 60;
+// A token signed with an unknown key triggers at most one JWKS refresh per interval
+const MIN_JWKS_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepositoryAuthConfig {
+    // Apache htpasswd file; MD5 ($apr1$, $1$) and {SHA} entries are accepted.
+    // Re-read when it changes
+    pub htpasswd_file: Option<String>,
+    pub oidc: Option<OidcConfig>,
+    pub realm: String,
+}
+
+impl Default for RepositoryAuthConfig {
+    fn default() -> Self {
+        Self {
+            htpasswd_file: None,
+            oidc: None,
+            realm: "aptg".to_string(),
+        }
+    }
+}
+
+impl RepositoryAuthConfig {
+    pub fn validate(&self, repository: &str) -> Result<()> {
+        if self.htpasswd_file.is_none() && self.oidc.is_none() {
+            return Err(anyhow!("Repository '{}': auth needs htpasswd_file or oidc", repository));
+        }
+        if let Some(oidc) = &self.oidc {
+            if oidc.issuer.is_empty() || oidc.audience.is_empty() {
+                return Err(anyhow!("Repository '{}': OIDC needs an issuer and an audience", repository));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    pub issuer: String,
+    // Expected aud claim, usually the client ID
+    pub audience: String,
+    // Defaults to the jwks_uri from the issuer's discovery document
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    // Claim recorded as the client identity
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+    #[serde(default = "default_jwks_refresh_minutes")]
+    pub jwks_refresh_minutes: u64,
+}
+
+fn default_username_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_jwks_refresh_minutes() -> u64 {
+    60
+}
+
+#[derive(Debug)]
+pub struct AuthFailure {
+    pub reason: String,
+}
+
+fn failure(reason: impl Into<String>) -> AuthFailure {
+    AuthFailure { reason: reason.into() }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// The MD5-based crypt used by `htpasswd -m` ($apr1$) and glibc ($1$)
+fn md5_crypt(password: &[u8], salt: &[u8], magic: &[u8]) -> String {
+    let salt = &salt[..salt.len().min(8)];
+    let alternate = Md5::new().chain_update(password).chain_update(salt).chain_update(password).finalize();
+    let mut context = Md5::new().chain_update(password).chain_update(magic).chain_update(salt);
+    for chunk in (0..password.len()).step_by(16) {
+        context.update(&alternate[..(password.len() - chunk).min(16)]);
+    }
+    let mut length = password.len();
+    while length > 0 {
+        context.update(if length & 1 == 1 { &[0u8][..] } else { &password[..1] });
+        length >>= 1;
+    }
+    let mut hash = context.finalize();
+    for round in 0..1000 {
+        let mut context = Md5::new();
+        if round & 1 == 1 { context.update(password) } else { context.update(hash) }
+        if round % 3 != 0 {
+            context.update(salt);
+        }
+        if round % 7 != 0 {
+            context.update(password);
+        }
+        if round & 1 == 1 { context.update(hash) } else { context.update(password) }
+        hash = context.finalize();
+    }
+
+    let mut encoded = String::with_capacity(22);
+    let mut push = |mut value: u32, count: usize| {
+        for _ in 0..count {
+            encoded.push(CRYPT_ALPHABET[(value & 0x3f) as usize] as char);
+            value >>= 6;
+        }
+    };
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        push((hash[a] as u32) << 16 | (hash[b] as u32) << 8 | hash[c] as u32, 4);
+    }
+    push(hash[11] as u32, 2);
+    format!("{}{}${}", String::from_utf8_lossy(magic), String::from_utf8_lossy(salt), encoded)
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    for magic in ["$apr1$", "$1$"] {
+        if let Some(rest) = hash.strip_prefix(magic) {
+            let salt = rest.split('$').next().unwrap_or_default();
+            return constant_time_eq(md5_crypt(password.as_bytes(), salt.as_bytes(), magic.as_bytes()).as_bytes(), hash.as_bytes());
+        }
+    }
+    if let Some(digest) = hash.strip_prefix("{SHA}") {
+        return constant_time_eq(STANDARD.encode(Sha1::digest(password.as_bytes())).as_bytes(), digest.as_bytes());
+    }
+    false
+}
+
+// user:hash lines; blank lines and # comments are skipped
+pub fn parse_htpasswd(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(user, hash)| {
+            let supported = ["$apr1$", "$1$", "{SHA}"].iter().any(|prefix| hash.starts_with(prefix));
+            if !supported {
+                warn!("htpasswd entry for '{}' uses an unsupported hash; recreate it with htpasswd -m", user);
+            }
+            supported
+        })
+        .map(|(user, hash)| (user.to_string(), hash.to_string()))
+        .collect()
+}
+
+struct HtpasswdFile {
+    path: String,
+    loaded: Mutex<(Option<SystemTime>, HashMap<String, String>)>,
+}
+
+impl HtpasswdFile {
+    fn new(path: &str) -> Self {
+        Self { path: path.to_string(), loaded: Mutex::new((None, HashMap::new())) }
+    }
+
+    fn verify(&self, user: &str, password: &str) -> Result<bool> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| anyhow!("Failed to read htpasswd file {}: {}", self.path, e))?;
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if loaded.0 != Some(modified) {
+            let contents = std::fs::read_to_string(&self.path)
+                .map_err(|e| anyhow!("Failed to read htpasswd file {}: {}", self.path, e))?;
+            *loaded = (Some(modified), parse_htpasswd(&contents));
+            info!("Loaded {} users from {}", loaded.1.len(), self.path);
+        }
+        Ok(loaded.1.get(user).is_some_and(|hash| verify_password(password, hash)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+// A verification key from the JWKS, decoded once
+enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    // Uncompressed SEC1 point
+    P256(Vec<u8>),
+}
+
+impl PublicKey {
+    fn from_jwk(jwk: &Jwk) -> Option<Self> {
+        let decode = |value: &Option<String>| value.as_deref().and_then(|v| URL_SAFE_NO_PAD.decode(v).ok());
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => Some(PublicKey::Rsa { n: decode(&jwk.n)?, e: decode(&jwk.e)? }),
+            ("EC", Some("P-256")) => Some(PublicKey::P256([vec![4], decode(&jwk.x)?, decode(&jwk.y)?].concat())),
+            _ => None,
+        }
+    }
+
+    fn verify(&self, algorithm: &str, message: &[u8], signature: &[u8]) -> bool {
+        match (self, algorithm) {
+            (PublicKey::Rsa { n, e }, "RS256") => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+                .is_ok(),
+            (PublicKey::P256(point), "ES256") => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, signature)
+                .is_ok(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+// Validates RS256 and ES256 ID or access tokens against the issuer's JWKS
+pub struct OidcValidator {
+    config: OidcConfig,
+    keys: ArcSwap<Vec<(Option<String>, PublicKey)>>,
+    last_refresh: Mutex<Option<Instant>>,
+    client: reqwest::Client,
+}
+
+impl OidcValidator {
+    pub fn new(config: &OidcConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent("aptg/0.1.0")
+            .build()
+            .unwrap_or_default();
+        Self {
+            config: config.clone(),
+            keys: ArcSwap::from_pointee(vec![]),
+            last_refresh: Mutex::new(None),
+            client,
+        }
+    }
+
+    // Returns the number of usable keys
+    pub fn update_keys(&self, jwks: &str) -> Result<usize> {
+        #[derive(Deserialize)]
+        struct JwkSet {
+            keys: Vec<Jwk>,
+        }
+        let set: JwkSet = serde_json::from_str(jwks).map_err(|e| anyhow!("Invalid JWKS: {}", e))?;
+        let keys: Vec<_> = set.keys.iter().filter_map(|jwk| Some((jwk.kid.clone(), PublicKey::from_jwk(jwk)?))).collect();
+        let count = keys.len();
+        self.keys.store(Arc::new(keys));
+        Ok(count)
+    }
+
+    async fn jwks_url(&self) -> Result<String> {
+        if let Some(url) = &self.config.jwks_url {
+            return Ok(url.clone());
+        }
+        let discovery = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+        let document: serde_json::Value = self.client.get(&discovery).send().await?.error_for_status()?.json().await?;
+        document["jwks_uri"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{} has no jwks_uri", discovery))
+    }
+
+    pub async fn refresh(&self) -> Result<usize> {
+        *self.last_refresh.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        let url = self.jwks_url().await?;
+        let jwks = self.client.get(&url).send().await?.error_for_status()?.text().await?;
+        let count = self.update_keys(&jwks)?;
+        info!("Loaded {} signing keys for {} from {}", count, self.config.issuer, url);
+        Ok(count)
+    }
+
+    pub fn spawn(self: Arc<Self>) {
+        let interval = Duration::from_secs(self.config.jwks_refresh_minutes.max(1) * 60);
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.refresh().await {
+                    warn!("OIDC keys for {} not refreshed: {}", self.config.issuer, e);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    fn check_signature(&self, header: &JwtHeader, message: &[u8], signature: &[u8]) -> Option<bool> {
+        let keys = self.keys.load();
+        let mut candidates = keys.iter().filter(|(kid, _)| header.kid.is_none() || kid == &header.kid).peekable();
+        candidates.peek()?;
+        Some(candidates.any(|(_, key)| key.verify(&header.alg, message, signature)))
+    }
+
+    // The configured username claim of a valid token
+    pub async fn validate(&self, token: &str) -> Result<String, AuthFailure> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(failure("Malformed token"));
+        };
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| failure("Malformed token"));
+        let jwt_header: JwtHeader = serde_json::from_slice(&decode(header)?).map_err(|_| failure("Malformed token header"))?;
+        let message = &token.as_bytes()[..header.len() + 1 + payload.len()];
+        let signature = decode(signature)?;
+        
+        let mut verified = self.check_signature(&jwt_header, message, &signature);
+        if verified.is_none() {
+            // Possibly a rotated key; fetch the JWKS again unless that just happened
+            let due = self.last_refresh.lock().unwrap_or_else(|e| e.into_inner()).is_none_or(|at| at.elapsed() >= MIN_JWKS_REFRESH);
+            if due {
+                if let Err(e) = self.refresh().await {
+                    warn!("OIDC keys for {} not refreshed: {}", self.config.issuer, e);
+                }
+                verified = self.check_signature(&jwt_header, message, &signature);
+            }
+        }
+        match verified {
+            Some(true) => {}
+            Some(false) => return Err(failure("Invalid token signature")),
+            None => return Err(failure("Token signed with an unknown key")),
+        }
+        
+        let claims: serde_json::Value = serde_json::from_slice(&decode(payload)?).map_err(|_| failure("Malformed token claims"))?;
+        self.check_claims(&claims, SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+    }
+
+    fn check_claims(&self, claims: &serde_json::Value, now: u64) -> Result<String, AuthFailure> {
+        if claims["iss"].as_str().map(|iss| iss.trim_end_matches('/')) != Some(self.config.issuer.trim_end_matches('/')) {
+            return Err(failure("Token from another issuer"));
+        }
+        let audience = &self.config.audience;
+        let audience_ok = match &claims["aud"] {
+            serde_json::Value::String(aud) => aud == audience,
+            serde_json::Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !audience_ok {
+            return Err(failure("Token for another audience"));
+        }
+        match claims["exp"].as_u64() {
+            Some(exp) if exp + CLOCK_LEEWAY_SECS > now => {}
+            _ => return Err(failure("Token expired")),
+        }
+        if claims["nbf"].as_u64().is_some_and(|nbf| nbf > now + CLOCK_LEEWAY_SECS) {
+            return Err(failure("Token not yet valid"));
+        }
+        claims[self.config.username_claim.as_str()]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| failure(format!("Token has no {} claim", self.config.username_claim)))
+    }
+}
+
+// Credentials required for one repository
+pub struct RepositoryAuth {
+    realm: String,
+    htpasswd: Option<HtpasswdFile>,
+    oidc: Option<Arc<OidcValidator>>,
+}
+
+impl RepositoryAuth {
+    pub fn new(config: &RepositoryAuthConfig) -> Self {
+        Self {
+            realm: config.realm.clone(),
+            htpasswd: config.htpasswd_file.as_deref().map(HtpasswdFile::new),
+            oidc: config.oidc.as_ref().map(|oidc| Arc::new(OidcValidator::new(oidc))),
+        }
+    }
+
+    pub fn challenge(&self) -> String {
+        let realm = self.realm.replace('"', "");
+        match (&self.htpasswd, &self.oidc) {
+            (None, Some(_)) => format!("Bearer realm=\"{}\"", realm),
+            _ => format!("Basic realm=\"{}\"", realm),
+        }
+    }
+
+    // The identity to record, e.g. "user:alice" or "oidc:alice". apt only sends
+    // basic credentials, so an OIDC token is also accepted as the password
+    pub async fn authenticate(&self, authorization: Option<&str>) -> Result<String, AuthFailure> {
+        let authorization = authorization.ok_or_else(|| failure("Credentials required"))?;
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            let oidc = self.oidc.as_ref().ok_or_else(|| failure("Bearer tokens are not accepted"))?;
+            return oidc.validate(token.trim()).await.map(|user| format!("oidc:{}", user));
+        }
+        let credentials = authorization
+            .strip_prefix("Basic ")
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .ok_or_else(|| failure("Unsupported authorization scheme"))?;
+        let (user, password) = credentials.split_once(':').ok_or_else(|| failure("Malformed basic credentials"))?;
+        
+        if let Some(htpasswd) = &self.htpasswd {
+            match htpasswd.verify(user, password) {
+                Ok(true) => return Ok(format!("user:{}", user)),
+                Ok(false) => {}
+                Err(e) => return Err(failure(e.to_string())),
+            }
+        }
+        match &self.oidc {
+            Some(oidc) if password.matches('.').count() == 2 => oidc.validate(password).await.map(|user| format!("oidc:{}", user)),
+            _ => Err(failure(format!("Invalid password for '{}'", user))),
+        }
+    }
+}
+
+// Repository name -> its credentials; repositories without an entry are public
+#[derive(Default)]
+pub struct RepositoryAccess {
+    repositories: HashMap<String, RepositoryAuth>,
+}
+
+impl RepositoryAccess {
+    pub fn from_repositories(repositories: &[RepositoryConfig]) -> Self {
+        let repositories = repositories
+            .iter()
+            .filter_map(|repository| Some((repository.name.clone(), RepositoryAuth::new(repository.auth.as_ref()?))))
+            .collect();
+        Self { repositories }
+    }
+
+    pub fn get(&self, repository: &str) -> Option<&RepositoryAuth> {
+        self.repositories.get(repository)
+    }
+
+    // Loads the OIDC signing keys and keeps them fresh
+    pub fn spawn(&self) {
+        for auth in self.repositories.values() {
+            if let Some(oidc) = &auth.oidc {
+                oidc.clone().spawn();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair};
+
+    fn basic(user: &str, password: &str) -> String {
+        format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password)))
+    }
+
+    #[tokio::test]
+    async fn test_htpasswd() {
+        // Reference hashes from openssl passwd -apr1 / -1 and {SHA} of "password"
+        assert_eq!(md5_crypt(b"password", b"r31.....", b"$apr1$"), "$apr1$r31.....$ARC3pREO82RIm0aQ2zszC0");
+        assert_eq!(md5_crypt(b"password", b"3azHgidD", b"$1$"), "$1$3azHgidD$SrJPt7B.9rekpmwJwtON31");
+        assert!(verify_password("password", "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("htpasswd");
+        std::fs::write(&path, "# builders\nalice:$apr1$r31.....$ARC3pREO82RIm0aQ2zszC0\nbob:$2y$05$unsupported\n").unwrap();
+        let auth = RepositoryAuth::new(&RepositoryAuthConfig {
+            htpasswd_file: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        });
+        
+        assert_eq!(auth.authenticate(Some(&basic("alice", "password"))).await.unwrap(), "user:alice");
+        assert!(auth.authenticate(Some(&basic("alice", "wrong"))).await.is_err());
+        assert!(auth.authenticate(Some(&basic("bob", "password"))).await.is_err());
+        assert!(auth.authenticate(Some("Bearer a.b.c")).await.is_err());
+        assert!(auth.authenticate(None).await.is_err());
+        assert_eq!(auth.challenge(), "Basic realm=\"aptg\"");
+    }
+
+    #[tokio::test]
+    async fn test_oidc_token() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = key.public_key().as_ref();
+        let jwks = serde_json::json!({"keys": [{
+            "kty": "EC", "crv": "P-256", "kid": "k1",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]), "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        }]});
+        let sign = |claims: serde_json::Value| {
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","kid":"k1"}"#);
+            let message = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+            let signature = key.sign(&rng, message.as_bytes()).unwrap();
+            format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let claims = |aud: &str, exp: u64| serde_json::json!({"iss": "https://id.example.org", "aud": aud, "exp": exp, "sub": "ci-runner"});
+        
+        let auth = RepositoryAuth::new(&RepositoryAuthConfig {
+            oidc: Some(OidcConfig {
+                issuer: "https://id.example.org/".to_string(),
+                audience: "aptg".to_string(),
+                jwks_url: Some("http://127.0.0.1:9/jwks".to_string()),
+                username_claim: default_username_claim(),
+                jwks_refresh_minutes: 60,
+            }),
+            ..Default::default()
+        });
+        let oidc = auth.oidc.as_ref().unwrap();
+        assert_eq!(oidc.update_keys(&jwks.to_string()).unwrap(), 1);
+        
+        let token = sign(claims("aptg", now + 300));
+        assert_eq!(auth.authenticate(Some(&format!("Bearer {}", token))).await.unwrap(), "oidc:ci-runner");
+        assert_eq!(auth.authenticate(Some(&basic("token", &token))).await.unwrap(), "oidc:ci-runner");
+        assert_eq!(auth.challenge(), "Bearer realm=\"aptg\"");
+        
+        let auth = &auth;
+        let reason = |token: String| async move { auth.authenticate(Some(&format!("Bearer {}", token))).await.unwrap_err().reason };
+        assert_eq!(reason(sign(claims("other", now + 300))).await, "Token for another audience");
+        assert_eq!(reason(sign(claims("aptg", now - 600))).await, "Token expired");
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = URL_SAFE_NO_PAD.encode(serde_json::json!({"iss": "https://id.example.org", "aud": "aptg", "exp": now + 300, "sub": "admin"}).to_string());
+        assert_eq!(reason(format!("{}.{}.{}", parts[0], forged, parts[2])).await, "Invalid token signature");
+    }
+}
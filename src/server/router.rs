@@ -1,180 +1,1505 @@
+use anyhow::anyhow;
+use bytes::Bytes;
 use warp::{Filter, Reply, Rejection};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
-use crate::mirror::fetch::MirrorFetcher;
-use crate::policy::rules::PolicyEngine;
-use crate::cache::cache::CacheManager;
-use crate::audit::log::AuditLogger;
-use crate::verify::gpg::GpgVerifier;
-use crate::geoip::policy::{GeoPolicyEngine, GeoPolicy};
-
-fn with_fetcher<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+use std::time::{Duration, Instant};
+use tracing::{info_span, warn, Instrument};
+use crate::policy::advisories::{AdvisoryFeed, AdvisoryStore};
+use crate::policy::external::{ExternalAnswer, ExternalPolicy, ExternalRequest, FailureMode};
+use crate::policy::osv::OsvScanner;
+use crate::notify::webhook::Notifier;
+use crate::policy::reload::{PolicyReloader, PolicySource, SharedPolicy};
+use crate::policy::rules::{PolicyDecision, PolicyEngine, PolicyViolation};
+use crate::policy::version::DebFilename;
+use crate::metrics::downloads::DownloadStats;
+use crate::metrics::registry::Metrics;
+use crate::server::admin::{admin_routes, download_stats_routes, local_package_routes, reload_routes, snapshot_routes};
+use crate::server::auth::{admin_auth, handle_admin_denied, AdminAuth};
+use crate::server::dashboard::{dashboard_routes, Dashboard};
+use crate::server::client_ip::{client_identity, client_ip, TrustedProxies};
+use crate::server::headers::SecurityHeadersConfig;
+use crate::server::reload::SubsystemReloader;
+use crate::server::ratelimit::{rate_limit, ConcurrencyLimiter, RateLimited, RateLimiter, SlidingWindowLimiter};
+use crate::server::tenant::Namespace;
+use crate::cache::cache::{CacheManager, FetchTurn};
+use crate::audit::log::{AuditLogger, RequestContext};
+use crate::verify::control::{ControlStore, DebControl};
+use crate::verify::debsig::DebSigVerifier;
+use crate::verify::keyring::{KeyringMap, SharedKeyrings, VerificationConfig};
+use crate::verify::gpg::GpgVerificationResult;
+use crate::verify::index::PackageIndexStore;
+use crate::verify::quarantine::QuarantineStore;
+use crate::verify::release::{EnforcementMode, ReleaseFile};
+use crate::config::settings::AppConfig;
+use crate::geoip::mirrors::{MirrorMap, MirrorMode};
+use crate::geoip::policy::GeoPolicyEngine;
+use crate::geoip::reload::GeoIpReloader;
+use crate::geoip::reputation::ReputationScorer;
+use crate::geoip::updater::GeoIpUpdater;
+use crate::mirror::chaos::ChaosUpstream;
+use crate::mirror::fetch::{Upstream, UpstreamStatus};
+use crate::mirror::local::LocalRepository;
+use crate::mirror::snapshot::SnapshotStore;
+use crate::verify::signing::ReleaseSigner;
+use crate::tls::expiry::ExpiryMonitor;
+use crate::tls::reload::CertReloader;
+use crate::tls::identity::ClientIdentity;
+use crate::telemetry::trace_context::TraceContext;
+
+fn with_cache<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-fn with_policy<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+fn with_verification<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-fn with_cache<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+fn with_geo_policy<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-fn with_audit<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+fn with_decision_headers<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-fn with_gpg_verifier<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+fn with_downloads<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-fn with_geo_policy<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+fn with_certificates<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
 }
 
-pub fn build_routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let fetcher = Arc::new(MirrorFetcher::new());
-    let policy = Arc::new(PolicyEngine::new());
-    let cache = Arc::new(CacheManager::new());
-    let audit = Arc::new(AuditLogger::new());
-    let gpg_verifier = Arc::new(GpgVerifier::new("/etc/debian-archive-keyring.gpg"));
+fn with_external_policy<T: Clone + Send + Sync>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || item.clone())
+}
+
+// GeoIP policy, the counters behind its RateLimit rules and the NearestMirror map
+#[derive(Clone)]
+struct GeoServices {
+    engine: Arc<GeoPolicyEngine>,
+    limiter: Arc<SlidingWindowLimiter>,
+    mirrors: Arc<MirrorMap>,
+}
+
+// Verification state, bundled to stay within warp's limit on handler arguments
+#[derive(Clone)]
+struct VerificationServices {
+    keyrings: SharedKeyrings,
+    verification: Arc<VerificationConfig>,
+    index_store: Arc<PackageIndexStore>,
+    quarantine: Arc<QuarantineStore>,
+    debsig: Arc<DebSigVerifier>,
+    snapshots: Arc<SnapshotStore>,
+    controls: Arc<ControlStore>,
+    // Set when a repository is re-signed and the key could be loaded
+    signer: Option<Arc<ReleaseSigner>>,
+    osv: Option<Arc<OsvScanner>>,
+    notifier: Arc<Notifier>,
+    download_stats: Option<Arc<DownloadStats>>,
+}
+
+fn with_repository(
+    default: Arc<Namespace>,
+    tenants: Arc<HashMap<String, Arc<Namespace>>>,
+) -> impl Filter<Extract = (Arc<Namespace>, Option<String>, String), Error = Rejection> + Clone {
+    // /t/<tenant>/<repository>/... for tenants, /<repository>/... otherwise,
+    // either with /snapshots/<id> before the repository name.
+    // Only configured repositories are proxied; anything else is a 404
+    let snapshot = || {
+        warp::path("snapshots")
+            .and(warp::path::param::<String>())
+            .map(Some)
+            .or(warp::any().map(|| None))
+            .unify()
+    };
+    let tenant = warp::path("t")
+        .and(warp::path::param::<String>())
+        .and(snapshot())
+        .and(warp::path::param::<String>())
+        .and_then(move |tenant: String, snapshot: Option<String>, name: String| {
+            let namespace = tenants.get(&tenant).filter(|namespace| namespace.contains(&name)).cloned();
+            async move { namespace.map(|namespace| (namespace, snapshot, name)).ok_or_else(warp::reject::not_found) }
+        });
+    let repository = snapshot().and(warp::path::param::<String>()).and_then(move |snapshot: Option<String>, name: String| {
+        let namespace = default.contains(&name).then(|| default.clone());
+        async move { namespace.map(|namespace| (namespace, snapshot, name)).ok_or_else(warp::reject::not_found) }
+    });
+    tenant.or(repository).unify().untuple_one()
+}
+
+// Assembles aptg's routes for serving, or for embedding in another warp
+// service. Whatever is not provided is built from the configuration
+pub struct RouterBuilder {
+    config: AppConfig,
+    audit: Option<Arc<AuditLogger>>,
+    cache: Option<Arc<CacheManager>>,
+    policy: Option<SharedPolicy>,
+    upstream: Option<Arc<dyn Upstream>>,
+    tls: Option<CertReloader>,
+}
+
+impl RouterBuilder {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            config: config.clone(),
+            audit: None,
+            cache: None,
+            policy: None,
+            upstream: None,
+            tls: None,
+        }
+    }
+
+    // Shared with the TLS listener, which records failed handshakes
+    pub fn with_audit(mut self, audit: Arc<AuditLogger>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    // The caller runs its cleanup; storage full alerts need with_notifier on it
+    pub fn with_cache(mut self, cache: Arc<CacheManager>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Not watched for file changes; engines stored into it apply from the next request
+    pub fn with_policy(mut self, policy: SharedPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    // Serves the global repositories; tenants keep fetching from their configured upstreams
+    pub fn with_upstream(mut self, upstream: Arc<dyn Upstream>) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    // The listener's certificate reloader, for /admin/reload/tls
+    pub fn with_tls(mut self, tls: CertReloader) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    // Fails on settings that must not be skipped, e.g. an invalid policy rule
+    pub fn build(self) -> anyhow::Result<impl Filter<Extract = impl Reply, Error = Rejection> + Clone> {
+        build_routes(self)
+    }
+}
+
+// Puts the namespace's upstream behind fault injection when [chaos] applies
+fn with_chaos(namespace: Namespace, config: &AppConfig) -> Namespace {
+    if !config.chaos.is_active() {
+        return namespace;
+    }
+    let fetcher = namespace.fetcher.clone();
+    namespace.with_fetcher(Arc::new(ChaosUpstream::new(fetcher, config.chaos.clone())))
+}
+
+fn build_routes(builder: RouterBuilder) -> anyhow::Result<impl Filter<Extract = impl Reply, Error = Rejection> + Clone> {
+    let RouterBuilder { config, audit, cache, policy, upstream, tls } = builder;
+    let config = &config;
+    let audit = audit.unwrap_or_else(|| Arc::new(AuditLogger::from_config(&config.audit)));
+    let notifier = Arc::new(Notifier::new(config.notifications.clone()));
+    let cache = cache.unwrap_or_else(|| {
+        let cache = Arc::new(CacheManager::from_config(&config.cache).with_notifier(notifier.clone()));
+        cache.clone().spawn_cleanup(Duration::from_secs(600));
+        cache
+    });
+    let mut verification = VerificationServices {
+        keyrings: Arc::new(arc_swap::ArcSwap::from_pointee(KeyringMap::from_config(&config.verification))),
+        verification: Arc::new(config.verification.clone()),
+        index_store: Arc::new(PackageIndexStore::new()),
+        quarantine: Arc::new(QuarantineStore::new(config.verification.quarantine.clone())),
+        debsig: Arc::new(DebSigVerifier::from_config(&config.verification)),
+        snapshots: Arc::new(SnapshotStore::new(config.snapshots.clone())),
+        controls: Arc::new(ControlStore::new()),
+        signer: None,
+        osv: None,
+        notifier: notifier.clone(),
+        download_stats: None,
+    };
+    let snapshots = verification.snapshots.clone();
+    let keyrings = verification.keyrings.clone();
+    let resigns = config.repositories.iter().chain(config.tenants.iter().flat_map(|t| &t.repositories)).any(|r| r.resign);
+    if resigns {
+        match ReleaseSigner::from_config(&config.verification.signing) {
+            Ok(signer) => verification.signer = Some(Arc::new(signer)),
+            // Re-signed repositories then refuse to serve InRelease rather than pass on the upstream signature
+            Err(e) => warn!("Re-signing disabled: {}", e),
+        }
+    }
+    if config.policy.osv.enabled {
+        match OsvScanner::new(config.policy.osv.clone()) {
+            Ok(scanner) => {
+                let scanner = Arc::new(scanner);
+                scanner.clone().spawn();
+                verification.osv = Some(scanner);
+            }
+            Err(e) => warn!("OSV scanning disabled: {}", e),
+        }
+    }
+    if config.download_stats.enabled {
+        match DownloadStats::open(&config.download_stats) {
+            Ok(stats) => {
+                let stats = Arc::new(stats);
+                stats.spawn();
+                verification.download_stats = Some(stats);
+            }
+            Err(e) => warn!("Download statistics disabled: {}", e),
+        }
+    }
+    let download_stats = verification.download_stats.clone();
+    let archive_key = verification.signer.as_ref().map(|signer| Bytes::copy_from_slice(signer.public_key()));
+
+    let watch_policy = policy.is_none() && config.policy_hot_reload;
+    // Strict like a reload, so a mistyped rule stops startup instead of being skipped
+    let policy: SharedPolicy = match policy {
+        Some(policy) => policy,
+        None => Arc::new(arc_swap::ArcSwap::from_pointee(PolicyEngine::try_from_config(config.policy.clone(), Arc::new(AdvisoryStore::new()))?)),
+    };
+    let engine = policy.load_full();
+    if engine.advisory_config().enabled {
+        AdvisoryFeed::new(engine.advisory_config().clone(), engine.advisory_store()).spawn();
+    }
+
+    if watch_policy {
+        match PolicySource::from_config(config) {
+            Some(source) => {
+                if let Err(e) = PolicyReloader::new(source, policy.clone()).spawn() {
+                    warn!("Policy hot reload disabled: {}", e);
+                }
+            }
+            None => warn!("Policy hot reload requested but no configuration file was loaded"),
+        }
+    }
+
+    let local = Arc::new(LocalRepository::new(config.local_packages.clone()));
+    if local.is_enabled() {
+        local.clone().spawn(cache.clone());
+    }
+    let mut namespace = Namespace::new(&config.repositories, policy.clone(), audit.clone()).with_local(local.is_enabled().then(|| local.clone()));
+    if let Some(upstream) = upstream {
+        namespace = namespace.with_fetcher(upstream);
+    }
+    if config.chaos.is_active() {
+        warn!("Chaos mode is on; upstream fetches get injected faults");
+    }
+    let namespace = Arc::new(with_chaos(namespace, config));
+    namespace.spawn();
+    let latency = namespace.fetcher.latency();
+    let tenants: HashMap<String, Arc<Namespace>> = config.tenants
+        .iter()
+        .map(|tenant| {
+            let namespace = with_chaos(Namespace::for_tenant(tenant, policy.clone(), audit.clone(), latency.clone())?, config);
+            namespace.spawn();
+            Ok((tenant.name.clone(), Arc::new(namespace)))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    if config.verification.strict_mode && !config.verification.enable_gpg_verification {
+        warn!("Strict mode is enabled without GPG verification; no pool file can be served");
+    }
     
-    let geo_policy = GeoPolicy::default();
-    let geo_policy_engine = Arc::new(GeoPolicyEngine::new(geo_policy));
+    let external_policy = if config.policy.external.enabled {
+        match ExternalPolicy::new(config.policy.external.clone()) {
+            Ok(external) => Some(Arc::new(external)),
+            // Only a fail-open policy may be left out; fail-closed must not quietly allow everything
+            Err(e) if config.policy.external.failure_mode == FailureMode::Open => {
+                warn!("External policy disabled: {}", e);
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        None
+    };
+
+    let mut geo_policy_engine = GeoPolicyEngine::new(config.geoip.clone());
+    if config.geoip.enabled && !config.geoip.reputation.feeds.is_empty() {
+        let interval = Duration::from_secs(config.geoip.reputation.refresh_interval_hours.max(1) * 3600);
+        match ReputationScorer::new(&config.geoip.reputation) {
+            Ok(scorer) => {
+                let scorer = Arc::new(scorer);
+                scorer.clone().spawn(interval);
+                geo_policy_engine = geo_policy_engine.with_scorer(scorer);
+            }
+            Err(e) => warn!("GeoIP reputation feeds disabled: {}", e),
+        }
+    }
+    let geo_policy_engine = Arc::new(geo_policy_engine);
+    geo_policy_engine.clone().spawn_load_retry();
+    if config.geoip.enabled {
+        notifier.watch_geoip(geo_policy_engine.clone());
+    }
+    if config.geoip.enabled && config.geoip.watch_database {
+        if let Err(e) = GeoIpReloader::new(&config.geoip, geo_policy_engine.clone()).spawn() {
+            warn!("GeoIP database changes will need a restart: {}", e);
+        }
+    }
+    if config.geoip.enabled && config.geoip.update.enabled {
+        let interval = Duration::from_secs(config.geoip.update_interval_hours.max(1) * 3600);
+        match GeoIpUpdater::new(config.geoip.update.clone(), &config.geoip.database_path) {
+            Ok(updater) => updater.spawn(geo_policy_engine.clone(), interval),
+            Err(e) => warn!("GeoIP database updates disabled: {}", e),
+        }
+    }
     
-    warp::path("debian")
+    let metrics = warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(handle_metrics);
+
+    // Certificates are only watched when aptg terminates TLS itself
+    let certificates = config.server.enable_https.then(|| {
+        let monitor = ExpiryMonitor::new(Arc::new(config.tls.clone()), audit.clone(), notifier);
+        monitor.spawn();
+        monitor
+    });
+    // The re-signing key, for clients to add to their keyring or signed-by
+    let archive_key = warp::path("archive-key.asc")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(move || {
+            let archive_key = archive_key.clone();
+            async move {
+                match archive_key {
+                    Some(key) => Ok(warp::reply::with_header(warp::reply::Response::new(key.into()), "content-type", "application/pgp-keys")),
+                    None => Err(warp::reject::not_found()),
+                }
+            }
+        });
+    let healthz = warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_certificates(certificates))
+        .and_then(handle_healthz);
+
+    // Limits are read once at startup; a policy reload does not resize the buckets
+    let limiter = Arc::new(RateLimiter::from_limits(&config.policy.limits));
+    let downloads = Arc::new(ConcurrencyLimiter::from_limits(&config.policy.limits));
+    let geo = GeoServices {
+        engine: geo_policy_engine.clone(),
+        limiter: Arc::new(SlidingWindowLimiter::default()),
+        mirrors: Arc::new(MirrorMap::new(config.geoip.mirror_mode, &config.geoip.mirrors).with_latency(latency.clone())),
+    };
+    let proxies = Arc::new(TrustedProxies::from_config(&config.trusted_proxies));
+
+    let repositories = with_repository(namespace, Arc::new(tenants))
+        .and(rate_limit(limiter, proxies.clone()))
         .and(warp::path::tail())
         .and(warp::method())
         .and(warp::header::headers_cloned())
-        .and(warp::header::optional("x-forwarded-for"))
-        .and(with_fetcher(fetcher.clone()))
-        .and(with_policy(policy.clone()))
+        .and(client_ip(proxies.clone()))
+        .and(client_identity())
         .and(with_cache(cache.clone()))
-        .and(with_audit(audit.clone()))
-        .and(with_gpg_verifier(gpg_verifier.clone()))
-        .and(with_geo_policy(geo_policy_engine.clone()))
+        .and(with_verification(verification))
+        .and(with_downloads(downloads))
+        .and(with_geo_policy(geo))
+        .and(with_external_policy(external_policy))
+        .and(with_decision_headers(config.decision_headers))
         .and_then(handle_debian_request)
+        .recover(handle_rate_limited)
+        .with(warp::log::custom(observe_request));
+
+    let dashboard = Arc::new(Dashboard::new(audit.clone(), geo_policy_engine.clone(), latency));
+    let admin_tokens = Arc::new(AdminAuth::new(&config.admin));
+    if !admin_tokens.is_enabled() {
+        warn!("No [[admin.tokens]] configured; read-only /admin endpoints are unauthenticated and changes are refused");
+    }
+    let reloader = Arc::new(SubsystemReloader::new(config, policy, keyrings, geo_policy_engine.clone(), tls));
+    let admin = admin_auth(admin_tokens.clone(), audit.clone(), proxies)
+        .and(
+            dashboard_routes(dashboard)
+                .or(admin_routes(audit.clone(), geo_policy_engine, admin_tokens.clone()))
+                .or(snapshot_routes(snapshots, admin_tokens.clone()))
+                .or(local_package_routes(local, cache.clone(), admin_tokens.clone()))
+                .or(download_stats_routes(download_stats))
+                .or(reload_routes(reloader, audit, admin_tokens)),
+        )
+        .recover(handle_admin_denied);
+
+    let headers: Arc<SecurityHeadersConfig> = Arc::new(config.server.security_headers().clone());
+    Ok(metrics
+        .or(healthz)
+        .or(archive_key)
+        .or(admin)
+        .or(repositories)
+        .map(move |reply| headers.apply(reply)))
+}
+
+// End-to-end latency of repository requests, including rate-limited ones
+fn observe_request(info: warp::log::Info<'_>) {
+    Metrics::global()
+        .request_duration
+        .with_label_values(&[info.status().as_str()])
+        .observe(info.elapsed().as_secs_f64());
+}
+
+async fn handle_rate_limited(rejection: Rejection) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let Some(limited) = rejection.find::<RateLimited>() else {
+        return Err(rejection);
+    };
+    Ok(Box::new(rate_limited_reply(limited)))
+}
+
+fn rate_limited_reply(limited: &RateLimited) -> impl Reply {
+    let retry_after = limited.retry_after.as_secs().max(1).to_string();
+    warp::reply::with_header(
+        warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({"error": "Rate limit exceeded", "scope": limited.scope})),
+            warp::http::StatusCode::TOO_MANY_REQUESTS,
+        ),
+        "retry-after",
+        retry_after,
+    )
+}
+
+async fn handle_metrics() -> Result<Box<dyn Reply + Send>, Rejection> {
+    match Metrics::global().render() {
+        Ok(body) => Ok(Box::new(warp::reply::with_header(body, "content-type", "text/plain; version=0.0.4"))),
+        Err(e) => Ok(Box::new(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({"error": e.to_string()})),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ))),
+    }
 }
 
+// Always 200 while serving; certificate problems show as "warning" so a
+// load balancer does not pull the mirror before the certificate actually expires
+async fn handle_healthz(certificates: Option<Arc<ExpiryMonitor>>) -> Result<Box<dyn Reply + Send>, Rejection> {
+    let certificates = certificates.map(|monitor| monitor.status()).unwrap_or_default();
+    let status = if certificates.iter().any(|c| c.expiring) { "warning" } else { "ok" };
+    Ok(Box::new(warp::reply::json(&serde_json::json!({"status": status, "certificates": certificates}))))
+}
+
+// Root span of the request trace; each stage below gets a child span. Its
+// fields are on every log line written while handling the request
+#[tracing::instrument(
+    name = "request",
+    skip_all,
+    fields(
+        method = %method,
+        path = tracing::field::Empty,
+        request_id = tracing::field::Empty,
+        client_ip = tracing::field::Empty,
+        traceparent = tracing::field::Empty,
+        tracestate = tracing::field::Empty,
+    )
+)]
 async fn handle_debian_request(
+    namespace: Arc<Namespace>,
+    snapshot: Option<String>,
+    repository: String,
     path_tail: warp::path::Tail,
     method: warp::http::Method,
     headers: warp::http::HeaderMap,
-    forwarded_for: Option<String>,
-    fetcher: Arc<MirrorFetcher>,
-    policy: Arc<PolicyEngine>,
+    client_addr: Option<IpAddr>,
+    client_identity: Option<ClientIdentity>,
     cache: Arc<CacheManager>,
-    audit: Arc<AuditLogger>,
-    gpg_verifier: Arc<GpgVerifier>,
-    geo_policy_engine: Arc<GeoPolicyEngine>,
+    verification: VerificationServices,
+    downloads: Arc<ConcurrencyLimiter>,
+    geo: GeoServices,
+    external_policy: Option<Arc<ExternalPolicy>>,
+    decision_headers: bool,
 ) -> Result<Box<dyn Reply + Send>, Rejection> {
-    let path = format!("/debian/{}", path_tail.as_str());
-    
-    let client_ip = extract_client_ip(&headers, &forwarded_for);
+    let VerificationServices { keyrings, verification, index_store, quarantine, debsig, snapshots, controls, signer, osv, notifier, download_stats } = verification;
+    let GeoServices { engine: geo_policy_engine, limiter: geo_limiter, mirrors } = geo;
+    let Namespace { fetcher, access, policy, audit, .. } = namespace.as_ref();
+    // Policy, keyrings, package signature rules and the upstream see the path
+    // within the namespace; the cache, index store and audit log see the full one
+    let repository_path = format!("/{}/{}", repository, path_tail.as_str());
+    let path = format!("{}{}", namespace.prefix(), repository_path);
+    // The repository as named in notifications, e.g. /t/acme/debian
+    let repository_label = format!("{}/{}", namespace.prefix(), repository);
+
+    if let Err(limited) = namespace.check_quota(Instant::now()) {
+        Metrics::global().rate_limited.with_label_values(&[limited.scope]).inc();
+        return Ok(Box::new(rate_limited_reply(&limited)));
+    }
     
-    audit.log_request(&method, &path, &headers).await;
+    let mut request = RequestContext::new(client_addr);
+    request.client_identity = client_identity.as_ref().map(|identity| identity.subject.clone());
+    let span = tracing::Span::current();
+    span.record("path", path.as_str());
+    span.record("request_id", request.id.as_str());
+    if let Some(ip) = client_addr {
+        span.record("client_ip", audit.client_label(Some(ip)).as_str());
+    }
+    // Passed on to upstream fetches, so the client's trace continues there
+    let trace_context = TraceContext::from_headers(&headers);
+    if let Some(trace_context) = &trace_context {
+        trace_context.attach(&span);
+    }
+    // Known for uploaded packages, and for pool packages once they have been served
+    let control = match &namespace.local {
+        Some(local) => local.control(&repository_path).await,
+        None => None,
+    };
+    let control = control.or_else(|| controls.get(&path));
+    if let Some(control) = &control {
+        request.set_package(control);
+    }
+    // Policy, GeoIP and external checks take the address as a string
+    let client_ip = client_addr.map(|ip| ip.to_string());
+
+    // Looked up before anything is logged so every event can carry the location;
+    // the GeoIP policy decision is acted on after the request policy below
+    let geo_check = client_ip
+        .as_deref()
+        .map(|ip| info_span!("geo_lookup").in_scope(|| geo_policy_engine.check_request(ip, &path)));
+    if let Some(Ok(action_result)) = &geo_check {
+        if audit.enriches_geo() && geo_policy_engine.is_enabled() {
+            request.set_location(&action_result.location);
+        }
+    }
     
-    if let Some(_cached_response) = cache.get(&path).await {
-        audit.log_cache_hit(&path).await;
-        return Ok(Box::new(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({"cached": true})),
-            warp::http::StatusCode::OK,
+    // Checked before the cache and every policy, so cached content stays private.
+    // The authenticated user replaces the certificate subject in audit events
+    let mut challenge = None;
+    if let Some(auth) = access.get(&repository) {
+        let authorization = headers.get("authorization").and_then(|v| v.to_str().ok());
+        match auth.authenticate(authorization).instrument(info_span!("authenticate")).await {
+            Ok(identity) => request.client_identity = Some(identity),
+            Err(failure) => challenge = Some((auth.challenge(), failure.reason)),
+        }
+    }
+
+    audit.log_request(&request, &method, &path, &headers).await;
+
+    if let Some((challenge, reason)) = challenge {
+        audit.log_authentication_failed(&request, &path, &reason).await;
+        return Ok(Box::new(warp::reply::with_header(
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Authentication required"})),
+                warp::http::StatusCode::UNAUTHORIZED,
+            ),
+            "www-authenticate",
+            challenge,
         )));
     }
     
-    if !policy.check_request(&path, &method) {
-        audit.log_request(&method, &path, &headers).await;
-        return Ok(Box::new(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({"error": "Access denied by policy"})),
-            warp::http::StatusCode::FORBIDDEN,
-        )));
+    let mut decision = DecisionHeaders::new(decision_headers);
+    let policy_violations = &Metrics::global().policy_violations;
+    let section = if path.contains("/pool/") { index_store.section(&path).await } else { None };
+    let checks = PackageChecks {
+        policy: PolicyRequest {
+            policy,
+            path: &repository_path,
+            method: &method,
+            client_ip: client_ip.as_deref(),
+            client_identity: client_identity.as_ref(),
+            section: section.as_deref(),
+        },
+        controls: &controls,
+        osv: osv.as_deref(),
+    };
+    let checked = info_span!("policy_check").in_scope(|| checks.policy.check(control.as_deref()));
+    if let Err(denied) = apply_policy_decision(checked, &mut decision, audit, &request, &path).await {
+        return Ok(denied);
     }
     
-    if let Some(ip) = &client_ip {
-        if let Ok(action_result) = geo_policy_engine.check_request(ip, &path) {
-            match action_result.action {
-                crate::geoip::policy::GeoAction::Deny => {
-                    audit.log_geoip_denied(ip, &path, "Policy denied").await;
-                    return Ok(Box::new(warp::reply::with_status(
-                        warp::reply::json(&serde_json::json!({"error": "Access denied by GeoIP policy"})),
-                        warp::http::StatusCode::FORBIDDEN,
-                    )));
+    let is_pool = path.contains("/pool/");
+    let mut geo_location = None;
+    let mut mirror = None;
+    if let Some(Ok(action_result)) = geo_check {
+        if geo_policy_engine.is_enabled() {
+            geo_location = Some(action_result.location.clone());
+        }
+        decision.set("x-aptg-geo", action_result.action.as_str());
+        if let Some(rule) = &action_result.rule_name {
+            decision.set("x-aptg-geo-rule", rule);
+        }
+        match action_result.action {
+            crate::geoip::policy::GeoAction::Deny => {
+                audit.log_geoip_denied(&request, &path, "Policy denied").await;
+                return Ok(decision.apply(warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({"error": "Access denied by GeoIP policy"})),
+                    warp::http::StatusCode::FORBIDDEN,
+                )));
+            }
+            crate::geoip::policy::GeoAction::RateLimit { requests_per_minute } => {
+                // Counted per client and matched rule; requests under the limit carry on
+                let rule = action_result.rule_name.as_deref().unwrap_or("default");
+                let checked = client_addr.map(|ip| geo_limiter.check(rule, ip, requests_per_minute, Instant::now()));
+                if let Some(Err(limited)) = checked {
+                    Metrics::global().rate_limited.with_label_values(&[limited.scope]).inc();
+                    audit.log_geoip_rate_limit(&request, &path, requests_per_minute).await;
+                    return Ok(decision.apply(rate_limited_reply(&limited)));
                 }
-                crate::geoip::policy::GeoAction::RateLimit { requests_per_minute: _ } => {
-                    audit.log_geoip_rate_limit(ip, &path, 100).await;
-                    return Ok(Box::new(warp::reply::with_status(
-                        warp::reply::json(&serde_json::json!({"error": "Rate limited by GeoIP policy"})),
-                        warp::http::StatusCode::TOO_MANY_REQUESTS,
-                    )));
+            }
+            crate::geoip::policy::GeoAction::Allow => {
+                audit.log_geoip_allowed(&request, &path, "Allowed").await;
+            }
+            crate::geoip::policy::GeoAction::LogOnly => {
+                audit.log_geoip_log_only(&request, &path, "Log only").await;
+            }
+            crate::geoip::policy::GeoAction::Redirect { url } => {
+                audit.log_geoip_redirect(&request, &path, &url).await;
+                return Ok(decision.apply(warp::reply::with_header(
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"redirect": url})),
+                        warp::http::StatusCode::FOUND,
+                    ),
+                    "location",
+                    url,
+                )));
+            }
+            crate::geoip::policy::GeoAction::NearestMirror => {
+                // Indexes are always served here so they can be verified; clients
+                // without a mirror nearby stay on the repository upstream
+                if is_pool && namespace.tenant().is_none() {
+                    mirror = mirrors.nearest(&repository, &action_result.location).map(str::to_string);
                 }
-                crate::geoip::policy::GeoAction::Allow => {
-                    audit.log_geoip_allowed(ip, &path, "Allowed").await;
+                if let Some(url) = &mirror {
+                    decision.set("x-aptg-mirror", url);
                 }
-                crate::geoip::policy::GeoAction::LogOnly => {
-                    audit.log_geoip_log_only(ip, &path, "Log only").await;
+            }
+        }
+    }
+
+    if let Some(external) = &external_policy {
+        let mut external_request = ExternalRequest::new(&repository_path, method.as_str(), client_ip.as_deref(), section.as_deref(), geo_location);
+        external_request.client_identity = client_identity.clone();
+        external_request.tenant = namespace.tenant().map(str::to_string);
+        match external.check(&external_request).instrument(info_span!("external_policy")).await {
+            ExternalAnswer::Allow { rule } => {
+                decision.set("x-aptg-external", "allow");
+                if let Some(rule) = rule {
+                    decision.set("x-aptg-external-rule", &rule);
                 }
-                crate::geoip::policy::GeoAction::Redirect { url } => {
-                    audit.log_geoip_redirect(ip, &path, &url).await;
-                    return Ok(Box::new(warp::reply::with_status(
-                        warp::reply::json(&serde_json::json!({"redirect": url})),
-                        warp::http::StatusCode::FOUND,
-                    )));
+            }
+            ExternalAnswer::Deny { rule, reason } => {
+                let rule = rule.unwrap_or_else(|| "external".to_string());
+                policy_violations.with_label_values(&["enforced", "external"]).inc();
+                decision.set("x-aptg-external", "deny");
+                decision.set("x-aptg-external-rule", &rule);
+                audit.log_policy_violation(&request, &path, &format!("External policy rule '{}': {}", rule, reason.unwrap_or_default())).await;
+                return Ok(decision.apply(warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({"error": "Access denied by external policy"})),
+                    warp::http::StatusCode::FORBIDDEN,
+                )));
+            }
+            ExternalAnswer::RateLimit { rule, retry_after_secs } => {
+                decision.set("x-aptg-external", "ratelimit");
+                if let Some(rule) = rule {
+                    decision.set("x-aptg-external-rule", &rule);
                 }
+                return Ok(decision.apply(warp::reply::with_header(
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"error": "Rate limited by external policy"})),
+                        warp::http::StatusCode::TOO_MANY_REQUESTS,
+                    ),
+                    "retry-after",
+                    retry_after_secs.unwrap_or(60).max(1).to_string(),
+                )));
             }
         }
     }
     
-    match fetcher.fetch(&path).await {
-        Ok(response) => {
-            audit.log_fetch_success(&path).await;
-            cache.store(&path, &response).await;
-            
+
+    // The upstream's detached signature would not match what clients trust;
+    // apt falls back to Release.gpg only when InRelease is missing
+    let resign = namespace.resigns(&repository);
+    if resign && path.ends_with("/Release.gpg") {
+        return Ok(decision.apply(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({"error": "This repository is re-signed; use InRelease"})),
+            warp::http::StatusCode::NOT_FOUND,
+        )));
+    }
+
+    // A snapshot serves the Release and index files it captured; pool files are
+    // immutable, so they come from the cache or upstream like any other
+    if let Some(id) = &snapshot {
+        let served = match snapshots.get(id).await {
+            Some(_) if is_pool => None,
+            Some(_) => Some(snapshots.read(id, &path).await.ok_or("Not captured in this snapshot")),
+            None => Some(Err("Unknown snapshot")),
+        };
+        match served {
+            Some(Ok(body)) => return Ok(decision.apply(warp::reply::Response::new(body.into()))),
+            Some(Err(message)) => {
+                return Ok(decision.apply(warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({"error": message})),
+                    warp::http::StatusCode::NOT_FOUND,
+                )));
+            }
+            None => {}
+        }
+    }
+
+    // Uploaded packages and the indices generated for them; neither goes
+    // through the cache, and strict mode does not apply to them
+    let local = match &namespace.local {
+        Some(local) => local.serve(&repository_path).await,
+        None => None,
+    };
+    match local {
+        Some(Ok(body)) => {
+            record_download(download_stats.as_deref(), &index_store, &repository_label, &path, body.len()).await;
+            return Ok(decision.apply(warp::reply::Response::new(body.into())));
+        }
+        Some(Err(e)) => {
+            warn!("Not serving {}: {}", path, e);
+            return Ok(decision.apply(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Local package could not be read"})),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+        None => {}
+    }
+
+    if verification.strict_mode && is_pool {
+        if let Err(e) = index_store.check_trusted(&path, chrono::Utc::now()).await {
+            audit.log_unverified_denied(&request, &path, &e.to_string()).await;
+            return Ok(decision.apply(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Not listed in a verified index"})),
+                warp::http::StatusCode::FORBIDDEN,
+            )));
+        }
+    }
+
+    if let (Some(osv), true) = (osv.as_deref(), is_pool) {
+        if let Some(target) = scan_target(control.as_deref(), &path) {
+            if let Err(reply) = scan_package(osv, target, &mut decision, audit, &request, &path).await {
+                return Ok(reply);
+            }
+        }
+    }
+
+    // Only after every access check, since cached content is shared by all clients
+    if let Some(cached) = cache.get(&path).instrument(info_span!("cache_lookup")).await {
+        if control.is_none() {
+            if let Err(denied) = check_served_package(&checks, &cached.body, &mut decision, audit, &mut request, &path).await {
+                return Ok(denied);
+            }
+        }
+        audit.log_cache_hit(&request, &path).await;
+        record_download(download_stats.as_deref(), &index_store, &repository_label, &path, cached.body.len()).await;
+        return Ok(decision.apply(cached));
+    }
+
+    if let Some(url) = mirror.as_deref().filter(|_| mirrors.mode() == MirrorMode::Redirect) {
+        let location = format!("{}/{}", url, path_tail.as_str());
+        audit.log_geoip_redirect(&request, &path, &location).await;
+        return Ok(decision.apply(warp::reply::with_header(
+            warp::reply::with_status(warp::reply::json(&serde_json::json!({"redirect": location})), warp::http::StatusCode::FOUND),
+            "location",
+            location,
+        )));
+    }
+
+    // While another replica fetches the same path, wait for it to reach the
+    // shared cache rather than fetching it again
+    let _claim = match cache.begin_fetch(&path).instrument(info_span!("fetch_claim")).await {
+        FetchTurn::Fetch(claim) => claim,
+        FetchTurn::Cached(cached) => {
+            if control.is_none() {
+                if let Err(denied) = check_served_package(&checks, &cached.body, &mut decision, audit, &mut request, &path).await {
+                    return Ok(denied);
+                }
+            }
+            audit.log_cache_hit(&request, &path).await;
+            record_download(download_stats.as_deref(), &index_store, &repository_label, &path, cached.body.len()).await;
+            return Ok(decision.apply(cached));
+        }
+    };
+
+    // The permit is held until the response has been verified and handed to warp
+    let _permit = match downloads.try_acquire(client_addr) {
+        Ok(permit) => permit,
+        Err(limited) => {
+            Metrics::global().rate_limited.with_label_values(&[limited.scope]).inc();
+            return Ok(decision.apply(rate_limited_reply(&limited)));
+        }
+    };
+    let _quota_permit = match namespace.try_acquire_download() {
+        Ok(permit) => permit,
+        Err(limited) => {
+            Metrics::global().rate_limited.with_label_values(&[limited.scope]).inc();
+            return Ok(decision.apply(rate_limited_reply(&limited)));
+        }
+    };
+
+    let fetch_started = Instant::now();
+    let fetch_span = info_span!("upstream_fetch", traceparent = tracing::field::Empty);
+    let fetched = match &mirror {
+        Some(url) => TraceContext::scope(trace_context.clone(), fetcher.fetch_from(url, &repository_path).instrument(fetch_span)).await,
+        None => TraceContext::scope(trace_context.clone(), fetcher.fetch(&repository_path).instrument(fetch_span)).await,
+    };
+    let upstream = fetch_started.elapsed();
+    Metrics::global().upstream_fetch_duration.observe(upstream.as_secs_f64());
+    // A 4xx answer is about the file, not the upstream
+    let upstream_name = mirror.clone().unwrap_or_else(|| repository_label.clone());
+    match &fetched {
+        Ok(_) => notifier.upstream_fetched(&upstream_name),
+        Err(e) if !matches!(e.downcast_ref::<UpstreamStatus>(), Some(UpstreamStatus(status)) if status.is_client_error()) => {
+            notifier.upstream_failed(&upstream_name, e);
+        }
+        Err(_) => {}
+    }
+    match fetched {
+        Ok(mut response) => {
             let path_str = path.as_str();
-            if path_str.ends_with("InRelease") || path_str.ends_with("Release") {
-                let response_bytes = extract_response_bytes(&response);
-                if let Ok(verification_result) = gpg_verifier.verify_inrelease(&response_bytes) {
-                    if verification_result.valid {
-                        audit.log_verification_success(&path).await;
-                    } else {
-                        let error_msg = verification_result.error_message
-                            .as_deref()
-                            .unwrap_or("Unknown error");
-                        audit.log_verification_failed(&path, error_msg).await;
-                        return Ok(Box::new(warp::reply::with_status(
-                            warp::reply::json(&serde_json::json!({"error": "GPG verification failed"})),
-                            warp::http::StatusCode::BAD_REQUEST,
+            let is_inrelease = path_str.ends_with("/InRelease");
+            let is_release = is_inrelease || path_str.ends_with("/Release");
+            let mut signature = None;
+            let mut release_payload = None;
+            if verification.enable_gpg_verification && is_release {
+                let gpg_verifier = keyrings.load().verifier_for_path(&repository_path);
+                let body = response.body.clone();
+                // gpg runs as a blocking subprocess; keep it off the async workers
+                let result = if is_inrelease {
+                    tokio::task::spawn_blocking(move || gpg_verifier.verify_inrelease_payload(&body))
+                        .instrument(info_span!("verify_release_signature"))
+                        .await
+                        .unwrap_or_else(|e| Err(anyhow!("Signature check did not complete: {}", e)))
+                } else {
+                    // A plain Release is signed by the Release.gpg next to it
+                    let signature_path = format!("{}.gpg", repository_path);
+                    let detached = match &mirror {
+                        Some(url) => TraceContext::scope(trace_context, fetcher.fetch_from(url, &signature_path)).await,
+                        None => TraceContext::scope(trace_context, fetcher.fetch(&signature_path)).await,
+                    };
+                    match detached {
+                        Ok(detached) => tokio::task::spawn_blocking(move || {
+                            let result = gpg_verifier.verify_release_with_sig(&body, &detached.body)?;
+                            let payload = result.valid.then(|| String::from_utf8_lossy(&body).into_owned());
+                            Ok((result, payload))
+                        })
+                        .instrument(info_span!("verify_release_signature"))
+                        .await
+                        .unwrap_or_else(|e| Err(anyhow!("Signature check did not complete: {}", e))),
+                        Err(e) => Err(anyhow!("Failed to fetch {}: {}", signature_path, e)),
+                    }
+                };
+                // gpg that could not run fails closed like a bad signature
+                let (verification_result, payload) = match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        audit.log_verification_failed(&request, &path, &e.to_string()).await;
+                        notifier.verification_failed(&repository_label, &path, &e.to_string());
+                        quarantine_artifact(&quarantine, &path, &e.to_string(), &response.body).await;
+                        return Ok(decision.apply(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({"error": "GPG verification could not be completed"})),
+                            warp::http::StatusCode::BAD_GATEWAY,
                         )));
                     }
+                };
+                if verification_result.valid {
+                    audit.log_verification_success(&request, &path).await;
+                    signature = Some(verification_result);
+                    release_payload = payload;
+                } else {
+                    let error_msg = verification_result.error_message
+                        .as_deref()
+                        .unwrap_or("Unknown error");
+                    if verification_result.unexpected_signer {
+                        let signer = verification_result.signer().unwrap_or("unknown");
+                        audit.log_unexpected_signer(&request, &path, signer).await;
+                    }
+                    audit.log_verification_failed(&request, &path, error_msg).await;
+                    notifier.verification_failed(&repository_label, &path, error_msg);
+                    quarantine_artifact(&quarantine, &path, error_msg, &response.body).await;
+                    return Ok(decision.apply(warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"error": "GPG verification failed"})),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    )));
                 }
             }
             
-            Ok(Box::new(response))
+            if is_release {
+                // Prefer the verified cleartext over the raw armored body
+                let release = match &release_payload {
+                    Some(payload) => ReleaseFile::parse(payload),
+                    None => ReleaseFile::parse(&String::from_utf8_lossy(&response.body)),
+                };
+                for (mode, reason) in release_violations(&release, signature.as_ref(), &verification) {
+                    match mode {
+                        EnforcementMode::Off => {}
+                        EnforcementMode::Warn => {
+                            audit.log_verification_warning(&request, &path, &reason).await;
+                            if let Ok(value) = warp::http::HeaderValue::from_str(&reason) {
+                                response.headers.append("x-aptg-warning", value);
+                            }
+                        }
+                        EnforcementMode::Deny => {
+                            audit.log_verification_failed(&request, &path, &reason).await;
+                            notifier.verification_failed(&repository_label, &path, &reason);
+                            quarantine_artifact(&quarantine, &path, &reason, &response.body).await;
+                            return Ok(decision.apply(warp::reply::with_status(
+                                warp::reply::json(&serde_json::json!({"error": reason})),
+                                warp::http::StatusCode::BAD_GATEWAY,
+                            )));
+                        }
+                    }
+                }
+                
+                let mut local_indices = Vec::new();
+                if resign {
+                    // The upstream Release extended with the local component, before it is signed
+                    if let (Some(local), Some(payload)) = (&namespace.local, &release_payload) {
+                        if let Some((merged, files)) = local.merge_release(&repository_path, payload).await {
+                            release_payload = Some(merged);
+                            local_indices = files;
+                        }
+                    }
+                    let resigned = match (&signer, &release_payload) {
+                        (Some(signer), Some(payload)) if path_str.ends_with("/InRelease") => {
+                            let (signer, payload) = (signer.clone(), payload.clone());
+                            tokio::task::spawn_blocking(move || signer.clearsign(&payload))
+                                .instrument(info_span!("resign_release"))
+                                .await
+                                .unwrap_or_else(|e| Err(anyhow!("Signing task failed: {}", e)))
+                        }
+                        (None, _) => Err(anyhow!("No signing key is loaded")),
+                        _ => Err(anyhow!("Only a verified InRelease can be re-signed")),
+                    };
+                    match resigned {
+                        Ok(body) => {
+                            response.body = body.into();
+                            response.headers.remove(warp::http::header::CONTENT_LENGTH);
+                            response.headers.remove(warp::http::header::ETAG);
+                        }
+                        Err(e) => {
+                            warn!("Not serving {}: {}", path, e);
+                            return Ok(decision.apply(warp::reply::with_status(
+                                warp::reply::json(&serde_json::json!({"error": "Release could not be re-signed"})),
+                                warp::http::StatusCode::BAD_GATEWAY,
+                            )));
+                        }
+                    }
+                }
+                
+                if let Some(payload) = &release_payload {
+                    let valid_until = release.valid_until().unwrap_or(None);
+                    if let Err(e) = index_store.record_verified_release(path_str, payload, valid_until).await {
+                        warn!("Failed to record verified Release {}: {}", path, e);
+                    }
+                    if let Err(e) = snapshots.stage(path_str, &response.body, Some(payload)).await {
+                        warn!("Failed to stage {} for snapshots: {}", path, e);
+                    }
+                    let suite_dir = path_str.rsplit_once('/').map_or(path_str, |(dir, _)| dir);
+                    for (name, data) in &local_indices {
+                        if let Err(e) = snapshots.stage(&format!("{}/{}", suite_dir, name), data, None).await {
+                            warn!("Failed to stage local index {} for snapshots: {}", name, e);
+                        }
+                    }
+                }
+            }
+            
+            // By-hash fetches are indexed and staged under the name the Release gives them
+            let index_path = index_store.index_path(path_str).await;
+            if let Some(index_path) = &index_path {
+                if let Err(e) = index_store.update_from_index(index_path, &response.body).await {
+                    warn!("Failed to index {}: {}", path, e);
+                }
+            }
+            // Indices are checked against the verified Release when a snapshot is captured
+            if let Some(staged) = index_path.as_deref().or(path_str.ends_with("/Release.gpg").then_some(path_str)) {
+                if let Err(e) = snapshots.stage(staged, &response.body, None).await {
+                    warn!("Failed to stage {} for snapshots: {}", path, e);
+                }
+            }
+            
+            // Strict mode always checks pool hashes, whatever enable_hash_verification says
+            if (verification.enable_hash_verification || verification.strict_mode) && is_pool {
+                let content_length = response.headers
+                    .get(warp::http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok());
+                let verified = index_store
+                    .verify(path_str, content_length, &response.body)
+                    .instrument(info_span!("verify_hashes"))
+                    .await;
+                match verified {
+                    Ok(true) => audit.log_verification_success(&request, &path).await,
+                    Ok(false) if verification.strict_mode => {
+                        audit.log_unverified_denied(&request, &path, "dropped from index during fetch").await;
+                        return Ok(decision.apply(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({"error": "Not listed in a verified index"})),
+                            warp::http::StatusCode::FORBIDDEN,
+                        )));
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        audit.log_verification_failed(&request, &path, &e.to_string()).await;
+                        notifier.verification_failed(&repository_label, &path, &e.to_string());
+                        quarantine_artifact(&quarantine, &path, &e.to_string(), &response.body).await;
+                        return Ok(decision.apply(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({"error": "Hash verification failed"})),
+                            warp::http::StatusCode::BAD_GATEWAY,
+                        )));
+                    }
+                }
+            }
+            
+            if debsig.rule_for(&repository_path).is_some() {
+                let verifier = debsig.clone();
+                let debsig_path = repository_path.clone();
+                let body = response.body.clone();
+                let result = tokio::task::spawn_blocking(move || verifier.verify(&debsig_path, &body))
+                    .instrument(info_span!("verify_package_signature"))
+                    .await
+                    // A check that panicked or was cancelled fails closed like a bad signature
+                    .unwrap_or_else(|e| Err(anyhow!("Package signature check did not complete: {}", e)));
+                match result {
+                    Ok(_) => audit.log_verification_success(&request, &path).await,
+                    Err(e) => {
+                        audit.log_verification_failed(&request, &path, &e.to_string()).await;
+                        notifier.verification_failed(&repository_label, &path, &e.to_string());
+                        quarantine_artifact(&quarantine, &path, &e.to_string(), &response.body).await;
+                        return Ok(decision.apply(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({"error": "Package signature verification failed"})),
+                            warp::http::StatusCode::BAD_GATEWAY,
+                        )));
+                    }
+                }
+            }
+            
+            // Only content that passed verification reaches the cache
+            cache.store(&path, &(&response).into()).instrument(info_span!("cache_store")).await;
+            if control.is_none() {
+                if let Err(denied) = check_served_package(&checks, &response.body, &mut decision, audit, &mut request, &path).await {
+                    return Ok(denied);
+                }
+            }
+            audit.log_fetch_success(&request, &path, upstream, response.body.len() as u64).await;
+            record_download(download_stats.as_deref(), &index_store, &repository_label, &path, response.body.len()).await;
+            
+            Ok(decision.apply(response))
         }
         Err(e) => {
-            audit.log_fetch_error(&path, &e).await;
-            Ok(Box::new(warp::reply::with_status(
+            audit.log_fetch_error(&request, &path, &e, upstream).await;
+            let status = match e.downcast_ref::<UpstreamStatus>() {
+                Some(UpstreamStatus(warp::http::StatusCode::NOT_FOUND)) => {
+                    cache.store_missing(&path).await;
+                    warp::http::StatusCode::NOT_FOUND
+                }
+                _ => warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Ok(decision.apply(warp::reply::with_status(
                 warp::reply::json(&serde_json::json!({"error": e.to_string()})),
-                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                status,
             )))
         }
     }
 }
 
-fn extract_client_ip(headers: &warp::http::HeaderMap, forwarded_for: &Option<String>) -> Option<String> {
-    if let Some(forwarded) = forwarded_for {
-        return Some(forwarded.split(',').next().unwrap_or("").trim().to_string());
+// The request as the policy engine sees it, kept to check it again once the
+// package's control file is known
+struct PolicyRequest<'a> {
+    policy: &'a SharedPolicy,
+    path: &'a str,
+    method: &'a warp::http::Method,
+    client_ip: Option<&'a str>,
+    client_identity: Option<&'a ClientIdentity>,
+    section: Option<&'a str>,
+}
+
+impl PolicyRequest<'_> {
+    fn check(&self, control: Option<&DebControl>) -> anyhow::Result<PolicyDecision> {
+        self.policy.load().check_request(self.path, self.method, self.client_ip, self.client_identity, self.section, control)
     }
-    
-    if let Some(real_ip) = headers.get("X-Real-IP") {
-        return Some(real_ip.to_str().unwrap_or("").to_string());
+}
+
+// Sets the decision headers and audits the outcome; Err is the reply for a denied request
+async fn apply_policy_decision(
+    checked: anyhow::Result<PolicyDecision>,
+    decision: &mut DecisionHeaders,
+    audit: &AuditLogger,
+    request: &RequestContext,
+    path: &str,
+) -> Result<(), Box<dyn Reply + Send>> {
+    let policy_violations = &Metrics::global().policy_violations;
+    match checked {
+        Ok(policy_decision) => {
+            decision.set("x-aptg-policy", "allow");
+            if let Some(rule) = &policy_decision.matched_rule {
+                decision.set("x-aptg-rule", rule);
+            }
+            if !policy_decision.roles.is_empty() {
+                decision.set("x-aptg-roles", &policy_decision.roles.join(", "));
+            }
+            if !policy_decision.dry_run.is_empty() {
+                let rules: Vec<&str> = policy_decision.dry_run.iter().map(|v| v.rule.as_str()).collect();
+                decision.set("x-aptg-dry-run", &rules.join(", "));
+            }
+            for violation in policy_decision.dry_run {
+                policy_violations.with_label_values(&["dry_run", violation.kind.as_str()]).inc();
+                audit.log_policy_dry_run(request, path, &violation.reason).await;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            let violation = e.downcast_ref::<PolicyViolation>();
+            policy_violations.with_label_values(&["enforced", violation.map_or("request", |v| v.kind.as_str())]).inc();
+            decision.set("x-aptg-policy", "deny");
+            decision.set("x-aptg-rule", violation.map_or("request", |v| v.rule.as_str()));
+            audit.log_policy_violation(request, path, &e.to_string()).await;
+            Err(decision.apply(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Access denied by policy"})),
+                warp::http::StatusCode::FORBIDDEN,
+            )))
+        }
     }
-    
-    if let Some(x_forwarded) = headers.get("X-Forwarded") {
-        return Some(x_forwarded.to_str().unwrap_or("").to_string());
+}
+
+// Counts a served package for /admin/stats/top-packages; other files are not counted
+async fn record_download(stats: Option<&DownloadStats>, index_store: &PackageIndexStore, repository: &str, path: &str, bytes: usize) {
+    let Some(stats) = stats else {
+        return;
+    };
+    let Some(deb) = path.rsplit('/').next().and_then(DebFilename::parse) else {
+        return;
+    };
+    let suite = index_store.suite(path).await.unwrap_or_default();
+    stats.record(repository, &deb.name, &suite, &deb.architecture, bytes as u64);
+}
+
+// Checks on a pool package that depend on what it contains
+struct PackageChecks<'a> {
+    policy: PolicyRequest<'a>,
+    controls: &'a ControlStore,
+    osv: Option<&'a OsvScanner>,
+}
+
+// OSV lists Debian vulnerabilities by source package, which only the control
+// file names; until it is known the binary package from the file name stands in
+fn scan_target(control: Option<&DebControl>, path: &str) -> Option<(String, String)> {
+    match control {
+        Some(control) => {
+            let (package, version) = control.source();
+            Some((package.to_string(), version.to_string()))
+        }
+        None => DebFilename::parse(path.rsplit('/').next()?).map(|deb| (deb.name, deb.version)),
     }
-    
-    None
 }
 
-fn extract_response_bytes(_response: &impl Reply) -> Vec<u8> {
-    vec![]
+// Annotates the request with the package's known vulnerabilities; Err is the
+// reply when they block it or the scan fails closed
+async fn scan_package(
+    osv: &OsvScanner,
+    (package, version): (String, String),
+    decision: &mut DecisionHeaders,
+    audit: &AuditLogger,
+    request: &RequestContext,
+    path: &str,
+) -> Result<(), Box<dyn Reply + Send>> {
+    match osv.scan(&package, &version).instrument(info_span!("osv_scan")).await {
+        Ok(vulnerabilities) if vulnerabilities.is_empty() => Ok(()),
+        Ok(vulnerabilities) => {
+            decision.set("x-aptg-vulnerabilities", &vulnerabilities.join(", "));
+            audit.log_vulnerable_package(request, path, &vulnerabilities, osv.blocks()).await;
+            if !osv.blocks() {
+                return Ok(());
+            }
+            Err(decision.apply(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Package has known vulnerabilities", "vulnerabilities": *vulnerabilities})),
+                warp::http::StatusCode::FORBIDDEN,
+            )))
+        }
+        Err(e) => {
+            warn!("Vulnerability scan of {} {} failed: {}", package, version, e);
+            if !osv.fails_closed() {
+                return Ok(());
+            }
+            Err(decision.apply(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({"error": "Vulnerability scan unavailable"})),
+                warp::http::StatusCode::SERVICE_UNAVAILABLE,
+            )))
+        }
+    }
+}
+
+// The first time a pool package is served its control file is read and kept,
+// and the request checked again against rules on Maintainer, Priority and
+// Depends, and for vulnerabilities of its source package
+async fn check_served_package(
+    checks: &PackageChecks<'_>,
+    body: &[u8],
+    decision: &mut DecisionHeaders,
+    audit: &AuditLogger,
+    request: &mut RequestContext,
+    path: &str,
+) -> Result<(), Box<dyn Reply + Send>> {
+    if !path.contains("/pool/") || !path.ends_with(".deb") {
+        return Ok(());
+    }
+    let control = match DebControl::from_deb(body) {
+        Ok(control) => Arc::new(control),
+        Err(e) => {
+            warn!("No control file read from {}: {}", path, e);
+            return Ok(());
+        }
+    };
+    checks.controls.insert(path, control.clone());
+    request.set_package(&control);
+    let checked = checks.policy.check(Some(&control));
+    apply_policy_decision(checked, decision, audit, request, path).await?;
+
+    // Already scanned under this name and version before the fetch
+    match (checks.osv, scan_target(Some(&control), path)) {
+        (Some(osv), Some(target)) if scan_target(None, path).as_ref() != Some(&target) => {
+            scan_package(osv, target, decision, audit, request, path).await
+        }
+        _ => Ok(()),
+    }
+}
+
+// X-Aptg-* headers telling clients why a request was allowed or denied
+struct DecisionHeaders {
+    enabled: bool,
+    headers: warp::http::HeaderMap,
+}
+
+impl DecisionHeaders {
+    fn new(enabled: bool) -> Self {
+        Self { enabled, headers: warp::http::HeaderMap::new() }
+    }
+
+    fn set(&mut self, name: &'static str, value: &str) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(value) = warp::http::HeaderValue::from_str(value) {
+            self.headers.insert(name, value);
+        }
+    }
+
+    fn apply(&self, reply: impl Reply + 'static) -> Box<dyn Reply + Send> {
+        if self.headers.is_empty() {
+            return Box::new(reply);
+        }
+        let mut response = reply.into_response();
+        response.headers_mut().extend(self.headers.clone());
+        Box::new(response)
+    }
+}
+
+async fn quarantine_artifact(quarantine: &QuarantineStore, path: &str, reason: &str, data: &[u8]) {
+    // Quarantine failures must not change the response sent to the client
+    if let Err(e) = quarantine.quarantine(path, reason, data).await {
+        warn!("Failed to quarantine {}: {}", path, e);
+    }
+}
+
+fn release_violations(
+    release: &ReleaseFile,
+    signature: Option<&GpgVerificationResult>,
+    verification: &VerificationConfig,
+) -> Vec<(EnforcementMode, String)> {
+    let mut violations = Vec::new();
+
+    if let Err(e) = release.check_valid_until(chrono::Utc::now()) {
+        violations.push((verification.valid_until, e.to_string()));
+    }
+
+    let max_age = verification.max_release_age_days.map(chrono::Duration::days);
+    let skew = chrono::Duration::seconds(verification.release_date_skew_secs);
+    if let Err(e) = release.check_date(chrono::Utc::now(), skew, max_age) {
+        violations.push((verification.release_date, e.to_string()));
+    }
+
+    if let Some(algorithm) = signature.and_then(|s| s.weak_digest()) {
+        violations.push((verification.weak_digests, format!("Release signed with weak {} digest", algorithm)));
+    }
+
+    if let Some(reason) = release.weak_digest_issue() {
+        violations.push((verification.weak_digests, reason));
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decision_headers_only_when_enabled() {
+        let mut enabled = DecisionHeaders::new(true);
+        enabled.set("x-aptg-policy", "deny");
+        enabled.set("x-aptg-rule", "deny.packages");
+        let response = enabled.apply(warp::reply()).into_response();
+        assert_eq!(response.headers()["x-aptg-policy"], "deny");
+        assert_eq!(response.headers()["x-aptg-rule"], "deny.packages");
+        
+        let mut disabled = DecisionHeaders::new(false);
+        disabled.set("x-aptg-policy", "deny");
+        assert!(disabled.apply(warp::reply()).into_response().headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_healthz_reports_certificates() {
+        let response = warp::test::request().path("/healthz").reply(&RouterBuilder::new(&AppConfig::default()).build().unwrap()).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+        assert!(response.headers().get("strict-transport-security").is_none());
+        
+        let monitor = ExpiryMonitor::new(Arc::new(Default::default()), Arc::new(AuditLogger::new()), Arc::new(Notifier::new(Default::default())));
+        monitor.check().await;
+        let response = handle_healthz(Some(monitor)).await.unwrap().into_response();
+        let body: serde_json::Value = serde_json::from_slice(&warp::hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["status"], "warning");
+        assert_eq!(body["certificates"][0]["path"], "cert.pem");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_namespaces() {
+        let tenant = |name: &str| -> crate::server::tenant::TenantConfig {
+            toml::from_str(&format!("name = \"{}\"\n[[repositories]]\nname = \"internal\"\nupstream = \"http://127.0.0.1:9/internal\"\n", name)).unwrap()
+        };
+        let mut acme = tenant("acme");
+        acme.quota.requests_per_minute = 1;
+        let mut beta = tenant("beta");
+        let mut policy = crate::policy::rules::PolicyConfig::default();
+        policy.allow.suites.push("sid".to_string());
+        beta.policy = Some(policy);
+        let config = AppConfig { tenants: vec![acme, beta], ..AppConfig::default() };
+        let routes = RouterBuilder::new(&config).build().unwrap();
+        let status = |path: &'static str| {
+            let routes = routes.clone();
+            async move { warp::test::request().path(path).reply(&routes).await.status().as_u16() }
+        };
+        
+        // The global policy only allows bookworm and bullseye; beta's own allows sid,
+        // so its request gets as far as the (unreachable) upstream
+        assert_eq!(status("/t/acme/internal/dists/sid/InRelease").await, 403);
+        assert_eq!(status("/t/beta/internal/dists/sid/InRelease").await, 500);
+        // acme's quota of one request per minute is used up
+        assert_eq!(status("/t/acme/internal/dists/sid/InRelease").await, 429);
+        assert_eq!(status("/t/acme/debian/dists/bookworm/InRelease").await, 404);
+        assert_eq!(status("/t/other/internal/dists/bookworm/InRelease").await, 404);
+        assert_eq!(status("/internal/dists/bookworm/InRelease").await, 404);
+    }
+
+    // Answers 404 for every path and counts the fetches
+    #[derive(Default)]
+    struct MissingUpstream {
+        fetches: std::sync::atomic::AtomicUsize,
+        latency: Arc<crate::mirror::latency::UpstreamLatency>,
+    }
+
+    impl Upstream for MissingUpstream {
+        fn fetch<'a>(&'a self, _path: &'a str) -> crate::mirror::fetch::FetchFuture<'a> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async { Err(UpstreamStatus(warp::http::StatusCode::NOT_FOUND).into()) })
+        }
+        
+        fn fetch_from<'a>(&'a self, _mirror: &'a str, path: &'a str) -> crate::mirror::fetch::FetchFuture<'a> {
+            self.fetch(path)
+        }
+        
+        fn latency(&self) -> Arc<crate::mirror::latency::UpstreamLatency> {
+            self.latency.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unusable_external_policy() {
+        let mut config = AppConfig::default();
+        config.policy.external.enabled = true;
+        config.policy.external.url = "not a url".to_string();
+        assert!(RouterBuilder::new(&config).build().is_err());
+        
+        config.policy.external.failure_mode = FailureMode::Open;
+        assert!(RouterBuilder::new(&config).build().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_external_policy_sees_tenant_requests() {
+        use warp::Filter;
+        
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let decision = warp::post().and(warp::body::json()).map(move |request: serde_json::Value| {
+            recorded.lock().unwrap().push(request);
+            warp::reply::json(&serde_json::json!({"decision": "deny", "rule": "test"}))
+        });
+        let (addr, server) = warp::serve(decision).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        
+        let mut config = AppConfig {
+            tenants: vec![toml::from_str("name = \"acme\"\n[[repositories]]\nname = \"internal\"\nupstream = \"http://127.0.0.1:9/internal\"\n").unwrap()],
+            ..AppConfig::default()
+        };
+        config.policy.external.enabled = true;
+        config.policy.external.url = format!("http://{}/decision", addr);
+        let routes = RouterBuilder::new(&config).build().unwrap();
+        
+        let response = warp::test::request().path("/t/acme/internal/dists/bookworm/main/binary-amd64/Packages.xz").reply(&routes).await;
+        assert_eq!(response.status(), warp::http::StatusCode::FORBIDDEN);
+        let request = seen.lock().unwrap().pop().unwrap();
+        assert_eq!(request["path"], "/internal/dists/bookworm/main/binary-amd64/Packages.xz");
+        assert_eq!(request["tenant"], "acme");
+        assert_eq!(request["repository"], "internal");
+        assert_eq!(request["suite"], "bookworm");
+        assert_eq!(request["component"], "main");
+        assert_eq!(request["filename"], "Packages.xz");
+    }
+
+    #[tokio::test]
+    async fn test_builder_uses_provided_services() {
+        let upstream = Arc::new(MissingUpstream::default());
+        let cache = Arc::new(CacheManager::new());
+        let mut policy = crate::policy::rules::PolicyConfig::default();
+        policy.allow.suites.push("sid".to_string());
+        let routes = RouterBuilder::new(&AppConfig::default())
+            .with_upstream(upstream.clone())
+            .with_cache(cache.clone())
+            .with_policy(Arc::new(arc_swap::ArcSwap::from_pointee(PolicyEngine::from_config(policy))))
+            .build()
+            .unwrap();
+        let path = "/debian/dists/sid/main/binary-amd64/Packages.gz";
+        
+        assert_eq!(warp::test::request().path(path).reply(&routes).await.status(), warp::http::StatusCode::NOT_FOUND);
+        // The miss is remembered in the provided cache rather than fetched again
+        assert_eq!(warp::test::request().path(path).reply(&routes).await.status(), warp::http::StatusCode::NOT_FOUND);
+        assert_eq!(upstream.fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(cache.get(path).await.is_some());
+    }
+    // Serves a plain Release without the Release.gpg that signs it
+    #[derive(Default)]
+    struct UnsignedReleaseUpstream {
+        fetched: std::sync::Mutex<Vec<String>>,
+        latency: Arc<crate::mirror::latency::UpstreamLatency>,
+    }
+
+    impl Upstream for UnsignedReleaseUpstream {
+        fn fetch<'a>(&'a self, path: &'a str) -> crate::mirror::fetch::FetchFuture<'a> {
+            self.fetched.lock().unwrap().push(path.to_string());
+            Box::pin(async move {
+                if !path.ends_with("/Release") {
+                    return Err(UpstreamStatus(warp::http::StatusCode::NOT_FOUND).into());
+                }
+                Ok(crate::mirror::fetch::UpstreamResponse {
+                    status: warp::http::StatusCode::OK,
+                    headers: warp::http::HeaderMap::new(),
+                    body: bytes::Bytes::from_static(b"Suite: stable\nCodename: bookworm\n"),
+                })
+            })
+        }
+        
+        fn fetch_from<'a>(&'a self, _mirror: &'a str, path: &'a str) -> crate::mirror::fetch::FetchFuture<'a> {
+            self.fetch(path)
+        }
+        
+        fn latency(&self) -> Arc<crate::mirror::latency::UpstreamLatency> {
+            self.latency.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plain_release_needs_detached_signature() {
+        let upstream = Arc::new(UnsignedReleaseUpstream::default());
+        let routes = RouterBuilder::new(&AppConfig::default()).with_upstream(upstream.clone()).build().unwrap();
+        let response = warp::test::request().path("/debian/dists/bookworm/Release").reply(&routes).await;
+        assert_eq!(response.status(), warp::http::StatusCode::BAD_GATEWAY);
+        assert!(upstream.fetched.lock().unwrap().iter().any(|path| path.ends_with("/dists/bookworm/Release.gpg")));
+    }
 }
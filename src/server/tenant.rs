@@ -0,0 +1,300 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::audit::log::{AuditConfig, AuditLogger};
+use crate::config::settings::RepositoryConfig;
+use crate::mirror::fetch::{MirrorFetcher, Upstream};
+use crate::mirror::latency::UpstreamLatency;
+use crate::mirror::local::LocalRepository;
+use crate::policy::advisories::{AdvisoryFeed, AdvisoryStore};
+use crate::policy::rules::{PolicyConfig, PolicyEngine};
+use crate::policy::reload::SharedPolicy;
+use crate::server::ratelimit::{RateLimited, TokenBucket};
+use crate::server::repo_auth::RepositoryAccess;
+
+// Limits across all of a tenant's clients, applied on top of [policy.limits]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantQuota {
+    // Token bucket over the tenant's requests; 0 disables it. The burst
+    // defaults to one minute's worth
+    pub requests_per_minute: u32,
+    pub burst: Option<u32>,
+    // Upstream transfers the tenant may have in flight; 0 disables the limit
+    pub max_concurrent_downloads: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantConfig {
+    // Path segment after /t/, e.g. "acme" for /t/acme/debian/dists/...
+    pub name: String,
+    // The tenant's own upstreams; names only need to be unique within the tenant
+    pub repositories: Vec<RepositoryConfig>,
+    // Replaces the global [policy] rules for this tenant's requests. Limits and
+    // the external policy still come from the global section
+    #[serde(default)]
+    pub policy: Option<PolicyConfig>,
+    #[serde(default)]
+    pub quota: TenantQuota,
+    // The tenant's own audit sinks and store; without it events go to the global
+    // audit log, where their paths start with /t/<name>/
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+}
+
+impl TenantConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow!("Tenant name '{}' must be letters, digits, '-' or '_'", self.name));
+        }
+        if self.repositories.is_empty() {
+            return Err(anyhow!("Tenant '{}' has no repositories", self.name));
+        }
+        for (index, repository) in self.repositories.iter().enumerate() {
+            if self.repositories[..index].iter().any(|other| other.name == repository.name) {
+                return Err(anyhow!("Tenant '{}' has repository '{}' twice", self.name, repository.name));
+            }
+            repository.spki_pins()?;
+            if let Some(auth) = &repository.auth {
+                auth.validate(&format!("{}/{}", self.name, repository.name))?;
+            }
+        }
+        if let Some(policy) = &self.policy {
+            policy.validate().map_err(|e| anyhow!("Tenant '{}' policy: {}", self.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+// Released when the transfer is done; the counter belongs to the tenant
+#[derive(Debug)]
+pub struct QuotaPermit(Arc<AtomicUsize>);
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct QuotaLimiter {
+    requests: Option<Mutex<TokenBucket>>,
+    max_downloads: usize,
+    downloads: Arc<AtomicUsize>,
+}
+
+impl QuotaLimiter {
+    fn new(quota: &TenantQuota) -> Self {
+        let requests = (quota.requests_per_minute > 0).then(|| {
+            let rate = quota.requests_per_minute;
+            Mutex::new(TokenBucket::new(rate, quota.burst.unwrap_or(rate), Instant::now()))
+        });
+        Self {
+            requests,
+            max_downloads: quota.max_concurrent_downloads,
+            downloads: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+// The repositories of one namespace and the state kept apart per tenant. The
+// default namespace serves /<repository>/... with the global configuration;
+// each tenant serves /t/<tenant>/<repository>/... with its own
+pub struct Namespace {
+    tenant: Option<String>,
+    repositories: HashSet<String>,
+    // Repositories whose InRelease is signed with the local key
+    resigned: HashSet<String>,
+    pub fetcher: Arc<dyn Upstream>,
+    pub access: Arc<RepositoryAccess>,
+    pub policy: SharedPolicy,
+    // Whether the policy is the tenant's own rather than the shared global one
+    own_policy: bool,
+    pub audit: Arc<AuditLogger>,
+    quota: QuotaLimiter,
+    // Uploaded packages, served in the default namespace only
+    pub local: Option<Arc<LocalRepository>>,
+}
+
+impl Namespace {
+    pub fn new(repositories: &[RepositoryConfig], policy: SharedPolicy, audit: Arc<AuditLogger>) -> Self {
+        Self {
+            tenant: None,
+            repositories: repositories.iter().map(|r| r.name.clone()).collect(),
+            resigned: repositories.iter().filter(|r| r.resign).map(|r| r.name.clone()).collect(),
+            fetcher: Arc::new(MirrorFetcher::from_repositories(repositories)),
+            access: Arc::new(RepositoryAccess::from_repositories(repositories)),
+            policy,
+            own_policy: false,
+            audit,
+            quota: QuotaLimiter::new(&TenantQuota::default()),
+            local: None,
+        }
+    }
+
+    pub fn with_local(mut self, local: Option<Arc<LocalRepository>>) -> Self {
+        self.local = local;
+        self
+    }
+
+    // Replaces the HTTP fetcher built from the repository configuration
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn Upstream>) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
+
+    // Settings the tenant leaves out fall back to the global policy and audit log.
+    // Upstream latency is shared so GeoIP mirror selection and the dashboard see every fetch
+    pub fn for_tenant(config: &TenantConfig, policy: SharedPolicy, audit: Arc<AuditLogger>, latency: Arc<UpstreamLatency>) -> Result<Self> {
+        let (policy, own_policy) = match &config.policy {
+            Some(policy) => {
+                let engine = PolicyEngine::try_from_config(policy.clone(), Arc::new(AdvisoryStore::new()))
+                    .map_err(|e| anyhow!("Tenant '{}' policy: {}", config.name, e))?;
+                (Arc::new(arc_swap::ArcSwap::from_pointee(engine)), true)
+            }
+            None => (policy, false),
+        };
+        let audit = match &config.audit {
+            Some(audit) => Arc::new(AuditLogger::from_config(audit)),
+            None => audit,
+        };
+        Ok(Self {
+            tenant: Some(config.name.clone()),
+            repositories: config.repositories.iter().map(|r| r.name.clone()).collect(),
+            resigned: config.repositories.iter().filter(|r| r.resign).map(|r| r.name.clone()).collect(),
+            fetcher: Arc::new(MirrorFetcher::from_repositories(&config.repositories).with_latency(latency)),
+            access: Arc::new(RepositoryAccess::from_repositories(&config.repositories)),
+            policy,
+            own_policy,
+            audit,
+            quota: QuotaLimiter::new(&config.quota),
+            local: None,
+        })
+    }
+
+    // Starts the OIDC key refresh and, for a tenant's own policy, its advisory feed
+    pub fn spawn(&self) {
+        self.access.spawn();
+        let engine = self.policy.load();
+        if self.own_policy && engine.advisory_config().enabled {
+            AdvisoryFeed::new(engine.advisory_config().clone(), engine.advisory_store()).spawn();
+        }
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub fn contains(&self, repository: &str) -> bool {
+        self.repositories.contains(repository)
+    }
+
+    pub fn resigns(&self, repository: &str) -> bool {
+        self.resigned.contains(repository)
+    }
+
+    // Prepended to repository paths in the cache, the verified index store,
+    // quarantine and audit events, so tenants never share any of them
+    pub fn prefix(&self) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("/t/{}", tenant),
+            None => String::new(),
+        }
+    }
+
+    pub fn check_quota(&self, now: Instant) -> Result<(), RateLimited> {
+        let Some(requests) = &self.quota.requests else {
+            return Ok(());
+        };
+        requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_acquire(now)
+            .map_err(|retry_after| RateLimited { scope: "tenant", retry_after })
+    }
+
+    // Counted per tenant, whatever the client address
+    pub fn try_acquire_download(&self) -> Result<Option<QuotaPermit>, RateLimited> {
+        if self.quota.max_downloads == 0 {
+            return Ok(None);
+        }
+        let max = self.quota.max_downloads;
+        self.quota.downloads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| (active < max).then_some(active + 1))
+            .map(|_| Some(QuotaPermit(self.quota.downloads.clone())))
+            .map_err(|_| RateLimited { scope: "tenant", retry_after: Duration::from_secs(1) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(toml: &str) -> TenantConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let config = tenant(r#"
+name = "acme"
+[[repositories]]
+name = "debian"
+upstream = "https://deb.debian.org/debian"
+"#);
+        assert!(config.validate().is_ok());
+        assert!(config.policy.is_none());
+        assert_eq!(config.quota.requests_per_minute, 0);
+        
+        let mut invalid = config.clone();
+        invalid.name = "acme/corp".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.repositories.push(config.repositories[0].clone());
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.repositories.clear();
+        assert!(invalid.validate().is_err());
+        
+        // A deny pattern that does not compile is an error, not a rule that is skipped
+        let mut invalid = config;
+        let mut policy = PolicyConfig::default();
+        policy.deny.packages.push("^(unclosed".to_string());
+        invalid.policy = Some(policy);
+        assert!(invalid.validate().is_err());
+        let global: SharedPolicy = Arc::new(arc_swap::ArcSwap::from_pointee(PolicyEngine::new()));
+        assert!(Namespace::for_tenant(&invalid, global, Arc::new(AuditLogger::new()), Arc::new(UpstreamLatency::default())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        let config = tenant(r#"
+name = "acme"
+[[repositories]]
+name = "debian"
+upstream = "https://deb.debian.org/debian"
+[quota]
+requests_per_minute = 60
+burst = 2
+max_concurrent_downloads = 1
+"#);
+        let policy: SharedPolicy = Arc::new(arc_swap::ArcSwap::from_pointee(PolicyEngine::from_config(PolicyConfig::default())));
+        let namespace = Namespace::for_tenant(&config, policy, Arc::new(AuditLogger::new()), Arc::new(UpstreamLatency::default())).unwrap();
+        assert_eq!(namespace.prefix(), "/t/acme");
+        assert!(namespace.contains("debian"));
+        
+        let now = Instant::now();
+        assert!(namespace.check_quota(now).is_ok());
+        assert!(namespace.check_quota(now).is_ok());
+        assert_eq!(namespace.check_quota(now).unwrap_err().scope, "tenant");
+        assert!(namespace.check_quota(now + Duration::from_secs(1)).is_ok());
+        
+        let permit = namespace.try_acquire_download().unwrap();
+        assert!(permit.is_some());
+        assert!(namespace.try_acquire_download().is_err());
+        drop(permit);
+        assert!(namespace.try_acquire_download().unwrap().is_some());
+    }
+}
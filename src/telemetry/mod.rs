@@ -0,0 +1,3 @@
+pub mod json;
+pub mod otel;
+pub mod trace_context;
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use crate::telemetry::json::JsonLayer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // One JSON object per line, request span fields included
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    // OTLP/gRPC collector, e.g. http://localhost:4317; unset disables trace export
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    // Fraction of new traces exported, 0.0 to 1.0
+    pub sample_ratio: f64,
+    pub log_format: LogFormat,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "aptg".to_string(),
+            sample_ratio: 1.0,
+            log_format: LogFormat::Text,
+        }
+    }
+}
+
+// Flushes buffered spans when dropped at shutdown
+#[derive(Default)]
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+// Installs the global tracing subscriber. With an OTLP endpoint every request
+// span is exported as a trace as well as being logged
+pub fn init(config: &TelemetryConfig) -> Result<TelemetryGuard> {
+    match &config.otlp_endpoint {
+        Some(endpoint) => init_otlp(config, endpoint),
+        None => {
+            tracing_subscriber::registry().with(LevelFilter::INFO).with(log_layer(config.log_format)).init();
+            Ok(TelemetryGuard::default())
+        }
+    }
+}
+
+// Log lines to stdout in the configured format
+fn log_layer<S>(format: LogFormat) -> Box<dyn tracing_subscriber::Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Text => Box::new(tracing_subscriber::fmt::layer()),
+        LogFormat::Json => Box::new(JsonLayer::new(std::io::stdout)),
+    }
+}
+
+#[cfg(feature = "otel")]
+fn init_otlp(config: &TelemetryConfig, endpoint: &str) -> Result<TelemetryGuard> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+    use tracing::info;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let ratio = config.sample_ratio.clamp(0.0, 1.0);
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))))
+        .with_resource(opentelemetry_sdk::Resource::new(vec![KeyValue::new("service.name", config.service_name.clone())]))
+        .build();
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("aptg")))
+        .with(log_layer(config.log_format))
+        .init();
+    info!("Exporting traces to {}", endpoint);
+    Ok(TelemetryGuard { provider: Some(provider) })
+}
+
+#[cfg(not(feature = "otel"))]
+fn init_otlp(config: &TelemetryConfig, _endpoint: &str) -> Result<TelemetryGuard> {
+    tracing_subscriber::registry().with(LevelFilter::INFO).with(log_layer(config.log_format)).init();
+    tracing::warn!("telemetry.otlp_endpoint is set but aptg was built without the otel feature");
+    Ok(TelemetryGuard::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_disabled_by_default() {
+        let config: TelemetryConfig = toml::from_str("").unwrap();
+        assert!(config.otlp_endpoint.is_none());
+        assert_eq!(config.service_name, "aptg");
+        assert_eq!(config.sample_ratio, 1.0);
+        assert_eq!(config.log_format, LogFormat::Text);
+    }
+}
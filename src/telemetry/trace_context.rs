@@ -0,0 +1,173 @@
+use rand::RngCore;
+use std::future::Future;
+use warp::http::HeaderMap;
+
+// Longer tracestate lists are dropped rather than passed on, as W3C allows
+const MAX_TRACESTATE_LEN: usize = 512;
+
+tokio::task_local! {
+    // The context of the request whose upstream fetch runs in this task
+    static CURRENT: TraceContext;
+}
+
+// W3C Trace Context sent by a client or ingress in traceparent / tracestate,
+// passed on to upstream so the trace continues through aptg to the CDN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    // Lowercase hex, 32 and 16 digits
+    pub trace_id: String,
+    pub parent_id: String,
+    pub flags: u8,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let traceparent = headers.get("traceparent")?.to_str().ok()?;
+        // Repeated tracestate headers make up one list
+        let tracestate: Vec<&str> = headers
+            .get_all("tracestate")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect();
+        Self::parse(traceparent, (!tracestate.is_empty()).then(|| tracestate.join(",")))
+    }
+
+    // None for a malformed traceparent, which is ignored along with tracestate
+    pub fn parse(traceparent: &str, tracestate: Option<String>) -> Option<Self> {
+        let hex = |field: &str, digits: usize| field.len() == digits && field.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        let zero = |field: &str| field.bytes().all(|b| b == b'0');
+        let mut fields = traceparent.trim().split('-');
+        let (version, trace_id, parent_id, flags) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        // Later versions may append fields; version 00 has exactly four
+        if !hex(version, 2) || version == "ff" || (version == "00" && fields.next().is_some()) {
+            return None;
+        }
+        if !hex(trace_id, 32) || zero(trace_id) || !hex(parent_id, 16) || zero(parent_id) || !hex(flags, 2) {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate: tracestate.filter(|state| state.len() <= MAX_TRACESTATE_LEN),
+        })
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.parent_id, self.flags)
+    }
+
+    // The same trace with aptg's current span as the parent, for an upstream
+    // request. Without an exported span a random ID stands in for it
+    pub fn child(&self) -> Self {
+        Self { parent_id: exported_span_id(&self.trace_id).unwrap_or_else(random_span_id), ..self.clone() }
+    }
+
+    // Records the context on the request span and, when traces are exported,
+    // makes the client's span its parent
+    pub fn attach(&self, span: &tracing::Span) {
+        span.record("traceparent", self.traceparent().as_str());
+        if let Some(tracestate) = &self.tracestate {
+            span.record("tracestate", tracestate.as_str());
+        }
+        #[cfg(feature = "otel")]
+        {
+            use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId};
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+            
+            let (Ok(trace_id), Ok(span_id)) = (TraceId::from_hex(&self.trace_id), SpanId::from_hex(&self.parent_id)) else {
+                return;
+            };
+            let trace_state = self.tracestate.as_deref().and_then(|state| state.parse().ok()).unwrap_or_default();
+            let remote = SpanContext::new(trace_id, span_id, TraceFlags::new(self.flags), true, trace_state);
+            span.set_parent(opentelemetry::Context::new().with_remote_span_context(remote));
+        }
+    }
+
+    // Makes the context available to the upstream fetches the future makes
+    pub async fn scope<F: Future>(context: Option<Self>, future: F) -> F::Output {
+        match context {
+            Some(context) => CURRENT.scope(context, future).await,
+            None => future.await,
+        }
+    }
+
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+}
+
+#[cfg(feature = "otel")]
+fn exported_span_id(trace_id: &str) -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    (span_context.is_valid() && format!("{:032x}", span_context.trace_id()) == trace_id)
+        .then(|| format!("{:016x}", span_context.span_id()))
+}
+
+#[cfg(not(feature = "otel"))]
+fn exported_span_id(_trace_id: &str) -> Option<String> {
+    None
+}
+
+fn random_span_id() -> String {
+    let mut id = [0u8; 8];
+    while id == [0u8; 8] {
+        rand::thread_rng().fill_bytes(&mut id);
+    }
+    hex::encode(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", TRACEPARENT.parse().unwrap());
+        headers.append("tracestate", "congo=t61rcWkgMzE".parse().unwrap());
+        headers.append("tracestate", "rojo=00f067aa0ba902b7".parse().unwrap());
+        let context = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.flags, 1);
+        assert_eq!(context.tracestate.as_deref(), Some("congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"));
+        assert_eq!(context.traceparent(), TRACEPARENT);
+        
+        let child = context.child();
+        assert_eq!((child.trace_id.as_str(), child.flags), (context.trace_id.as_str(), 1));
+        assert_ne!(child.parent_id, context.parent_id);
+        assert!(TraceContext::parse(&child.traceparent(), None).is_some());
+        
+        for malformed in [
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert!(TraceContext::parse(malformed, None).is_none(), "{}", malformed);
+        }
+        // A later version may carry more fields
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra", None).is_some());
+        assert_eq!(TraceContext::parse(TRACEPARENT, Some("a=b".repeat(200))).unwrap().tracestate, None);
+    }
+
+    #[tokio::test]
+    async fn test_scope() {
+        let context = TraceContext::parse(TRACEPARENT, None);
+        assert_eq!(TraceContext::scope(context.clone(), async { TraceContext::current() }).await, context);
+        assert_eq!(TraceContext::scope(None, async { TraceContext::current() }).await, None);
+        assert_eq!(TraceContext::current(), None);
+    }
+}
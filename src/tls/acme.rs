@@ -0,0 +1,558 @@
+use anyhow::{Result, anyhow};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, DnType};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls::sign::CertifiedKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn, error};
+use warp::Filter;
+use crate::tls::certificate_simple::KeyType;
+use crate::tls::reload::CertReloader;
+use crate::tls::simple_server::TlsServerConfig;
+
+// ALPN protocol validation servers offer for TLS-ALPN-01 (RFC 8737)
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+// Authorizations and orders are polled this many times, two seconds apart
+const POLL_ATTEMPTS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChallengeType {
+    // Token served over plain HTTP on port 80
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    // Challenge certificate served on the HTTPS port
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+}
+
+impl ChallengeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http01 => "http-01",
+            Self::TlsAlpn01 => "tls-alpn-01",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AcmeConfig {
+    pub enabled: bool,
+    pub directory_url: String,
+    pub contact_email: Option<String>,
+    // Names on the certificate; the first is also its common name
+    pub domains: Vec<String>,
+    pub challenge: ChallengeType,
+    // Account key, created on first use
+    pub account_key_path: String,
+    pub renew_before_days: u32,
+    pub check_interval_hours: u64,
+    // Where HTTP-01 challenges are answered; validation servers always connect to 80
+    pub http_port: u16,
+    // Key for issued certificates; CAs generally refuse Ed25519
+    pub key_type: KeyType,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory_url: "https://acme-v02.api.letsencrypt.org/directory".to_string(),
+            contact_email: None,
+            domains: vec![],
+            challenge: ChallengeType::Http01,
+            account_key_path: "certs/acme-account.key".to_string(),
+            renew_before_days: 30,
+            check_interval_hours: 12,
+            http_port: 80,
+            key_type: KeyType::EcdsaP256,
+        }
+    }
+}
+
+// Challenge responses waiting to be fetched by the validation server
+#[derive(Clone, Default)]
+pub struct AcmeChallenges {
+    // HTTP-01 token to key authorization
+    http: Arc<RwLock<HashMap<String, String>>>,
+    // Domain to TLS-ALPN-01 certificate
+    tls_alpn: Arc<RwLock<HashMap<String, Arc<CertifiedKey>>>>,
+}
+
+impl AcmeChallenges {
+    fn key_authorization(&self, token: &str) -> Option<String> {
+        self.http.read().unwrap_or_else(|e| e.into_inner()).get(token).cloned()
+    }
+
+    pub fn tls_alpn_certificate(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.tls_alpn.read().unwrap_or_else(|e| e.into_inner()).get(domain).cloned()
+    }
+
+    fn install(&self, kind: ChallengeType, domain: &str, token: &str, key_authorization: &str) -> Result<()> {
+        match kind {
+            ChallengeType::Http01 => {
+                self.http.write().unwrap_or_else(|e| e.into_inner()).insert(token.to_string(), key_authorization.to_string());
+            }
+            ChallengeType::TlsAlpn01 => {
+                let certificate = Arc::new(tls_alpn_certificate(domain, key_authorization)?);
+                self.tls_alpn.write().unwrap_or_else(|e| e.into_inner()).insert(domain.to_string(), certificate);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, kind: ChallengeType, domain: &str, token: &str) {
+        match kind {
+            ChallengeType::Http01 => {
+                self.http.write().unwrap_or_else(|e| e.into_inner()).remove(token);
+            }
+            ChallengeType::TlsAlpn01 => {
+                self.tls_alpn.write().unwrap_or_else(|e| e.into_inner()).remove(domain);
+            }
+        }
+    }
+}
+
+pub fn challenge_route(challenges: AcmeChallenges) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path!(".well-known" / "acme-challenge" / String)
+        .and(warp::get())
+        .and_then(move |token: String| {
+            let key_authorization = challenges.key_authorization(&token);
+            async move { key_authorization.ok_or_else(warp::reject::not_found) }
+        })
+}
+
+fn b64(data: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+// Certificate or CSR parameters naming the domains, the first as common name
+fn domain_params(domains: &[String]) -> Result<CertificateParams> {
+    let mut params = CertificateParams::new(domains.to_vec())?;
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, domains[0].as_str());
+    params.distinguished_name = distinguished_name;
+    Ok(params)
+}
+
+// Self-signed certificate carrying the key authorization digest (RFC 8737)
+fn tls_alpn_certificate(domain: &str, key_authorization: &str) -> Result<CertifiedKey> {
+    let key = KeyType::EcdsaP256.generate()?;
+    let mut params = domain_params(&[domain.to_string()])?;
+    params.not_before = time::OffsetDateTime::now_utc();
+    params.not_after = params.not_before + time::Duration::days(7);
+    // Critical, with the SHA-256 digest wrapped in a DER OCTET STRING
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(&Sha256::digest(key_authorization.as_bytes()))];
+    let certificate = params.self_signed(&key)?;
+
+    let signing_key = rustls::sign::any_ecdsa_type(&rustls::PrivateKey(key.serialize_der()))
+        .map_err(|e| anyhow!("Unusable challenge key: {}", e))?;
+    Ok(CertifiedKey::new(vec![rustls::Certificate(certificate.der().to_vec())], signing_key))
+}
+
+// Renew when the certificate is unreadable, self-signed (a bootstrap or
+// challenge certificate) or inside the renewal window
+pub fn needs_renewal(cert_path: &str, renew_before_days: u32) -> bool {
+    let Ok(pem) = std::fs::read(cert_path) else {
+        return true;
+    };
+    let Some(der) = rustls_pemfile::certs(&mut pem.as_slice()).ok().and_then(|certs| certs.into_iter().next()) else {
+        return true;
+    };
+    let Ok((_, certificate)) = x509_parser::parse_x509_certificate(&der) else {
+        return true;
+    };
+    if certificate.subject() == certificate.issuer() {
+        return true;
+    }
+    let remaining = certificate.validity().not_after.timestamp() - chrono::Utc::now().timestamp();
+    remaining < i64::from(renew_before_days) * 86400
+}
+
+struct Account {
+    key: EcdsaKeyPair,
+    jwk: Value,
+    // Base64url SHA-256 of the JWK (RFC 7638), the suffix of every key authorization
+    thumbprint: String,
+}
+
+impl Account {
+    fn load_or_create(path: &str) -> Result<Self> {
+        let key = match std::fs::read(path) {
+            Ok(pem) => rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())
+                .ok()
+                .and_then(|keys| keys.into_iter().next())
+                .ok_or_else(|| anyhow!("Failed to parse ACME account key {}: expected a PKCS#8 key", path))?,
+            Err(_) => {
+                let key = KeyType::EcdsaP256.generate()?;
+                if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)?;
+                }
+                write_private(path, key.serialize_pem().as_bytes())?;
+                info!("Created ACME account key {}", path);
+                key.serialize_der()
+            }
+        };
+        Self::from_key(&key)
+    }
+
+    fn from_key(pkcs8: &[u8]) -> Result<Self> {
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &SystemRandom::new())
+            .map_err(|_| anyhow!("ACME account key must be a P-256 EC key"))?;
+        // Uncompressed point: 0x04, then the 32-byte x and y coordinates
+        let point = key.public_key().as_ref();
+        let (x, y) = (b64(&point[1..33]), b64(&point[33..65]));
+        // Members in lexicographic order, no whitespace, as the thumbprint requires
+        let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y);
+        Ok(Self {
+            key,
+            jwk: json!({"crv": "P-256", "kty": "EC", "x": x, "y": y}),
+            thumbprint: b64(Sha256::digest(canonical.as_bytes())),
+        })
+    }
+
+    // ES256 signatures are the raw 32-byte r and s values, not DER
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let signature = self.key.sign(&SystemRandom::new(), data)
+            .map_err(|_| anyhow!("Failed to sign ACME request"))?;
+        Ok(signature.as_ref().to_vec())
+    }
+
+    fn key_authorization(&self, token: &str) -> String {
+        format!("{}.{}", token, self.thumbprint)
+    }
+}
+
+fn write_private(path: &str, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| anyhow!("Failed to write {}: {}", path, e))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    account: Account,
+    // Account URL, known once registered
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    async fn new(config: &AcmeConfig) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let directory = http.get(&config.directory_url).send().await?.error_for_status()?.json().await
+            .map_err(|e| anyhow!("Invalid ACME directory {}: {}", config.directory_url, e))?;
+        Ok(Self {
+            http,
+            directory,
+            account: Account::load_or_create(&config.account_key_path)?,
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    async fn new_nonce(&self) -> Result<String> {
+        let response = self.http.head(&self.directory.new_nonce).send().await?;
+        replay_nonce(&response).ok_or_else(|| anyhow!("ACME server returned no nonce"))
+    }
+
+    // JWS-signed POST; a missing payload is a POST-as-GET
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<reqwest::Response> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.new_nonce().await?,
+            };
+            let mut protected = json!({"alg": "ES256", "nonce": nonce, "url": url});
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.account.jwk.clone(),
+            }
+            let protected = b64(protected.to_string());
+            let payload = payload.map_or_else(String::new, |payload| b64(payload.to_string()));
+            let signature = b64(self.account.sign(format!("{}.{}", protected, payload).as_bytes())?);
+            let body = json!({"protected": protected, "payload": payload, "signature": signature});
+            
+            let response = self.http
+                .post(url)
+                .header("content-type", "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await?;
+            self.nonce = replay_nonce(&response);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+            
+            let problem: Value = response.json().await.unwrap_or_default();
+            // Nonces expire; the error carries a fresh one to retry with
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(anyhow!("ACME request to {} failed: {}", url, problem["detail"].as_str().unwrap_or("unknown error")));
+        }
+    }
+
+    async fn register(&mut self, contact_email: Option<&str>) -> Result<()> {
+        let contact: Vec<String> = contact_email.map(|email| format!("mailto:{}", email)).into_iter().collect();
+        let new_account = self.directory.new_account.clone();
+        let response = self.post(&new_account, Some(&json!({"termsOfServiceAgreed": true, "contact": contact}))).await?;
+        self.kid = Some(location(&response)?);
+        Ok(())
+    }
+
+    // Returns the PEM certificate chain and its PEM private key
+    async fn obtain(&mut self, config: &AcmeConfig, challenges: &AcmeChallenges) -> Result<(Vec<u8>, Vec<u8>)> {
+        self.register(config.contact_email.as_deref()).await?;
+        
+        let identifiers: Vec<Value> = config.domains.iter().map(|domain| json!({"type": "dns", "value": domain})).collect();
+        let new_order = self.directory.new_order.clone();
+        let response = self.post(&new_order, Some(&json!({"identifiers": identifiers}))).await?;
+        let order_url = location(&response)?;
+        let order: Order = response.json().await?;
+        
+        for authorization in &order.authorizations {
+            self.authorize(authorization, config.challenge, challenges).await?;
+        }
+        
+        let key = config.key_type.generate()?;
+        let csr = domain_params(&config.domains)?.serialize_request(&key)?;
+        self.post(&order.finalize, Some(&json!({"csr": b64(csr.der())}))).await?;
+        
+        let mut certificate_url = None;
+        for _ in 0..POLL_ATTEMPTS {
+            let order: Order = self.post(&order_url, None).await?.json().await?;
+            match order.status.as_str() {
+                "valid" => {
+                    certificate_url = order.certificate;
+                    break;
+                }
+                "invalid" => return Err(anyhow!("ACME order {} became invalid", order_url)),
+                _ => tokio::time::sleep(Duration::from_secs(2)).await,
+            }
+        }
+        let certificate_url = certificate_url.ok_or_else(|| anyhow!("ACME order {} was not issued in time", order_url))?;
+        let chain = self.post(&certificate_url, None).await?.bytes().await?;
+        
+        Ok((chain.to_vec(), key.serialize_pem().into_bytes()))
+    }
+
+    async fn authorize(&mut self, url: &str, kind: ChallengeType, challenges: &AcmeChallenges) -> Result<()> {
+        let authorization: Authorization = self.post(url, None).await?.json().await?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+        let domain = authorization.identifier.value;
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|challenge| challenge.kind == kind.as_str())
+            .ok_or_else(|| anyhow!("ACME server offers no {} challenge for {}", kind.as_str(), domain))?;
+        
+        challenges.install(kind, &domain, &challenge.token, &self.account.key_authorization(&challenge.token))?;
+        let result = self.validate(url, &challenge.url, &domain).await;
+        challenges.remove(kind, &domain, &challenge.token);
+        result
+    }
+
+    async fn validate(&mut self, authorization_url: &str, challenge_url: &str, domain: &str) -> Result<()> {
+        self.post(challenge_url, Some(&json!({}))).await?;
+        for _ in 0..POLL_ATTEMPTS {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            let authorization: Authorization = self.post(authorization_url, None).await?.json().await?;
+            match authorization.status.as_str() {
+                "valid" => {
+                    info!("ACME validation for {} succeeded", domain);
+                    return Ok(());
+                }
+                "invalid" => {
+                    let detail = authorization
+                        .challenges
+                        .iter()
+                        .find_map(|challenge| challenge.error.as_ref()?["detail"].as_str().map(str::to_string))
+                        .unwrap_or_else(|| "no detail".to_string());
+                    return Err(anyhow!("ACME validation for {} failed: {}", domain, detail));
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow!("ACME validation for {} timed out", domain))
+    }
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response.headers().get("replay-nonce").and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+fn location(response: &reqwest::Response) -> Result<String> {
+    response
+        .headers()
+        .get("location")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("ACME response has no Location header"))
+}
+
+// Keeps the server certificate issued and renewed, writing it where the
+// server loads it from and swapping it in without a restart
+pub struct AcmeManager {
+    config: Arc<TlsServerConfig>,
+    challenges: AcmeChallenges,
+    reloader: CertReloader,
+}
+
+impl AcmeManager {
+    pub fn new(config: Arc<TlsServerConfig>, challenges: AcmeChallenges, reloader: CertReloader) -> Self {
+        Self { config, challenges, reloader }
+    }
+
+    async fn renew(&self) -> Result<()> {
+        let acme = &self.config.acme;
+        info!("Requesting certificate for {} from {}", acme.domains.join(", "), acme.directory_url);
+        let mut client = AcmeClient::new(acme).await?;
+        let (chain, key) = client.obtain(acme, &self.challenges).await?;
+        
+        write_private(&self.config.key_path, &key)?;
+        std::fs::write(&self.config.cert_path, chain)
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.config.cert_path, e))?;
+        self.reloader.reload()
+    }
+
+    pub fn spawn(self) {
+        let acme = &self.config.acme;
+        if acme.challenge == ChallengeType::Http01 {
+            match warp::serve(challenge_route(self.challenges.clone())).try_bind_ephemeral(([0, 0, 0, 0], acme.http_port)) {
+                Ok((addr, server)) => {
+                    info!("Answering ACME HTTP-01 challenges on {}", addr);
+                    tokio::spawn(server);
+                }
+                Err(e) => error!("Failed to listen for ACME HTTP-01 challenges on port {}: {}", acme.http_port, e),
+            }
+        }
+        
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(self.config.acme.check_interval_hours.max(1) * 3600);
+            loop {
+                if needs_renewal(&self.config.cert_path, self.config.acme.renew_before_days) {
+                    if let Err(e) = self.renew().await {
+                        warn!("Certificate renewal failed, retrying in {:?}: {}", interval, e);
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::certificate_simple::CertificateManager;
+
+    #[test]
+    fn test_signature_verifies() {
+        let account = Account::from_key(&KeyType::EcdsaP256.generate().unwrap().serialize_der()).unwrap();
+        let signature = account.sign(b"protected.payload").unwrap();
+        assert_eq!(signature.len(), 64);
+        
+        let public_key = ring::signature::UnparsedPublicKey::new(&ring::signature::ECDSA_P256_SHA256_FIXED, account.key.public_key().as_ref());
+        assert!(public_key.verify(b"protected.payload", &signature).is_ok());
+        assert_eq!(account.key_authorization("token").split('.').nth(1), Some(account.thumbprint.as_str()));
+    }
+
+    #[test]
+    fn test_tls_alpn_certificate_carries_digest() {
+        const ACME_IDENTIFIER_OID: &str = "1.3.6.1.5.5.7.1.31";
+        let certified = tls_alpn_certificate("mirror.example.org", "token.thumbprint").unwrap();
+        let (_, certificate) = x509_parser::parse_x509_certificate(&certified.cert[0].0).unwrap();
+        let extension = certificate
+            .extensions()
+            .iter()
+            .find(|extension| extension.oid.to_id_string() == ACME_IDENTIFIER_OID)
+            .unwrap();
+        assert!(extension.critical);
+        assert_eq!(&extension.value[2..], Sha256::digest(b"token.thumbprint").as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_http_challenge_route() {
+        let challenges = AcmeChallenges::default();
+        challenges.install(ChallengeType::Http01, "mirror.example.org", "abc", "abc.thumb").unwrap();
+        let route = challenge_route(challenges.clone());
+        
+        let response = warp::test::request().path("/.well-known/acme-challenge/abc").reply(&route).await;
+        assert_eq!(response.body().as_ref(), b"abc.thumb");
+        challenges.remove(ChallengeType::Http01, "mirror.example.org", "abc");
+        let response = warp::test::request().path("/.well-known/acme-challenge/abc").reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_self_signed_certificates_need_renewal() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("server.pem").to_str().unwrap().to_string();
+        let key_path = dir.path().join("server.key").to_str().unwrap().to_string();
+        assert!(needs_renewal(&cert_path, 30));
+        CertificateManager::generate_self_signed_cert("mirror.example.org", &cert_path, &key_path).unwrap();
+        assert!(needs_renewal(&cert_path, 30));
+    }
+}
@@ -1,9 +1,12 @@
 use anyhow::{Result, anyhow};
-use openssl::x509::X509;
-use openssl::pkey::PKey;
-use openssl::rsa::Rsa;
-use openssl::asn1::Asn1Time;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose, SignatureAlgorithm,
+};
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::net::IpAddr;
+use time::{Duration, OffsetDateTime};
 use tracing::info;
 
 pub struct CertificateManager {
@@ -19,26 +22,31 @@ impl CertificateManager {
         }
     }
 
-    pub fn load_certificate(&self) -> Result<X509> {
+    // DER of the first certificate in the PEM file
+    pub fn load_certificate(&self) -> Result<Vec<u8>> {
         info!("Loading certificate from: {}", self.cert_path);
         
         let cert_data = fs::read(&self.cert_path)
             .map_err(|e| anyhow!("Failed to read certificate file: {}", e))?;
         
-        let cert = X509::from_pem(&cert_data)
-            .map_err(|e| anyhow!("Failed to parse certificate PEM: {}", e))?;
+        let cert = rustls_pemfile::certs(&mut cert_data.as_slice())
+            .map_err(|e| anyhow!("Failed to parse certificate PEM: {}", e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No certificates found in {}", self.cert_path))?;
         
         info!("Certificate loaded successfully");
         Ok(cert)
     }
 
-    pub fn load_private_key(&self) -> Result<PKey<openssl::pkey::Private>> {
+    // PKCS#8 PEM, as written by the generators below
+    pub fn load_private_key(&self) -> Result<KeyPair> {
         info!("Loading private key from: {}", self.key_path);
         
-        let key_data = fs::read(&self.key_path)
+        let key_data = fs::read_to_string(&self.key_path)
             .map_err(|e| anyhow!("Failed to read private key file: {}", e))?;
         
-        let key = PKey::private_key_from_pem(&key_data)
+        let key = KeyPair::from_pem(&key_data)
             .map_err(|e| anyhow!("Failed to parse private key PEM: {}", e))?;
         
         info!("Private key loaded successfully");
@@ -46,54 +54,232 @@ impl CertificateManager {
     }
 
     pub fn generate_self_signed_cert(common_name: &str, cert_path: &str, key_path: &str) -> Result<()> {
-        info!("Generating self-signed certificate for CN: {}", common_name);
-        
-        // Generate RSA private key
-        let rsa = Rsa::generate(2048)?;
-        let private_key = PKey::from_rsa(rsa)?;
-        
-        // Create certificate
-        let mut builder = openssl::x509::X509::builder()?;
-        builder.set_version(2)?;
-        
-        // Set serial number
-        let serial = openssl::bn::BigNum::from_u32(1)?;
-        let serial_asn1 = openssl::asn1::Asn1Integer::from_bn(&serial)?;
-        builder.set_serial_number(&serial_asn1)?;
-        
-        // Set validity period (1 year)
-        let not_before = Asn1Time::days_from_now(0)?;
-        let not_after = Asn1Time::days_from_now(365)?;
-        builder.set_not_before(&not_before)?;
-        builder.set_not_after(&not_after)?;
-        
-        // Set subject and issuer (self-signed)
-        let mut name_builder = openssl::x509::X509Name::builder()?;
-        name_builder.append_entry_by_text("CN", common_name)?;
-        let name = name_builder.build();
-        builder.set_subject_name(&name)?;
-        builder.set_issuer_name(&name)?;
-        
-        // Set public key
-        builder.set_pubkey(&private_key)?;
-        
-        // Add extensions
-        let _context = builder.x509v3_context(None, None);
-        builder.append_extension(openssl::x509::extension::BasicConstraints::new().build().unwrap())?;
-        builder.append_extension(openssl::x509::extension::KeyUsage::new().digital_signature().key_encipherment().build().unwrap())?;
-        builder.append_extension(openssl::x509::extension::ExtendedKeyUsage::new().server_auth().build().unwrap())?;
-        
-        // Sign certificate
-        builder.sign(&private_key, openssl::hash::MessageDigest::sha256())?;
-        let certificate = builder.build();
-        
-        // Save certificate and private key
-        fs::write(cert_path, certificate.to_pem()?)?;
-        fs::write(key_path, private_key.private_key_to_pem_pkcs8()?)?;
+        Self::generate_self_signed_cert_with_key(common_name, KeyType::default(), cert_path, key_path)
+    }
+        
+    pub fn generate_self_signed_cert_with_key(common_name: &str, key_type: KeyType, cert_path: &str, key_path: &str) -> Result<()> {
+        let options = CertificateOptions { key_type, ..CertificateOptions::new(common_name) };
+        Self::generate_self_signed_cert_with_options(&options, cert_path, key_path)
+    }
+        
+    pub fn generate_self_signed_cert_with_options(options: &CertificateOptions, cert_path: &str, key_path: &str) -> Result<()> {
+        info!("Generating self-signed {} certificate for CN: {}", options.key_type.as_str(), options.common_name);
+        
+        let private_key = options.key_type.generate()?;
+        let mut params = options.params(CertificatePurpose::Server)?;
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let certificate = params.self_signed(&private_key)?;
         
+        Self::write_certificate(&certificate, &private_key, cert_path, key_path)?;
         info!("Self-signed certificate generated successfully");
         Ok(())
     }
+
+    fn write_certificate(certificate: &Certificate, private_key: &KeyPair, cert_path: &str, key_path: &str) -> Result<()> {
+        fs::write(cert_path, certificate.pem())
+            .map_err(|e| anyhow!("Failed to write {}: {}", cert_path, e))?;
+        fs::write(key_path, private_key.serialize_pem())
+            .map_err(|e| anyhow!("Failed to write {}: {}", key_path, e))?;
+        Ok(())
+    }
+
+    // Self-signed CA that may only sign certificates, not serve TLS itself
+    pub fn generate_ca(common_name: &str, key_type: KeyType, cert_path: &str, key_path: &str) -> Result<()> {
+        info!("Generating {} CA certificate for CN: {}", key_type.as_str(), common_name);
+        
+        let private_key = key_type.generate()?;
+        let options = CertificateOptions { validity_days: 3650, key_type, ..CertificateOptions::new(common_name) };
+        let mut params = options.params(CertificatePurpose::Client)?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let certificate = params.self_signed(&private_key)?;
+        
+        Self::write_certificate(&certificate, &private_key, cert_path, key_path)?;
+        info!("CA certificate generated successfully");
+        Ok(())
+    }
+
+    // Leaf certificate signed by the CA at ca_cert_path/ca_key_path
+    pub fn issue_certificate(
+        ca_cert_path: &str,
+        ca_key_path: &str,
+        common_name: &str,
+        purpose: CertificatePurpose,
+        key_type: KeyType,
+        cert_path: &str,
+        key_path: &str,
+    ) -> Result<()> {
+        let options = CertificateOptions { key_type, ..CertificateOptions::new(common_name) };
+        Self::issue_certificate_with_options(ca_cert_path, ca_key_path, &options, purpose, cert_path, key_path)
+    }
+
+    pub fn issue_certificate_with_options(
+        ca_cert_path: &str,
+        ca_key_path: &str,
+        options: &CertificateOptions,
+        purpose: CertificatePurpose,
+        cert_path: &str,
+        key_path: &str,
+    ) -> Result<()> {
+        info!("Issuing {:?} certificate for CN: {}", purpose, options.common_name);
+        
+        let ca_pem = fs::read_to_string(ca_cert_path)
+            .map_err(|e| anyhow!("Failed to read CA certificate {}: {}", ca_cert_path, e))?;
+        let ca_key = CertificateManager::new(ca_cert_path.to_string(), ca_key_path.to_string()).load_private_key()?;
+        // Re-signing the CA's own parameters gives rcgen its name and key identifier
+        let ca = CertificateParams::from_ca_cert_pem(&ca_pem)?.self_signed(&ca_key)?;
+        
+        let private_key = options.key_type.generate()?;
+        let mut params = options.params(purpose)?;
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = match purpose {
+            CertificatePurpose::Server => vec![ExtendedKeyUsagePurpose::ServerAuth],
+            CertificatePurpose::Client => vec![ExtendedKeyUsagePurpose::ClientAuth],
+        };
+        params.use_authority_key_identifier_extension = true;
+        let certificate = params.signed_by(&private_key, &ca, &ca_key)?;
+        
+        Self::write_certificate(&certificate, &private_key, cert_path, key_path)?;
+        info!("Certificate issued successfully");
+        Ok(())
+    }
+}
+
+// Subject, names and lifetime of a generated certificate
+#[derive(Debug, Clone)]
+pub struct CertificateOptions {
+    pub common_name: String,
+    pub organization: Option<String>,
+    pub organizational_unit: Option<String>,
+    // Two-letter country code
+    pub country: Option<String>,
+    pub dns_names: Vec<String>,
+    pub ip_addresses: Vec<IpAddr>,
+    pub validity_days: u32,
+    pub key_type: KeyType,
+}
+
+impl CertificateOptions {
+    pub fn new(common_name: &str) -> Self {
+        Self {
+            common_name: common_name.to_string(),
+            organization: None,
+            organizational_unit: None,
+            country: None,
+            dns_names: vec![],
+            ip_addresses: vec![],
+            validity_days: 365,
+            key_type: KeyType::default(),
+        }
+    }
+
+    // TLS clients match host names against the SANs only, never the CN, so
+    // server certificates without explicit names get the CN (and the loopback
+    // addresses for localhost). Client certificates only carry what is given
+    fn subject_alt_names(&self, purpose: CertificatePurpose) -> Vec<String> {
+        let explicit: Vec<String> = self
+            .dns_names
+            .iter()
+            .cloned()
+            .chain(self.ip_addresses.iter().map(IpAddr::to_string))
+            .collect();
+        match purpose {
+            CertificatePurpose::Server if explicit.is_empty() && self.common_name == "localhost" => {
+                vec![self.common_name.clone(), "127.0.0.1".to_string(), "::1".to_string()]
+            }
+            CertificatePurpose::Server if explicit.is_empty() => vec![self.common_name.clone()],
+            _ => explicit,
+        }
+    }
+
+    fn params(&self, purpose: CertificatePurpose) -> Result<CertificateParams> {
+        if self.validity_days == 0 {
+            return Err(anyhow!("Certificate validity must be at least one day"));
+        }
+        if self.country.as_ref().is_some_and(|c| c.len() != 2 || !c.chars().all(|c| c.is_ascii_alphabetic())) {
+            return Err(anyhow!("Country must be a two-letter code"));
+        }
+        let mut params = CertificateParams::new(self.subject_alt_names(purpose))?;
+        let mut distinguished_name = DistinguishedName::new();
+        distinguished_name.push(DnType::CommonName, self.common_name.as_str());
+        if let Some(organization) = &self.organization {
+            distinguished_name.push(DnType::OrganizationName, organization.as_str());
+        }
+        if let Some(organizational_unit) = &self.organizational_unit {
+            distinguished_name.push(DnType::OrganizationalUnitName, organizational_unit.as_str());
+        }
+        if let Some(country) = &self.country {
+            distinguished_name.push(DnType::CountryName, country.to_ascii_uppercase());
+        }
+        params.distinguished_name = distinguished_name;
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = params.not_before + Duration::days(i64::from(self.validity_days));
+        Ok(params)
+    }
+}
+
+// RSA keys are still served when supplied, but can't be generated without
+// OpenSSL. Read through parse, so a config still asking for rsa2048 fails to
+// load with the reason instead of as an unknown variant
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", try_from = "String")]
+pub enum KeyType {
+    #[default]
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+}
+
+impl KeyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyType::EcdsaP256 => "ecdsa-p256",
+            KeyType::EcdsaP384 => "ecdsa-p384",
+            KeyType::Ed25519 => "ed25519",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        if name.starts_with("rsa") {
+            return Err(anyhow!(
+                "Key type '{}' can no longer be generated; use ecdsa-p256, or supply an RSA certificate and key in cert_path and key_path",
+                name
+            ));
+        }
+        [KeyType::EcdsaP256, KeyType::EcdsaP384, KeyType::Ed25519]
+            .into_iter()
+            .find(|key_type| key_type.as_str() == name)
+            .ok_or_else(|| anyhow!("Unknown key type '{}' (ecdsa-p256, ecdsa-p384 or ed25519)", name))
+    }
+
+    pub fn algorithm(&self) -> &'static SignatureAlgorithm {
+        match self {
+            KeyType::EcdsaP256 => &rcgen::PKCS_ECDSA_P256_SHA256,
+            KeyType::EcdsaP384 => &rcgen::PKCS_ECDSA_P384_SHA384,
+            KeyType::Ed25519 => &rcgen::PKCS_ED25519,
+        }
+    }
+
+    pub fn generate(&self) -> Result<KeyPair> {
+        Ok(KeyPair::generate_for(self.algorithm())?)
+    }
+}
+
+impl TryFrom<String> for KeyType {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        Self::parse(&name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CertificatePurpose {
+    Server,
+    Client,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -105,3 +291,82 @@ pub struct CertificateInfo {
     pub serial_number: String,
     pub sha256_fingerprint: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::client::{ServerCertVerifier, WebPkiVerifier};
+
+    fn der(path: &std::path::Path) -> rustls::Certificate {
+        let pem = fs::read(path).unwrap();
+        rustls::Certificate(rustls_pemfile::certs(&mut pem.as_slice()).unwrap().remove(0))
+    }
+
+    #[test]
+    fn test_issued_certificates_chain_to_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        CertificateManager::generate_ca("aptg test CA", KeyType::EcdsaP384, &path("ca.pem"), &path("ca.key")).unwrap();
+        CertificateManager::issue_certificate(&path("ca.pem"), &path("ca.key"), "localhost", CertificatePurpose::Server, KeyType::EcdsaP256, &path("server.pem"), &path("server.key")).unwrap();
+        CertificateManager::issue_certificate(&path("ca.pem"), &path("ca.key"), "builder-01", CertificatePurpose::Client, KeyType::Ed25519, &path("client.pem"), &path("client.key")).unwrap();
+        
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&der(dir.path().join("ca.pem").as_path())).unwrap();
+        let server = der(dir.path().join("server.pem").as_path());
+        let verifier = WebPkiVerifier::new(roots.clone(), None);
+        let localhost = rustls::ServerName::try_from("localhost").unwrap();
+        verifier.verify_server_cert(&server, &[], &localhost, &mut std::iter::empty(), &[], std::time::SystemTime::now()).unwrap();
+        
+        let client = der(dir.path().join("client.pem").as_path());
+        let client_verifier = rustls::server::AllowAnyAuthenticatedClient::new(roots);
+        rustls::server::ClientCertVerifier::verify_client_cert(&client_verifier, &client, &[], std::time::SystemTime::now()).unwrap();
+        // A client certificate can't stand in for the server
+        assert!(verifier.verify_server_cert(&client, &[], &localhost, &mut std::iter::empty(), &[], std::time::SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn test_self_signed_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("server.pem");
+        let key_path = dir.path().join("server.key");
+        let options = CertificateOptions {
+            organization: Some("Example Org".to_string()),
+            country: Some("de".to_string()),
+            dns_names: vec!["mirror.example.org".to_string(), "*.mirror.example.org".to_string()],
+            ip_addresses: vec!["192.0.2.10".parse().unwrap()],
+            validity_days: 30,
+            key_type: KeyType::EcdsaP384,
+            ..CertificateOptions::new("mirror.example.org")
+        };
+        CertificateManager::generate_self_signed_cert_with_options(&options, cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+        
+        let certificate = der(&cert_path);
+        let (_, parsed) = x509_parser::parse_x509_certificate(&certificate.0).unwrap();
+        let sans = crate::tls::identity::ClientIdentity::from_der(&certificate.0).unwrap().sans;
+        assert_eq!(sans, vec!["mirror.example.org", "*.mirror.example.org", "192.0.2.10"]);
+        assert_eq!(parsed.subject().to_string(), "CN=mirror.example.org, O=Example Org, C=DE");
+        let days = parsed.validity().time_to_expiration().unwrap().whole_days();
+        assert!((29..=30).contains(&days));
+        
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&certificate).unwrap();
+        let verifier = WebPkiVerifier::new(roots, None);
+        for name in ["cdn.mirror.example.org", "192.0.2.10"] {
+            let server_name = rustls::ServerName::try_from(name).unwrap();
+            verifier.verify_server_cert(&certificate, &[], &server_name, &mut std::iter::empty(), &[], std::time::SystemTime::now()).unwrap();
+        }
+        assert!(CertificateOptions { validity_days: 0, ..options }.params(CertificatePurpose::Server).is_err());
+    }
+
+    #[test]
+    fn test_rsa_key_type_is_rejected() {
+        #[derive(Deserialize)]
+        struct Acme {
+            key_type: KeyType,
+        }
+        assert_eq!(toml::from_str::<Acme>("key_type = \"ecdsa-p384\"").unwrap().key_type, KeyType::EcdsaP384);
+        let error = toml::from_str::<Acme>("key_type = \"rsa2048\"").err().unwrap();
+        assert!(error.to_string().contains("can no longer be generated"));
+        assert!(KeyType::parse("rsa4096").is_err());
+    }
+}
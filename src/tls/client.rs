@@ -4,6 +4,9 @@ use std::fs::File;
 use std::io::BufReader;
 use rustls::{ClientConfig, RootCertStore};
 use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::tls::pinning::{default_roots, parse_pin, PinnedCertVerifier};
 use tracing::{info, warn, error};
 
 pub struct TlsClientConfig {
@@ -12,6 +15,8 @@ pub struct TlsClientConfig {
     pub client_key_path: Option<String>,
     pub verify_hostname: bool,
     pub min_tls_version: rustls::ProtocolVersion,
+    // Host -> accepted public keys (base64 SHA-256 of the SPKI)
+    pub pinned_spki: HashMap<String, Vec<String>>,
 }
 
 impl Default for TlsClientConfig {
@@ -22,6 +27,7 @@ impl Default for TlsClientConfig {
             client_key_path: None,
             verify_hostname: true,
             min_tls_version: rustls::ProtocolVersion::TLSv1_2,
+            pinned_spki: HashMap::new(),
         }
     }
 }
@@ -79,6 +85,15 @@ impl TlsClient {
             client_builder = client_builder.identity(identity);
         }
 
+        // Pinning needs a verifier reqwest doesn't expose, so the rustls config
+        // is built here and replaces the CA and identity set above
+        if !config.pinned_spki.is_empty() {
+            if !config.verify_hostname {
+                return Err(anyhow!("SPKI pinning requires certificate verification"));
+            }
+            client_builder = client_builder.use_preconfigured_tls(Self::pinned_tls_config(config)?);
+        }
+        
         // Configure hostname verification
         if !config.verify_hostname {
             warn!("Hostname verification disabled - this is insecure!");
@@ -93,6 +108,44 @@ impl TlsClient {
         Ok(client)
     }
 
+    fn pinned_tls_config(config: &TlsClientConfig) -> Result<ClientConfig> {
+        let pins = config.pinned_spki
+            .iter()
+            .map(|(host, pins)| Ok((host.clone(), pins.iter().map(|pin| parse_pin(pin)).collect::<Result<Vec<_>>>()?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let mut roots = default_roots();
+        if let Some(ref ca_cert_path) = config.ca_cert_path {
+            let mut reader = BufReader::new(File::open(ca_cert_path)
+                .map_err(|e| anyhow!("Failed to read CA certificate: {}", e))?);
+            for cert in certs(&mut reader)? {
+                roots.add(&rustls::Certificate(cert))
+                    .map_err(|e| anyhow!("Failed to parse CA certificate: {}", e))?;
+            }
+        }
+        
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(roots, pins)));
+        let tls_config = match (&config.client_cert_path, &config.client_key_path) {
+            (Some(client_cert_path), Some(client_key_path)) => {
+                let cert_chain = certs(&mut BufReader::new(File::open(client_cert_path)
+                    .map_err(|e| anyhow!("Failed to read client certificate: {}", e))?))?
+                    .into_iter()
+                    .map(rustls::Certificate)
+                    .collect();
+                let key = pkcs8_private_keys(&mut BufReader::new(File::open(client_key_path)
+                    .map_err(|e| anyhow!("Failed to read client private key: {}", e))?))?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("No PKCS#8 private key in {}", client_key_path))?;
+                builder.with_client_auth_cert(cert_chain, rustls::PrivateKey(key))
+                    .map_err(|e| anyhow!("Failed to create client identity: {}", e))?
+            }
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(tls_config)
+    }
+
     pub fn get_client(&self) -> &Client {
         &self.client
     }
@@ -160,6 +213,7 @@ pub fn create_secure_client_config() -> TlsClientConfig {
         client_key_path: Some("certs/client.key".to_string()),
         verify_hostname: true,
         min_tls_version: rustls::ProtocolVersion::TLSv1_3,
+        pinned_spki: HashMap::new(),
     }
 }
 
@@ -170,6 +224,7 @@ pub fn create_insecure_client_config() -> TlsClientConfig {
         client_key_path: None,
         verify_hostname: false,
         min_tls_version: rustls::ProtocolVersion::TLSv1_2,
+        pinned_spki: HashMap::new(),
     }
 }
 
@@ -0,0 +1,164 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use crate::audit::log::AuditLogger;
+use crate::metrics::registry::Metrics;
+use crate::notify::webhook::{NotificationKind, Notifier};
+use crate::tls::simple_server::TlsServerConfig;
+
+// Renewals and reloads are picked up within this long
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateStatus {
+    pub path: String,
+    pub not_after: Option<DateTime<Utc>>,
+    pub days_remaining: Option<f64>,
+    // Within the warning threshold, expired or unreadable
+    pub expiring: bool,
+    pub error: Option<String>,
+}
+
+pub fn read_not_after(cert_path: &str) -> Result<DateTime<Utc>> {
+    let pem = std::fs::read(cert_path).map_err(|e| anyhow!("Failed to read {}: {}", cert_path, e))?;
+    let der = rustls_pemfile::certs(&mut pem.as_slice())?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No certificates found in {}", cert_path))?;
+    not_after(&der, cert_path)
+}
+
+fn not_after(der: &[u8], cert_path: &str) -> Result<DateTime<Utc>> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| anyhow!("Failed to parse {}: {}", cert_path, e))?;
+    DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| anyhow!("Invalid expiry date in {}", cert_path))
+}
+
+// Tracks how long the served certificates have left, for /healthz, the
+// expiry gauge and warning audit events
+pub struct ExpiryMonitor {
+    config: Arc<TlsServerConfig>,
+    audit: Arc<AuditLogger>,
+    notifier: Arc<Notifier>,
+    status: RwLock<Vec<CertificateStatus>>,
+    // Whole days remaining at the last warning, so each certificate warns once a day
+    warned: Mutex<HashMap<String, i64>>,
+}
+
+impl ExpiryMonitor {
+    pub fn new(config: Arc<TlsServerConfig>, audit: Arc<AuditLogger>, notifier: Arc<Notifier>) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            audit,
+            notifier,
+            status: RwLock::new(Vec::new()),
+            warned: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn status(&self) -> Vec<CertificateStatus> {
+        self.status.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub async fn check(&self) {
+        let threshold = f64::from(self.config.expiry_warning_days);
+        let default = match &self.config.pkcs12 {
+            Some(pkcs12) => (&pkcs12.path, pkcs12.load().and_then(|(chain, _)| not_after(&chain[0].0, &pkcs12.path))),
+            None => (&self.config.cert_path, read_not_after(&self.config.cert_path)),
+        };
+        let sni = self.config.certificates.iter().map(|sni| (&sni.cert_path, read_not_after(&sni.cert_path)));
+        let mut statuses = Vec::new();
+        for (path, not_after) in std::iter::once(default).chain(sni) {
+            let status = match not_after {
+                Ok(not_after) => {
+                    let days = (not_after - Utc::now()).num_seconds() as f64 / 86400.0;
+                    Metrics::global().certificate_expiry_days.with_label_values(&[path]).set(days);
+                    CertificateStatus {
+                        path: path.clone(),
+                        not_after: Some(not_after),
+                        days_remaining: Some(days),
+                        expiring: days < threshold,
+                        error: None,
+                    }
+                }
+                Err(e) => CertificateStatus {
+                    path: path.clone(),
+                    not_after: None,
+                    days_remaining: None,
+                    expiring: true,
+                    error: Some(e.to_string()),
+                },
+            };
+            if let Some(days) = status.days_remaining.filter(|_| status.expiring) {
+                self.warn_once_a_day(path, days.floor() as i64).await;
+            }
+            statuses.push(status);
+        }
+        
+        *self.status.write().unwrap_or_else(|e| e.into_inner()) = statuses;
+    }
+
+    async fn warn_once_a_day(&self, path: &str, days: i64) {
+        let previous = self.warned.lock().unwrap_or_else(|e| e.into_inner()).insert(path.to_string(), days);
+        if previous != Some(days) {
+            self.audit.log_certificate_expiring(path, days).await;
+            self.notifier.notify(NotificationKind::CertificateExpiring, path, format!("{} expires in {} days", path, days));
+        }
+    }
+
+    pub fn spawn(self: &Arc<Self>) {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            loop {
+                monitor.check().await;
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::store::AuditQuery;
+    use crate::tls::certificate_simple::CertificateManager;
+
+    #[tokio::test]
+    async fn test_expiring_certificate_warns_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("server.pem").to_str().unwrap().to_string();
+        let key_path = dir.path().join("server.key").to_str().unwrap().to_string();
+        CertificateManager::generate_self_signed_cert("localhost", &cert_path, &key_path).unwrap();
+        let mut audit_config = crate::audit::log::AuditConfig::default();
+        audit_config.store.enabled = true;
+        audit_config.store.path = dir.path().join("audit.db").to_str().unwrap().to_string();
+        let audit = Arc::new(AuditLogger::from_config(&audit_config));
+        // Generated certificates are valid for a year
+        let config = TlsServerConfig { cert_path: cert_path.clone(), key_path, expiry_warning_days: 400, ..Default::default() };
+        let monitor = ExpiryMonitor::new(Arc::new(config), audit.clone(), Arc::new(Notifier::new(Default::default())));
+        
+        monitor.check().await;
+        monitor.check().await;
+        let status = monitor.status();
+        assert_eq!(status.len(), 1);
+        assert!(status[0].expiring);
+        assert!(status[0].days_remaining.unwrap() > 364.0);
+        let events = audit.query_events(&AuditQuery::default()).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].path, cert_path);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_certificate_is_reported() {
+        let config = TlsServerConfig { cert_path: "/nonexistent/server.pem".to_string(), ..Default::default() };
+        let monitor = ExpiryMonitor::new(Arc::new(config), Arc::new(AuditLogger::new()), Arc::new(Notifier::new(Default::default())));
+        monitor.check().await;
+        let status = monitor.status();
+        assert!(status[0].expiring);
+        assert!(status[0].error.as_deref().unwrap().contains("/nonexistent/server.pem"));
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use x509_parser::prelude::*;
+
+// The certificate a client presented on a mutual TLS connection. Only
+// certificates that chained to the configured CA get this far
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClientIdentity {
+    // Common name of the certificate subject, or the whole subject when it has none
+    pub subject: String,
+    // SHA-256 of the DER certificate, lowercase hex
+    pub fingerprint: String,
+    // DNS names, e-mail addresses, URIs and IP addresses from subjectAltName
+    pub sans: Vec<String>,
+    pub organizational_units: Vec<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let (_, certificate) = X509Certificate::from_der(der)
+            .map_err(|e| anyhow!("Failed to parse client certificate: {}", e))?;
+        let subject = certificate
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| certificate.subject().to_string());
+        let sans = match certificate.subject_alternative_name() {
+            Ok(Some(extension)) => extension.value.general_names.iter().filter_map(general_name).collect(),
+            _ => Vec::new(),
+        };
+        let organizational_units = certificate
+            .subject()
+            .iter_organizational_unit()
+            .filter_map(|ou| ou.as_str().ok())
+            .map(str::to_string)
+            .collect();
+        
+        Ok(Self {
+            subject,
+            fingerprint: hex::encode(Sha256::digest(der)),
+            sans,
+            organizational_units,
+        })
+    }
+
+    // The leaf certificate of a completed handshake, if the client sent one
+    pub fn from_connection(connection: &rustls::ServerConnection) -> Option<Self> {
+        let certificate = connection.peer_certificates()?.first()?;
+        Self::from_der(&certificate.0).ok()
+    }
+}
+
+fn general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(value) | GeneralName::RFC822Name(value) | GeneralName::URI(value) => Some(value.to_string()),
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => Some(IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?).to_string()),
+            16 => Some(IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?).to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::certificate_simple::CertificateManager;
+
+    #[test]
+    fn test_identity_from_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("client.pem");
+        let key_path = dir.path().join("client.key");
+        CertificateManager::generate_self_signed_cert("builder-01", cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+        let pem = std::fs::read(&cert_path).unwrap();
+        let der = rustls_pemfile::certs(&mut pem.as_slice()).unwrap().remove(0);
+        
+        let identity = ClientIdentity::from_der(&der).unwrap();
+        assert_eq!(identity.subject, "builder-01");
+        assert_eq!(identity.fingerprint, hex::encode(Sha256::digest(&der)));
+        assert_eq!(identity.sans, vec!["builder-01".to_string()]);
+        assert!(identity.organizational_units.is_empty());
+        assert!(ClientIdentity::from_der(b"not a certificate").is_err());
+    }
+}
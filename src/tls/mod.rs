@@ -1,3 +1,10 @@
+pub mod acme;
 pub mod certificate_simple;
-pub mod simple_server;
 pub mod client;
+pub mod expiry;
+pub mod identity;
+pub mod pinning;
+pub mod pkcs11;
+pub mod pkcs12;
+pub mod reload;
+pub mod simple_server;
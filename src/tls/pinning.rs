@@ -0,0 +1,128 @@
+use anyhow::{Result, anyhow};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::warn;
+use x509_parser::prelude::*;
+
+pub type SpkiHash = [u8; 32];
+
+// Pins use the HPKP pin-sha256 form: base64 of the SHA-256 of the DER
+// SubjectPublicKeyInfo, as printed by
+// openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64
+pub fn parse_pin(pin: &str) -> Result<SpkiHash> {
+    let encoded = pin.strip_prefix("sha256/").unwrap_or(pin);
+    STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid SPKI pin '{}': expected base64 of a SHA-256 hash", pin))
+}
+
+pub fn spki_hash(der: &[u8]) -> Result<SpkiHash> {
+    let (_, certificate) = X509Certificate::from_der(der)
+        .map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+    Ok(Sha256::digest(certificate.public_key().raw).into())
+}
+
+// The same public roots reqwest trusts by default
+pub fn default_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+    roots
+}
+
+// Verifies the chain as usual, then requires one of the pinned keys for hosts
+// that have pins. Pinning an intermediate the server sends survives leaf renewals
+pub struct PinnedCertVerifier {
+    inner: WebPkiVerifier,
+    // Host name -> accepted keys
+    pins: HashMap<String, Vec<SpkiHash>>,
+}
+
+impl PinnedCertVerifier {
+    pub fn new(roots: RootCertStore, pins: HashMap<String, Vec<SpkiHash>>) -> Self {
+        Self { inner: WebPkiVerifier::new(roots, None), pins }
+    }
+}
+
+fn chain_matches(pins: &[SpkiHash], end_entity: &Certificate, intermediates: &[Certificate]) -> bool {
+    std::iter::once(end_entity)
+        .chain(intermediates)
+        .filter_map(|certificate| spki_hash(&certificate.0).ok())
+        .any(|hash| pins.contains(&hash))
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => return Ok(verified),
+        };
+        match self.pins.get(&host) {
+            Some(pins) if !chain_matches(pins, end_entity, intermediates) => {
+                warn!("Certificate chain for {} matches none of its pinned keys", host);
+                Err(rustls::Error::General(format!("No pinned public key in certificate chain for {}", host)))
+            }
+            _ => Ok(verified),
+        }
+    }
+}
+
+// Client config for reqwest's use_preconfigured_tls, pinning the given hosts
+pub fn pinned_client_config(pins: HashMap<String, Vec<SpkiHash>>) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(default_roots(), pins)))
+        .with_no_client_auth()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::certificate_simple::CertificateManager;
+
+    #[test]
+    fn test_parse_pin() {
+        let pin = STANDARD.encode([7u8; 32]);
+        assert_eq!(parse_pin(&pin).unwrap(), [7u8; 32]);
+        assert_eq!(parse_pin(&format!("sha256/{}", pin)).unwrap(), [7u8; 32]);
+        assert!(parse_pin("not base64!").is_err());
+        assert!(parse_pin(&STANDARD.encode([7u8; 20])).is_err());
+    }
+
+    #[test]
+    fn test_chain_matches_pinned_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("server.pem").to_str().unwrap().to_string();
+        let key_path = dir.path().join("server.key").to_str().unwrap().to_string();
+        CertificateManager::generate_self_signed_cert("deb.debian.org", &cert_path, &key_path).unwrap();
+        let pem = std::fs::read(&cert_path).unwrap();
+        let certificate = Certificate(rustls_pemfile::certs(&mut pem.as_slice()).unwrap().remove(0));
+        
+        // Same hash as over the key's own DER SubjectPublicKeyInfo
+        let key = CertificateManager::new(cert_path.clone(), key_path.clone()).load_private_key().unwrap();
+        let expected: SpkiHash = Sha256::digest(key.public_key_der()).into();
+        assert_eq!(spki_hash(&certificate.0).unwrap(), expected);
+        
+        assert!(chain_matches(&[[0u8; 32], expected], &certificate, &[]));
+        assert!(!chain_matches(&[[0u8; 32]], &certificate, &[]));
+    }
+}
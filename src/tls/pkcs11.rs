@@ -0,0 +1,262 @@
+use anyhow::{Result, anyhow};
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{Attribute, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use rustls::sign::{CertifiedKey, Signer, SigningKey};
+use rustls::{Certificate, SignatureAlgorithm, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::info;
+use x509_parser::prelude::*;
+use x509_parser::public_key::PublicKey;
+
+// A private key held by an HSM, TPM or smart card, used through its PKCS#11
+// module. Only signatures leave the token; the certificate chain still comes
+// from cert_path. The PIN is read from an environment variable or a file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pkcs11Config {
+    // e.g. /usr/lib/softhsm/libsofthsm2.so or /usr/lib/x86_64-linux-gnu/libtpm2_pkcs11.so
+    pub module: String,
+    // The first token found is used when unset
+    pub token_label: Option<String>,
+    // CKA_LABEL and/or CKA_ID (hex) of the private key
+    pub key_label: Option<String>,
+    pub key_id: Option<String>,
+    pub pin_env: Option<String>,
+    pub pin_file: Option<String>,
+}
+
+// Modules can only be initialized once per process, so reloads reuse them
+fn modules() -> &'static Mutex<HashMap<String, Pkcs11>> {
+    static MODULES: OnceLock<Mutex<HashMap<String, Pkcs11>>> = OnceLock::new();
+    MODULES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+impl Pkcs11Config {
+    pub fn pin(&self) -> Result<String> {
+        if let Some(variable) = &self.pin_env {
+            return std::env::var(variable)
+                .map_err(|_| anyhow!("PKCS#11 PIN variable {} is not set", variable));
+        }
+        if let Some(pin_file) = &self.pin_file {
+            let pin = std::fs::read_to_string(pin_file)
+                .map_err(|e| anyhow!("Failed to read PKCS#11 PIN file {}: {}", pin_file, e))?;
+            return Ok(pin.trim_end_matches(['\r', '\n']).to_string());
+        }
+        Err(anyhow!("PKCS#11 needs pin_env or pin_file"))
+    }
+
+    fn context(&self) -> Result<Pkcs11> {
+        let mut modules = modules().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(context) = modules.get(&self.module) {
+            return Ok(context.clone());
+        }
+        let context = Pkcs11::new(&self.module)
+            .map_err(|e| anyhow!("Failed to load PKCS#11 module {}: {}", self.module, e))?;
+        context
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(|e| anyhow!("Failed to initialize PKCS#11 module {}: {}", self.module, e))?;
+        modules.insert(self.module.clone(), context.clone());
+        Ok(context)
+    }
+
+    fn open_session(&self) -> Result<(Session, ObjectHandle)> {
+        let context = self.context()?;
+        let slots = context.get_slots_with_token()?;
+        let slot = match &self.token_label {
+            Some(label) => slots
+                .into_iter()
+                .find(|slot| context.get_token_info(*slot).is_ok_and(|info| info.label().trim() == label))
+                .ok_or_else(|| anyhow!("No PKCS#11 token labelled '{}' in {}", label, self.module))?,
+            None => slots.into_iter().next().ok_or_else(|| anyhow!("No PKCS#11 token in {}", self.module))?,
+        };
+        
+        let session = context.open_ro_session(slot)?;
+        session
+            .login(UserType::User, Some(&AuthPin::new(self.pin()?)))
+            .map_err(|e| anyhow!("PKCS#11 login failed: {}", e))?;
+        let mut template = vec![Attribute::Class(ObjectClass::PRIVATE_KEY)];
+        if let Some(label) = &self.key_label {
+            template.push(Attribute::Label(label.as_bytes().to_vec()));
+        }
+        if let Some(id) = &self.key_id {
+            let id = hex::decode(id).map_err(|_| anyhow!("Invalid PKCS#11 key_id '{}': expected hex", id))?;
+            template.push(Attribute::Id(id));
+        }
+        let key = match session.find_objects(&template)?.as_slice() {
+            [key] => *key,
+            [] => return Err(anyhow!("No matching private key on the PKCS#11 token")),
+            _ => return Err(anyhow!("Several private keys on the PKCS#11 token match; set key_label or key_id")),
+        };
+        Ok((session, key))
+    }
+
+    // The key type is taken from the certificate, which must belong to the token's key
+    pub fn certified_key(&self, chain: Vec<Certificate>) -> Result<Arc<CertifiedKey>> {
+        let leaf = chain.first().ok_or_else(|| anyhow!("No certificate for the PKCS#11 key"))?;
+        let kind = KeyKind::from_certificate(&leaf.0)?;
+        let (session, key) = self.open_session()?;
+        info!("Using {:?} private key from PKCS#11 module {}", kind, self.module);
+        let signing_key = Pkcs11SigningKey { session: Arc::new(Mutex::new(session)), key, kind };
+        Ok(Arc::new(CertifiedKey::new(chain, Arc::new(signing_key))))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Rsa,
+    EcdsaP256,
+    EcdsaP384,
+}
+
+impl KeyKind {
+    fn from_certificate(der: &[u8]) -> Result<Self> {
+        let (_, certificate) = X509Certificate::from_der(der)
+            .map_err(|e| anyhow!("Failed to parse certificate: {}", e))?;
+        match certificate.public_key().parsed() {
+            Ok(PublicKey::RSA(_)) => Ok(Self::Rsa),
+            // Uncompressed points: 0x04 || x || y
+            Ok(PublicKey::EC(point)) if point.data().len() == 65 => Ok(Self::EcdsaP256),
+            Ok(PublicKey::EC(point)) if point.data().len() == 97 => Ok(Self::EcdsaP384),
+            _ => Err(anyhow!("Unsupported key type for PKCS#11 signing; use RSA, P-256 or P-384")),
+        }
+    }
+
+    // In order of preference
+    fn schemes(&self) -> &'static [SignatureScheme] {
+        match self {
+            Self::Rsa => &[
+                SignatureScheme::RSA_PSS_SHA256,
+                SignatureScheme::RSA_PSS_SHA384,
+                SignatureScheme::RSA_PKCS1_SHA256,
+                SignatureScheme::RSA_PKCS1_SHA384,
+                SignatureScheme::RSA_PKCS1_SHA512,
+            ],
+            Self::EcdsaP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+            Self::EcdsaP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+        }
+    }
+}
+
+struct Pkcs11SigningKey {
+    // Sessions are not thread-safe, so handshakes take turns signing
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    kind: KeyKind,
+}
+
+impl SigningKey for Pkcs11SigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        let scheme = self.kind.schemes().iter().find(|scheme| offered.contains(scheme))?;
+        Some(Box::new(Pkcs11Signer { session: self.session.clone(), key: self.key, scheme: *scheme }))
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        match self.kind {
+            KeyKind::Rsa => SignatureAlgorithm::RSA,
+            KeyKind::EcdsaP256 | KeyKind::EcdsaP384 => SignatureAlgorithm::ECDSA,
+        }
+    }
+}
+
+struct Pkcs11Signer {
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    scheme: SignatureScheme,
+}
+
+fn pss(hash_alg: MechanismType, mgf: PkcsMgfType, s_len: u64) -> PkcsPssParams {
+    PkcsPssParams { hash_alg, mgf, s_len: s_len.into() }
+}
+
+impl Signer for Pkcs11Signer {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        // ECDSA is hashed here, since not every token offers the combined mechanisms
+        let (mechanism, data) = match self.scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 => (Mechanism::Ecdsa, Sha256::digest(message).to_vec()),
+            SignatureScheme::ECDSA_NISTP384_SHA384 => (Mechanism::Ecdsa, Sha384::digest(message).to_vec()),
+            SignatureScheme::RSA_PSS_SHA256 => (Mechanism::Sha256RsaPkcsPss(pss(MechanismType::SHA256, PkcsMgfType::MGF1_SHA256, 32)), message.to_vec()),
+            SignatureScheme::RSA_PSS_SHA384 => (Mechanism::Sha384RsaPkcsPss(pss(MechanismType::SHA384, PkcsMgfType::MGF1_SHA384, 48)), message.to_vec()),
+            SignatureScheme::RSA_PKCS1_SHA256 => (Mechanism::Sha256RsaPkcs, message.to_vec()),
+            SignatureScheme::RSA_PKCS1_SHA384 => (Mechanism::Sha384RsaPkcs, message.to_vec()),
+            SignatureScheme::RSA_PKCS1_SHA512 => (Mechanism::Sha512RsaPkcs, message.to_vec()),
+            scheme => return Err(rustls::Error::General(format!("Unsupported signature scheme {:?}", scheme))),
+        };
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let signature = session
+            .sign(&mechanism, self.key, &data)
+            .map_err(|e| rustls::Error::General(format!("PKCS#11 signing failed: {}", e)))?;
+        match self.scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 | SignatureScheme::ECDSA_NISTP384_SHA384 => Ok(ecdsa_der(&signature)),
+            _ => Ok(signature),
+        }
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+}
+
+// PKCS#11 returns ECDSA signatures as r || s; TLS wants a DER
+// SEQUENCE { r INTEGER, s INTEGER }. Short-form lengths suffice up to P-384
+fn ecdsa_der(raw: &[u8]) -> Vec<u8> {
+    let integer = |bytes: &[u8]| {
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+        let bytes = &bytes[start..];
+        let mut encoded = vec![0x02];
+        if bytes[0] & 0x80 != 0 {
+            encoded.extend([bytes.len() as u8 + 1, 0x00]);
+        } else {
+            encoded.push(bytes.len() as u8);
+        }
+        encoded.extend_from_slice(bytes);
+        encoded
+    };
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let body = [integer(r), integer(s)].concat();
+    let mut der = vec![0x30, body.len() as u8];
+    der.extend(body);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::certificate_simple::{CertificateManager, KeyType};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    #[test]
+    fn test_raw_ecdsa_signature_to_der() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let public_key = UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key.public_key().as_ref().to_vec());
+        // Enough signatures that some have r or s with the high bit set or a leading zero
+        for _ in 0..32 {
+            let raw = key.sign(&rng, b"handshake transcript").unwrap();
+            assert!(public_key.verify(b"handshake transcript", &ecdsa_der(raw.as_ref())).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_key_kind_from_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("server.pem").to_str().unwrap().to_string();
+        let key_path = dir.path().join("server.key").to_str().unwrap().to_string();
+        for (key_type, kind) in [(KeyType::EcdsaP256, KeyKind::EcdsaP256), (KeyType::EcdsaP384, KeyKind::EcdsaP384)] {
+            CertificateManager::generate_self_signed_cert_with_key("localhost", key_type, &cert_path, &key_path).unwrap();
+            let der = CertificateManager::new(cert_path.clone(), key_path.clone()).load_certificate().unwrap();
+            assert_eq!(KeyKind::from_certificate(&der).unwrap(), kind);
+        }
+        CertificateManager::generate_self_signed_cert_with_key("localhost", KeyType::Ed25519, &cert_path, &key_path).unwrap();
+        let der = CertificateManager::new(cert_path, key_path).load_certificate().unwrap();
+        assert!(KeyKind::from_certificate(&der).is_err());
+    }
+}
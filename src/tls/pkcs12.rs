@@ -0,0 +1,93 @@
+use anyhow::{Result, anyhow};
+use p12_keystore::KeyStore;
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, PrivateKey};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// A .p12/.pfx bundle holding the server certificate, its chain and key. The
+// password comes from an environment variable or a file, never the config itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pkcs12Config {
+    pub path: String,
+    pub password_env: Option<String>,
+    pub password_file: Option<String>,
+}
+
+impl Pkcs12Config {
+    // Bundles exported without a password use the empty one
+    pub fn password(&self) -> Result<String> {
+        if let Some(variable) = &self.password_env {
+            return std::env::var(variable)
+                .map_err(|_| anyhow!("PKCS#12 password variable {} is not set", variable));
+        }
+        if let Some(password_file) = &self.password_file {
+            let password = std::fs::read_to_string(password_file)
+                .map_err(|e| anyhow!("Failed to read PKCS#12 password file {}: {}", password_file, e))?;
+            return Ok(password.trim_end_matches(['\r', '\n']).to_string());
+        }
+        Ok(String::new())
+    }
+
+    // Leaf first, then the CA certificates in the bundle, plus the PKCS#8 key
+    pub fn load(&self) -> Result<(Vec<Certificate>, PrivateKey)> {
+        let der = std::fs::read(&self.path)
+            .map_err(|e| anyhow!("Failed to read PKCS#12 bundle {}: {}", self.path, e))?;
+        let password = self.password()?;
+        let keystore = KeyStore::from_pkcs12(&der, &password)
+            .map_err(|e| anyhow!("Failed to open PKCS#12 bundle {} (wrong password?): {}", self.path, e))?;
+        
+        let (_, key_chain) = keystore
+            .private_key_chain()
+            .ok_or_else(|| anyhow!("No private key in PKCS#12 bundle {}", self.path))?;
+        let chain: Vec<Certificate> = key_chain.chain().iter().map(|c| Certificate(c.as_der().to_vec())).collect();
+        if chain.is_empty() {
+            return Err(anyhow!("No certificate in PKCS#12 bundle {}", self.path));
+        }
+        Ok((chain, PrivateKey(key_chain.key().to_vec())))
+    }
+
+    pub fn certified_key(&self) -> Result<Arc<CertifiedKey>> {
+        let (chain, key) = self.load()?;
+        let private_key = rustls::sign::any_supported_type(&key)
+            .map_err(|e| anyhow!("Unsupported private key in {}: {}", self.path, e))?;
+        Ok(Arc::new(CertifiedKey::new(chain, private_key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::certificate_simple::{CertificateManager, CertificatePurpose, KeyType};
+    use p12_keystore::{KeyStoreEntry, PrivateKeyChain};
+
+    #[test]
+    fn test_load_bundle_with_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        CertificateManager::generate_ca("aptg test CA", KeyType::EcdsaP384, &path("ca.pem"), &path("ca.key")).unwrap();
+        CertificateManager::issue_certificate(&path("ca.pem"), &path("ca.key"), "localhost", CertificatePurpose::Server, KeyType::EcdsaP256, &path("server.pem"), &path("server.key")).unwrap();
+        let server = CertificateManager::new(path("server.pem"), path("server.key"));
+        let ca = CertificateManager::new(path("ca.pem"), path("ca.key"));
+        let chain = [server.load_certificate().unwrap(), ca.load_certificate().unwrap()]
+            .iter()
+            .map(|der| p12_keystore::Certificate::from_der(der).unwrap())
+            .collect::<Vec<_>>();
+        let key = server.load_private_key().unwrap().serialize_der();
+        let mut keystore = KeyStore::new();
+        keystore.add_entry("aptg", KeyStoreEntry::PrivateKeyChain(PrivateKeyChain::new(key, [1u8; 20], chain)));
+        let bundle = keystore.writer("s3cret").write().unwrap();
+        std::fs::write(path("server.p12"), bundle).unwrap();
+        std::fs::write(path("password"), "s3cret\n").unwrap();
+        
+        let config = Pkcs12Config { path: path("server.p12"), password_env: None, password_file: Some(path("password")) };
+        let (chain, _) = config.load().unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].0, server.load_certificate().unwrap());
+        assert!(config.certified_key().is_ok());
+        
+        let wrong = Pkcs12Config { password_file: None, ..config };
+        assert!(wrong.load().unwrap_err().to_string().contains("wrong password"));
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Result, anyhow};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, error};
+use crate::tls::acme::AcmeChallenges;
+use crate::tls::simple_server::{SharedServerConfig, TlsServer, TlsServerConfig};
+
+pub struct CertReloader {
+    config: Arc<TlsServerConfig>,
+    server_config: SharedServerConfig,
+    challenges: AcmeChallenges,
+}
+
+impl CertReloader {
+    pub fn new(config: Arc<TlsServerConfig>, server_config: SharedServerConfig, challenges: AcmeChallenges) -> Self {
+        Self { config, server_config, challenges }
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let server_config = TlsServer::build_server_config(&self.config, &self.challenges)?;
+        self.server_config.store(Arc::new(server_config));
+        info!("TLS certificate reloaded from {}", self.config.cert_path);
+        Ok(())
+    }
+
+    fn watched_files(&self) -> Result<Vec<(PathBuf, OsString)>> {
+        let pkcs12 = self.config.pkcs12.as_ref();
+        // A token-held key never changes on disk; only its certificate is watched
+        let key_path = Some(&self.config.key_path).filter(|_| self.config.pkcs11.is_none());
+        let paths = [
+            Some(&self.config.cert_path),
+            key_path,
+            self.config.ca_path.as_ref(),
+            pkcs12.map(|p| &p.path),
+            pkcs12.and_then(|p| p.password_file.as_ref()),
+        ];
+        let sni_paths = self.config.certificates.iter().flat_map(|sni| [&sni.cert_path, &sni.key_path]);
+        paths
+            .into_iter()
+            .flatten()
+            .chain(sni_paths)
+            .map(|path| {
+                let path = Path::new(path);
+                let file_name = path.file_name().map(|f| f.to_os_string())
+                    .ok_or_else(|| anyhow!("Invalid certificate path {}", path.display()))?;
+                let directory = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                    _ => Path::new(".").to_path_buf(),
+                };
+                Ok((directory, file_name))
+            })
+            .collect()
+    }
+
+    pub fn spawn(self) -> Result<()> {
+        let files = self.watched_files()?;
+        let file_names: HashSet<OsString> = files.iter().map(|(_, name)| name.clone()).collect();
+        let directories: HashSet<PathBuf> = files.into_iter().map(|(directory, _)| directory).collect();
+        
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let relevant = event.kind.is_modify() || event.kind.is_create();
+                if relevant && event.paths.iter().any(|p| p.file_name().is_some_and(|f| file_names.contains(f))) {
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+        // Watch the directories: renewal tools replace files by rename or swap symlinks
+        for directory in &directories {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        }
+        info!("Watching {} for certificate changes", self.config.cert_path);
+        
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            while rx.recv().await.is_some() {
+                // Certificate and key are usually written one after the other; wait for both
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                while rx.try_recv().is_ok() {}
+                
+                if let Err(e) = self.reload() {
+                    error!("Rejected certificate change, keeping current certificate: {}", e);
+                }
+            }
+        });
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arc_swap::ArcSwap;
+    use crate::tls::certificate_simple::CertificateManager;
+
+    fn reloader(dir: &Path) -> CertReloader {
+        let cert_path = dir.join("server.pem").to_str().unwrap().to_string();
+        let key_path = dir.join("server.key").to_str().unwrap().to_string();
+        CertificateManager::generate_self_signed_cert("localhost", &cert_path, &key_path).unwrap();
+        let config = TlsServerConfig { cert_path, key_path, ..Default::default() };
+        let challenges = AcmeChallenges::default();
+        let server_config = TlsServer::build_server_config(&config, &challenges).unwrap();
+        CertReloader::new(Arc::new(config), Arc::new(ArcSwap::from_pointee(server_config)), challenges)
+    }
+
+    #[test]
+    fn test_reload_swaps_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = reloader(dir.path());
+        let before = reloader.server_config.load_full();
+        
+        CertificateManager::generate_self_signed_cert("localhost", &reloader.config.cert_path, &reloader.config.key_path).unwrap();
+        reloader.reload().unwrap();
+        assert!(!Arc::ptr_eq(&before, &reloader.server_config.load_full()));
+    }
+
+    #[test]
+    fn test_invalid_certificate_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = reloader(dir.path());
+        let before = reloader.server_config.load_full();
+        
+        // A renewal caught halfway, with the certificate truncated
+        std::fs::write(&reloader.config.cert_path, "").unwrap();
+        assert!(reloader.reload().is_err());
+        assert!(Arc::ptr_eq(&before, &reloader.server_config.load_full()));
+    }
+}
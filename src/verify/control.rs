@@ -0,0 +1,203 @@
+use anyhow::{Result, anyhow};
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use xz2::read::XzDecoder;
+use crate::verify::debsig::DebSigVerifier;
+
+// Control files kept for policy checks; about a kilobyte each
+const CONTROL_STORE_CAPACITY: usize = 50_000;
+
+// The control file of a binary package, fields in their original order and case
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebControl {
+    fields: Vec<(String, String)>,
+}
+
+impl DebControl {
+    // Reads ./control from the control.tar member of a .deb
+    pub fn from_deb(data: &[u8]) -> Result<Self> {
+        let members = DebSigVerifier::parse_ar(data)?;
+        let member = members
+            .iter()
+            .find(|member| member.name.starts_with("control.tar"))
+            .ok_or_else(|| anyhow!("Package has no control.tar member"))?;
+        let reader: Box<dyn Read> = match member.name.as_str() {
+            "control.tar.gz" => Box::new(GzDecoder::new(member.data)),
+            "control.tar.xz" => Box::new(XzDecoder::new(member.data)),
+            "control.tar" => Box::new(member.data),
+            name => return Err(anyhow!("Unsupported control member {}", name)),
+        };
+        
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.to_str().map(|path| path.trim_start_matches("./")) == Some("control") {
+                let mut content = String::new();
+                entry.read_to_string(&mut content).map_err(|e| anyhow!("Invalid control file: {}", e))?;
+                return Self::parse(&content);
+            }
+        }
+        Err(anyhow!("control.tar has no control file"))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in content.lines() {
+            if line.trim().is_empty() {
+                break;
+            }
+            if line.starts_with(char::is_whitespace) {
+                // Continuation lines, e.g. the long Description
+                let (_, value) = fields.last_mut().ok_or_else(|| anyhow!("Control file starts with a continuation line"))?;
+                value.push('\n');
+                value.push_str(line);
+                continue;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| anyhow!("Invalid control line: {}", line))?;
+            fields.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        
+        let control = Self { fields };
+        for required in ["Package", "Version", "Architecture"] {
+            if control.get(required).is_none_or(str::is_empty) {
+                return Err(anyhow!("Control file has no {} field", required));
+            }
+        }
+        Ok(control)
+    }
+
+    // Field names are case-insensitive
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(field, _)| field.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    pub fn package(&self) -> &str {
+        self.get("Package").unwrap_or_default()
+    }
+
+    pub fn version(&self) -> &str {
+        self.get("Version").unwrap_or_default()
+    }
+
+    pub fn architecture(&self) -> &str {
+        self.get("Architecture").unwrap_or_default()
+    }
+
+    // Source package name and version; OSV and the security tracker list
+    // vulnerabilities by source package
+    pub fn source(&self) -> (&str, &str) {
+        match self.get("Source") {
+            Some(source) => match source.split_once('(') {
+                Some((name, version)) => (name.trim(), version.trim_end_matches(')').trim()),
+                None => (source.trim(), self.version()),
+            },
+            None => (self.package(), self.version()),
+        }
+    }
+
+    // Package names from Depends and Pre-Depends, alternatives included and
+    // version constraints and architecture qualifiers left out
+    pub fn depends(&self) -> Vec<&str> {
+        ["Pre-Depends", "Depends"]
+            .into_iter()
+            .filter_map(|field| self.get(field))
+            .flat_map(|value| value.split([',', '|']))
+            .filter_map(|relation| relation.split(|c: char| c.is_whitespace() || c == '(' || c == ':').find(|part| !part.is_empty()))
+            .collect()
+    }
+
+    // As a Packages stanza, without the trailing blank line
+    pub fn to_stanza(&self) -> String {
+        self.fields.iter().map(|(name, value)| format!("{}: {}\n", name, value)).collect()
+    }
+}
+
+// Control files of pool packages fetched so far, by cache path, so later
+// requests for them are checked against rules on their fields before being
+// served. The oldest entries are dropped first
+#[derive(Default)]
+pub struct ControlStore {
+    entries: Mutex<ControlEntries>,
+}
+
+#[derive(Default)]
+struct ControlEntries {
+    controls: HashMap<String, Arc<DebControl>>,
+    // Insertion order, oldest first
+    order: VecDeque<String>,
+}
+
+impl ControlStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, path: &str) -> Option<Arc<DebControl>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).controls.get(path).cloned()
+    }
+
+    pub fn insert(&self, path: &str, control: Arc<DebControl>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.controls.insert(path.to_string(), control).is_none() {
+            entries.order.push_back(path.to_string());
+        }
+        while entries.order.len() > CONTROL_STORE_CAPACITY {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.controls.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROL: &str = "Package: hello-internal
+Version: 1:1.2-3
+Architecture: amd64
+Section: utils
+Pre-Depends: dpkg (>= 1.19)
+Depends: libc6:any (>= 2.36), curl | wget, python3
+Description: Internal tool
+ It says hello.
+";
+
+    // A .deb with only the members aptg reads
+    fn deb(control: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(control.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "./control", control.as_bytes()).unwrap();
+        let control_tar = builder.into_inner().unwrap();
+        
+        let mut archive = b"!<arch>\n".to_vec();
+        for (name, data) in [("debian-binary", b"2.0\n".as_slice()), ("control.tar", control_tar.as_slice()), ("data.tar", b"".as_slice())] {
+            archive.extend_from_slice(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, data.len()).as_bytes());
+            archive.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                archive.push(b'\n');
+            }
+        }
+        archive
+    }
+
+    #[test]
+    fn test_control_from_deb() {
+        let control = DebControl::from_deb(&deb(CONTROL)).unwrap();
+        assert_eq!(control.package(), "hello-internal");
+        assert_eq!(control.get("section"), Some("utils"));
+        assert_eq!(control.to_stanza(), CONTROL);
+        assert_eq!(control.depends(), vec!["dpkg", "libc6", "curl", "wget", "python3"]);
+        assert_eq!(control.source(), ("hello-internal", "1:1.2-3"));
+        let binary = DebControl::parse("Package: libssl3\nSource: openssl (3.0.11-1~deb12u2)\nVersion: 3.0.11-1~deb12u2+b1\nArchitecture: amd64\n").unwrap();
+        assert_eq!(binary.source(), ("openssl", "3.0.11-1~deb12u2"));
+        
+        assert!(DebControl::parse("Package: x\nVersion: 1\n").is_err());
+        assert!(DebControl::from_deb(b"!<arch>\n").is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::{Result, anyhow};
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::Command;
+use std::sync::Arc;
+use tracing::info;
+use crate::verify::gpg::GpgVerifier;
+use crate::verify::keyring::VerificationConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DebSigMethod {
+    // Policy-based verification through the debsig-verify tool
+    #[default]
+    DebsigVerify,
+    // Native check of the _gpgbuilder member written by dpkg-sig
+    DpkgSig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebSignatureRule {
+    pub repository: String,
+    #[serde(default)]
+    pub method: DebSigMethod,
+    pub keyring_path: Option<String>,
+    #[serde(default)]
+    pub signed_by: Vec<String>,
+}
+
+impl DebSignatureRule {
+    // debsig-verify picks keys through its own policies under /etc/debsig, so
+    // a keyring or key pin here would be silently ignored
+    pub fn validate(&self) -> Result<()> {
+        if self.method == DebSigMethod::DebsigVerify && (self.keyring_path.is_some() || !self.signed_by.is_empty()) {
+            return Err(anyhow!(
+                "deb_signatures for '{}': keyring_path and signed_by need method = \"dpkg-sig\"; debsig-verify uses its policies",
+                self.repository
+            ));
+        }
+        for pin in &self.signed_by {
+            GpgVerifier::parse_pin(pin).map_err(|e| anyhow!("deb_signatures for '{}': {}", self.repository, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArMember<'a> {
+    pub name: String,
+    pub data: &'a [u8],
+}
+
+pub struct DebSigVerifier {
+    rules: Vec<(DebSignatureRule, Arc<GpgVerifier>)>,
+}
+
+impl DebSigVerifier {
+    pub fn from_config(config: &VerificationConfig) -> Self {
+        let rules = config
+            .deb_signatures
+            .iter()
+            .map(|rule| {
+                let keyring_path = rule.keyring_path.as_deref().unwrap_or(&config.gpg_keyring_path);
+                info!("Verifying .deb signatures for repository '{}' with {:?}", rule.repository, rule.method);
+                let verifier = GpgVerifier::new(keyring_path)
+                    .with_signed_by(&rule.signed_by)
+                    .with_input_mode(config.gpg_input);
+                (rule.clone(), Arc::new(verifier))
+            })
+            .collect();
+        
+        Self { rules }
+    }
+
+    pub fn rule_for(&self, path: &str) -> Option<&DebSignatureRule> {
+        self.find(path).map(|(rule, _)| rule)
+    }
+
+    fn find(&self, path: &str) -> Option<&(DebSignatureRule, Arc<GpgVerifier>)> {
+        if !(path.ends_with(".deb") || path.ends_with(".udeb")) {
+            return None;
+        }
+        
+        let repository = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        self.rules.iter().find(|(rule, _)| rule.repository == repository)
+    }
+
+    // Returns Ok(false) when no rule covers the path
+    pub fn verify(&self, path: &str, data: &[u8]) -> Result<bool> {
+        let Some((rule, gpg_verifier)) = self.find(path) else {
+            return Ok(false);
+        };
+        
+        match rule.method {
+            DebSigMethod::DebsigVerify => Self::run_debsig_verify(data)?,
+            DebSigMethod::DpkgSig => Self::verify_dpkg_sig(gpg_verifier, data)?,
+        }
+        
+        info!("Package signature verified for {}", path);
+        Ok(true)
+    }
+
+    fn run_debsig_verify(data: &[u8]) -> Result<()> {
+        let mut deb_file = tempfile::Builder::new()
+            .prefix("aptg-")
+            .suffix(".deb")
+            .tempfile()
+            .map_err(|e| anyhow!("Failed to create temporary file: {}", e))?;
+        deb_file.write_all(data)?;
+        deb_file.flush()?;
+        
+        let output = Command::new("debsig-verify")
+            .arg("--quiet")
+            .arg(deb_file.path())
+            .output()
+            .map_err(|e| anyhow!("Failed to run debsig-verify: {}", e))?;
+        
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "debsig-verify rejected package (exit {}): {}",
+                output.status.code().unwrap_or(-1),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    fn verify_dpkg_sig(gpg_verifier: &GpgVerifier, data: &[u8]) -> Result<()> {
+        let members = Self::parse_ar(data)?;
+        let signature = members
+            .iter()
+            .find(|m| m.name == "_gpgbuilder")
+            .ok_or_else(|| anyhow!("Package has no dpkg-sig _gpgbuilder signature"))?;
+        
+        let (result, payload) = gpg_verifier.verify_inrelease_payload(signature.data)?;
+        let Some(payload) = payload else {
+            return Err(anyhow!(
+                "Invalid package signature: {}",
+                result.error_message.as_deref().unwrap_or("unknown error")
+            ));
+        };
+        
+        Self::check_file_list(&payload, &members)
+    }
+
+    // The signed payload lists "md5 sha1 size name" for every other member
+    fn check_file_list(payload: &str, members: &[ArMember]) -> Result<()> {
+        let files: Vec<Vec<&str>> = payload
+            .lines()
+            .skip_while(|line| !line.starts_with("Files:"))
+            .skip(1)
+            .take_while(|line| line.starts_with(char::is_whitespace))
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|parts| parts.len() == 4)
+            .collect();
+        
+        for member in members.iter().filter(|m| !m.name.starts_with('_')) {
+            let entry = files
+                .iter()
+                .find(|parts| parts[3] == member.name)
+                .ok_or_else(|| anyhow!("Member {} is not covered by the signature", member.name))?;
+            
+            if entry[2].parse::<usize>().ok() != Some(member.data.len()) {
+                return Err(anyhow!("Size mismatch for member {}", member.name));
+            }
+            if hex::encode(Md5::digest(member.data)) != entry[0].to_lowercase()
+                || hex::encode(Sha1::digest(member.data)) != entry[1].to_lowercase()
+            {
+                return Err(anyhow!("Checksum mismatch for member {}", member.name));
+            }
+        }
+        
+        Ok(())
+    }
+
+    pub fn parse_ar(data: &[u8]) -> Result<Vec<ArMember<'_>>> {
+        let mut rest = data
+            .strip_prefix(b"!<arch>\n".as_slice())
+            .ok_or_else(|| anyhow!("Not an ar archive"))?;
+        let mut members = Vec::new();
+        
+        // Header: name(16) mtime(12) uid(6) gid(6) mode(8) size(10) magic(2)
+        while rest.len() >= 60 {
+            let header = &rest[..60];
+            if &header[58..60] != b"`\n" {
+                return Err(anyhow!("Corrupt ar member header"));
+            }
+            
+            let name = String::from_utf8_lossy(&header[..16]).trim_end().trim_end_matches('/').to_string();
+            let size = std::str::from_utf8(&header[48..58])?
+                .trim()
+                .parse::<usize>()
+                .map_err(|e| anyhow!("Invalid ar member size for {}: {}", name, e))?;
+            
+            let body = &rest[60..];
+            if body.len() < size {
+                return Err(anyhow!("Truncated ar member {}", name));
+            }
+            members.push(ArMember { name, data: &body[..size] });
+            
+            // Members are padded to an even offset
+            let padded = size + size % 2;
+            rest = &body[padded.min(body.len())..];
+        }
+        
+        Ok(members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ar_archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = b"!<arch>\n".to_vec();
+        for (name, data) in members {
+            archive.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 100644, data.len()).as_bytes());
+            archive.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                archive.push(b'\n');
+            }
+        }
+        archive
+    }
+
+    fn file_line(name: &str, data: &[u8]) -> String {
+        format!(
+            "\t{} {} {} {}",
+            hex::encode(Md5::digest(data)),
+            hex::encode(Sha1::digest(data)),
+            data.len(),
+            name
+        )
+    }
+
+    #[test]
+    fn test_parse_ar_members() {
+        let archive = ar_archive(&[("debian-binary", b"2.0\n"), ("control.tar.xz", b"abc")]);
+        let members = DebSigVerifier::parse_ar(&archive).unwrap();
+        
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, "debian-binary");
+        assert_eq!(members[1].data, b"abc");
+        assert!(DebSigVerifier::parse_ar(b"not an archive").is_err());
+    }
+
+    #[test]
+    fn test_dpkg_sig_file_list() {
+        let archive = ar_archive(&[("debian-binary", b"2.0\n"), ("data.tar.xz", b"payload")]);
+        let members = DebSigVerifier::parse_ar(&archive).unwrap();
+        
+        let payload = format!(
+            "Version: 4\nSigner: Builder\nRole: builder\nFiles: \n{}\n{}\n",
+            file_line("debian-binary", b"2.0\n"),
+            file_line("data.tar.xz", b"payload")
+        );
+        assert!(DebSigVerifier::check_file_list(&payload, &members).is_ok());
+        
+        let tampered = payload.replace(&file_line("data.tar.xz", b"payload"), &file_line("data.tar.xz", b"other!!"));
+        assert!(DebSigVerifier::check_file_list(&tampered, &members).is_err());
+    }
+
+    #[test]
+    fn test_rule_selection() {
+        let config = VerificationConfig {
+            deb_signatures: vec![DebSignatureRule {
+                repository: "internal".to_string(),
+                method: DebSigMethod::DpkgSig,
+                keyring_path: None,
+                signed_by: vec![],
+            }],
+            ..VerificationConfig::default()
+        };
+        let verifier = DebSigVerifier::from_config(&config);
+        
+        assert!(verifier.rule_for("/internal/pool/main/t/tool/tool_1.0_amd64.deb").is_some());
+        assert!(verifier.rule_for("/internal/dists/stable/InRelease").is_none());
+        assert!(!verifier.verify("/debian/pool/main/h/hello/hello_2.10-3_amd64.deb", b"").unwrap());
+        
+        let mut rule = config.deb_signatures[0].clone();
+        rule.signed_by = vec!["B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8".to_string()];
+        assert!(rule.validate().is_ok());
+        rule.method = DebSigMethod::DebsigVerify;
+        assert!(rule.validate().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Result, anyhow};
-use std::process::Command;
-use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
 use tracing::{info, warn};
 use serde::{Deserialize, Serialize};
 
@@ -21,64 +22,278 @@ pub struct GpgVerificationResult {
     pub signature_date: String,
     pub trust_level: String,
     pub error_message: Option<String>,
+    // Every good signature gpg reported; narrowed to the pinned ones when signed_by is set
+    pub signatures: Vec<GpgSignature>,
+    pub unexpected_signer: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpgSignature {
+    pub fingerprint: String,
+    pub primary_fingerprint: Option<String>,
+    pub hash_algorithm: Option<String>,
+}
+
+impl GpgVerificationResult {
+    pub fn has_weak_digest(&self) -> bool {
+        self.weak_digest().is_some()
+    }
+
+    // A weak digest on any accepted signature counts, wherever it sits in the file
+    pub fn weak_digest(&self) -> Option<&str> {
+        self.signatures
+            .iter()
+            .filter_map(|s| s.hash_algorithm.as_deref())
+            .find(|algorithm| matches!(*algorithm, "MD5" | "SHA1" | "RIPEMD160"))
+    }
+
+    pub fn signer(&self) -> Option<&str> {
+        self.signatures.first().map(|s| s.fingerprint.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpgInputMode {
+    #[default]
+    TempFile,
+    Stdin,
 }
 
 pub struct GpgVerifier {
     keyring_path: String,
+    additional_keyrings: Vec<String>,
+    keyring_glob: Option<String>,
+    signed_by: Vec<String>,
+    input_mode: GpgInputMode,
 }
 
 impl GpgVerifier {
     pub fn new(keyring_path: &str) -> Self {
         Self {
             keyring_path: keyring_path.to_string(),
+            additional_keyrings: Vec::new(),
+            keyring_glob: None,
+            signed_by: Vec::new(),
+            input_mode: GpgInputMode::default(),
         }
     }
 
+    pub fn with_additional_keyrings(mut self, keyrings: &[String]) -> Self {
+        self.additional_keyrings = keyrings.to_vec();
+        self
+    }
+
+    pub fn with_keyring_glob(mut self, pattern: Option<&str>) -> Self {
+        self.keyring_glob = pattern.map(|p| p.to_string());
+        self
+    }
+
+    // Search order: primary keyring, explicit extras, then glob matches sorted by path.
+    // The glob is expanded on every call so newly dropped-in keyrings are picked up.
+    pub fn keyrings(&self) -> Vec<String> {
+        let mut keyrings = vec![self.keyring_path.clone()];
+        keyrings.extend(self.additional_keyrings.iter().cloned());
+        
+        if let Some(pattern) = &self.keyring_glob {
+            match glob::glob(pattern) {
+                Ok(paths) => {
+                    let mut matched: Vec<String> = paths
+                        .filter_map(|p| p.ok())
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect();
+                    matched.sort();
+                    keyrings.extend(matched);
+                }
+                Err(e) => warn!("Invalid keyring glob {}: {}", pattern, e),
+            }
+        }
+        
+        // Keep the first occurrence so the search order is unchanged
+        let mut seen = std::collections::HashSet::new();
+        keyrings.retain(|k| seen.insert(k.clone()));
+        keyrings
+    }
+
+    pub fn with_input_mode(mut self, input_mode: GpgInputMode) -> Self {
+        self.input_mode = input_mode;
+        self
+    }
+
+    pub fn input_mode(&self) -> GpgInputMode {
+        self.input_mode
+    }
+
+    // Pins are full fingerprints; short or long key IDs can collide
+    pub fn parse_pin(pin: &str) -> Result<String> {
+        let fingerprint = Self::normalize_fingerprint(pin);
+        if fingerprint.len() != 40 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("signed_by '{}' is not a full 40-digit key fingerprint", pin));
+        }
+        Ok(fingerprint)
+    }
+
+    pub fn with_signed_by(mut self, fingerprints: &[String]) -> Self {
+        self.signed_by = fingerprints.iter().map(|f| Self::normalize_fingerprint(f)).collect();
+        self
+    }
+
+    pub fn signed_by(&self) -> &[String] {
+        &self.signed_by
+    }
+
+    pub fn keyring_path(&self) -> &str {
+        &self.keyring_path
+    }
+
     pub fn verify_inrelease(&self, inrelease_data: &[u8]) -> Result<GpgVerificationResult> {
+        self.verify_inrelease_payload(inrelease_data).map(|(result, _)| result)
+    }
+
+    // Returns the signed Release text only when the signature checked out. The
+    // text is what gpg wrote out after verifying it, not a re-parse of the armor
+    pub fn verify_inrelease_payload(&self, inrelease_data: &[u8]) -> Result<(GpgVerificationResult, Option<String>)> {
         info!("Verifying InRelease file with GPG");
+        Self::check_clearsigned(inrelease_data)?;
+        
+        // Temp files are removed on drop
+        let inrelease_file = match self.input_mode {
+            GpgInputMode::TempFile => Some(Self::write_temp_file(inrelease_data, "InRelease")?),
+            GpgInputMode::Stdin => None,
+        };
+        let plaintext = Self::write_temp_file(b"", "Release")?;
+        
+        let result = self.verify_with_keyrings("--decrypt", |command| {
+            command.arg("--yes").arg("--output").arg(plaintext.path());
+            match &inrelease_file {
+                Some(file) => Ok(command.arg(file.path()).output()?),
+                None => Self::run_with_stdin(command.arg("-"), inrelease_data),
+            }
+        })?;
+        if !result.valid {
+            return Ok((result, None));
+        }
         
-        // Write to temporary file
-        let temp_path = "/tmp/inrelease_temp";
-        fs::write(temp_path, inrelease_data)?;
+        let mut payload = String::from_utf8(std::fs::read(plaintext.path())?)
+            .map_err(|e| anyhow!("Signed text is not valid UTF-8: {}", e))?;
+        if !payload.ends_with('\n') {
+            payload.push('\n');
+        }
+        Ok((result, Some(payload)))
+    }
+
+    // Like apt, accepts exactly one clearsigned message: nothing before it, only
+    // Hash armor headers, and nothing but blank lines after the signature
+    pub fn check_clearsigned(data: &[u8]) -> Result<()> {
+        let content = std::str::from_utf8(data)
+            .map_err(|e| anyhow!("Clearsigned message is not valid UTF-8: {}", e))?;
+        let mut lines = content.lines();
+        
+        if lines.next().map(str::trim_end) != Some("-----BEGIN PGP SIGNED MESSAGE-----") {
+            return Err(anyhow!("Data does not start with a clearsigned message"));
+        }
         
-        let output = Command::new("gpg")
-            .arg("--verify")
-            .arg("--verbose")
-            .arg("--keyring")
-            .arg(&self.keyring_path)
-            .arg(temp_path)
-            .output()?;
+        for line in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+            if !line.starts_with("Hash: ") {
+                return Err(anyhow!("Unexpected armor header '{}' in clearsigned message", line));
+            }
+        }
         
-        // Clean up temp file
-        let _ = fs::remove_file(temp_path);
+        // Dash-escaping (RFC 4880 section 7.1) means a signed line never starts with "-----"
+        let mut signature_found = false;
+        for line in lines.by_ref() {
+            if line.trim_end() == "-----BEGIN PGP SIGNATURE-----" {
+                signature_found = true;
+                break;
+            }
+            if line.starts_with("-----") {
+                return Err(anyhow!("Unexpected '{}' inside clearsigned message", line.trim_end()));
+            }
+        }
+        if !signature_found {
+            return Err(anyhow!("Clearsigned message is missing its PGP signature block"));
+        }
         
-        self.parse_gpg_output(&output)
+        if !lines.by_ref().any(|line| line.trim_end() == "-----END PGP SIGNATURE-----") {
+            return Err(anyhow!("Clearsigned message has an unterminated PGP signature block"));
+        }
+        if lines.any(|line| !line.trim().is_empty()) {
+            return Err(anyhow!("Data follows the PGP signature block"));
+        }
+        Ok(())
     }
 
     pub fn verify_release_with_sig(&self, release_data: &[u8], signature_data: &[u8]) -> Result<GpgVerificationResult> {
         info!("Verifying Release file with detached signature");
         
-        // Write to temporary files
-        let release_path = "/tmp/release_temp";
-        let sig_path = "/tmp/release_sig_temp";
-        
-        fs::write(release_path, release_data)?;
-        fs::write(sig_path, signature_data)?;
-        
-        let output = Command::new("gpg")
-            .arg("--verify")
+        // The detached signature carries no plaintext, so it always goes through a file
+        let sig_file = Self::write_temp_file(signature_data, "Release.gpg")?;
+        let release_file = match self.input_mode {
+            GpgInputMode::TempFile => Some(Self::write_temp_file(release_data, "Release")?),
+            GpgInputMode::Stdin => None,
+        };
+        
+        self.verify_with_keyrings("--verify", |command| {
+            command.arg(sig_file.path());
+            match &release_file {
+                Some(file) => Ok(command.arg(file.path()).output()?),
+                None => Self::run_with_stdin(command.arg("-"), release_data),
+            }
+        })
+    }
+
+    // One gpg run sees every keyring, like apt, so signatures spread over
+    // several keyrings are all checked
+    fn verify_with_keyrings<F>(&self, operation: &str, run: F) -> Result<GpgVerificationResult>
+    where
+        F: FnOnce(&mut Command) -> Result<std::process::Output>,
+    {
+        let keyrings = self.keyrings();
+        let output = run(&mut self.verify_command(&keyrings, operation))?;
+        let result = self.parse_gpg_output(&output)?;
+        if result.valid {
+            info!("Signature verified against keyrings {:?}", keyrings);
+        }
+        Ok(result)
+    }
+
+    fn verify_command(&self, keyrings: &[String], operation: &str) -> Command {
+        let mut command = Command::new("gpg");
+        command
+            .arg("--no-default-keyring")
+            .arg("--batch")
+            .arg("--enable-special-filenames")
+            .arg(operation)
             .arg("--verbose")
-            .arg("--keyring")
-            .arg(&self.keyring_path)
-            .arg(sig_path)
-            .arg(release_path)
-            .output()?;
-        
-        // Clean up temp files
-        let _ = fs::remove_file(release_path);
-        let _ = fs::remove_file(sig_path);
+            .arg("--status-fd")
+            .arg("1");
+        for keyring in keyrings {
+            command.arg("--keyring").arg(keyring);
+        }
+        command
+    }
         
-        self.parse_gpg_output(&output)
+    pub(crate) fn run_with_stdin(command: &mut Command, data: &[u8]) -> Result<std::process::Output> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        
+        // Feed stdin from a separate thread so gpg can't block on a full stdout pipe
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("Failed to open gpg stdin"))?;
+        let data = data.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&data));
+        
+        let output = child.wait_with_output()?;
+        writer
+            .join()
+            .map_err(|_| anyhow!("gpg stdin writer panicked"))?
+            .map_err(|e| anyhow!("Failed to stream data to gpg: {}", e))?;
+        Ok(output)
     }
 
     pub fn list_keys(&self) -> Result<Vec<GpgKeyInfo>> {
@@ -97,21 +312,16 @@ impl GpgVerifier {
     pub fn import_key(&self, key_data: &[u8]) -> Result<String> {
         info!("Importing GPG key into keyring");
         
-        // Write to temporary file
-        let temp_path = "/tmp/key_temp.asc";
-        fs::write(temp_path, key_data)?;
+        let key_file = Self::write_temp_file(key_data, "key.asc")?;
         
         let output = Command::new("gpg")
             .arg("--import")
             .arg("--verbose")
             .arg("--keyring")
             .arg(&self.keyring_path)
-            .arg(temp_path)
+            .arg(key_file.path())
             .output()?;
         
-        // Clean up temp file
-        let _ = fs::remove_file(temp_path);
-        
         // Extract key ID from output
         let output_str = String::from_utf8_lossy(&output.stdout);
         if let Some(key_line) = output_str.lines().find(|line| line.contains("imported")) {
@@ -170,6 +380,19 @@ impl GpgVerifier {
         self.import_key(&output.stdout)
     }
 
+    fn write_temp_file(data: &[u8], name: &str) -> Result<NamedTempFile> {
+        // Unique, 0600 files created with O_EXCL so concurrent requests never share
+        // a path and a pre-planted symlink cannot redirect the write
+        let mut file = tempfile::Builder::new()
+            .prefix("aptg-")
+            .suffix(&format!("-{}", name))
+            .tempfile()
+            .map_err(|e| anyhow!("Failed to create temporary file: {}", e))?;
+        file.write_all(data)?;
+        file.flush()?;
+        Ok(file)
+    }
+
     fn parse_gpg_output(&self, output: &std::process::Output) -> Result<GpgVerificationResult> {
         let output_str = String::from_utf8_lossy(&output.stdout);
         let error_str = String::from_utf8_lossy(&output.stderr);
@@ -182,6 +405,8 @@ impl GpgVerifier {
                 signature_date: String::new(),
                 trust_level: "ultimate".to_string(),
                 error_message: None,
+                signatures: Vec::new(),
+                unexpected_signer: false,
             };
             
             // Extract key information from output
@@ -191,9 +416,23 @@ impl GpgVerifier {
                         result.key_id = Some(key_part.trim_end_matches(',').to_string());
                     }
                 }
+                
+                // VALIDSIG <fpr> <date> <timestamp> <expire> <version> <reserved> <pubkey-algo> <hash-algo> ...
+                if let Some(status) = line.strip_prefix("[GNUPG:] VALIDSIG ") {
+                    let fields: Vec<&str> = status.split_whitespace().collect();
+                    if let Some(fingerprint) = fields.first() {
+                        result.signatures.push(GpgSignature {
+                            fingerprint: fingerprint.to_string(),
+                            primary_fingerprint: fields.get(9).map(|f| f.to_string()),
+                            hash_algorithm: fields.get(7)
+                                .and_then(|id| id.parse::<u8>().ok())
+                                .map(|id| Self::hash_algorithm_name(id).to_string()),
+                        });
+                    }
+                }
             }
             
-            Ok(result)
+            Ok(self.enforce_signed_by(result))
         } else {
             // Parse error
             let error_msg = if error_str.is_empty() {
@@ -208,10 +447,62 @@ impl GpgVerifier {
                 signature_date: String::new(),
                 trust_level: "unknown".to_string(),
                 error_message: Some(error_msg),
+                signatures: Vec::new(),
+                unexpected_signer: false,
             })
         }
     }
 
+    fn enforce_signed_by(&self, mut result: GpgVerificationResult) -> GpgVerificationResult {
+        if self.signed_by.is_empty() || !result.valid {
+            return result;
+        }
+        
+        // Pins may name the signing subkey or the primary key. A multi-signed
+        // Release passes when any of its signatures is from a pinned key
+        let pinned = |signature: &GpgSignature| {
+            std::iter::once(&signature.fingerprint)
+                .chain(signature.primary_fingerprint.as_ref())
+                .map(|f| Self::normalize_fingerprint(f))
+                .any(|f| self.signed_by.contains(&f))
+        };
+        
+        if result.signatures.iter().any(pinned) {
+            result.signatures.retain(pinned);
+        } else {
+            let signer = result.signer().unwrap_or("unknown").to_string();
+            warn!("Signature made by unexpected key {} (expected one of {:?})", signer, self.signed_by);
+            result.valid = false;
+            result.unexpected_signer = true;
+            result.error_message = Some(format!("Signed by unexpected key {}", signer));
+        }
+        
+        result
+    }
+
+    fn normalize_fingerprint(fingerprint: &str) -> String {
+        fingerprint
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .trim_start_matches("0x")
+            .to_uppercase()
+    }
+
+    fn hash_algorithm_name(id: u8) -> &'static str {
+        // OpenPGP hash algorithm IDs (RFC 4880 section 9.4)
+        match id {
+            1 => "MD5",
+            2 => "SHA1",
+            3 => "RIPEMD160",
+            8 => "SHA256",
+            9 => "SHA384",
+            10 => "SHA512",
+            11 => "SHA224",
+            _ => "UNKNOWN",
+        }
+    }
+
     fn parse_key_list(&self, output: &std::process::Output) -> Result<Vec<GpgKeyInfo>> {
         let output_str = String::from_utf8_lossy(&output.stdout);
         let mut keys = Vec::new();
@@ -249,6 +540,8 @@ impl GpgVerifier {
         let output = Command::new("gpg")
             .arg("--verify")
             .arg("--verbose")
+            .arg("--status-fd")
+            .arg("1")
             .arg("--keyring")
             .arg(&self.keyring_path)
             .arg(file_path)
@@ -308,7 +601,6 @@ pub struct KeyringInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::NamedTempFile;
 
     #[test]
     fn test_gpg_verifier_creation() {
@@ -329,4 +621,208 @@ mod tests {
             assert_eq!(key_info.trust_level, "u");
         }
     }
+
+    #[test]
+    fn test_parse_validsig_hash_algorithm() {
+        use std::os::unix::process::ExitStatusExt;
+        
+        let verifier = GpgVerifier::new("test.gpg");
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"[GNUPG:] VALIDSIG 4D64FEC119C2029067D6E791F8D2585B8783D481 2023-06-10 1686387157 0 4 0 22 2 00 4D64FEC119C2029067D6E791F8D2585B8783D481\n".to_vec(),
+            stderr: vec![],
+        };
+        
+        let result = verifier.parse_gpg_output(&output).unwrap();
+        assert!(result.valid);
+        assert_eq!(result.signatures[0].hash_algorithm.as_deref(), Some("SHA1"));
+        assert!(result.has_weak_digest());
+    }
+
+    #[test]
+    fn test_weak_digest_on_any_signature() {
+        use std::os::unix::process::ExitStatusExt;
+        
+        let verifier = GpgVerifier::new("test.gpg");
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"[GNUPG:] VALIDSIG 4D64FEC119C2029067D6E791F8D2585B8783D481 2023-06-10 1686387157 0 4 0 22 2 00 4D64FEC119C2029067D6E791F8D2585B8783D481
+[GNUPG:] VALIDSIG 05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0 2023-06-10 1686387157 0 4 0 1 8 00 05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0
+".to_vec(),
+            stderr: vec![],
+        };
+        
+        // A SHA256 signature after the SHA1 one does not hide it
+        let result = verifier.parse_gpg_output(&output).unwrap();
+        assert_eq!(result.weak_digest(), Some("SHA1"));
+        
+        // Only the signatures that satisfied the pin are judged
+        let pinned = GpgVerifier::new("test.gpg")
+            .with_signed_by(&["05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0".to_string()]);
+        let result = pinned.parse_gpg_output(&output).unwrap();
+        assert!(result.valid);
+        assert!(!result.has_weak_digest());
+    }
+
+    #[test]
+    fn test_signed_by_pinning() {
+        use std::os::unix::process::ExitStatusExt;
+        
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"[GNUPG:] VALIDSIG 4D64FEC119C2029067D6E791F8D2585B8783D481 2023-06-10 1686387157 0 4 0 22 10 00 B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8\n".to_vec(),
+            stderr: vec![],
+        };
+        
+        let pinned = GpgVerifier::new("test.gpg")
+            .with_signed_by(&["b8b8 0b5b 623e ab6a d877  5c45 b7c5 d7d6 3509 47f8".to_string()]);
+        let result = pinned.parse_gpg_output(&output).unwrap();
+        assert!(result.valid);
+        
+        // The long key ID of the pinned key is a suffix of its fingerprint, but not a pin
+        let key_id = GpgVerifier::new("test.gpg")
+            .with_signed_by(&["0xB7C5D7D6350947F8".to_string()]);
+        let result = key_id.parse_gpg_output(&output).unwrap();
+        assert!(!result.valid);
+        assert!(result.unexpected_signer);
+        assert_eq!(result.signer(), Some("4D64FEC119C2029067D6E791F8D2585B8783D481"));
+    }
+
+    #[test]
+    fn test_signed_by_checks_every_signature() {
+        use std::os::unix::process::ExitStatusExt;
+        
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"[GNUPG:] VALIDSIG 4D64FEC119C2029067D6E791F8D2585B8783D481 2023-06-10 1686387157 0 4 0 22 10 00 B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8
+[GNUPG:] VALIDSIG 05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0 2023-06-10 1686387157 0 4 0 1 10 00 05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0
+".to_vec(),
+            stderr: vec![],
+        };
+        
+        // The pinned key signed first, followed by a key that is not pinned
+        let pinned = GpgVerifier::new("test.gpg")
+            .with_signed_by(&["B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8".to_string()]);
+        let result = pinned.parse_gpg_output(&output).unwrap();
+        assert!(result.valid);
+        assert_eq!(result.signer(), Some("4D64FEC119C2029067D6E791F8D2585B8783D481"));
+        
+        let pinned = GpgVerifier::new("test.gpg")
+            .with_signed_by(&["05AB90340C0C5E797F44A8C8254CF3B5AEC0A8F0".to_string()]);
+        let result = pinned.parse_gpg_output(&output).unwrap();
+        assert!(result.valid);
+        assert_eq!(result.signatures.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_pin() {
+        assert_eq!(
+            GpgVerifier::parse_pin("0xb8b8 0b5b 623e ab6a d877  5c45 b7c5 d7d6 3509 47f8").unwrap(),
+            "B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8"
+        );
+        assert!(GpgVerifier::parse_pin("350947F8").is_err());
+        assert!(GpgVerifier::parse_pin("B7C5D7D6350947F8").is_err());
+        assert!(GpgVerifier::parse_pin("").is_err());
+        assert!(GpgVerifier::parse_pin("Z8B80B5B623EAB6AD8775C45B7C5D7D6350947F8").is_err());
+    }
+
+    #[test]
+    fn test_temp_files_are_unique_and_removed() {
+        let first = GpgVerifier::write_temp_file(b"first", "InRelease").unwrap();
+        let second = GpgVerifier::write_temp_file(b"second", "InRelease").unwrap();
+        assert_ne!(first.path(), second.path());
+        assert_eq!(std::fs::read(first.path()).unwrap(), b"first");
+        
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_verifier_is_shareable_across_requests() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<GpgVerifier>();
+    }
+
+    #[test]
+    fn test_check_clearsigned() {
+        let inrelease = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Debian
+Suite: stable
+- -----dash escaped line
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+-----END PGP SIGNATURE-----
+";
+        assert!(GpgVerifier::check_clearsigned(inrelease.as_bytes()).is_ok());
+        assert!(GpgVerifier::check_clearsigned(format!("{}\n", inrelease).as_bytes()).is_ok());
+
+        assert!(GpgVerifier::check_clearsigned(b"Origin: Debian\n").is_err());
+        assert!(GpgVerifier::check_clearsigned(format!("Suite: unstable\n{}", inrelease).as_bytes()).is_err());
+        assert!(GpgVerifier::check_clearsigned(format!("{}Suite: unstable\n", inrelease).as_bytes()).is_err());
+        assert!(GpgVerifier::check_clearsigned(format!("{}{}", inrelease, inrelease).as_bytes()).is_err());
+        assert!(GpgVerifier::check_clearsigned(inrelease.replace("Hash: SHA512", "Comment: hi").as_bytes()).is_err());
+        assert!(GpgVerifier::check_clearsigned(inrelease.replace("- -----dash", "-----dash").as_bytes()).is_err());
+        assert!(GpgVerifier::check_clearsigned(inrelease.replace("-----END PGP SIGNATURE-----\n", "").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_keyring_search_list() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b-local.gpg"), b"").unwrap();
+        std::fs::write(dir.path().join("a-local.gpg"), b"").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        
+        let pattern = format!("{}/*.gpg", dir.path().display());
+        let verifier = GpgVerifier::new("/usr/share/keyrings/debian-archive-keyring.gpg")
+            .with_additional_keyrings(&["/usr/share/keyrings/debian-security.gpg".to_string()])
+            .with_keyring_glob(Some(&pattern));
+        
+        let keyrings = verifier.keyrings();
+        assert_eq!(keyrings.len(), 4);
+        assert_eq!(keyrings[0], "/usr/share/keyrings/debian-archive-keyring.gpg");
+        assert_eq!(keyrings[1], "/usr/share/keyrings/debian-security.gpg");
+        assert!(keyrings[2].ends_with("a-local.gpg"));
+        assert!(keyrings[3].ends_with("b-local.gpg"));
+        
+        // A glob match already listed explicitly is searched once, in its first position
+        let local = dir.path().join("b-local.gpg").display().to_string();
+        let verifier = GpgVerifier::new("/usr/share/keyrings/debian-archive-keyring.gpg")
+            .with_additional_keyrings(&[local.clone(), "/usr/share/keyrings/debian-archive-keyring.gpg".to_string()])
+            .with_keyring_glob(Some(&pattern));
+        let keyrings = verifier.keyrings();
+        assert_eq!(keyrings.len(), 3);
+        assert_eq!(keyrings[1], local);
+        assert!(keyrings[2].ends_with("a-local.gpg"));
+    }
+
+    #[test]
+    fn test_verify_command_passes_every_keyring() {
+        let verifier = GpgVerifier::new("/usr/share/keyrings/debian-archive-keyring.gpg")
+            .with_additional_keyrings(&[
+                "/usr/share/keyrings/debian-security.gpg".to_string(),
+                "/usr/share/keyrings/debian-archive-keyring.gpg".to_string(),
+            ]);
+        let command = verifier.verify_command(&verifier.keyrings(), "--verify");
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        
+        assert_eq!(args[0], "--no-default-keyring");
+        let keyrings: Vec<_> = args.windows(2).filter(|w| w[0] == "--keyring").map(|w| w[1].as_str()).collect();
+        assert_eq!(keyrings, ["/usr/share/keyrings/debian-archive-keyring.gpg", "/usr/share/keyrings/debian-security.gpg"]);
+    }
+
+    #[test]
+    fn test_input_mode_config() {
+        let verifier = GpgVerifier::new("test.gpg");
+        assert_eq!(verifier.input_mode(), GpgInputMode::TempFile);
+        
+        let verifier = verifier.with_input_mode(GpgInputMode::Stdin);
+        assert_eq!(verifier.input_mode(), GpgInputMode::Stdin);
+        
+        let mode: GpgInputMode = serde_json::from_str("\"stdin\"").unwrap();
+        assert_eq!(mode, GpgInputMode::Stdin);
+    }
 }
@@ -1,57 +1,134 @@
 use anyhow::{Result, anyhow};
-use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use sha2::{Sha256, Sha512, Digest};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use tracing::{info, error};
 
+// Ordered weakest to strongest so the highest variant is the preferred digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "SHA256",
+            DigestAlgorithm::Sha512 => "SHA512",
+        }
+    }
+
+    pub fn from_field_name(name: &str) -> Option<Self> {
+        match name {
+            "SHA256" => Some(DigestAlgorithm::Sha256),
+            "SHA512" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> String {
+        match self {
+            DigestAlgorithm::Sha256 => hex::encode(Sha256::digest(data)),
+            DigestAlgorithm::Sha512 => hex::encode(Sha512::digest(data)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileDigests {
+    pub size: u64,
+    pub digests: BTreeMap<DigestAlgorithm, String>,
+}
+
+impl FileDigests {
+    pub fn get(&self, algorithm: DigestAlgorithm) -> Option<&str> {
+        self.digests.get(&algorithm).map(|s| s.as_str())
+    }
+
+    pub fn strongest(&self) -> Option<(DigestAlgorithm, &str)> {
+        self.digests.iter().next_back().map(|(algo, hash)| (*algo, hash.as_str()))
+    }
+}
+
 pub struct HashVerifier;
 
 impl HashVerifier {
     pub fn verify_package_hash(data: &[u8], expected_hash: &str) -> Result<bool> {
-        info!("Verifying SHA256 hash for package data");
+        Self::verify_digest(data, DigestAlgorithm::Sha256, expected_hash)
+    }
+        
+    pub fn verify_digest(data: &[u8], algorithm: DigestAlgorithm, expected_hash: &str) -> Result<bool> {
+        info!("Verifying {} hash for package data", algorithm.field_name());
         
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let calculated_hash = format!("{:x}", hasher.finalize());
+        let calculated_hash = algorithm.compute(data);
         
-        if calculated_hash == expected_hash {
+        if calculated_hash.eq_ignore_ascii_case(expected_hash) {
             info!("Hash verification successful");
             Ok(true)
         } else {
             error!("Hash mismatch: expected {}, got {}", expected_hash, calculated_hash);
-            Err(anyhow!("SHA256 hash verification failed"))
+            Err(anyhow!("{} hash verification failed", algorithm.field_name()))
         }
     }
     
     pub fn parse_release_hashes(release_content: &str) -> Result<HashMap<String, String>> {
+        let hashes: HashMap<String, String> = Self::parse_release_digests(release_content)?
+            .into_iter()
+            .filter_map(|(filename, digests)| {
+                digests.get(DigestAlgorithm::Sha256).map(|hash| (filename, hash.to_string()))
+            })
+            .collect();
+        
+        Ok(hashes)
+    }
+
+    pub fn parse_release_digests(release_content: &str) -> Result<HashMap<String, FileDigests>> {
         info!("Parsing hashes from Release file");
         
-        let mut hashes = HashMap::new();
-        let mut in_hashes_section = false;
+        let mut entries: HashMap<String, FileDigests> = HashMap::new();
+        let mut current_algorithm: Option<DigestAlgorithm> = None;
         
         for line in release_content.lines() {
-            if line.starts_with("SHA256:") {
-                in_hashes_section = true;
+            if line.trim().is_empty() {
+                current_algorithm = None;
                 continue;
             }
             
-            if line.is_empty() || line.starts_with("MD5Sum:") || line.starts_with("SHA1:") {
-                in_hashes_section = false;
+            // A new field header ends the previous checksum stanza
+            if let Some(field) = Self::field_header(line) {
+                current_algorithm = DigestAlgorithm::from_field_name(field);
                 continue;
             }
             
-            if in_hashes_section {
-                // SHA256 format: hash size filename
+            if let Some(algorithm) = current_algorithm {
+                // Format: hash size filename
                 let parts: Vec<&str> = line.split_whitespace().collect();
                 if parts.len() >= 3 {
-                    let hash = parts[0].to_string();
-                    let filename = parts[2].to_string();
-                    hashes.insert(filename, hash);
+                    let size = parts[1].parse::<u64>()
+                        .map_err(|e| anyhow!("Invalid size in {} entry '{}': {}", algorithm.field_name(), line.trim(), e))?;
+                    let entry = entries.entry(parts[2].to_string()).or_default();
+                    entry.size = size;
+                    entry.digests.insert(algorithm, parts[0].to_lowercase());
                 }
             }
         }
         
-        info!("Parsed {} hash entries", hashes.len());
-        Ok(hashes)
+        info!("Parsed {} hash entries", entries.len());
+        Ok(entries)
+    }
+
+    fn field_header(line: &str) -> Option<&str> {
+        if line.starts_with(char::is_whitespace) {
+            return None;
+        }
+        
+        let (field, _) = line.split_once(':')?;
+        if field.is_empty() || field.contains(char::is_whitespace) {
+            return None;
+        }
+        
+        Some(field)
     }
     
     pub fn verify_file_against_release(
@@ -65,6 +142,29 @@ impl HashVerifier {
             Err(anyhow!("No hash found for file: {}", filename))
         }
     }
+
+    pub fn verify_file_against_digests(
+        file_data: &[u8],
+        filename: &str,
+        release_digests: &HashMap<String, FileDigests>,
+    ) -> Result<bool> {
+        let entry = release_digests.get(filename)
+            .ok_or_else(|| anyhow!("No hash found for file: {}", filename))?;
+        
+        if entry.size != file_data.len() as u64 {
+            return Err(anyhow!(
+                "Size mismatch for {}: expected {} bytes, got {}",
+                filename,
+                entry.size,
+                file_data.len()
+            ));
+        }
+        
+        let (algorithm, expected_hash) = entry.strongest()
+            .ok_or_else(|| anyhow!("No supported digest for file: {}", filename))?;
+        
+        Self::verify_digest(file_data, algorithm, expected_hash)
+    }
 }
 
 #[cfg(test)]
@@ -93,4 +193,37 @@ def456 2048 main/binary-amd64/Packages.gz
         assert_eq!(hashes.len(), 2);
         assert_eq!(hashes.get("main/binary-amd64/Packages"), Some(&"abc123".to_string()));
     }
+
+    #[test]
+    fn test_sha512_parsing_does_not_leak_into_sha256() {
+        let release_content = "Origin: Debian
+SHA256:
+ aaa111 9 main/binary-amd64/Packages
+SHA512:
+ bbb222 9 main/binary-amd64/Packages
+ ccc333 4 main/binary-amd64/Release
+";
+
+        let digests = HashVerifier::parse_release_digests(release_content).unwrap();
+        let packages = &digests["main/binary-amd64/Packages"];
+        assert_eq!(packages.get(DigestAlgorithm::Sha256), Some("aaa111"));
+        assert_eq!(packages.strongest(), Some((DigestAlgorithm::Sha512, "bbb222")));
+        
+        let sha256_only = HashVerifier::parse_release_hashes(release_content).unwrap();
+        assert_eq!(sha256_only.len(), 1);
+    }
+
+    #[test]
+    fn test_verify_against_strongest_digest() {
+        let data = b"test data";
+        let release_content = "SHA256:
+ 0000000000000000000000000000000000000000000000000000000000000000 9 test
+SHA512:
+ 0e1e21ecf105ec853d24d728867ad70613c21663a4693074b2a3619c1bd39d66b588c33723bb466c72424e80e3ca63c249078ab347bab9428500e7ee43059d0d 9 test
+";
+
+        let digests = HashVerifier::parse_release_digests(release_content).unwrap();
+        assert!(HashVerifier::verify_file_against_digests(data, "test", &digests).is_ok());
+        assert!(HashVerifier::verify_file_against_digests(b"test dat", "test", &digests).is_err());
+    }
 }
@@ -0,0 +1,574 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use tokio::sync::RwLock;
+use tracing::{info, warn, error};
+use crate::verify::hashes::{DigestAlgorithm, FileDigests, HashVerifier};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEntry {
+    pub size: u64,
+    pub sha256: String,
+    // Archive section such as "games" or "contrib/games", when the index lists one
+    pub section: Option<String>,
+}
+
+pub struct IndexParser;
+
+impl IndexParser {
+    pub fn is_index_path(path: &str) -> bool {
+        let filename = path.rsplit('/').next().unwrap_or("");
+        path.contains("/dists/") && (filename.starts_with("Packages") || filename.starts_with("Sources"))
+    }
+
+    // apt fetches indices as <dir>/by-hash/<algorithm>/<digest> when the Release
+    // says Acquire-By-Hash: yes, as Debian's do
+    pub fn split_by_hash(path: &str) -> Option<(&str, DigestAlgorithm, &str)> {
+        let (dir, rest) = path.rsplit_once("/by-hash/")?;
+        let (algorithm, digest) = rest.split_once('/')?;
+        if digest.is_empty() || digest.contains('/') {
+            return None;
+        }
+        Some((dir, DigestAlgorithm::from_field_name(algorithm)?, digest))
+    }
+
+    pub fn decompress(path: &str, data: &[u8]) -> Result<String> {
+        let mut content = String::new();
+        
+        if path.ends_with(".gz") {
+            flate2::read::GzDecoder::new(data).read_to_string(&mut content)?;
+        } else if path.ends_with(".xz") {
+            xz2::read::XzDecoder::new(data).read_to_string(&mut content)?;
+        } else {
+            content = String::from_utf8(data.to_vec())
+                .map_err(|e| anyhow!("Index {} is not valid UTF-8: {}", path, e))?;
+        }
+        
+        Ok(content)
+    }
+
+    pub fn parse_stanzas(content: &str) -> Vec<HashMap<String, String>> {
+        let mut stanzas = Vec::new();
+        let mut current: HashMap<String, String> = HashMap::new();
+        let mut last_field: Option<String> = None;
+        
+        for line in content.lines() {
+            if line.trim().is_empty() {
+                if !current.is_empty() {
+                    stanzas.push(std::mem::take(&mut current));
+                }
+                last_field = None;
+                continue;
+            }
+            
+            if line.starts_with(char::is_whitespace) {
+                // Continuation of a multi-line field such as Checksums-Sha256
+                if let Some(value) = last_field.as_ref().and_then(|f| current.get_mut(f)) {
+                    value.push('\n');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            
+            if let Some((name, value)) = line.split_once(':') {
+                let name = name.trim().to_lowercase();
+                current.insert(name.clone(), value.trim().to_string());
+                last_field = Some(name);
+            }
+        }
+        
+        if !current.is_empty() {
+            stanzas.push(current);
+        }
+        
+        stanzas
+    }
+
+    pub fn parse_packages(content: &str) -> Result<Vec<(String, PoolEntry)>> {
+        let mut entries = Vec::new();
+        
+        for stanza in Self::parse_stanzas(content) {
+            let (Some(filename), Some(size), Some(sha256)) =
+                (stanza.get("filename"), stanza.get("size"), stanza.get("sha256"))
+            else {
+                continue;
+            };
+            
+            let size = size.parse::<u64>()
+                .map_err(|e| anyhow!("Invalid Size for {}: {}", filename, e))?;
+            entries.push((filename.clone(), PoolEntry {
+                size,
+                sha256: sha256.to_lowercase(),
+                section: stanza.get("section").cloned(),
+            }));
+        }
+        
+        Ok(entries)
+    }
+
+    pub fn parse_sources(content: &str) -> Result<Vec<(String, PoolEntry)>> {
+        let mut entries = Vec::new();
+        
+        for stanza in Self::parse_stanzas(content) {
+            let (Some(directory), Some(checksums)) =
+                (stanza.get("directory"), stanza.get("checksums-sha256"))
+            else {
+                continue;
+            };
+            
+            // Format: hash size filename
+            for line in checksums.lines() {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                if parts.len() < 3 {
+                    continue;
+                }
+                let size = parts[1].parse::<u64>()
+                    .map_err(|e| anyhow!("Invalid size for {}: {}", parts[2], e))?;
+                entries.push((
+                    format!("{}/{}", directory.trim_end_matches('/'), parts[2]),
+                    PoolEntry { size, sha256: parts[0].to_lowercase(), section: stanza.get("section").cloned() },
+                ));
+            }
+        }
+        
+        Ok(entries)
+    }
+}
+
+struct IndexRecord {
+    digest: String,
+    size: u64,
+    pool_paths: Vec<String>,
+    // Set when the index digest matched a signature-verified Release
+    verified_by: Option<String>,
+}
+
+struct TrustedRelease {
+    digests: HashMap<String, FileDigests>,
+    valid_until: Option<DateTime<Utc>>,
+}
+
+// Pool path -> entry as listed by each index; a file stays known while any
+// index lists it, e.g. arch:all packages listed by every binary-* index
+type PoolOwners = BTreeMap<String, PoolEntry>;
+
+pub struct PackageIndexStore {
+    entries: RwLock<HashMap<String, PoolOwners>>,
+    indices: RwLock<HashMap<String, IndexRecord>>,
+    // Suite directory (e.g. /debian/dists/bookworm) -> verified Release
+    releases: RwLock<HashMap<String, TrustedRelease>>,
+}
+
+impl PackageIndexStore {
+    pub fn new() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            indices: RwLock::new(HashMap::new()),
+            releases: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn split_suite_path(path: &str) -> Option<(&str, &str)> {
+        // Example: /debian/dists/bookworm/main/binary-amd64/Packages.xz
+        //   -> ("/debian/dists/bookworm", "main/binary-amd64/Packages.xz")
+        let dists = path.find("/dists/")? + "/dists/".len();
+        let suite_end = dists + path[dists..].find('/')?;
+        Some((&path[..suite_end], &path[suite_end + 1..]))
+    }
+
+    // Only call with the cleartext of a Release whose signature was verified
+    pub async fn record_verified_release(
+        &self,
+        release_path: &str,
+        release_content: &str,
+        valid_until: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let (suite_dir, _) = Self::split_suite_path(release_path)
+            .ok_or_else(|| anyhow!("Not a Release path: {}", release_path))?;
+        let digests = HashVerifier::parse_release_digests(release_content)?;
+        
+        info!("Trusting {} index digests from {}", digests.len(), release_path);
+        let mut releases = self.releases.write().await;
+        let mut indices = self.indices.write().await;
+        
+        // An index stays verified only while the newest Release lists it unchanged
+        for (index_path, record) in indices.iter_mut() {
+            let Some((index_suite, relative)) = Self::split_suite_path(index_path) else {
+                continue;
+            };
+            if index_suite != suite_dir {
+                continue;
+            }
+            let listed = digests.get(relative).is_some_and(|entry| {
+                entry.size == record.size
+                    && entry.get(DigestAlgorithm::Sha256).is_some_and(|d| d.eq_ignore_ascii_case(&record.digest))
+            });
+            if record.verified_by.is_some() && !listed {
+                warn!("Index {} is no longer covered by the verified Release", index_path);
+            }
+            record.verified_by = listed.then(|| suite_dir.to_string());
+        }
+        
+        releases.insert(suite_dir.to_string(), TrustedRelease { digests, valid_until });
+        Ok(())
+    }
+
+    // The index a fetched path holds: the path itself for an index, or for a
+    // by-hash path the entry in the same directory of the verified Release
+    // with that digest. None when the Release has no such index
+    pub async fn index_path(&self, path: &str) -> Option<String> {
+        let Some((dir, algorithm, digest)) = IndexParser::split_by_hash(path) else {
+            return IndexParser::is_index_path(path).then(|| path.to_string());
+        };
+        let (suite_dir, relative_dir) = Self::split_suite_path(dir)?;
+        let releases = self.releases.read().await;
+        let release = releases.get(suite_dir)?;
+        
+        let name = release.digests.iter().find_map(|(name, digests)| {
+            let in_dir = name.rsplit_once('/').is_some_and(|(parent, _)| parent == relative_dir);
+            let same_digest = digests.get(algorithm).is_some_and(|d| d.eq_ignore_ascii_case(digest));
+            (in_dir && same_digest).then(|| format!("{}/{}", suite_dir, name))
+        })?;
+        IndexParser::is_index_path(&name).then_some(name)
+    }
+
+    fn verifying_release(releases: &HashMap<String, TrustedRelease>, index_path: &str, data: &[u8]) -> Option<String> {
+        let (suite_dir, relative) = Self::split_suite_path(index_path)?;
+        let release = releases.get(suite_dir)?;
+        
+        match HashVerifier::verify_file_against_digests(data, relative, &release.digests) {
+            Ok(true) => Some(suite_dir.to_string()),
+            _ => {
+                warn!("Index {} does not match its verified Release", index_path);
+                None
+            }
+        }
+    }
+
+    pub async fn update_from_index(&self, index_path: &str, data: &[u8]) -> Result<usize> {
+        // Example: /debian/dists/bookworm/main/binary-amd64/Packages.xz -> /debian
+        let repository_root = index_path.split("/dists/").next().unwrap_or("");
+        
+        // The Release lock is held while a verdict is stored, so a newer Release
+        // recorded meanwhile cannot be overwritten by one from the older one
+        let digest = DigestAlgorithm::Sha256.compute(data);
+        {
+            let releases = self.releases.read().await;
+            if let Some(record) = self.indices.write().await.get_mut(index_path) {
+                if record.digest == digest {
+                    record.verified_by = Self::verifying_release(&releases, index_path, data);
+                    return Ok(0);
+                }
+            }
+        }
+        
+        let content = IndexParser::decompress(index_path, data)?;
+        let filename = index_path.rsplit('/').next().unwrap_or("");
+        let parsed = if filename.starts_with("Sources") {
+            IndexParser::parse_sources(&content)?
+        } else {
+            IndexParser::parse_packages(&content)?
+        };
+        
+        let releases = self.releases.read().await;
+        let verified_by = Self::verifying_release(&releases, index_path, data);
+        let mut entries = self.entries.write().await;
+        let mut indices = self.indices.write().await;
+        
+        // Drop what the previous version of this index listed; other indices keep theirs
+        if let Some(previous) = indices.remove(index_path) {
+            for pool_path in previous.pool_paths {
+                if let Some(owners) = entries.get_mut(&pool_path) {
+                    owners.remove(index_path);
+                    if owners.is_empty() {
+                        entries.remove(&pool_path);
+                    }
+                }
+            }
+        }
+        
+        let mut pool_paths = Vec::with_capacity(parsed.len());
+        for (filename, entry) in parsed {
+            let pool_path = format!("{}/{}", repository_root, filename);
+            entries.entry(pool_path.clone()).or_default().insert(index_path.to_string(), entry);
+            pool_paths.push(pool_path);
+        }
+        
+        let count = pool_paths.len();
+        indices.insert(index_path.to_string(), IndexRecord { digest, size: data.len() as u64, pool_paths, verified_by });
+        
+        info!("Indexed {} pool files from {}", count, index_path);
+        Ok(count)
+    }
+
+    // The entry of an index covered by a verified Release when there is one,
+    // so an unverified index never overrides what a verified one lists
+    async fn preferred(&self, pool_path: &str) -> Option<(String, PoolEntry)> {
+        let entries = self.entries.read().await;
+        let owners = entries.get(pool_path)?;
+        let indices = self.indices.read().await;
+        owners
+            .iter()
+            .find(|(index_path, _)| indices.get(*index_path).is_some_and(|record| record.verified_by.is_some()))
+            .or_else(|| owners.iter().next())
+            .map(|(index_path, entry)| (index_path.clone(), entry.clone()))
+    }
+
+    pub async fn lookup(&self, pool_path: &str) -> Option<PoolEntry> {
+        self.preferred(pool_path).await.map(|(_, entry)| entry)
+    }
+
+    pub async fn section(&self, pool_path: &str) -> Option<String> {
+        self.lookup(pool_path).await.and_then(|entry| entry.section)
+    }
+
+    // Suite of the index the pool file's entry comes from, e.g. bookworm
+    pub async fn suite(&self, pool_path: &str) -> Option<String> {
+        let (index_path, _) = self.preferred(pool_path).await?;
+        let (suite_dir, _) = Self::split_suite_path(&index_path)?;
+        suite_dir.rsplit('/').next().map(str::to_string)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.entries.read().await.is_empty()
+    }
+
+    // Checks that a pool file is listed by an index which is itself covered by a
+    // signature-verified Release that has not passed its Valid-Until
+    pub async fn check_trusted(&self, pool_path: &str, now: DateTime<Utc>) -> Result<()> {
+        let (index_path, _) = self.preferred(pool_path).await
+            .ok_or_else(|| anyhow!("{} is not listed in any known index", pool_path))?;
+        
+        let suite_dir = self.indices.read().await.get(&index_path).and_then(|r| r.verified_by.clone())
+            .ok_or_else(|| anyhow!("Index {} is not covered by a verified Release", index_path))?;
+        
+        let releases = self.releases.read().await;
+        let release = releases.get(&suite_dir)
+            .ok_or_else(|| anyhow!("No verified Release for {}", suite_dir))?;
+        if release.valid_until.is_some_and(|valid_until| now > valid_until) {
+            return Err(anyhow!("Verified Release for {} has expired", suite_dir));
+        }
+        
+        Ok(())
+    }
+
+    // Returns Ok(false) when the path is not covered by any known index
+    pub async fn verify(&self, pool_path: &str, content_length: Option<u64>, data: &[u8]) -> Result<bool> {
+        let Some(entry) = self.lookup(pool_path).await else {
+            return Ok(false);
+        };
+        
+        if let Some(length) = content_length {
+            if length != entry.size {
+                error!("Content-Length mismatch for {}: expected {}, got {}", pool_path, entry.size, length);
+                return Err(anyhow!("Content-Length {} does not match indexed size {}", length, entry.size));
+            }
+        }
+        
+        if data.len() as u64 != entry.size {
+            return Err(anyhow!("Size {} does not match indexed size {}", data.len(), entry.size));
+        }
+        
+        HashVerifier::verify_digest(data, DigestAlgorithm::Sha256, &entry.sha256)
+    }
+}
+
+impl Default for PackageIndexStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGES: &str = "Package: hello
+Version: 2.10-3
+Architecture: amd64
+Section: devel
+Filename: pool/main/h/hello/hello_2.10-3_amd64.deb
+Size: 9
+SHA256: 916f0027a575074ce72a331777c3478d6513f786a591bd892da1a577bf2335f9
+
+Package: broken
+Version: 1.0
+";
+
+    const SOURCES: &str = "Package: hello
+Directory: pool/main/h/hello
+Checksums-Sha256:
+ aaa111 1024 hello_2.10-3.dsc
+ bbb222 2048 hello_2.10.orig.tar.gz
+";
+
+    #[test]
+    fn test_parse_packages() {
+        let entries = IndexParser::parse_packages(PACKAGES).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "pool/main/h/hello/hello_2.10-3_amd64.deb");
+        assert_eq!(entries[0].1.size, 9);
+    }
+
+    #[test]
+    fn test_parse_sources() {
+        let entries = IndexParser::parse_sources(SOURCES).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].0, "pool/main/h/hello/hello_2.10.orig.tar.gz");
+        assert_eq!(entries[1].1, PoolEntry { size: 2048, sha256: "bbb222".to_string(), section: None });
+    }
+
+    #[tokio::test]
+    async fn test_store_verifies_pool_files() {
+        let store = PackageIndexStore::new();
+        let count = store
+            .update_from_index("/debian/dists/bookworm/main/binary-amd64/Packages", PACKAGES.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        
+        let path = "/debian/pool/main/h/hello/hello_2.10-3_amd64.deb";
+        assert!(store.verify(path, Some(9), b"test data").await.unwrap());
+        assert!(store.verify(path, Some(10), b"test data").await.is_err());
+        assert!(store.verify(path, None, b"test dat!").await.is_err());
+        assert!(!store.verify("/debian/pool/main/o/other/other.deb", None, b"").await.unwrap());
+        assert_eq!(store.section(path).await.as_deref(), Some("devel"));
+        assert_eq!(store.suite(path).await.as_deref(), Some("bookworm"));
+    }
+
+    #[tokio::test]
+    async fn test_store_replaces_changed_index() {
+        let store = PackageIndexStore::default();
+        assert!(store.is_empty().await);
+        let index = "/debian/dists/bookworm/main/binary-amd64/Packages";
+        store.update_from_index(index, PACKAGES.as_bytes()).await.unwrap();
+        
+        let updated = PACKAGES.replace("hello_2.10-3", "hello_2.10-4");
+        store.update_from_index(index, updated.as_bytes()).await.unwrap();
+        
+        assert_eq!(store.len().await, 1);
+        assert!(store.lookup("/debian/pool/main/h/hello/hello_2.10-3_amd64.deb").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_store_keeps_files_other_indices_list() {
+        let store = PackageIndexStore::new();
+        let pool_path = "/debian/pool/main/h/hello/hello_2.10-3_amd64.deb";
+        let amd64 = "/debian/dists/bookworm/main/binary-amd64/Packages";
+        let arm64 = "/debian/dists/bookworm/main/binary-arm64/Packages";
+        store.update_from_index(amd64, PACKAGES.as_bytes()).await.unwrap();
+        store.update_from_index(arm64, PACKAGES.as_bytes()).await.unwrap();
+        
+        // A new version of one index no longer lists it; the other still does
+        store.update_from_index(amd64, PACKAGES.replace("hello_2.10-3", "hello_2.10-4").as_bytes()).await.unwrap();
+        assert!(store.verify(pool_path, None, b"test data").await.unwrap());
+        store.update_from_index(arm64, b"").await.unwrap();
+        assert!(store.lookup(pool_path).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_store_prefers_verified_index() {
+        let store = PackageIndexStore::new();
+        let pool_path = "/debian/pool/main/h/hello/hello_2.10-3_amd64.deb";
+        let release = format!(
+            "Suite: stable\nSHA256:\n {} {} main/binary-amd64/Packages\n",
+            DigestAlgorithm::Sha256.compute(PACKAGES.as_bytes()),
+            PACKAGES.len()
+        );
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release, None).await.unwrap();
+        store.update_from_index("/debian/dists/bookworm/main/binary-amd64/Packages", PACKAGES.as_bytes()).await.unwrap();
+        
+        // Listed with another hash by an index no verified Release covers
+        let forged = PACKAGES.replace("916f0027", "00000000");
+        store.update_from_index("/debian/dists/bookworm/main/binary-all/Packages", forged.as_bytes()).await.unwrap();
+        assert!(store.verify(pool_path, None, b"test data").await.unwrap());
+        assert!(store.check_trusted(pool_path, Utc::now()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_by_hash_index_resolves_through_release() {
+        let store = PackageIndexStore::new();
+        let digest = DigestAlgorithm::Sha256.compute(PACKAGES.as_bytes());
+        let release = format!(
+            "Suite: stable\nAcquire-By-Hash: yes\nSHA256:\n {} {} main/binary-amd64/Packages\n {} 12 main/i18n/Translation-en\n",
+            digest,
+            PACKAGES.len(),
+            DigestAlgorithm::Sha256.compute(b"translation\n"),
+        );
+        let by_hash = format!("/debian/dists/bookworm/main/binary-amd64/by-hash/SHA256/{}", digest);
+        
+        // Nothing to resolve against before the Release is verified
+        assert!(store.index_path(&by_hash).await.is_none());
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release, None).await.unwrap();
+        
+        let index = store.index_path(&by_hash).await.unwrap();
+        assert_eq!(index, "/debian/dists/bookworm/main/binary-amd64/Packages");
+        store.update_from_index(&index, PACKAGES.as_bytes()).await.unwrap();
+        assert!(store.check_trusted("/debian/pool/main/h/hello/hello_2.10-3_amd64.deb", Utc::now()).await.is_ok());
+        
+        // Same digest, other directory; a digest the Release lists for no index
+        assert!(store.index_path(&by_hash.replace("binary-amd64", "binary-arm64")).await.is_none());
+        let translation = format!("/debian/dists/bookworm/main/i18n/by-hash/SHA256/{}", DigestAlgorithm::Sha256.compute(b"translation\n"));
+        assert!(store.index_path(&translation).await.is_none());
+        
+        assert_eq!(store.index_path("/debian/dists/bookworm/main/source/Sources.xz").await.as_deref(), Some("/debian/dists/bookworm/main/source/Sources.xz"));
+        assert!(store.index_path("/debian/dists/bookworm/InRelease").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_newer_release_revokes_changed_index() {
+        let store = PackageIndexStore::new();
+        let index = "/debian/dists/bookworm/main/binary-amd64/Packages";
+        let pool_path = "/debian/pool/main/h/hello/hello_2.10-3_amd64.deb";
+        let release = |packages: &str| format!(
+            "Suite: stable\nSHA256:\n {} {} main/binary-amd64/Packages\n",
+            DigestAlgorithm::Sha256.compute(packages.as_bytes()),
+            packages.len()
+        );
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release(PACKAGES), None).await.unwrap();
+        store.update_from_index(index, PACKAGES.as_bytes()).await.unwrap();
+        assert!(store.check_trusted(pool_path, Utc::now()).await.is_ok());
+        
+        // The next Release lists a changed index the stored one no longer matches
+        let updated = PACKAGES.replace("hello_2.10-3", "hello_2.10-4");
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release(&updated), None).await.unwrap();
+        assert!(store.check_trusted(pool_path, Utc::now()).await.is_err());
+        
+        // A Release that drops the index altogether
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release(PACKAGES), None).await.unwrap();
+        assert!(store.check_trusted(pool_path, Utc::now()).await.is_ok());
+        store.record_verified_release("/debian/dists/bookworm/InRelease", "Suite: stable\nSHA256:\n", None).await.unwrap();
+        assert!(store.check_trusted(pool_path, Utc::now()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strict_trust_chain() {
+        let store = PackageIndexStore::new();
+        let index = "/debian/dists/bookworm/main/binary-amd64/Packages";
+        let pool_path = "/debian/pool/main/h/hello/hello_2.10-3_amd64.deb";
+        let now = Utc::now();
+        
+        // Index seen before any verified Release: listed but not trusted
+        store.update_from_index(index, PACKAGES.as_bytes()).await.unwrap();
+        assert!(store.check_trusted(pool_path, now).await.is_err());
+        
+        let release = format!(
+            "Suite: stable\nSHA256:\n {} {} main/binary-amd64/Packages\n",
+            DigestAlgorithm::Sha256.compute(PACKAGES.as_bytes()),
+            PACKAGES.len()
+        );
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release, None).await.unwrap();
+        store.update_from_index(index, PACKAGES.as_bytes()).await.unwrap();
+        assert!(store.check_trusted(pool_path, now).await.is_ok());
+        assert!(store.check_trusted("/debian/pool/main/o/other/other.deb", now).await.is_err());
+        
+        let expired = now - chrono::Duration::hours(1);
+        store.record_verified_release("/debian/dists/bookworm/InRelease", &release, Some(expired)).await.unwrap();
+        assert!(store.check_trusted(pool_path, now).await.is_err());
+    }
+}
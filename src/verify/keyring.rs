@@ -0,0 +1,252 @@
+use anyhow::{Result, anyhow};
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use crate::verify::debsig::DebSignatureRule;
+use crate::verify::gpg::{GpgInputMode, GpgVerifier};
+use crate::verify::quarantine::QuarantineConfig;
+use crate::verify::release::EnforcementMode;
+use crate::verify::signing::SigningConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerificationConfig {
+    pub gpg_keyring_path: String,
+    pub additional_keyrings: Vec<String>,
+    pub keyring_glob: Option<String>,
+    pub enable_gpg_verification: bool,
+    pub gpg_input: GpgInputMode,
+    pub enable_hash_verification: bool,
+    pub strict_mode: bool,
+    pub valid_until: EnforcementMode,
+    pub weak_digests: EnforcementMode,
+    pub release_date: EnforcementMode,
+    pub release_date_skew_secs: i64,
+    pub max_release_age_days: Option<i64>,
+    pub keyrings: Vec<RepositoryKeyring>,
+    pub deb_signatures: Vec<DebSignatureRule>,
+    pub quarantine: QuarantineConfig,
+    pub signing: SigningConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryKeyring {
+    pub repository: String,
+    #[serde(default)]
+    pub suites: Vec<String>,
+    pub keyring_path: Option<String>,
+    #[serde(default)]
+    pub signed_by: Vec<String>,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            gpg_keyring_path: "/etc/debian-archive-keyring.gpg".to_string(),
+            additional_keyrings: vec![],
+            keyring_glob: None,
+            enable_gpg_verification: true,
+            gpg_input: GpgInputMode::default(),
+            enable_hash_verification: true,
+            strict_mode: false,
+            valid_until: EnforcementMode::Deny,
+            weak_digests: EnforcementMode::Deny,
+            release_date: EnforcementMode::Warn,
+            release_date_skew_secs: 300,
+            max_release_age_days: None,
+            keyrings: vec![],
+            deb_signatures: vec![],
+            quarantine: QuarantineConfig::default(),
+            signing: SigningConfig::default(),
+        }
+    }
+}
+
+impl RepositoryKeyring {
+    pub fn validate(&self) -> Result<()> {
+        for pin in &self.signed_by {
+            GpgVerifier::parse_pin(pin).map_err(|e| anyhow!("Keyring for repository '{}': {}", self.repository, e))?;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, repository: &str, suite: Option<&str>) -> bool {
+        if self.repository != repository {
+            return false;
+        }
+        
+        // An empty suite list covers every suite of the repository
+        self.suites.is_empty() || suite.is_some_and(|s| self.suites.iter().any(|allowed| allowed == s))
+    }
+}
+
+// Swapped as a whole when the keyrings are reloaded
+pub type SharedKeyrings = Arc<ArcSwap<KeyringMap>>;
+
+pub struct KeyringMap {
+    default_verifier: Arc<GpgVerifier>,
+    entries: Vec<(RepositoryKeyring, Arc<GpgVerifier>)>,
+}
+
+impl KeyringMap {
+    pub fn from_config(config: &VerificationConfig) -> Self {
+        let default_verifier = Arc::new(Self::default_search(GpgVerifier::new(&config.gpg_keyring_path), config));
+        
+        // Suite-specific mappings are checked before repository-wide ones
+        let mut keyrings = config.keyrings.clone();
+        keyrings.sort_by_key(|k| k.suites.is_empty());
+        
+        let entries = keyrings
+            .into_iter()
+            .map(|keyring| {
+                let verifier = match keyring.keyring_path.as_deref() {
+                    Some(keyring_path) => GpgVerifier::new(keyring_path).with_input_mode(config.gpg_input),
+                    // Without a dedicated keyring the entry inherits the global search list
+                    None => Self::default_search(GpgVerifier::new(&config.gpg_keyring_path), config),
+                };
+                info!(
+                    "Using keyrings {:?} for repository '{}' (suites: {:?}, signed-by: {:?})",
+                    verifier.keyrings(), keyring.repository, keyring.suites, keyring.signed_by
+                );
+                let verifier = verifier.with_signed_by(&keyring.signed_by);
+                let verifier = Arc::new(verifier);
+                (keyring, verifier)
+            })
+            .collect();
+        
+        Self {
+            default_verifier,
+            entries,
+        }
+    }
+
+    fn default_search(verifier: GpgVerifier, config: &VerificationConfig) -> GpgVerifier {
+        verifier
+            .with_additional_keyrings(&config.additional_keyrings)
+            .with_keyring_glob(config.keyring_glob.as_deref())
+            .with_input_mode(config.gpg_input)
+    }
+
+    // Every keyring file searched, glob matches included
+    pub fn keyring_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = std::iter::once(&self.default_verifier)
+            .chain(self.entries.iter().map(|(_, verifier)| verifier))
+            .flat_map(|verifier| verifier.keyrings())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    pub fn verifier_for(&self, repository: &str, suite: Option<&str>) -> Arc<GpgVerifier> {
+        self.entries
+            .iter()
+            .find(|(keyring, _)| keyring.matches(repository, suite))
+            .map(|(_, verifier)| verifier.clone())
+            .unwrap_or_else(|| self.default_verifier.clone())
+    }
+
+    pub fn verifier_for_path(&self, path: &str) -> Arc<GpgVerifier> {
+        let (repository, suite) = Self::split_repository_path(path);
+        self.verifier_for(repository, suite)
+    }
+
+    fn split_repository_path(path: &str) -> (&str, Option<&str>) {
+        // Example: /debian/dists/bookworm/InRelease -> ("debian", Some("bookworm"))
+        let mut parts = path.trim_start_matches('/').split('/');
+        let repository = parts.next().unwrap_or("");
+        
+        let suite = match parts.next() {
+            Some("dists") => parts.next().filter(|s| !s.is_empty()),
+            _ => None,
+        };
+        
+        (repository, suite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> VerificationConfig {
+        VerificationConfig {
+            gpg_keyring_path: "/etc/debian-archive-keyring.gpg".to_string(),
+            keyrings: vec![
+                RepositoryKeyring {
+                    repository: "ubuntu".to_string(),
+                    suites: vec![],
+                    keyring_path: Some("/usr/share/keyrings/ubuntu-archive-keyring.gpg".to_string()),
+                    signed_by: vec![],
+                },
+                RepositoryKeyring {
+                    repository: "debian".to_string(),
+                    suites: vec!["bookworm-security".to_string()],
+                    keyring_path: Some("/usr/share/keyrings/debian-security.gpg".to_string()),
+                    signed_by: vec![],
+                },
+                RepositoryKeyring {
+                    repository: "debian".to_string(),
+                    suites: vec!["bookworm".to_string()],
+                    keyring_path: None,
+                    signed_by: vec!["B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8".to_string()],
+                },
+            ],
+            ..VerificationConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_repository_and_suite_selection() {
+        let map = KeyringMap::from_config(&config());
+        
+        let ubuntu = map.verifier_for_path("/ubuntu/dists/noble/InRelease");
+        assert_eq!(ubuntu.keyring_path(), "/usr/share/keyrings/ubuntu-archive-keyring.gpg");
+        
+        let security = map.verifier_for_path("/debian/dists/bookworm-security/InRelease");
+        assert_eq!(security.keyring_path(), "/usr/share/keyrings/debian-security.gpg");
+    }
+
+    #[test]
+    fn test_fallback_to_default_keyring() {
+        let map = KeyringMap::from_config(&config());
+        
+        let verifier = map.verifier_for_path("/debian/dists/bullseye/InRelease");
+        assert_eq!(verifier.keyring_path(), "/etc/debian-archive-keyring.gpg");
+        assert!(verifier.signed_by().is_empty());
+    }
+
+    #[test]
+    fn test_dedicated_keyring_skips_global_search_list() {
+        let mut config = config();
+        config.additional_keyrings = vec!["/etc/apt/keyrings/site-local.gpg".to_string()];
+        let map = KeyringMap::from_config(&config);
+        
+        let ubuntu = map.verifier_for_path("/ubuntu/dists/noble/InRelease");
+        assert_eq!(ubuntu.keyrings(), ["/usr/share/keyrings/ubuntu-archive-keyring.gpg".to_string()]);
+        
+        let default = map.verifier_for_path("/debian/dists/bullseye/InRelease");
+        assert_eq!(default.keyrings().len(), 2);
+    }
+
+    #[test]
+    fn test_signed_by_pin_uses_default_keyring() {
+        let map = KeyringMap::from_config(&config());
+        
+        let verifier = map.verifier_for_path("/debian/dists/bookworm/InRelease");
+        assert_eq!(verifier.keyring_path(), "/etc/debian-archive-keyring.gpg");
+        assert_eq!(verifier.signed_by(), ["B8B80B5B623EAB6AD8775C45B7C5D7D6350947F8".to_string()]);
+    }
+
+    #[test]
+    fn test_signed_by_needs_full_fingerprints() {
+        let mut keyring = config().keyrings.remove(2);
+        assert!(keyring.validate().is_ok());
+        
+        for pin in ["B7C5D7D6350947F8", "350947F8", ""] {
+            keyring.signed_by = vec![pin.to_string()];
+            assert!(keyring.validate().is_err(), "{:?} should be rejected", pin);
+        }
+    }
+}
@@ -1,2 +1,9 @@
+pub mod control;
+pub mod debsig;
 pub mod gpg;
 pub mod hashes;
+pub mod index;
+pub mod keyring;
+pub mod quarantine;
+pub mod release;
+pub mod signing;
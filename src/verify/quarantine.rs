@@ -0,0 +1,195 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use crate::verify::hashes::DigestAlgorithm;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuarantineConfig {
+    pub enabled: bool,
+    pub directory: String,
+    pub max_total_size_mb: u64,
+    pub retention_days: i64,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "/var/lib/aptg/quarantine".to_string(),
+            max_total_size_mb: 1024,
+            retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub id: String,
+    pub path: String,
+    pub reason: String,
+    pub quarantined_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+pub struct QuarantineStore {
+    config: QuarantineConfig,
+    // Serializes writes and pruning so the size cap is computed consistently
+    lock: Mutex<()>,
+}
+
+impl QuarantineStore {
+    pub fn new(config: QuarantineConfig) -> Self {
+        Self {
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub async fn quarantine(&self, path: &str, reason: &str, data: &[u8]) -> Result<Option<QuarantineRecord>> {
+        if !self.config.enabled {
+            return Ok(None);
+        }
+        
+        let max_bytes = self.config.max_total_size_mb * 1024 * 1024;
+        if data.len() as u64 > max_bytes {
+            warn!("Not quarantining {}: {} bytes exceeds quarantine size cap", path, data.len());
+            return Ok(None);
+        }
+        
+        let _guard = self.lock.lock().await;
+        let directory = Path::new(&self.config.directory);
+        tokio::fs::create_dir_all(directory).await
+            .map_err(|e| anyhow!("Failed to create quarantine directory {}: {}", self.config.directory, e))?;
+        
+        let sha256 = DigestAlgorithm::Sha256.compute(data);
+        let now = Utc::now();
+        let record = QuarantineRecord {
+            id: format!("{}-{}", now.format("%Y%m%dT%H%M%S%.3fZ"), &sha256[..12]),
+            path: path.to_string(),
+            reason: reason.to_string(),
+            quarantined_at: now,
+            size_bytes: data.len() as u64,
+            sha256,
+        };
+        
+        tokio::fs::write(self.artifact_path(&record.id), data).await?;
+        tokio::fs::write(self.metadata_path(&record.id), serde_json::to_vec_pretty(&record)?).await?;
+        
+        info!("Quarantined {} as {} ({})", path, record.id, reason);
+        
+        self.prune().await?;
+        Ok(Some(record))
+    }
+
+    pub async fn list(&self) -> Result<Vec<QuarantineRecord>> {
+        let mut records = Vec::new();
+        let mut dir = match tokio::fs::read_dir(&self.config.directory).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(records),
+            Err(e) => return Err(e.into()),
+        };
+        
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match tokio::fs::read(&path).await.map(|data| serde_json::from_slice::<QuarantineRecord>(&data)) {
+                Ok(Ok(record)) => records.push(record),
+                _ => warn!("Skipping unreadable quarantine record {}", path.display()),
+            }
+        }
+        
+        records.sort_by_key(|r| r.quarantined_at);
+        Ok(records)
+    }
+
+    // Caller must hold the store lock
+    async fn prune(&self) -> Result<()> {
+        let records = self.list().await?;
+        let cutoff = Utc::now() - Duration::days(self.config.retention_days);
+        let max_bytes = self.config.max_total_size_mb * 1024 * 1024;
+        let mut total: u64 = records.iter().map(|r| r.size_bytes).sum();
+        
+        // Records are oldest first, so expired and over-cap entries go together
+        for record in records {
+            if record.quarantined_at >= cutoff && total <= max_bytes {
+                break;
+            }
+            self.remove(&record.id).await;
+            total = total.saturating_sub(record.size_bytes);
+            info!("Pruned quarantined artifact {} ({})", record.id, record.path);
+        }
+        
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) {
+        let _ = tokio::fs::remove_file(self.artifact_path(id)).await;
+        let _ = tokio::fs::remove_file(self.metadata_path(id)).await;
+    }
+
+    fn artifact_path(&self, id: &str) -> PathBuf {
+        Path::new(&self.config.directory).join(format!("{}.bin", id))
+    }
+
+    fn metadata_path(&self, id: &str) -> PathBuf {
+        Path::new(&self.config.directory).join(format!("{}.json", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(directory: &Path, max_total_size_mb: u64) -> QuarantineStore {
+        QuarantineStore::new(QuarantineConfig {
+            enabled: true,
+            directory: directory.to_str().unwrap().to_string(),
+            max_total_size_mb,
+            retention_days: 30,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_writes_artifact_and_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path(), 1);
+        
+        let record = store
+            .quarantine("/debian/dists/bookworm/InRelease", "GPG verification failed", b"tampered")
+            .await
+            .unwrap()
+            .unwrap();
+        
+        let saved = std::fs::read(dir.path().join(format!("{}.bin", record.id))).unwrap();
+        assert_eq!(saved, b"tampered");
+        assert_eq!(store.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_artifact_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path(), 0);
+        
+        let record = store.quarantine("/debian/pool/main/a/apt/apt.deb", "hash mismatch", b"x").await.unwrap();
+        assert!(record.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_store_does_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = QuarantineStore::new(QuarantineConfig {
+            directory: dir.path().to_str().unwrap().to_string(),
+            ..QuarantineConfig::default()
+        });
+        
+        assert!(store.quarantine("/debian/x", "reason", b"data").await.unwrap().is_none());
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
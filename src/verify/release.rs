@@ -0,0 +1,185 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforcementMode {
+    Off,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseFile {
+    fields: HashMap<String, String>,
+}
+
+impl ReleaseFile {
+    pub fn parse(release_content: &str) -> Self {
+        let mut fields = HashMap::new();
+        
+        for line in release_content.lines() {
+            // Continuation lines belong to multi-line fields such as SHA256
+            if line.starts_with(char::is_whitespace) || line.starts_with("-----") {
+                continue;
+            }
+            
+            if let Some((name, value)) = line.split_once(':') {
+                if !name.is_empty() && !name.contains(char::is_whitespace) {
+                    fields.insert(name.to_lowercase(), value.trim().to_string());
+                }
+            }
+        }
+        
+        Self { fields }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(&name.to_lowercase()).map(|s| s.as_str())
+    }
+
+    pub fn suite(&self) -> Option<&str> {
+        self.field("Suite")
+    }
+
+    pub fn codename(&self) -> Option<&str> {
+        self.field("Codename")
+    }
+
+    pub fn date(&self) -> Result<Option<DateTime<Utc>>> {
+        self.field("Date").map(parse_release_date).transpose()
+    }
+
+    pub fn valid_until(&self) -> Result<Option<DateTime<Utc>>> {
+        self.field("Valid-Until").map(parse_release_date).transpose()
+    }
+
+    pub fn check_valid_until(&self, now: DateTime<Utc>) -> Result<()> {
+        if let Some(valid_until) = self.valid_until()? {
+            if now > valid_until {
+                return Err(anyhow!(
+                    "Release file expired at {} ({} seconds ago)",
+                    valid_until.to_rfc2822(),
+                    (now - valid_until).num_seconds()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_date(&self, now: DateTime<Utc>, max_future_skew: Duration, max_age: Option<Duration>) -> Result<()> {
+        let Some(date) = self.date()? else {
+            return Ok(());
+        };
+        
+        if date > now + max_future_skew {
+            return Err(anyhow!(
+                "Release file is dated {} seconds in the future ({})",
+                (date - now).num_seconds(),
+                date.to_rfc2822()
+            ));
+        }
+        
+        // A mirror that stopped syncing keeps serving the same old Date
+        if let Some(max_age) = max_age {
+            if now - date > max_age {
+                return Err(anyhow!(
+                    "Release file is {} days old ({})",
+                    (now - date).num_days(),
+                    date.to_rfc2822()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn weak_digest_issue(&self) -> Option<String> {
+        let has_strong = self.field("SHA256").is_some() || self.field("SHA512").is_some();
+        let has_weak = self.field("MD5Sum").is_some() || self.field("SHA1").is_some();
+        
+        if has_weak && !has_strong {
+            Some("Release file only publishes MD5/SHA1 checksums".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+pub fn parse_release_date(value: &str) -> Result<DateTime<Utc>> {
+    // Release files use RFC 2822 dates, usually with a "UTC" zone name
+    let normalized = value.trim().replace(" UTC", " +0000");
+    DateTime::parse_from_rfc2822(&normalized)
+        .map(|d| d.with_timezone(&Utc))
+        .map_err(|e| anyhow!("Invalid Release date '{}': {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const RELEASE: &str = "Origin: Debian
+Suite: stable-security
+Codename: bookworm-security
+Date: Sat, 10 Jun 2023 08:52:37 UTC
+Valid-Until: Sat, 17 Jun 2023 08:52:37 UTC
+SHA256:
+ abc123 1024 main/binary-amd64/Packages
+";
+
+    #[test]
+    fn test_parse_release_fields() {
+        let release = ReleaseFile::parse(RELEASE);
+        assert_eq!(release.suite(), Some("stable-security"));
+        assert_eq!(release.codename(), Some("bookworm-security"));
+        assert_eq!(release.field("sha256"), Some(""));
+        assert_eq!(
+            release.date().unwrap(),
+            Some(Utc.with_ymd_and_hms(2023, 6, 10, 8, 52, 37).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_valid_until_enforcement() {
+        let release = ReleaseFile::parse(RELEASE);
+        
+        let before = Utc.with_ymd_and_hms(2023, 6, 12, 0, 0, 0).unwrap();
+        assert!(release.check_valid_until(before).is_ok());
+        
+        let after = Utc.with_ymd_and_hms(2023, 6, 18, 0, 0, 0).unwrap();
+        assert!(release.check_valid_until(after).is_err());
+    }
+
+    #[test]
+    fn test_release_date_skew() {
+        let release = ReleaseFile::parse(RELEASE);
+        let skew = Duration::minutes(5);
+        let max_age = Some(Duration::days(14));
+        
+        let current = Utc.with_ymd_and_hms(2023, 6, 11, 0, 0, 0).unwrap();
+        assert!(release.check_date(current, skew, max_age).is_ok());
+        
+        let before_release = Utc.with_ymd_and_hms(2023, 6, 10, 8, 0, 0).unwrap();
+        assert!(release.check_date(before_release, skew, max_age).is_err());
+        
+        let frozen = Utc.with_ymd_and_hms(2023, 7, 1, 0, 0, 0).unwrap();
+        assert!(release.check_date(frozen, skew, max_age).is_err());
+        assert!(release.check_date(frozen, skew, None).is_ok());
+    }
+
+    #[test]
+    fn test_weak_digest_detection() {
+        assert!(ReleaseFile::parse(RELEASE).weak_digest_issue().is_none());
+        
+        let weak = ReleaseFile::parse("Suite: oldstable\nMD5Sum:\n abc 10 main/binary-amd64/Packages\n");
+        assert!(weak.weak_digest_issue().is_some());
+    }
+
+    #[test]
+    fn test_missing_valid_until_is_accepted() {
+        let release = ReleaseFile::parse("Suite: stable\n");
+        assert!(release.check_valid_until(Utc::now()).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::DirBuilderExt;
+use std::process::Command;
+use tracing::info;
+use crate::verify::gpg::GpgVerifier;
+
+// A site-local OpenPGP key for Release files aptg serves in place of the
+// upstream's, for repositories with resign = true
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    // Armored or binary secret key, e.g. from `gpg --export-secret-keys --armor`
+    pub secret_key_file: Option<String>,
+    pub passphrase_file: Option<String>,
+    // Fingerprint of the signing (sub)key; defaults to the first secret key
+    pub key_id: Option<String>,
+    // Private GnuPG home the key is imported into; created with mode 0700
+    pub gnupg_home: String,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            secret_key_file: None,
+            passphrase_file: None,
+            key_id: None,
+            gnupg_home: "/var/lib/aptg/gnupg".to_string(),
+        }
+    }
+}
+
+pub struct ReleaseSigner {
+    gnupg_home: String,
+    passphrase_file: Option<String>,
+    fingerprint: String,
+    // Armored public key, served to clients at /archive-key.asc
+    public_key: Vec<u8>,
+}
+
+impl ReleaseSigner {
+    pub fn from_config(config: &SigningConfig) -> Result<Self> {
+        let secret_key_file = config.secret_key_file.as_deref()
+            .ok_or_else(|| anyhow!("Re-signing needs [verification.signing] secret_key_file"))?;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&config.gnupg_home)
+            .map_err(|e| anyhow!("Failed to create GnuPG home {}: {}", config.gnupg_home, e))?;
+        let mut signer = Self {
+            gnupg_home: config.gnupg_home.clone(),
+            passphrase_file: config.passphrase_file.clone(),
+            fingerprint: String::new(),
+            public_key: Vec::new(),
+        };
+        
+        let output = signer.command().arg("--import").arg(secret_key_file).output()?;
+        if !output.status.success() {
+            return Err(anyhow!("Failed to import signing key {}: {}", secret_key_file, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        signer.fingerprint = match &config.key_id {
+            Some(key_id) => key_id.replace(' ', "").to_uppercase(),
+            None => {
+                let output = signer.command().arg("--with-colons").arg("--list-secret-keys").output()?;
+                Self::first_fingerprint(&String::from_utf8_lossy(&output.stdout))
+                    .ok_or_else(|| anyhow!("No secret key found in {}", secret_key_file))?
+            }
+        };
+        let output = signer.command().arg("--armor").arg("--export").arg(&signer.fingerprint).output()?;
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(anyhow!("Signing key {} was not imported", signer.fingerprint));
+        }
+        signer.public_key = output.stdout;
+        
+        info!("Re-signing Release files with key {}", signer.fingerprint);
+        Ok(signer)
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("gpg");
+        command.arg("--homedir").arg(&self.gnupg_home).arg("--batch").arg("--yes");
+        if let Some(passphrase_file) = &self.passphrase_file {
+            command.arg("--pinentry-mode").arg("loopback").arg("--passphrase-file").arg(passphrase_file);
+        }
+        command
+    }
+
+    // Primary key fingerprint from `gpg --with-colons --list-secret-keys`
+    fn first_fingerprint(listing: &str) -> Option<String> {
+        let mut lines = listing.lines();
+        lines.by_ref().find(|line| line.starts_with("sec:"))?;
+        lines
+            .find(|line| line.starts_with("fpr:"))
+            .and_then(|line| line.split(':').nth(9))
+            .filter(|fingerprint| !fingerprint.is_empty())
+            .map(str::to_string)
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    // An InRelease for the given Release text. Blocks on gpg, so call it from
+    // a blocking task
+    pub fn clearsign(&self, release: &str) -> Result<Vec<u8>> {
+        let output = GpgVerifier::run_with_stdin(
+            self.command()
+                .arg("--local-user")
+                .arg(&self.fingerprint)
+                .arg("--digest-algo")
+                .arg("SHA512")
+                .arg("--clearsign"),
+            release.as_bytes(),
+        )?;
+        if !output.status.success() {
+            return Err(anyhow!("Failed to sign Release: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_fingerprint() {
+        let listing = "\
+sec:u:255:22:8E33A4C1F0C6B2D7:1760000000:::u:::scESC:::+:::ed25519:::0:
+fpr:::::::::4C1D2E3F5A6B7C8D9E0F11228E33A4C1F0C6B2D7:
+grp:::::::::0123456789ABCDEF0123456789ABCDEF01234567:
+uid:u::::1760000000::0011223344556677::aptg archive <apt@example.org>::::::::::0:
+ssb:u:255:18:1122334455667788:1760000000::::::e:::+:::cv25519::
+fpr:::::::::AAAABBBBCCCCDDDDEEEEFFFF1122334455667788:
+";
+        assert_eq!(ReleaseSigner::first_fingerprint(listing).as_deref(), Some("4C1D2E3F5A6B7C8D9E0F11228E33A4C1F0C6B2D7"));
+        assert_eq!(ReleaseSigner::first_fingerprint("tru::1:1760000000:0:3:1:5\n"), None);
+    }
+}